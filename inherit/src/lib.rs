@@ -0,0 +1,59 @@
+#![deny(unsafe_code)]
+#![deny(missing_docs, missing_debug_implementations)]
+
+//! # `echo-rs-inherit` - adoption of listening sockets inherited by `echo-rs`
+//!
+//! Taking ownership of a file descriptor can't be done without `unsafe` code, which
+//! `echo-rs` itself forbids, so it's done here (and only here) instead.
+
+// Standard Library Imports
+use std::{
+    collections::HashSet,
+    io,
+    net::TcpListener,
+    sync::{Mutex, OnceLock},
+};
+
+/// The descriptors adopted so far, none of which may be adopted again
+fn adopted() -> &'static Mutex<HashSet<i32>> {
+    static ADOPTED: OnceLock<Mutex<HashSet<i32>>> = OnceLock::new();
+    ADOPTED.get_or_init(Default::default)
+}
+
+/// Adopt the listening TCP socket with the given file descriptor, which must have been
+/// handed down by the parent process for this process's sole use
+///
+/// Each descriptor can only be adopted once, and one that isn't a socket is left open.
+#[cfg(unix)]
+pub fn tcp_listener(fd: i32) -> io::Result<TcpListener> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+    if fd < 0 {
+        return Err(invalid(format!("invalid file descriptor {fd}")));
+    }
+
+    let mut adopted = adopted().lock().unwrap_or_else(|error| error.into_inner());
+
+    if !adopted.insert(fd) {
+        return Err(invalid(format!("file descriptor {fd} was already adopted")));
+    }
+
+    // SAFETY: the descriptor was handed down by the parent process for this
+    // process's sole use, and is adopted (i.e. owned, and so closed) just once
+    #[allow(unsafe_code)]
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+    if let Err(error) = listener.local_addr() {
+        // whatever it is, it isn't this process's to close
+        let _ = listener.into_raw_fd();
+        adopted.remove(&fd);
+
+        return Err(invalid(format!(
+            "file descriptor {fd} isn't a TCP socket: {error}"
+        )));
+    }
+
+    Ok(listener)
+}
@@ -0,0 +1,119 @@
+// Client Abort Detection
+
+// Standard Library Imports
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+// Third Party Imports
+use axum::{
+    body::Body,
+    http::{Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use tokio_stream::StreamExt;
+
+// Crate-Level Imports
+use crate::errors::TimedOut;
+
+/// Where in the exchange a client gave up
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+    /// Part-way through sending the request body, e.g. an aborted upload
+    Request,
+    /// Before the response was ready
+    Response,
+}
+
+impl Stage {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Request => "request",
+            Self::Response => "response",
+        }
+    }
+}
+
+/// Reports the request as aborted if it's dropped before it completes,
+/// which is what happens to a request whose client has disconnected
+struct Watch {
+    method: Method,
+    path: String,
+    started: Instant,
+    /// Set once reading the request body fails part-way through
+    truncated: Arc<AtomicBool>,
+    /// Set if the server, rather than the client, gave up on the request
+    timed_out: Option<TimedOut>,
+    completed: bool,
+}
+
+impl Watch {
+    fn report(&self, stage: Stage) {
+        tracing::warn!(
+            stage = stage.as_str(),
+            "Client aborted {} {} after {:?} ({})",
+            self.method,
+            self.path,
+            self.started.elapsed(),
+            match stage {
+                Stage::Request => "request body cut short",
+                Stage::Response => "disconnected before the response was sent",
+            }
+        );
+
+        metrics::increment_counter!(
+            "client_aborted_total",
+            "method" => self.method.to_string(),
+            "stage" => stage.as_str(),
+        );
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        if self.timed_out.as_ref().is_some_and(TimedOut::get) {
+            return;
+        }
+
+        if self.truncated.load(Ordering::Relaxed) {
+            self.report(Stage::Request);
+        } else if !self.completed {
+            self.report(Stage::Response);
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn detect(req: Request<Body>, next: Next<Body>) -> Response {
+    let truncated = Arc::new(AtomicBool::new(false));
+
+    let mut watch = Watch {
+        method: req.method().clone(),
+        path: req.uri().path().to_owned(),
+        started: Instant::now(),
+        truncated: truncated.clone(),
+        timed_out: req.extensions().get::<TimedOut>().cloned(),
+        completed: false,
+    };
+
+    let req = req.map(|body| {
+        Body::wrap_stream(body.map(move |chunk| {
+            if chunk.is_err() {
+                truncated.store(true, Ordering::Relaxed);
+            }
+
+            chunk
+        }))
+    });
+
+    let response = next.run(req).await;
+
+    watch.completed = true;
+
+    response
+}
@@ -0,0 +1,354 @@
+// Common / Combined Log Format Access Log
+
+// Standard Library Imports
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::Write as _,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, BoxBody, Bytes, HttpBody},
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+// Crate-Level Imports
+use crate::clock;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Layout of each access log line
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum AccessLogFormat {
+    /// `host ident user [time] "request" status bytes`
+    Common,
+    /// The common format, followed by `"referer" "user-agent"`
+    #[default]
+    Combined,
+}
+
+/// When the access log file is set aside for a fresh one
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Rotation {
+    /// Once the file has grown to this many bytes
+    pub(crate) max_size: Option<u64>,
+    /// Once the file has been written to for this long
+    pub(crate) every: Option<Duration>,
+}
+
+/// An append-only file, renamed (with its rotation time as a suffix) and
+/// replaced once it grows too large or too old
+#[derive(Debug)]
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened: Instant,
+    rotation: Rotation,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, rotation: Rotation) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            opened: Instant::now(),
+            rotation,
+        })
+    }
+
+    fn is_due(&self) -> bool {
+        self.rotation.max_size.is_some_and(|max| self.size >= max)
+            || self
+                .rotation
+                .every
+                .is_some_and(|every| self.opened.elapsed() >= every)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let stamp = humantime::format_rfc3339_seconds(clock::now())
+            .to_string()
+            .replace(':', "");
+
+        let mut rotated = suffixed(&self.path, &stamp);
+
+        for copy in 1.. {
+            if !rotated.exists() {
+                break;
+            }
+
+            rotated = suffixed(&self.path, &format!("{stamp}.{copy}"));
+        }
+
+        fs::rename(&self.path, &rotated)?;
+
+        *self = Self::open(self.path.clone(), self.rotation)?;
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.size > 0 && self.is_due() {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// The path, with `.{suffix}` appended to its file name
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{suffix}"));
+    path.with_file_name(name)
+}
+
+/// A classic (i.e. Apache-style) access log, written independently of the server's own logs
+#[derive(Debug)]
+pub(crate) struct AccessLog {
+    format: AccessLogFormat,
+    file: Mutex<RotatingFile>,
+}
+
+impl AccessLog {
+    pub(crate) fn open(
+        path: PathBuf,
+        format: AccessLogFormat,
+        rotation: Rotation,
+    ) -> anyhow::Result<Self> {
+        let file = RotatingFile::open(path.clone(), rotation)
+            .map_err(|error| anyhow::anyhow!("can't open access log {path:?}: {error}"))?;
+
+        Ok(Self {
+            format,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn write(&self, line: &str) {
+        let mut file = self.file.lock().unwrap();
+
+        if let Err(error) = file.write_line(line) {
+            tracing::warn!("Failed to write to access log {:?}: {error}", file.path);
+        }
+    }
+}
+
+/// Everything about a request that's logged, bar the size of its response
+#[derive(Debug)]
+struct Entry {
+    host: String,
+    user: Option<String>,
+    time: SystemTime,
+    request: String,
+    status: u16,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Entry {
+    fn line(&self, format: AccessLogFormat, bytes: u64) -> String {
+        let mut line = format!(
+            "{} - {} [{}] \"{}\" {} {}",
+            self.host,
+            self.user.as_deref().map_or_else(|| "-".to_owned(), escape),
+            timestamp(self.time),
+            escape(&self.request),
+            self.status,
+            match bytes {
+                0 => "-".to_owned(),
+                bytes => bytes.to_string(),
+            },
+        );
+
+        if format == AccessLogFormat::Combined {
+            let quoted =
+                |value: &Option<String>| value.as_deref().map_or_else(|| "-".to_owned(), escape);
+
+            let _ = write!(
+                line,
+                " \"{}\" \"{}\"",
+                quoted(&self.referer),
+                quoted(&self.user_agent)
+            );
+        }
+
+        line.push('\n');
+        line
+    }
+}
+
+/// Escape quotes, backslashes, and anything unprintable, as Apache does
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for char in value.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            char if char.is_ascii_graphic() || char == ' ' => escaped.push(char),
+            char => {
+                for byte in char.to_string().bytes() {
+                    let _ = write!(escaped, "\\x{byte:02x}");
+                }
+            }
+        }
+    }
+
+    escaped
+}
+
+/// The time, as it's written in the log (e.g. `10/Oct/2000:13:55:36 +0000`)
+fn timestamp(time: SystemTime) -> String {
+    // e.g. `2000-10-10T13:55:36Z`
+    let rfc3339 = humantime::format_rfc3339_seconds(time).to_string();
+
+    let month = rfc3339[5..7]
+        .parse::<usize>()
+        .ok()
+        .and_then(|month| MONTHS.get(month.wrapping_sub(1)))
+        .unwrap_or(&"Jan");
+
+    format!(
+        "{}/{month}/{}:{} +0000",
+        &rfc3339[8..10],
+        &rfc3339[..4],
+        &rfc3339[11..19]
+    )
+}
+
+/// The user named by the request's HTTP Basic credentials, if any
+fn basic_user(headers: &HeaderMap) -> Option<String> {
+    let credentials = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+
+    let decoded = String::from_utf8(STANDARD.decode(credentials.trim()).ok()?).ok()?;
+
+    decoded
+        .split_once(':')
+        .map(|(user, _)| user.to_owned())
+        .filter(|user| !user.is_empty())
+}
+
+/// Log each request once its response has been sent (or abandoned)
+#[tracing::instrument(skip_all)]
+pub(crate) async fn record<B>(
+    State(log): State<Arc<AccessLog>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let header = |name: header::HeaderName| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+    };
+
+    let mut entry = Entry {
+        host: req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or_else(
+                || "-".to_owned(),
+                |ConnectInfo(client)| client.ip().to_canonical().to_string(),
+            ),
+        user: basic_user(req.headers()),
+        time: clock::now(),
+        request: format!(
+            "{} {} {:?}",
+            req.method(),
+            req.uri()
+                .path_and_query()
+                .map_or_else(|| req.uri().path(), |target| target.as_str()),
+            req.version()
+        ),
+        status: 0,
+        referer: header(header::REFERER),
+        user_agent: header(header::USER_AGENT),
+    };
+
+    let response = next.run(req).await;
+
+    entry.status = response.status().as_u16();
+
+    response.map(|inner| {
+        body::boxed(LoggedBody {
+            inner,
+            sent: 0,
+            entry,
+            log,
+        })
+    })
+}
+
+/// Response body wrapper counting the bytes sent, and logging
+/// the request they answered once it's been dropped
+#[derive(Debug)]
+struct LoggedBody {
+    inner: BoxBody,
+    sent: u64,
+    entry: Entry,
+    log: Arc<AccessLog>,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_data(cx);
+
+        if let Poll::Ready(Some(Ok(data))) = &polled {
+            self.sent += data.len() as u64;
+        }
+
+        polled
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.log.write(&self.entry.line(self.log.format, self.sent));
+    }
+}
@@ -0,0 +1,108 @@
+// Socket Activation & Inherited Listeners
+
+// Standard Library Imports
+use std::{env, net::TcpListener};
+
+/// The first file descriptor passed by systemd (i.e. `SD_LISTEN_FDS_START`)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// The name a socket unit gives (via `FileDescriptorName=`) the metrics listener's socket
+const METRICS_NAME: &str = "metrics";
+
+/// Listening sockets handed down by the process that started this one, rather than bound by it
+#[derive(Debug, Default)]
+pub(crate) struct Inherited {
+    pub(crate) echo: Option<TcpListener>,
+    pub(crate) metrics: Option<TcpListener>,
+}
+
+impl Inherited {
+    /// Adopt the socket with the given file descriptor to serve the echo or,
+    /// failing that, the sockets passed by systemd (per `LISTEN_PID`, `LISTEN_FDS`,
+    /// and `LISTEN_FDNAMES`), where the one named `metrics` serves the metrics and
+    /// the first other one serves the echo
+    pub(crate) fn adopt(fd: Option<i32>) -> anyhow::Result<Self> {
+        let mut inherited = Self::default();
+
+        // an explicit descriptor may well be one of systemd's, which mustn't be adopted twice
+        if let Some(fd) = fd {
+            inherited.echo = Some(listener(fd)?);
+            return Ok(inherited);
+        }
+
+        for (name, listener) in systemd_listeners()? {
+            let slot = match name.as_deref() {
+                Some(METRICS_NAME) => &mut inherited.metrics,
+                _ => &mut inherited.echo,
+            };
+
+            match slot {
+                None => *slot = Some(listener),
+                Some(_) => tracing::warn!(
+                    "Ignoring surplus inherited socket {:?} ({})",
+                    name.unwrap_or_default(),
+                    describe(&listener)
+                ),
+            }
+        }
+
+        Ok(inherited)
+    }
+}
+
+/// A listener's local address, for logging
+pub(crate) fn describe(listener: &TcpListener) -> String {
+    listener
+        .local_addr()
+        .map_or_else(|error| format!("<{error}>"), |addr| addr.to_string())
+}
+
+/// The sockets systemd passed to this process (if it passed any), with their names
+fn systemd_listeners() -> anyhow::Result<Vec<(Option<String>, TcpListener)>> {
+    // the variables may have been meant for a parent process, and passed on by mistake
+    if env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        != Some(std::process::id())
+    {
+        return Ok(Vec::new());
+    }
+
+    let count = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or_default();
+
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':').map(str::to_owned);
+
+    (0..count)
+        .map(|offset| {
+            let name = names.next().filter(|name| !name.is_empty());
+
+            Ok((name, listener(systemd_fd(offset)?)?))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn systemd_fd(offset: i32) -> anyhow::Result<i32> {
+    Ok(LISTEN_FDS_START + offset)
+}
+
+#[cfg(not(unix))]
+fn systemd_fd(_: i32) -> anyhow::Result<i32> {
+    anyhow::bail!("socket activation is only supported on unix")
+}
+
+/// Adopt the listening TCP socket with the given file descriptor
+#[cfg(unix)]
+fn listener(fd: i32) -> anyhow::Result<TcpListener> {
+    Ok(echo_rs_inherit::tcp_listener(fd)?)
+}
+
+#[cfg(not(unix))]
+fn listener(fd: i32) -> anyhow::Result<TcpListener> {
+    anyhow::bail!("can't inherit file descriptor {fd}: only supported on unix")
+}
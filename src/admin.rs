@@ -0,0 +1,277 @@
+// Administrative Endpoint Access Control
+
+// Standard Library Imports
+use std::{fmt, net::SocketAddr, str::FromStr, sync::Arc};
+
+// Third Party Imports
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ring::digest::{digest, SHA256};
+
+// Crate-Level Imports
+use crate::{
+    audit::{AuditEntry, AuditLog},
+    clock,
+    errors::Failure,
+    request_id::RequestId,
+    routes::RouteAuth,
+    uploads::hex,
+};
+
+/// What a token lets its bearer do, each role permitting everything the ones before it do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub(crate) enum Role {
+    /// Read captures and runtime settings
+    Viewer,
+    /// Change runtime settings, and clear or replay captures
+    Operator,
+    /// Anything, including shutting the server down
+    Admin,
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Operator => "operator",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// A bearer token granted a role, as given on the command line: `<role>=<token>`
+#[derive(Clone)]
+pub(crate) struct RoleToken {
+    role: Role,
+    token: Arc<str>,
+    /// Identifies the token (e.g. in the audit log) without revealing it
+    id: Arc<str>,
+}
+
+impl RoleToken {
+    pub(crate) fn new(role: Role, token: &str) -> Self {
+        let fingerprint = hex(digest(&SHA256, token.as_bytes()).as_ref());
+
+        Self {
+            role,
+            token: Arc::from(token),
+            id: Arc::from(format!("tok_{}", &fingerprint[..12])),
+        }
+    }
+}
+
+impl FromStr for RoleToken {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (role, token) = value
+            .split_once('=')
+            .ok_or_else(|| "expected `<role>=<token>`".to_owned())?;
+
+        let role = <Role as clap::ValueEnum>::from_str(role.trim(), true).map_err(|_| {
+            format!("unknown role {role:?} (expected `viewer`, `operator`, or `admin`)")
+        })?;
+
+        match token.trim() {
+            "" => Err(format!("no token given for the `{}` role", role.name())),
+            token => Ok(Self::new(role, token)),
+        }
+    }
+}
+
+impl fmt::Debug for RoleToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RoleToken({}, {})", self.role.name(), self.id)
+    }
+}
+
+/// Bearer tokens (each granted a role) accepted by administrative endpoints
+#[derive(Clone)]
+pub(crate) struct AdminToken {
+    tokens: Arc<[RoleToken]>,
+    /// The role every request must be made with, regardless of its method
+    required: Option<Role>,
+    audit: Option<Arc<AuditLog>>,
+}
+
+impl AdminToken {
+    /// Accept the given tokens (if there are any)
+    pub(crate) fn new(tokens: Vec<RoleToken>) -> Option<Self> {
+        for token in &tokens {
+            tracing::info!(
+                "Accepting {} token for the `{}` role",
+                token.id,
+                token.role.name()
+            );
+        }
+
+        (!tokens.is_empty()).then(|| Self {
+            tokens: tokens.into(),
+            required: None,
+            audit: None,
+        })
+    }
+
+    /// Record every action (i.e. request other than a read) in the given audit log
+    pub(crate) fn with_audit(self, audit: Option<Arc<AuditLog>>) -> Self {
+        Self { audit, ..self }
+    }
+
+    /// Require (at least) the given role of every request, rather than
+    /// the `viewer` role of safe requests and `operator` of the rest
+    pub(crate) fn requiring(self, role: Role) -> Self {
+        Self {
+            required: Some(role),
+            ..self
+        }
+    }
+
+    /// The configured token matching the supplied one (with the highest role, should
+    /// it be configured more than once), checking it against each in constant time
+    fn granted(&self, candidate: &str) -> Option<&RoleToken> {
+        self.tokens
+            .iter()
+            .filter(|granted| constant_time_eq(granted.token.as_bytes(), candidate.as_bytes()))
+            .max_by_key(|granted| granted.role)
+    }
+}
+
+/// Compare two secrets without short-circuiting on the first difference
+pub(crate) fn constant_time_eq(expected: &[u8], candidate: &[u8]) -> bool {
+    expected.len() == candidate.len()
+        && expected
+            .iter()
+            .zip(candidate)
+            .fold(0u8, |diff, (left, right)| diff | (left ^ right))
+            == 0
+}
+
+impl fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminToken")
+            .field("tokens", &self.tokens)
+            .field("required", &self.required)
+            .field("audit", &self.audit)
+            .finish()
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn require_token<B>(
+    State(token): State<AdminToken>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let granted = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|candidate| token.granted(candidate.trim()))
+        .cloned();
+
+    let required = token.required.unwrap_or(if req.method().is_safe() {
+        Role::Viewer
+    } else {
+        Role::Operator
+    });
+
+    // reads aren't actions, so aren't audited
+    let audited = token
+        .audit
+        .as_ref()
+        .filter(|_| !req.method().is_safe())
+        .map(|audit| {
+            let entry = AuditEntry {
+                time: humantime::format_rfc3339_millis(clock::now()).to_string(),
+                actor: granted.as_ref().map(|granted| granted.id.to_string()),
+                role: granted.as_ref().map(|granted| granted.role.name()),
+                client: req
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(client)| client.ip().to_canonical().to_string()),
+                request_id: req
+                    .extensions()
+                    .get::<RequestId>()
+                    .map(|RequestId(id)| id.clone()),
+                method: req.method().to_string(),
+                path: req.uri().path().to_owned(),
+                status: 0,
+            };
+
+            (audit.clone(), entry)
+        });
+
+    let response = match granted.as_ref().map(|granted| granted.role) {
+        Some(role) if role >= required => next.run(req).await,
+        Some(role) => {
+            tracing::warn!(
+                "Rejecting {} request (requiring the `{}` role): {} {}",
+                role.name(),
+                required.name(),
+                req.method(),
+                req.uri().path()
+            );
+
+            Failure::new(
+                StatusCode::FORBIDDEN,
+                format!(
+                    "the `{}` role is required, but the token has the `{}` role",
+                    required.name(),
+                    role.name()
+                ),
+            )
+            .into_response()
+        }
+        None => {
+            tracing::warn!(
+                "Rejecting unauthorized admin request: {} {}",
+                req.method(),
+                req.uri().path()
+            );
+
+            (
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                Failure::new(StatusCode::UNAUTHORIZED, "missing or invalid admin token"),
+            )
+                .into_response()
+        }
+    };
+
+    if let Some((audit, entry)) = audited {
+        audit.record(AuditEntry {
+            status: response.status().as_u16(),
+            ..entry
+        });
+    }
+
+    response
+}
+
+/// Require the configured credentials of every request (e.g. to the metrics server)
+#[tracing::instrument(skip_all)]
+pub(crate) async fn require_credentials<B>(
+    State(auth): State<Arc<RouteAuth>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if auth.permits(req.headers()) {
+        return next.run(req).await;
+    }
+
+    tracing::warn!(
+        "Rejecting unauthorized request: {} {}",
+        req.method(),
+        req.uri().path()
+    );
+
+    (
+        [(header::WWW_AUTHENTICATE, auth.challenge())],
+        Failure::new(StatusCode::UNAUTHORIZED, "missing or invalid credentials"),
+    )
+        .into_response()
+}
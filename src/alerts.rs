@@ -0,0 +1,189 @@
+// Threshold Alerts (via Logs)
+
+// Standard Library Imports
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+use hdrhistogram::Histogram;
+use tokio::time::MissedTickBehavior;
+
+// Crate-Level Imports
+use crate::{
+    chaos::Injected,
+    latency::{histogram, MAX_TRACKED_MICROS},
+};
+
+/// Levels that, once crossed, are worth a warning in the log
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Thresholds {
+    /// Requests per second
+    pub(crate) request_rate: Option<f64>,
+    /// Fraction (0 to 1) of requests answered with an injected fault
+    pub(crate) error_rate: Option<f64>,
+    /// 99th percentile latency
+    pub(crate) latency: Option<Duration>,
+}
+
+impl Thresholds {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.request_rate.is_some() || self.error_rate.is_some() || self.latency.is_some()
+    }
+}
+
+/// What's been recorded since the last check
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    requests: u64,
+    injected: u64,
+    latency: Histogram<u64>,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: 0,
+            injected: 0,
+            latency: histogram(),
+        }
+    }
+}
+
+/// A single watched measure, and whether it was over its threshold last time it was checked
+#[derive(Debug)]
+struct Alert {
+    name: &'static str,
+    breached: bool,
+}
+
+impl Alert {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            breached: false,
+        }
+    }
+
+    /// Log (only) the moments the measure crosses its threshold, either way
+    fn check(&mut self, breached: bool, observed: &str, threshold: &str) {
+        match (self.breached, breached) {
+            (false, true) => {
+                tracing::warn!(
+                    alert = self.name,
+                    "Alert: {} is {observed}, over the {threshold} threshold",
+                    self.name
+                );
+                metrics::increment_counter!("alerts_fired_total", "alert" => self.name);
+            }
+            (true, false) => {
+                tracing::info!(
+                    alert = self.name,
+                    "Resolved: {} is back to {observed}, within the {threshold} threshold",
+                    self.name
+                );
+            }
+            _ => {}
+        }
+
+        self.breached = breached;
+    }
+}
+
+/// Watches the request rate, injected error rate, and latency of
+/// the echo routes, warning whenever one crosses its threshold
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    thresholds: Thresholds,
+    window: Mutex<Window>,
+}
+
+impl Watcher {
+    pub(crate) fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            window: Mutex::default(),
+        }
+    }
+
+    /// Check what's been recorded against the thresholds every `interval`, forever
+    pub(crate) async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        let (mut request_rate, mut error_rate, mut latency) = (
+            Alert::new("request rate"),
+            Alert::new("error injection rate"),
+            Alert::new("p99 latency"),
+        );
+
+        loop {
+            ticker.tick().await;
+
+            let window = std::mem::take(&mut *self.window.lock().unwrap());
+            let elapsed = window.started.elapsed().as_secs_f64();
+
+            if let Some(threshold) = self.thresholds.request_rate {
+                let rate = window.requests as f64 / elapsed;
+
+                request_rate.check(
+                    rate > threshold,
+                    &format!("{rate:.2} req/s"),
+                    &format!("{threshold} req/s"),
+                );
+            }
+
+            // without any requests there's no rate to speak of, so it's left as it was
+            if let (Some(threshold), true) = (self.thresholds.error_rate, window.requests > 0) {
+                let rate = window.injected as f64 / window.requests as f64;
+
+                error_rate.check(
+                    rate > threshold,
+                    &format!("{:.1}%", rate * 100.0),
+                    &format!("{:.1}%", threshold * 100.0),
+                );
+            }
+
+            if let (Some(threshold), true) = (self.thresholds.latency, window.requests > 0) {
+                let p99 = Duration::from_micros(window.latency.value_at_quantile(0.99));
+
+                latency.check(
+                    p99 > threshold,
+                    &format!("{p99:?}"),
+                    &humantime::format_duration(threshold).to_string(),
+                );
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn record<B>(
+    State(watcher): State<Arc<Watcher>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    let micros = u64::try_from(start.elapsed().as_micros())
+        .unwrap_or(u64::MAX)
+        .clamp(1, MAX_TRACKED_MICROS);
+
+    let mut window = watcher.window.lock().unwrap();
+
+    window.requests += 1;
+    window.latency.saturating_record(micros);
+
+    if response.extensions().get::<Injected>().is_some() {
+        window.injected += 1;
+    }
+
+    response
+}
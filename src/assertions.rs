@@ -0,0 +1,184 @@
+// Echo-Side Request Assertions
+
+// Standard Library Imports
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    middleware, routing, Router,
+};
+use serde_json::Value;
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+    jwt::unix_now,
+    template::Templates,
+};
+
+/// How many of the most recent failed assertions are kept
+const CAPACITY: usize = 1_000;
+
+/// An assertion a request made about itself that didn't hold
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct FailedAssertion {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) request_id: Option<String>,
+    pub(crate) assertion: String,
+    /// Why the assertion couldn't be evaluated, if it couldn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    /// Seconds since the unix epoch
+    pub(crate) received_at: u64,
+}
+
+/// Counts of the assertions evaluated, and the most recent of those that failed
+#[derive(Debug, Default)]
+pub(crate) struct Assertions {
+    passed: AtomicU64,
+    failed: AtomicU64,
+    failures: Mutex<VecDeque<FailedAssertion>>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct AssertionsReport {
+    passed: u64,
+    failed: u64,
+    failures: Vec<FailedAssertion>,
+}
+
+impl Assertions {
+    /// Evaluate each of the request's assertions against its echo, answering
+    /// with `417 Expectation Failed` if any of them don't hold
+    pub(crate) async fn check(
+        &self,
+        templates: &Arc<Templates>,
+        assertions: &[String],
+        echo: &Value,
+    ) -> Result<(), Failure> {
+        if assertions.is_empty() {
+            return Ok(());
+        }
+
+        let results = templates
+            .offload({
+                let (assertions, echo) = (assertions.to_vec(), echo.clone());
+
+                move |templates| {
+                    Ok(assertions
+                        .iter()
+                        .map(|assertion| templates.evaluate(assertion, &echo))
+                        .collect::<Vec<_>>())
+                }
+            })
+            .await
+            .unwrap_or_else(|error| vec![Err(error); assertions.len()]);
+
+        let mut failed = Vec::new();
+
+        for (assertion, result) in assertions.iter().zip(results) {
+            let (result, error) = match result {
+                Ok(true) => ("passed", None),
+                Ok(false) => ("failed", None),
+                Err(error) => ("error", Some(error)),
+            };
+
+            metrics::increment_counter!("echo_assertions_total", "result" => result);
+
+            if result == "passed" {
+                self.passed.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            self.record(FailedAssertion {
+                method: echo["method"].as_str().unwrap_or_default().to_owned(),
+                path: echo["path"].as_str().unwrap_or_default().to_owned(),
+                request_id: echo["request_id"].as_str().map(str::to_owned),
+                assertion: assertion.clone(),
+                error: error.clone(),
+                received_at: unix_now(),
+            });
+
+            failed.push(match error {
+                Some(error) => format!("`{assertion}` could not be evaluated: {error}"),
+                None => format!("`{assertion}` does not hold"),
+            });
+        }
+
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(Failure::new(
+                StatusCode::EXPECTATION_FAILED,
+                format!("assertion failed: {}", failed.join("; ")),
+            )),
+        }
+    }
+
+    fn record(&self, failure: FailedAssertion) {
+        tracing::warn!(
+            "{} {} failed assertion `{}`",
+            failure.method,
+            failure.path,
+            failure.assertion
+        );
+
+        self.failed.fetch_add(1, Ordering::Relaxed);
+
+        let mut failures = self.failures.lock().unwrap();
+
+        if failures.len() >= CAPACITY {
+            failures.pop_front();
+        }
+
+        failures.push_back(failure);
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(assertions: Arc<Assertions>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_assertions", routing::get(report).delete(reset))
+        .with_state(assertions);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn report(State(assertions): State<Arc<Assertions>>) -> Json<AssertionsReport> {
+    Json(AssertionsReport {
+        passed: assertions.passed.load(Ordering::Relaxed),
+        failed: assertions.failed.load(Ordering::Relaxed),
+        failures: assertions
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect(),
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn reset(State(assertions): State<Arc<Assertions>>) -> StatusCode {
+    assertions.failures.lock().unwrap().clear();
+    assertions.passed.store(0, Ordering::Relaxed);
+    assertions.failed.store(0, Ordering::Relaxed);
+
+    StatusCode::NO_CONTENT
+}
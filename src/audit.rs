@@ -0,0 +1,90 @@
+// Administrative Action Audit Log
+
+// Standard Library Imports
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+/// An administrative action (i.e. a request, other than a read,
+/// made of an administrative endpoint), whether it was permitted or not
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct AuditEntry {
+    /// When the action was taken, as an RFC 3339 timestamp
+    pub(crate) time: String,
+    /// ID of the token the action was taken with, if it was a known one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) actor: Option<String>,
+    /// Role granted to that token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) client: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) request_id: Option<String>,
+    pub(crate) method: String,
+    pub(crate) path: String,
+    /// Status code the action was answered with
+    pub(crate) status: u16,
+}
+
+/// An append-only file recording every administrative action, one JSON object per line
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub(crate) fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Record the action, written out (and synced) before the action is answered
+    pub(crate) fn record(&self, entry: AuditEntry) {
+        tracing::info!(
+            "Audit: {} {} by {} ({}): {}",
+            entry.method,
+            entry.path,
+            entry.actor.as_deref().unwrap_or("unknown token"),
+            entry.role.unwrap_or("no role"),
+            entry.status
+        );
+
+        metrics::increment_counter!(
+            "admin_actions_total",
+            "role" => entry.role.unwrap_or("none"),
+            "status" => entry.status.to_string()
+        );
+
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(error) => {
+                tracing::error!("Failed to serialize audit entry: {error}");
+                return;
+            }
+        };
+
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+
+        if let Err(error) = file.write_all(&line).and_then(|()| file.sync_data()) {
+            tracing::error!(
+                "Failed to write to audit log {}: {error}",
+                self.path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// Batched Echo Requests
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Json, State},
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    response::Response,
+    routing, Router,
+};
+use serde_json::Value;
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+// Crate-Level Imports
+use crate::{errors::Failure, listeners::Profile, sampling::coin_flip};
+
+/// The most sub-requests a single batch may hold
+const MAX_ITEMS: usize = 100;
+
+/// The longest a sub-request may be delayed
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The echo service, behind a lock only because routers aren't `Sync`
+type EchoService = Arc<Mutex<Router>>;
+
+/// The order a batch's echoes are answered in
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Order {
+    /// The order the sub-requests were given in
+    #[default]
+    Given,
+    Reversed,
+    Shuffled,
+    /// The order the sub-requests finished in (i.e. as their `delay`s dictate)
+    Completion,
+}
+
+/// A request to echo, as part of a batch
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubRequest {
+    #[serde(default = "SubRequest::default_method")]
+    method: String,
+    path: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    /// Sent as-is if it's a string, or else as JSON
+    #[serde(default)]
+    body: Option<Value>,
+    /// How long to wait before making the sub-request
+    #[serde(default, with = "humantime_serde")]
+    delay: Option<Duration>,
+}
+
+impl SubRequest {
+    fn default_method() -> String {
+        Method::GET.to_string()
+    }
+
+    /// The sub-request, as it's handed to the echo service
+    fn build(&self, client: Option<SocketAddr>, profile: Profile) -> Result<Request<Body>, String> {
+        if !self.path.starts_with('/') {
+            return Err(format!("path {:?} must start with `/`", self.path));
+        }
+
+        let mut req = Request::builder()
+            .method(self.method.to_ascii_uppercase().as_str())
+            .uri(&self.path);
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::try_from(name.as_str())
+                .map_err(|error| format!("header {name:?}: {error}"))?;
+            let value = HeaderValue::try_from(value.as_str())
+                .map_err(|error| format!("header {name:?}: {error}"))?;
+
+            req = req.header(name, value);
+        }
+
+        let body = match &self.body {
+            None => Body::empty(),
+            Some(Value::String(body)) => Body::from(body.clone()),
+            Some(body) => {
+                if !self
+                    .headers
+                    .keys()
+                    .any(|name| name.eq_ignore_ascii_case(header::CONTENT_TYPE.as_str()))
+                {
+                    req = req.header(header::CONTENT_TYPE, "application/json");
+                }
+
+                Body::from(body.to_string())
+            }
+        };
+
+        let mut req = req.body(body).map_err(|error| error.to_string())?;
+
+        // as though made over the batch's own connection
+        if let Some(client) = client {
+            req.extensions_mut().insert(ConnectInfo(client));
+        }
+
+        req.extensions_mut().insert(profile);
+
+        Ok(req)
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Batch {
+    #[serde(default)]
+    order: Order,
+    requests: Vec<SubRequest>,
+}
+
+/// A sub-request's echo (or whatever else it was answered with)
+#[derive(Clone, Debug, serde::Serialize)]
+struct SubResponse {
+    /// Position of the sub-request in the batch
+    index: usize,
+    status: u16,
+    headers: BTreeMap<String, String>,
+    /// Parsed if it's JSON, or else (lossily) as text
+    body: Value,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct BatchResponse {
+    order: Order,
+    responses: Vec<SubResponse>,
+}
+
+/// Answers batches of sub-requests with their echoes, made via the given (echo) router
+#[tracing::instrument(skip_all)]
+pub(crate) fn router(echo: Router) -> Router {
+    Router::new()
+        .route("/_echo/batch", routing::post(batch))
+        .with_state(Arc::new(Mutex::new(echo)))
+}
+
+/// Echo each of the batch's sub-requests (all at once, each after its
+/// `delay`), answering with their echoes in the requested order
+#[tracing::instrument(skip_all)]
+async fn batch(
+    State(echo): State<EchoService>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    profile: Option<axum::Extension<Profile>>,
+    Json(batch): Json<Batch>,
+) -> Result<Json<BatchResponse>, Failure> {
+    if batch.requests.len() > MAX_ITEMS {
+        return Err(Failure::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("a batch may hold at most {MAX_ITEMS} requests"),
+        ));
+    }
+
+    let client = client.map(|ConnectInfo(client)| client);
+    let profile = profile
+        .map(|axum::Extension(profile)| profile)
+        .unwrap_or_default();
+
+    let mut pending = JoinSet::new();
+
+    for (index, sub) in batch.requests.iter().enumerate() {
+        let req = sub.build(client, profile).map_err(|error| {
+            Failure::new(StatusCode::BAD_REQUEST, format!("request {index}: {error}"))
+        })?;
+        let delay = sub.delay.map(|delay| delay.min(MAX_DELAY));
+        let echo = echo.lock().unwrap().clone();
+
+        pending.spawn(async move {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+
+            let response = echo.oneshot(req).await;
+
+            (index, response.map_err(|error| error.to_string()))
+        });
+    }
+
+    // joined as they finish, which is the order `completion` answers in
+    let mut responses = Vec::with_capacity(batch.requests.len());
+
+    while let Some(joined) = pending.join_next().await {
+        let (index, response) = joined
+            .map_err(|error| Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+        let response =
+            response.map_err(|error| Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error))?;
+
+        responses.push(SubResponse::of(index, response).await?);
+    }
+
+    match batch.order {
+        Order::Given => responses.sort_by_key(|response| response.index),
+        Order::Reversed => responses.sort_by_key(|response| std::cmp::Reverse(response.index)),
+        Order::Shuffled => shuffle(&mut responses),
+        Order::Completion => {}
+    }
+
+    Ok(Json(BatchResponse {
+        order: batch.order,
+        responses,
+    }))
+}
+
+impl SubResponse {
+    async fn of(index: usize, response: Response) -> Result<Self, Failure> {
+        let (parts, body) = response.into_parts();
+
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|error| Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+
+        let mut headers = BTreeMap::<String, String>::new();
+
+        for (name, value) in &parts.headers {
+            let value = String::from_utf8_lossy(value.as_bytes());
+
+            headers
+                .entry(name.as_str().to_owned())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+
+        Ok(Self {
+            index,
+            status: parts.status.as_u16(),
+            headers,
+            body: serde_json::from_slice(&body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned())),
+        })
+    }
+}
+
+/// Fisher-Yates, in place
+fn shuffle<T>(items: &mut [T]) {
+    for last in (1..items.len()).rev() {
+        let other = ((coin_flip() * (last + 1) as f64) as usize).min(last);
+        items.swap(last, other);
+    }
+}
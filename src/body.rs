@@ -0,0 +1,437 @@
+// Request Body Handling
+
+// Standard Library Imports
+use std::{
+    fmt,
+    future::Future,
+    io::{self, Read},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, request::Parts, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body::{LengthLimitError, Limited};
+use serde_json::{json, Value};
+use tokio::time::Sleep;
+use tokio_stream::Stream;
+
+// Crate-Level Imports
+use crate::{errors::Failure, parsers};
+
+/// The most a compressed body may decompress to, so a decompression bomb
+/// can't exhaust the server's memory
+const MAX_DECODED_LEN: u64 = 16 * 1024 * 1024;
+
+/// Read a decoder's output, failing rather than reading past the limit
+fn read_limited(decoder: impl Read) -> io::Result<Vec<u8>> {
+    let mut decoded = vec![];
+    decoder
+        .take(MAX_DECODED_LEN + 1)
+        .read_to_end(&mut decoded)?;
+
+    if decoded.len() as u64 > MAX_DECODED_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("body decompresses to more than {MAX_DECODED_LEN} bytes"),
+        ));
+    }
+
+    Ok(decoded)
+}
+
+/// Undo a single `Content-Encoding`
+fn decode_one(encoding: &str, body: &[u8]) -> Result<Vec<u8>, String> {
+    let decoded = match encoding {
+        "gzip" | "x-gzip" => read_limited(flate2::read::MultiGzDecoder::new(body)),
+        // `deflate` is meant to be zlib-wrapped, but is often sent raw
+        "deflate" => read_limited(flate2::read::ZlibDecoder::new(body))
+            .or_else(|_| read_limited(flate2::read::DeflateDecoder::new(body))),
+        "br" => read_limited(brotli_decompressor::Decompressor::new(body, 4096)),
+        "zstd" => ruzstd::StreamingDecoder::new(body)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+            .and_then(read_limited),
+        other => return Err(format!("unsupported content encoding {other:?}")),
+    };
+
+    decoded.map_err(|error| format!("invalid {encoding} body: {error}"))
+}
+
+/// Undo the body's `Content-Encoding` (a list of codings, in the order
+/// they were applied), returning the encoding it was decoded from - or
+/// `None` if it wasn't encoded in the first place
+pub(crate) fn decode(
+    content_encoding: Option<&str>,
+    body: &[u8],
+) -> Result<Option<(String, Vec<u8>)>, String> {
+    let codings = content_encoding
+        .unwrap_or_default()
+        .split(',')
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect::<Vec<_>>();
+
+    if codings.is_empty() || body.is_empty() {
+        return Ok(None);
+    }
+
+    let mut decoded = body.to_vec();
+
+    for coding in codings.iter().rev() {
+        decoded = decode_one(coding, &decoded)?;
+    }
+
+    Ok(Some((codings.join(", "), decoded)))
+}
+
+/// Why a request body couldn't be parsed as JSON
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct ParseError {
+    /// The parser's description of the problem
+    pub(crate) message: String,
+    /// Byte offset into the body the problem was found at
+    pub(crate) offset: usize,
+    /// 1-based line the problem was found on
+    pub(crate) line: usize,
+    /// 1-based column the problem was found at
+    pub(crate) column: usize,
+    /// What the body appears to actually contain
+    pub(crate) sniffed_type: &'static str,
+}
+
+/// A parser for request bodies of particular content types, e.g.
+///
+/// ```
+/// use echo_rs::body::{BodyParser, ParserRegistry};
+///
+/// /// `text/csv` bodies, as an array of each row's fields
+/// #[derive(Debug)]
+/// struct Csv;
+///
+/// impl BodyParser for Csv {
+///     fn name(&self) -> &'static str {
+///         "csv"
+///     }
+///
+///     fn accepts(&self, media_type: &str) -> bool {
+///         media_type == "text/csv"
+///     }
+///
+///     fn parse(&self, body: &[u8]) -> Result<serde_json::Value, String> {
+///         let text = std::str::from_utf8(body).map_err(|error| error.to_string())?;
+///
+///         let rows = text.lines().map(|row| row.split(',').collect::<Vec<_>>());
+///
+///         Ok(serde_json::json!(rows.collect::<Vec<_>>()))
+///     }
+/// }
+///
+/// let state = echo_rs::EchoState::default()
+///     .with_parsers(ParserRegistry::default().register(Csv));
+/// ```
+pub trait BodyParser: fmt::Debug + Send + Sync {
+    /// Short name identifying the parser (e.g. `form`)
+    fn name(&self) -> &'static str;
+
+    /// Whether the parser handles the given (lowercase, parameter-less) media type
+    fn accepts(&self, media_type: &str) -> bool;
+
+    /// Parse the body into its JSON representation
+    fn parse(&self, body: &[u8]) -> Result<Value, String>;
+}
+
+/// The set of parsers request bodies are run through (by default, the built-in
+/// ones for text, forms, multipart uploads, XML, CBOR, and protobuf)
+#[derive(Clone, Debug)]
+pub struct ParserRegistry {
+    parsers: Vec<Arc<dyn BodyParser>>,
+}
+
+impl Default for ParserRegistry {
+    fn default() -> Self {
+        Self::empty()
+            .register(parsers::Text)
+            .register(parsers::Form)
+            .register(parsers::Multipart)
+            .register(parsers::Xml)
+            .register(parsers::Cbor)
+            .register(parsers::Protobuf)
+    }
+}
+
+impl ParserRegistry {
+    /// A registry without any parsers (beyond the JSON every body is tried as)
+    pub fn empty() -> Self {
+        Self { parsers: vec![] }
+    }
+
+    /// Add a parser, taking precedence over any already registered for the same media types
+    pub fn register(mut self, parser: impl BodyParser + 'static) -> Self {
+        self.parsers.insert(0, Arc::new(parser));
+        self
+    }
+
+    /// Parse the body as JSON, then (failing that) with whichever parser handles
+    /// its declared content type, falling back to the body as a string if it's
+    /// valid UTF-8 or base64-encoded if it isn't (along with a description of
+    /// why it wasn't valid JSON) if neither can
+    pub(crate) fn parse(
+        &self,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> (Value, Option<ParseError>) {
+        if body.is_empty() {
+            return (Value::Null, None);
+        }
+
+        let error = match serde_json::from_slice::<Value>(body) {
+            Ok(value) => return (value, None),
+            Err(error) => error,
+        };
+
+        let media_type = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if let Some(parser) = self
+            .parsers
+            .iter()
+            .find(|parser| parser.accepts(&media_type))
+        {
+            match parser.parse(body) {
+                Ok(value) => return (value, None),
+                Err(error) => tracing::debug!("`{}` parser rejected body: {error}", parser.name()),
+            }
+        }
+
+        let sniffed_type = sniff(body);
+
+        let value = match std::str::from_utf8(body) {
+            Ok(text) => Value::String(text.to_owned()),
+            Err(_) => json!({
+                "content_type": content_type.unwrap_or(sniffed_type),
+                "encoding": "base64",
+                "data": STANDARD.encode(body),
+            }),
+        };
+
+        (
+            value,
+            Some(ParseError {
+                message: error.to_string(),
+                offset: offset(body, error.line(), error.column()),
+                line: error.line(),
+                column: error.column(),
+                sniffed_type,
+            }),
+        )
+    }
+}
+
+/// Convert a (1-based) line and column into a byte offset
+fn offset(body: &[u8], line: usize, column: usize) -> usize {
+    let line_start = body
+        .split_inclusive(|byte| *byte == b'\n')
+        .take(line.saturating_sub(1))
+        .map(<[u8]>::len)
+        .sum::<usize>();
+
+    (line_start + column.saturating_sub(1)).min(body.len())
+}
+
+/// Make an educated guess at the body's content type
+pub(crate) fn sniff(body: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+
+    if let Some((_, content_type)) = MAGIC.iter().find(|(magic, _)| body.starts_with(magic)) {
+        return content_type;
+    }
+
+    let Ok(text) = std::str::from_utf8(body) else {
+        return "application/octet-stream";
+    };
+
+    let text = text.trim_start();
+
+    if text.starts_with("<?xml") {
+        "application/xml"
+    } else if text.len() >= 5 && text[..5].eq_ignore_ascii_case("<html")
+        || text.starts_with("<!DOCTYPE")
+    {
+        "text/html"
+    } else if text.starts_with('<') {
+        "application/xml"
+    } else if text.starts_with(['{', '[']) {
+        "application/json"
+    } else if !text.contains(char::is_whitespace) && text.contains('=') {
+        "application/x-www-form-urlencoded"
+    } else {
+        "text/plain"
+    }
+}
+
+/// Parse a size in bytes, optionally suffixed with a (binary) unit, e.g. `512KiB` or `10M`
+pub(crate) fn parse_size(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let split = value
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (count, unit) = value.split_at(split);
+
+    let scale = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        other => return Err(format!("unknown size unit {other:?}")),
+    };
+
+    count
+        .parse::<usize>()
+        .map_err(|error| format!("invalid size {value:?}: {error}"))?
+        .checked_mul(scale)
+        .ok_or_else(|| format!("size {value:?} is too large"))
+}
+
+/// The most a request body may hold
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BodyLimit(pub(crate) usize);
+
+impl BodyLimit {
+    pub(crate) fn reject(self) -> Response {
+        Failure::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("request body exceeds {} bytes", self.0),
+        )
+        .into_response()
+    }
+}
+
+/// Buffer a request body, up to the limit (if any) the request is subject to
+pub(crate) async fn buffer(parts: &Parts, body: Body) -> Result<Bytes, Response> {
+    let buffered = match parts.extensions.get::<BodyLimit>() {
+        None => hyper::body::to_bytes(body).await.map_err(Into::into),
+        Some(limit) => hyper::body::to_bytes(Limited::new(body, limit.0)).await,
+    };
+
+    buffered.map_err(|error| match parts.extensions.get::<BodyLimit>() {
+        Some(limit) if error.is::<LengthLimitError>() => limit.reject(),
+        _ => Failure::new(StatusCode::BAD_REQUEST, error.to_string()).into_response(),
+    })
+}
+
+/// Reject requests whose bodies are (declared to be) larger than the limit
+/// outright, and subject the rest to it wherever their bodies are buffered
+#[tracing::instrument(skip_all)]
+pub(crate) async fn limit(
+    State(limit): State<BodyLimit>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    let response = if declared.is_some_and(|length| length > limit.0 as u64) {
+        limit.reject()
+    } else {
+        req.extensions_mut().insert(limit);
+        next.run(req).await
+    };
+
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE
+        && response.extensions().get::<Failure>().is_some()
+    {
+        metrics::increment_counter!("request_body_rejected_total");
+    }
+
+    response
+}
+
+/// How long a request's body may take to arrive, once its head has
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ReadTimeout(pub(crate) Duration);
+
+/// A request body that fails if it isn't received in full by its deadline
+struct Deadlined {
+    body: Body,
+    deadline: Pin<Box<Sleep>>,
+    expired: Arc<AtomicBool>,
+}
+
+impl Stream for Deadlined {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.expired.load(Ordering::Relaxed) {
+            return Poll::Ready(None);
+        }
+
+        if let Poll::Ready(chunk) = Pin::new(&mut self.body).poll_next(cx) {
+            return Poll::Ready(chunk.map(|chunk| chunk.map_err(io::Error::other)));
+        }
+
+        ready!(self.deadline.as_mut().poll(cx));
+        self.expired.store(true, Ordering::Relaxed);
+
+        Poll::Ready(Some(Err(io::ErrorKind::TimedOut.into())))
+    }
+}
+
+/// Cut request bodies that take too long to arrive short, answering with 408
+/// (whichever layer or handler happened to be reading them at the time)
+#[tracing::instrument(skip_all)]
+pub(crate) async fn read_timeout(
+    State(ReadTimeout(timeout)): State<ReadTimeout>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let expired = Arc::new(AtomicBool::new(false));
+
+    let req = req.map(|body| {
+        Body::wrap_stream(Deadlined {
+            body,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+            expired: expired.clone(),
+        })
+    });
+
+    let response = next.run(req).await;
+
+    if !expired.load(Ordering::Relaxed) {
+        return response;
+    }
+
+    metrics::increment_counter!("request_body_timeouts_total");
+
+    Failure::new(
+        StatusCode::REQUEST_TIMEOUT,
+        format!(
+            "request body not received within {}",
+            humantime::format_duration(timeout)
+        ),
+    )
+    .into_response()
+}
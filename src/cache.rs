@@ -0,0 +1,406 @@
+// Proxy Response Cache
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Bytes, Full},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+
+// Crate-Level Imports
+use crate::clock;
+
+/// Statuses a response may be stored with (those RFC 9111 calls "understood")
+const STORABLE: &[u16] = &[200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// The `Cache-Control` directives the cache heeds
+#[derive(Clone, Copy, Debug, Default)]
+struct Directives {
+    no_store: bool,
+    no_cache: bool,
+    private: bool,
+    public: bool,
+    max_age: Option<u64>,
+    s_maxage: Option<u64>,
+}
+
+impl Directives {
+    fn of(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+
+        for directive in headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+        {
+            let (name, value) = directive
+                .split_once('=')
+                .map_or((directive, None), |(name, value)| (name, Some(value)));
+            let seconds =
+                value.and_then(|value| value.trim().trim_matches('"').parse::<u64>().ok());
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "no-store" => directives.no_store = true,
+                // `no-cache` may name the fields it applies to, which are taken to mean all of them
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "max-age" => directives.max_age = seconds.or(Some(0)),
+                "s-maxage" => directives.s_maxage = seconds.or(Some(0)),
+                _ => {}
+            }
+        }
+
+        // HTTP/1.0's equivalent, only heeded in the absence of `Cache-Control`
+        if !headers.contains_key(header::CACHE_CONTROL)
+            && headers
+                .get_all(header::PRAGMA)
+                .iter()
+                .any(|value| value.as_bytes().eq_ignore_ascii_case(b"no-cache"))
+        {
+            directives.no_cache = true;
+        }
+
+        directives
+    }
+}
+
+/// Every value of the named header, joined as a single list
+fn joined(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    let values = headers
+        .get_all(name)
+        .iter()
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).trim().to_owned())
+        .collect::<Vec<String>>();
+
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// The names of the request headers the response varies by, or `None` if it varies by `*`
+fn vary_names(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut names = Vec::new();
+
+    for name in headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if name == "*" {
+            return None;
+        }
+
+        if let Ok(name) = HeaderName::try_from(name) {
+            names.push(name);
+        }
+    }
+
+    Some(names)
+}
+
+/// A stored response
+#[derive(Debug)]
+struct Entry {
+    /// The values of the request headers the response varies by, as they were when it was stored
+    vary: Vec<(HeaderName, Option<String>)>,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored: Instant,
+    /// How old the response already was when it was stored
+    initial_age: Duration,
+    /// How long the response stays fresh
+    lifetime: Duration,
+}
+
+impl Entry {
+    fn age(&self) -> Duration {
+        self.initial_age + self.stored.elapsed()
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.age() < self.lifetime
+    }
+
+    fn selected_by(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| joined(headers, name) == *value)
+    }
+}
+
+/// What became of a request's cache lookup
+#[derive(Debug)]
+pub(crate) enum Lookup {
+    /// A fresh stored response answers it
+    Hit(Response),
+    /// It must be relayed, but its response may be stored
+    Miss,
+    /// It must be relayed, and its response must not be stored
+    Bypass,
+}
+
+impl Lookup {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Hit(_) => "hit",
+            Self::Miss => "miss",
+            Self::Bypass => "bypass",
+        }
+    }
+
+    /// Mark the relayed response with whether it could have been answered from the cache
+    pub(crate) fn mark(&self, response: &mut Response) {
+        response.headers_mut().insert(
+            "x-cache",
+            HeaderValue::from_static(match self {
+                Self::Hit(_) => "HIT",
+                Self::Miss => "MISS",
+                Self::Bypass => "BYPASS",
+            }),
+        );
+    }
+}
+
+/// A shared (i.e. intermediary) in-memory cache of upstream responses, honoring
+/// `Cache-Control`, `Expires`, and `Vary`
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    /// Stored responses, by request URI (several, where they vary)
+    entries: Mutex<HashMap<String, Vec<Entry>>>,
+    /// The most responses stored at once
+    capacity: usize,
+    /// The largest response body that's stored
+    max_body: usize,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(capacity: usize, max_body: usize) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            max_body,
+        }
+    }
+
+    /// Answer the request from the cache, if it can be
+    pub(crate) fn lookup(&self, parts: &Parts) -> Lookup {
+        let lookup = self.find(parts);
+
+        metrics::increment_counter!("proxy_cache_requests_total", "result" => lookup.label());
+
+        lookup
+    }
+
+    fn find(&self, parts: &Parts) -> Lookup {
+        if !matches!(parts.method, Method::GET | Method::HEAD) {
+            return Lookup::Bypass;
+        }
+
+        let requested = Directives::of(&parts.headers);
+
+        if requested.no_store {
+            return Lookup::Bypass;
+        }
+
+        // the client insists on a response straight from the upstream
+        if requested.no_cache || requested.max_age == Some(0) {
+            return Lookup::Miss;
+        }
+
+        let entries = self.entries.lock().unwrap();
+
+        let Some(entry) = entries
+            .get(&parts.uri.to_string())
+            .and_then(|entries| {
+                entries
+                    .iter()
+                    .find(|entry| entry.selected_by(&parts.headers))
+            })
+            .filter(|entry| entry.is_fresh())
+            .filter(|entry| {
+                requested
+                    .max_age
+                    .is_none_or(|max_age| entry.age() <= Duration::from_secs(max_age))
+            })
+        else {
+            return Lookup::Miss;
+        };
+
+        let mut headers = entry.headers.clone();
+        headers.insert(header::AGE, HeaderValue::from(entry.age().as_secs()));
+        headers.insert("x-cache", HeaderValue::from_static("HIT"));
+
+        let mut response = Response::new(body::boxed(Full::new(if parts.method == Method::HEAD {
+            Bytes::new()
+        } else {
+            entry.body.clone()
+        })));
+        *response.status_mut() = entry.status;
+        *response.headers_mut() = headers;
+
+        Lookup::Hit(response)
+    }
+
+    /// Store the upstream's response to a missed request, if it may be
+    pub(crate) fn store(
+        &self,
+        parts: &Parts,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) {
+        // responses to `HEAD` requests have no body to answer `GET` requests with
+        if parts.method != Method::GET
+            || !STORABLE.contains(&status.as_u16())
+            || body.len() > self.max_body
+            || self.capacity == 0
+        {
+            return;
+        }
+
+        let directives = Directives::of(headers);
+
+        if directives.no_store || directives.no_cache || directives.private {
+            return;
+        }
+
+        // shared caches may only store responses to authorized requests if told they can
+        if parts.headers.contains_key(header::AUTHORIZATION)
+            && !(directives.public || directives.s_maxage.is_some())
+        {
+            return;
+        }
+
+        let Some(vary) = vary_names(headers) else {
+            return;
+        };
+
+        let Some(lifetime) = lifetime(&directives, headers) else {
+            return;
+        };
+
+        let initial_age = headers
+            .get(header::AGE)
+            .and_then(|age| age.to_str().ok())
+            .and_then(|age| age.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or_default();
+
+        if initial_age >= lifetime {
+            return;
+        }
+
+        let entry = Entry {
+            vary: vary
+                .into_iter()
+                .map(|name| {
+                    let value = joined(&parts.headers, &name);
+                    (name, value)
+                })
+                .collect(),
+            status,
+            headers: headers.clone(),
+            body: body.clone(),
+            stored: Instant::now(),
+            initial_age,
+            lifetime,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        let key = parts.uri.to_string();
+
+        // replace whatever was stored for the same variant
+        if let Some(variants) = entries.get_mut(&key) {
+            variants.retain(|stored| stored.vary != entry.vary);
+        }
+
+        if stored_count(&entries) >= self.capacity {
+            entries
+                .values_mut()
+                .for_each(|variants| variants.retain(Entry::is_fresh));
+            entries.retain(|_, variants| !variants.is_empty());
+        }
+
+        // still full, so make room by evicting the longest-stored response
+        if stored_count(&entries) >= self.capacity {
+            let oldest = entries
+                .iter()
+                .flat_map(|(key, variants)| {
+                    variants
+                        .iter()
+                        .enumerate()
+                        .map(move |(index, stored)| (stored.stored, key.clone(), index))
+                })
+                .min();
+
+            if let Some((_, key, index)) = oldest {
+                if let Some(variants) = entries.get_mut(&key) {
+                    variants.remove(index);
+
+                    if variants.is_empty() {
+                        entries.remove(&key);
+                    }
+                }
+            }
+        }
+
+        entries.entry(key).or_default().push(entry);
+
+        metrics::gauge!("proxy_cache_entries", stored_count(&entries) as f64);
+    }
+
+    /// Drop every response stored for the request's URI, as a successful
+    /// unsafe request (e.g. `POST`, `PUT`, or `DELETE`) may have changed it
+    pub(crate) fn invalidate(&self, parts: &Parts, status: StatusCode) {
+        if parts.method.is_safe() || status.is_client_error() || status.is_server_error() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.remove(&parts.uri.to_string()).is_some() {
+            metrics::gauge!("proxy_cache_entries", stored_count(&entries) as f64);
+        }
+    }
+}
+
+fn stored_count(entries: &HashMap<String, Vec<Entry>>) -> usize {
+    entries.values().map(Vec::len).sum()
+}
+
+/// How long a response stays fresh, if it says (explicitly) at all
+fn lifetime(directives: &Directives, headers: &HeaderMap) -> Option<Duration> {
+    if let Some(seconds) = directives.s_maxage.or(directives.max_age) {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = |name: header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok())
+    };
+
+    // an invalid `Expires` (e.g. `0`) means already expired
+    let expires = headers
+        .get(header::EXPIRES)
+        .map(|_| date(header::EXPIRES))?;
+    let date = date(header::DATE).unwrap_or_else(clock::now);
+
+    Some(
+        expires
+            .and_then(|expires| expires.duration_since(date).ok())
+            .unwrap_or_default(),
+    )
+}
@@ -0,0 +1,128 @@
+// Canonical JSON (RFC 8785)
+
+// Third Party Imports
+use ring::digest;
+use serde_json::Value;
+
+/// A request body as it's canonicalized for signing, per the JSON
+/// Canonicalization Scheme (i.e. with sorted keys, normalized
+/// numbers, and minimal string escaping)
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct CanonicalBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json: Option<String>,
+    /// Hex-encoded SHA-256 digest of the canonical form
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// Why the body couldn't be canonicalized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl CanonicalBody {
+    /// Canonicalize the (decoded) request body, which must be JSON
+    pub(crate) fn of(body: &[u8]) -> Self {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(value) => {
+                let mut json = String::new();
+                write(&value, &mut json);
+
+                let sha256 = digest::digest(&digest::SHA256, json.as_bytes())
+                    .as_ref()
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect();
+
+                Self {
+                    json: Some(json),
+                    sha256: Some(sha256),
+                    error: None,
+                }
+            }
+            Err(error) => Self {
+                json: None,
+                sha256: None,
+                error: Some(format!("body isn't JSON: {error}")),
+            },
+        }
+    }
+}
+
+fn write(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) => out.push_str(&value.to_string()),
+        Value::Number(number) => out.push_str(&number_to_string(number.as_f64().unwrap_or(0.0))),
+        // `serde_json` escapes exactly (and only) what JCS calls for
+        Value::String(string) => out.push_str(&Value::String(string.clone()).to_string()),
+        Value::Array(values) => {
+            out.push('[');
+
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+
+                write(value, out);
+            }
+
+            out.push(']');
+        }
+        Value::Object(map) => {
+            // keys are ordered by their UTF-16 code units, as JavaScript orders them
+            let mut entries = map.iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+
+            out.push('{');
+
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write(value, out);
+            }
+
+            out.push('}');
+        }
+    }
+}
+
+/// A number as JavaScript's `Number.prototype.toString` renders it
+fn number_to_string(number: f64) -> String {
+    if number == 0.0 {
+        return "0".to_owned();
+    }
+
+    let sign = if number < 0.0 { "-" } else { "" };
+
+    // the shortest digits that round-trip, and the exponent of the first of them
+    let scientific = format!("{:e}", number.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let (k, n) = (
+        digits.len() as i32,
+        exponent.parse::<i32>().unwrap_or(0) + 1,
+    );
+
+    let rendered = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat(-n as usize))
+    } else {
+        let exponent = match n - 1 {
+            e if e < 0 => format!("e-{}", -e),
+            e => format!("e+{e}"),
+        };
+
+        match digits.split_at(1) {
+            (first, "") => format!("{first}{exponent}"),
+            (first, rest) => format!("{first}.{rest}{exponent}"),
+        }
+    };
+
+    format!("{sign}{rendered}")
+}
@@ -0,0 +1,174 @@
+// Capability Discovery
+
+// Standard Library Imports
+use std::{sync::Arc, time::Duration};
+
+// Third Party Imports
+use axum::{extract::State, routing, Json, Router};
+
+// Crate-Level Imports
+use crate::schema::EchoSchema;
+
+/// Whether a feature is enabled and, if it is, its key settings
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Feature<T> {
+    enabled: bool,
+    #[serde(flatten)]
+    settings: Option<T>,
+}
+
+impl<T> Feature<T> {
+    pub(crate) fn new(settings: Option<T>) -> Self {
+        Self {
+            enabled: settings.is_some(),
+            settings,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// A duration, as it's reported (e.g. `250ms`)
+pub(crate) fn duration(value: Duration) -> String {
+    humantime::format_duration(value).to_string()
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct ClientCertificates {
+    /// Whether clients may connect without one
+    pub(crate) optional: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Grpc {
+    pub(crate) methods: Vec<&'static str>,
+    pub(crate) stream_count: u32,
+    pub(crate) stream_interval: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct WebSocket {
+    pub(crate) paths: Vec<&'static str>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Chaos {
+    pub(crate) error_rate: f64,
+    pub(crate) abort_rate: f64,
+    pub(crate) latency: Option<String>,
+    pub(crate) latency_jitter: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Capture {
+    /// How many requests are kept
+    pub(crate) size: usize,
+    pub(crate) views: Vec<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Stubbing {
+    pub(crate) rules: Vec<String>,
+    /// Whether requests matching no rule are rejected
+    pub(crate) strict: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Proxy {
+    pub(crate) upstreams: usize,
+    pub(crate) fallback_to_echo: bool,
+    pub(crate) cache: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Mirror {
+    pub(crate) targets: usize,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Soap {
+    pub(crate) path: String,
+    pub(crate) wsdl: bool,
+    pub(crate) actions: Vec<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Uploads {
+    pub(crate) dir: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Metrics {
+    pub(crate) port: usize,
+    pub(crate) tls: bool,
+    pub(crate) auth: bool,
+}
+
+/// What this deployment of `echo-rs` has enabled, so test harnesses can adapt to it
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Capabilities {
+    pub(crate) service: String,
+    pub(crate) version: &'static str,
+    pub(crate) schema: EchoSchema,
+    pub(crate) tls: bool,
+    pub(crate) mtls: Feature<ClientCertificates>,
+    pub(crate) http3: bool,
+    pub(crate) grpc: Feature<Grpc>,
+    pub(crate) websocket: Feature<WebSocket>,
+    pub(crate) chaos: Feature<Chaos>,
+    pub(crate) capture: Feature<Capture>,
+    pub(crate) stubbing: Feature<Stubbing>,
+    pub(crate) proxy: Feature<Proxy>,
+    pub(crate) mirror: Feature<Mirror>,
+    pub(crate) oauth: bool,
+    pub(crate) soap: Feature<Soap>,
+    pub(crate) uploads: Feature<Uploads>,
+    pub(crate) s3: bool,
+    pub(crate) traffic: bool,
+    pub(crate) webhooks: bool,
+    pub(crate) admin: bool,
+    pub(crate) metrics: Feature<Metrics>,
+}
+
+impl Capabilities {
+    /// The names of the enabled features, for the startup banner
+    pub(crate) fn enabled(&self) -> Vec<&'static str> {
+        [
+            ("tls", self.tls),
+            ("mtls", self.mtls.is_enabled()),
+            ("http3", self.http3),
+            ("grpc", self.grpc.is_enabled()),
+            ("websocket", self.websocket.is_enabled()),
+            ("chaos", self.chaos.is_enabled()),
+            ("capture", self.capture.is_enabled()),
+            ("stubbing", self.stubbing.is_enabled()),
+            ("proxy", self.proxy.is_enabled()),
+            ("mirror", self.mirror.is_enabled()),
+            ("oauth", self.oauth),
+            ("soap", self.soap.is_enabled()),
+            ("uploads", self.uploads.is_enabled()),
+            ("s3", self.s3),
+            ("traffic", self.traffic),
+            ("webhooks", self.webhooks),
+            ("admin", self.admin),
+            ("metrics", self.metrics.is_enabled()),
+        ]
+        .into_iter()
+        .filter_map(|(name, enabled)| enabled.then_some(name))
+        .collect()
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(capabilities: Arc<Capabilities>) -> Router {
+    Router::new()
+        .route("/_capabilities", routing::get(capabilities_of))
+        .with_state(capabilities)
+}
+
+#[tracing::instrument(skip_all)]
+async fn capabilities_of(State(capabilities): State<Arc<Capabilities>>) -> Json<Capabilities> {
+    Json(capabilities.as_ref().clone())
+}
@@ -0,0 +1,324 @@
+// Fault Injection
+
+// Standard Library Imports
+use std::{
+    io,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Bytes, HttpBody},
+    extract::State,
+    http::{HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::{listeners::Profile, sampling::coin_flip};
+
+/// Statuses randomly-injected errors are answered with
+const ERROR_STATUSES: &[StatusCode] = &[
+    StatusCode::INTERNAL_SERVER_ERROR,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+/// Request header opting the request into a chaos profile of its own (in place of
+/// the configured one), e.g. `fault=0.5;delay=200ms..1s;reset=0.1`
+pub(crate) const CHAOS_HEADER: &str = "x-echo-chaos";
+
+/// Marks responses that are injected faults rather than genuine answers
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Injected;
+
+/// Marks responses that reset the connection rather than answering
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Reset;
+
+/// Mark the response as an injected fault
+pub(crate) fn injected(mut response: Response) -> Response {
+    response.extensions_mut().insert(Injected);
+    response
+}
+
+/// Parse a probability between 0 and 1
+pub(crate) fn parse_rate(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("{value:?}: expected a rate between 0 and 1")),
+    }
+}
+
+/// Faults randomly injected into the echo routes
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Chaos {
+    /// Fraction of requests answered with a 5xx status
+    pub(crate) error_rate: f64,
+    /// Fraction of requests whose connection is abruptly reset
+    pub(crate) abort_rate: f64,
+    /// Latency added to every request
+    pub(crate) latency: Option<Duration>,
+    /// Maximum random variation (either way) of the added latency
+    pub(crate) latency_jitter: Option<Duration>,
+}
+
+impl Chaos {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.error_rate > 0.0
+            || self.abort_rate > 0.0
+            || self.latency.is_some()
+            || self.latency_jitter.is_some()
+    }
+
+    /// The latency to add to a request, if any
+    fn delay(&self) -> Option<Duration> {
+        let Some(jitter) = self.latency_jitter else {
+            return self.latency;
+        };
+
+        let base = self.latency.unwrap_or_default();
+
+        // uniformly distributed over `base ± jitter`, but never negative
+        let offset = jitter.mul_f64(coin_flip() * 2.0);
+
+        Some((base + offset).saturating_sub(jitter))
+    }
+}
+
+impl FromStr for Chaos {
+    type Err = String;
+
+    /// Parse a per-request chaos profile, as `;`-separated `fault` (error) and
+    /// `reset` (abort) rates, and a `delay` that's either fixed or a `min..max` range
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut chaos = Self::default();
+
+        for setting in value
+            .split(';')
+            .map(str::trim)
+            .filter(|setting| !setting.is_empty())
+        {
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("{setting:?}: expected `name=value`"))?;
+
+            let duration = |value: &str| {
+                humantime::parse_duration(value.trim())
+                    .map_err(|error| format!("{value:?}: {error}"))
+            };
+
+            match name.trim().to_ascii_lowercase().as_str() {
+                "fault" => chaos.error_rate = parse_rate(value)?,
+                "reset" => chaos.abort_rate = parse_rate(value)?,
+                // a range is uniformly distributed about its midpoint, i.e. latency ± jitter
+                "delay" => match value.split_once("..") {
+                    None => chaos.latency = Some(duration(value)?),
+                    Some((min, max)) => {
+                        let (min, max) = (duration(min)?, duration(max)?);
+
+                        if max < min {
+                            return Err(format!("{value:?}: range ends before it starts"));
+                        }
+
+                        chaos.latency = Some(min + (max - min) / 2);
+                        chaos.latency_jitter = Some((max - min) / 2);
+                    }
+                },
+                name => {
+                    return Err(format!(
+                        "unknown chaos setting {name:?} (expected `fault`, `delay`, or `reset`)"
+                    ))
+                }
+            }
+        }
+
+        Ok(chaos)
+    }
+}
+
+/// A delay as written in the configuration file: either fixed (e.g. `250ms`),
+/// or drawn from a distribution (e.g. `{uniform: {min: 10ms, max: 50ms}}`)
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub(crate) enum Delay {
+    Fixed(#[serde(with = "humantime_serde")] Duration),
+    Distribution(DelayDistribution),
+}
+
+/// A distribution delays are drawn from
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) enum DelayDistribution {
+    /// Uniformly distributed between `min` and `max`
+    Uniform {
+        #[serde(with = "humantime_serde")]
+        min: Duration,
+        #[serde(with = "humantime_serde")]
+        max: Duration,
+    },
+    /// Log-normally distributed around `median`, with the given
+    /// `sigma` (the standard deviation of the delay's logarithm)
+    Lognormal {
+        #[serde(with = "humantime_serde")]
+        median: Duration,
+        sigma: f64,
+    },
+}
+
+impl Delay {
+    /// Draw a delay
+    pub(crate) fn sample(&self) -> Duration {
+        match self {
+            Self::Fixed(delay) => *delay,
+            Self::Distribution(DelayDistribution::Uniform { min, max }) => {
+                *min + max.saturating_sub(*min).mul_f64(coin_flip())
+            }
+            Self::Distribution(DelayDistribution::Lognormal { median, sigma }) => {
+                // Box-Muller, with `1 - u` keeping the logarithm finite
+                let (u, v) = (1.0 - coin_flip(), coin_flip());
+                let normal = (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos();
+
+                Duration::try_from_secs_f64(median.as_secs_f64() * (sigma * normal).exp())
+                    .unwrap_or(*median)
+            }
+        }
+    }
+}
+
+/// A fault injected into a fraction of the requests matching a route rule
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Fault {
+    /// Fraction (0 to 1) of matching requests the fault is injected into
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub(crate) rate: f64,
+    /// Status faulted requests are answered with
+    #[serde(default = "Fault::default_status")]
+    pub(crate) status: u16,
+    /// Reset the connection rather than answering at all
+    #[serde(default)]
+    pub(crate) abort: bool,
+}
+
+impl Fault {
+    fn default_status() -> u16 {
+        StatusCode::SERVICE_UNAVAILABLE.as_u16()
+    }
+
+    /// The faulted response, if the fault is injected into this request
+    pub(crate) fn inject(&self, rule: &str) -> Option<Response> {
+        if coin_flip() >= self.rate {
+            return None;
+        }
+
+        if self.abort {
+            metrics::increment_counter!("chaos_injected_total", "fault" => "abort", "rule" => rule.to_owned());
+            return Some(injected(abort()));
+        }
+
+        metrics::increment_counter!("chaos_injected_total", "fault" => "error", "rule" => rule.to_owned());
+
+        Some(injected(
+            StatusCode::from_u16(self.status)
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE)
+                .into_response(),
+        ))
+    }
+}
+
+fn deserialize_rate<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let rate = <f64 as serde::Deserialize>::deserialize(deserializer)?;
+
+    parse_rate(&rate.to_string()).map_err(serde::de::Error::custom)
+}
+
+/// A response which resets the connection rather than answering
+pub(crate) fn abort() -> Response {
+    let mut response = Response::new(body::boxed(Abort));
+    response.extensions_mut().insert(Reset);
+    response
+}
+
+/// A response body which fails immediately, aborting the connection
+struct Abort;
+
+impl HttpBody for Abort {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(Some(Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "connection aborted by chaos",
+        ))))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn inject<B>(
+    State(chaos): State<Arc<Chaos>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !Profile::of(&req).injects_chaos() {
+        return next.run(req).await;
+    }
+
+    // a request's own profile stands in for the configured one, whatever that is
+    let chaos = req
+        .headers()
+        .get(CHAOS_HEADER)
+        .and_then(|value| {
+            value
+                .to_str()
+                .map_err(|error| error.to_string())
+                .and_then(str::parse::<Chaos>)
+                .map_err(|error| tracing::warn!("Ignoring invalid `{CHAOS_HEADER}`: {error}"))
+                .ok()
+        })
+        .unwrap_or(*chaos);
+
+    if let Some(delay) = chaos.delay() {
+        tokio::time::sleep(delay).await;
+    }
+
+    if chaos.abort_rate > 0.0 && coin_flip() < chaos.abort_rate {
+        tracing::info!("Chaos: aborting {} {}", req.method(), req.uri());
+        metrics::increment_counter!("chaos_injected_total", "fault" => "abort");
+
+        return injected(abort());
+    }
+
+    if chaos.error_rate > 0.0 && coin_flip() < chaos.error_rate {
+        let status = ERROR_STATUSES
+            [((coin_flip() * ERROR_STATUSES.len() as f64) as usize).min(ERROR_STATUSES.len() - 1)];
+
+        tracing::info!(
+            "Chaos: failing {} {} with {status}",
+            req.method(),
+            req.uri()
+        );
+        metrics::increment_counter!("chaos_injected_total", "fault" => "error");
+
+        return injected(status.into_response());
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,2264 @@
+// Command Line Interface
+
+// Standard Library Imports
+use std::{
+    collections::BTreeSet,
+    env,
+    net::{SocketAddr, TcpListener},
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+// Third Party Imports
+use axum::{
+    extract::DefaultBodyLimit,
+    http::{HeaderName, HeaderValue, StatusCode},
+    middleware, Extension, Router,
+};
+use axum_server::{
+    accept::DefaultAcceptor,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+    Handle, HttpConfig,
+};
+use regex_lite::Regex;
+use tracing_subscriber::{layer::SubscriberExt, Layer};
+
+// Crate-Level Imports
+use crate::{
+    access_log, activation, admin, alerts, assertions, audit, batch, body, cache, capabilities,
+    chaos, client_ip, clock, collapse, concurrency, config, conn, consul, cors, counters, doh,
+    echo_router, errors, fail_window, grpc, header_limits, health, history, http3, inflight, jwt,
+    kube, l4, latency, layout, listeners, log_control, logging, mdns, metrics, mirror, negotiate,
+    oauth, otel, ping, proxy, ratelimit, recording, redact, request_id, routes, s3, sampling,
+    scenarios, schedule, schema, self_traffic, shaping, shutdown, soap, stubs, tail, throttle, tls,
+    traffic, transform, unmatched, uploads, warmup, webhooks, ws, EchoFeatures, EchoState,
+};
+
+#[derive(Clone, Debug, clap::Parser)]
+#[command(author, version, about)]
+struct Args {
+    #[arg(long = "host", env = "ECHO_HOST", default_value = "[::]")]
+    pub host: String,
+    #[arg(long = "port", env = "ECHO_PORT", default_value_t = 8080)]
+    pub port: usize,
+    #[arg(
+        long = "profile",
+        env = "ECHO_PROFILE",
+        value_enum,
+        default_value_t = listeners::Profile::Echo,
+        long_help = "Behavior profile of the main listener (see `--listener`)."
+    )]
+    pub profile: listeners::Profile,
+    #[arg(
+        long = "listener",
+        env = "ECHO_LISTENERS",
+        value_delimiter = ',',
+        long_help = "Additional listener with its own behavior profile, as a `port=profile` pair, e.g. '8081=log-only'. May be given multiple times.\n\nProfiles are `echo` (every configured behavior), `log-only` (requests are logged, but answered with an empty 204), `mirror` (requests are mirrored to the `--mirror-to` targets, but no chaos is injected), and `chaos` (the configured chaos is injected, but requests aren't mirrored).\nEvery listener shares the main listener's host, TLS settings, and state, so one process can stand in for several distinct backends."
+    )]
+    pub listener: Vec<listeners::Listener>,
+    #[arg(long = "metrics", env = "ECHO_METRICS", default_value_t = true)]
+    pub metrics: core::primitive::bool,
+    #[arg(
+        long = "metrics-port",
+        env = "ECHO_METRICS_PORT",
+        default_value_t = 9090
+    )]
+    pub metrics_port: usize,
+    #[arg(
+        long = "admin-port",
+        env = "ECHO_ADMIN_PORT",
+        long_help = "Port to serve the runtime logging controls on, rather than the metrics port (requires `--admin-token`).\n\n`GET /_logging` reports the log level, `skip-logging-for` patterns, and whether log-only mode is on, which are changed with `PUT /_logging/level` ({\"level\": \"debug\"}), `POST` or `DELETE /_logging/skip` ({\"pattern\": \"...\"}), and `PUT /_logging/log-only` ({\"enabled\": true}). Log-only mode answers every request as a `log-only` listener does. Changes last until the configuration file is reloaded, or the server restarts."
+    )]
+    pub admin_port: Option<usize>,
+    #[arg(
+        long = "metrics-buckets",
+        env = "ECHO_METRICS_BUCKETS",
+        value_delimiter = ',',
+        long_help = "Upper bounds (in seconds) of the `http_requests_duration_seconds` (and `route_rule_duration_seconds`) histograms' buckets, e.g. '0.05,0.1,0.3,1'.\n\nDefaults to exponential buckets from 5ms to 10s."
+    )]
+    pub metrics_buckets: Vec<f64>,
+    #[arg(
+        long = "metrics-label",
+        env = "ECHO_METRICS_LABELS",
+        value_delimiter = ',',
+        value_parser = metrics::parse_label,
+        long_help = "Static label attached to every exported series, as `name=value`, e.g. 'env=staging'. May be given more than once.\n\nTakes precedence over the Kubernetes labels (e.g. `namespace`) of the same name."
+    )]
+    pub metrics_labels: Vec<(String, String)>,
+    #[arg(
+        long = "metrics-auth",
+        env = "ECHO_METRICS_AUTH",
+        long_help = "Credentials required of every request to the metrics server, as `bearer:<token>` or `basic:<user>:<password>`. Requests without them are answered with 401."
+    )]
+    pub metrics_auth: Option<routes::RouteAuth>,
+    #[arg(
+        long = "metrics-path-label",
+        env = "ECHO_METRICS_PATH_LABEL",
+        value_enum,
+        default_value_t = metrics::PathLabel::Route,
+        long_help = "Where the `path` label of request metrics comes from: the `route` the request matched (e.g. '/*key' for every echoed request), the first `--metrics-path-template` its path matches (falling back to its route), or its `raw` path.\n\nAt most `--metrics-max-paths` distinct values are used; requests beyond them are labelled 'other'."
+    )]
+    pub metrics_path_label: metrics::PathLabel,
+    #[arg(
+        long = "metrics-path-template",
+        env = "ECHO_METRICS_PATH_TEMPLATES",
+        value_delimiter = ',',
+        long_help = "Path template (a glob, where `*` matches within a segment and `**` across them) requests are labelled with under `--metrics-path-label=template`, e.g. '/users/*/orders/**'. May be given multiple times; the first match applies."
+    )]
+    pub metrics_path_template: Vec<String>,
+    #[arg(
+        long = "metrics-max-paths",
+        env = "ECHO_METRICS_MAX_PATHS",
+        default_value_t = 100,
+        long_help = "The most distinct `path` label values request metrics are broken down by, guarding against unbounded cardinality."
+    )]
+    pub metrics_max_paths: usize,
+    #[arg(
+        long = "tcp-port",
+        env = "ECHO_TCP_PORT",
+        long_help = "Also serve a raw (RFC 862) TCP echo listener on the given port, echoing bytes back verbatim.\n\nBytes in/out are counted in `l4_bytes_received_total` / `l4_bytes_sent_total`."
+    )]
+    pub tcp_port: Option<u16>,
+    #[arg(
+        long = "udp-port",
+        env = "ECHO_UDP_PORT",
+        long_help = "Also serve a raw (RFC 862) UDP echo socket on the given port, echoing datagrams back verbatim."
+    )]
+    pub udp_port: Option<u16>,
+    #[cfg(windows)]
+    #[arg(
+        long = "pipe",
+        env = "ECHO_PIPE",
+        long_help = "Also serve the echo app over the named pipe at the given path, e.g. '\\\\.\\pipe\\echo-rs'.\n\nRequests over the pipe are echoed with an unspecified client address."
+    )]
+    pub pipe: Option<String>,
+    #[cfg(target_os = "linux")]
+    #[arg(
+        long = "vsock-port",
+        env = "ECHO_VSOCK_PORT",
+        long_help = "Also serve the echo app on the given AF_VSOCK port (of any CID), e.g. as the target of guest/host communication in Firecracker or Nitro Enclave environments.\n\nRequests over vsock are echoed with an unspecified client address."
+    )]
+    pub vsock_port: Option<u32>,
+    #[arg(
+        long = "inherit-fd",
+        env = "ECHO_INHERIT_FD",
+        long_help = "Serve the echo app on the already-listening TCP socket with the given file descriptor (e.g. one handed down by a zero-downtime supervisor), rather than binding `--host` and `--port`.\n\nWithout it, sockets passed by systemd socket activation (`LISTEN_FDS`) are adopted instead: the one named `metrics` (via `FileDescriptorName=`) serves the metrics, and the first other one the echo app."
+    )]
+    pub inherit_fd: Option<i32>,
+    #[arg(
+        long = "grpc-stream-count",
+        env = "ECHO_GRPC_STREAM_COUNT",
+        default_value_t = 1,
+        long_help = "How many times the gRPC echo service's streaming methods (`echo.v1.Echo/ServerStream` and `echo.v1.Echo/BidiStream`) send back each message they receive.\n\nOverridable per-call via the `x-echo-stream-count` metadata."
+    )]
+    pub grpc_stream_count: u32,
+    #[arg(
+        long = "grpc-stream-interval",
+        env = "ECHO_GRPC_STREAM_INTERVAL",
+        value_parser = humantime::parse_duration,
+        long_help = "Delay before the gRPC echo service sends each response message, e.g. '500ms'.\n\nOverridable per-call via the `x-echo-stream-interval` metadata. Calls are cut short with DEADLINE_EXCEEDED once their `grpc-timeout` elapses."
+    )]
+    pub grpc_stream_interval: Option<Duration>,
+    #[arg(
+        long = "log-level",
+        env = "ECHO_LOG_LEVEL",
+        default_value_t = tracing::Level::INFO,
+    )]
+    pub log_level: tracing::Level,
+    #[arg(
+        long = "log-schema",
+        env = "ECHO_LOG_SCHEMA",
+        value_enum,
+        default_value_t = logging::LogSchema::Text,
+        long_help = "Layout of each log record: human-readable `text`, Elastic Common Schema (`ecs`) JSON, or OpenTelemetry log data model (`otel`) JSON.\n\nStructured records name the service after `--service-name`."
+    )]
+    pub log_schema: logging::LogSchema,
+    #[arg(
+        long = "log-format",
+        env = "ECHO_LOG_FORMAT",
+        value_enum,
+        default_value_t = logging::LogFormat::Full,
+        long_help = "Layout of `text` schema log records: single `full` lines, multi-line `pretty` ones, terse `compact` ones, or `json` objects, one per line.\n\nStructured (i.e. `json`, `ecs`, or `otel`) records carry each echo as an `echo` object field rather than in their message."
+    )]
+    pub log_format: logging::LogFormat,
+    #[arg(
+        long = "frozen-time",
+        env = "ECHO_FROZEN_TIME",
+        value_parser = humantime::parse_rfc3339_weak,
+        long_help = "Stop the server's clock at the given RFC 3339 time (e.g. '2024-01-01T00:00:00Z'), so that timestamps in echoes, logs, and tokens are reproducible.\n\nThe clock can also be frozen, moved, or unfrozen at runtime via `PUT` / `DELETE /_time` when an admin token is configured."
+    )]
+    pub frozen_time: Option<SystemTime>,
+    #[arg(
+        long = "echo-schema",
+        env = "ECHO_SCHEMA",
+        value_enum,
+        default_value_t = schema::EchoSchema::V1,
+        long_help = "Layout of the echo payload, reported in it as `schema_version`.\n\n`v1` echoes each header and query parameter as a single string, `v2` as a list of every value it was sent with."
+    )]
+    pub echo_schema: schema::EchoSchema,
+    #[arg(
+        long = "echo-field-case",
+        env = "ECHO_FIELD_CASE",
+        value_enum,
+        default_value_t = layout::FieldCase::Snake,
+        long_help = "Casing of the echo payload's own field names (header names, parameters and bodies are left as they were sent)."
+    )]
+    pub echo_field_case: layout::FieldCase,
+    #[arg(
+        long = "echo-rename",
+        env = "ECHO_RENAME",
+        value_delimiter = ',',
+        long_help = "Move echo payload fields to other (dot-separated) paths, given as `from=to`, e.g. 'method=http.request.method,client=client.address'.\n\n`from` is the field's original (snake_case) path, `to` is used exactly as given."
+    )]
+    pub echo_rename: Vec<layout::FieldRename>,
+    #[arg(
+        long = "echo-wrap",
+        env = "ECHO_WRAP",
+        long_help = "Nest the whole echo payload under the given key, e.g. 'request'."
+    )]
+    pub echo_wrap: Option<String>,
+    #[arg(
+        long = "pad-response-to",
+        env = "ECHO_PAD_RESPONSE_TO",
+        value_parser = body::parse_size,
+        long_help = "Pad every echo with trailing spaces until it's this long, e.g. '4KiB', so responses are a constant size. Echoes that are already longer are left as they are.\n\nOverridable per-request via the `X-Echo-Pad` header (or `echo_pad` query parameter). Echoes are never padded beyond 10MiB."
+    )]
+    pub pad_response_to: Option<usize>,
+    #[arg(
+        long = "canonical-json",
+        env = "ECHO_CANONICAL_JSON",
+        default_value_t = false,
+        long_help = "Add the request body's canonical form (per RFC 8785, i.e. with sorted keys and normalized numbers) and its SHA-256 hash to every echo as `canonical_body`, to help debug signature schemes that depend on canonical JSON.\n\nAlso available per-request via the `X-Echo-Canonical` header (or `echo_canonical` query parameter)."
+    )]
+    pub canonical_json: bool,
+    #[arg(
+        long = "timestamp-tolerance",
+        env = "ECHO_TIMESTAMP_TOLERANCE",
+        value_parser = humantime::parse_duration,
+        default_value = "5m",
+        long_help = "How far the signed timestamps requests carry (`Stripe-Signature`'s `t=`, `X-Amz-Date` (header or query parameter), `X-Slack-Request-Timestamp`, `svix-timestamp`, and `webhook-timestamp`) may be from the server's clock before a warning is logged.\n\nEach is reported in the echo under `signed_timestamps`, with its skew (how far the server's clock is ahead of it), and those outside the tolerance are counted in `signed_timestamps_out_of_tolerance_total`."
+    )]
+    pub timestamp_tolerance: Duration,
+    #[arg(long = "tls-key", env = "ECHO_TLS_KEY")]
+    pub tls_key: Option<PathBuf>,
+    #[arg(long = "tls-cert", env = "ECHO_TLS_CERT")]
+    pub tls_cert: Option<PathBuf>,
+    #[arg(
+        long = "tls-client-ca",
+        env = "ECHO_TLS_CLIENT_CA",
+        long_help = "Require (mutual TLS) client certificates issued by the CA(s) in the given PEM bundle.\n\nThe presented certificate's subject, issuer, SANs and fingerprint are echoed back as `client_certificate`."
+    )]
+    pub tls_client_ca: Option<PathBuf>,
+    #[arg(
+        long = "tls-client-optional",
+        env = "ECHO_TLS_CLIENT_OPTIONAL",
+        default_value_t = false,
+        long_help = "Accept clients that present no certificate at all (those that do present one must still pass `--tls-client-ca` verification)."
+    )]
+    pub tls_client_optional: bool,
+    #[arg(
+        long = "tls-reload-interval",
+        env = "ECHO_TLS_RELOAD_INTERVAL",
+        value_parser = humantime::parse_duration,
+        default_value = "30s",
+        long_help = "How often the TLS certificate, key (and client CA) files are checked for changes, which are then served without a restart.\n\nA reload can also be forced by sending the process `SIGHUP`."
+    )]
+    pub tls_reload_interval: Duration,
+    #[arg(
+        long = "http3",
+        env = "ECHO_HTTP3",
+        default_value_t = false,
+        long_help = "Also serve HTTP/3 (QUIC) on the same port (over UDP), advertising it to HTTP/1.1 and HTTP/2 clients via the `Alt-Svc` header. Requires TLS."
+    )]
+    pub http3: bool,
+    #[arg(
+        long = "metrics-use-tls",
+        env = "ECHO_METRICS_USE_TLS",
+        default_value_t = false
+    )]
+    pub metrics_use_tls: bool,
+    #[arg(
+        long = "skip-logging-for",
+        env = "ECHO_SKIP_LOGGING_FOR",
+        default_value = "",
+        long_help = "Comma or semi-colon separated list of URL patterns that should not be logged.\n\nExample:\n  echo-rs ... --skip-logging-for='some/endpoint; another/endpoint\\?with=some-param'"
+    )]
+    pub unlogged: String,
+    #[arg(
+        long = "collapse-duplicate-logs",
+        env = "ECHO_COLLAPSE_DUPLICATE_LOGS",
+        value_parser = humantime::parse_duration,
+        long_help = "Log only the first of a run of identical consecutive requests (same method, path, and client address), e.g. liveness probes, summarizing the rest with a count once the run ends or every given window, e.g. '1m'."
+    )]
+    pub collapse_duplicate_logs: Option<Duration>,
+    #[arg(
+        long = "history-size",
+        env = "ECHO_HISTORY_SIZE",
+        default_value_t = 100,
+        long_help = "How many of the most recently echoed requests to keep in memory, listed by `GET /_echo/requests` (filterable with e.g. `?method=POST&path=/foo`) and cleared by `DELETE /_echo/requests`.\n\nRequests are listed as they were echoed, i.e. after redaction, but also kept as they were received, so that `POST /_echo/requests/{id}/replay` can re-send one to the server itself or to another (given by `?target=`, e.g. 'http://localhost:3000'). As captures may carry other clients' credentials, replaying to a `target` requires an `operator` (or `--admin-token`) token, and isn't possible without one.\n\n`GET /_echo/requests/export` downloads them as NDJSON, optionally compressed (with `?compression=gzip` or `zstd`). Either endpoint lists only those captured after a cursor (`?since=<id>`, as reported by the last listing's `cursor`, or export's `X-Echo-Cursor` header) or since a time (`?since_time=`, as an RFC 3339 timestamp or unix seconds), so captures can be pulled incrementally.\n\nSet to 0 to keep none and disable the endpoints."
+    )]
+    pub history_size: usize,
+    #[arg(
+        long = "history-view",
+        env = "ECHO_HISTORY_VIEWS",
+        value_delimiter = ',',
+        long_help = "Saved view of the captured requests, as `name=query`, where the query is `;`-separated conditions on the `method`, `path`, `status` (of the echo's response), or `client`, e.g. 'stripe-failures=method=POST;path~/stripe;status>=400'.\n\nConditions compare with `=`, `!=`, `~` (contains), or (for `status`) `>=`, `<=`, `>`, or `<`. Views are listed by `GET /_echo/views` and queried by `GET /_echo/views/{name}` (or `GET /_echo/requests?view={name}`), and more may be saved by `PUT /_echo/views/{name}` with the query as the body (or removed by `DELETE`). May be given more than once."
+    )]
+    pub history_views: Vec<history::SavedView>,
+    #[arg(
+        long = "response-template",
+        env = "ECHO_RESPONSE_TEMPLATE",
+        long_help = "Template file (minijinja / Jinja2 syntax) the echo is rendered with in place of the usual JSON, with the echo available as `echo`, e.g. `{\"seen\": \"{{ echo.method }} {{ echo.path }}\"}`.\n\nA template may also be given per-request via the `X-Echo-Template` header (or `echo_template` query parameter), which takes precedence. Rendered bodies are labeled as JSON if they parse as such, and as plain text otherwise."
+    )]
+    pub response_template: Option<PathBuf>,
+    #[arg(
+        long = "raw-dump",
+        env = "ECHO_RAW_DUMP",
+        default_value_t = false,
+        long_help = "Include the raw request head (request line + headers), exactly as received on the wire, base64-encoded in the echoed payload.\n\nOnly applies to HTTP/1.x connections."
+    )]
+    pub raw_dump: bool,
+    #[arg(
+        long = "jwks-url",
+        env = "ECHO_JWKS_URL",
+        long_help = "JSON Web Key Set URL (e.g. 'https://issuer.example.com/.well-known/jwks.json') that JWTs sent as `Authorization: Bearer` credentials are verified against.\n\nSuch tokens' header and claims are always echoed (under `auth`), but only with `verified` (and a `verification_error`, if they fail) if they're checked. The keys are fetched lazily and kept for 5 minutes."
+    )]
+    pub jwks_url: Option<String>,
+    #[arg(
+        long = "ttfb-delay",
+        env = "ECHO_TTFB_DELAY",
+        value_parser = humantime::parse_duration,
+        long_help = "Delay applied before the response head (status + headers) is sent, e.g. '250ms'.\n\nOverridable per-request via the `X-Echo-Ttfb-Delay` header."
+    )]
+    pub ttfb_delay: Option<Duration>,
+    #[arg(
+        long = "body-delay",
+        env = "ECHO_BODY_DELAY",
+        value_parser = humantime::parse_duration,
+        long_help = "Delay applied after the response head is sent but before the response body is, e.g. '2s'.\n\nOverridable per-request via the `X-Echo-Body-Delay` header."
+    )]
+    pub body_delay: Option<Duration>,
+    #[arg(
+        long = "response-rate",
+        env = "ECHO_RESPONSE_RATE",
+        value_parser = shaping::parse_rate,
+        long_help = "Trickle response bodies out at the given rate (in bytes/sec), e.g. '1k' or '512/s', to exercise client read timeouts and proxy buffering.\n\nOverridable per-request via the `X-Echo-Throttle` header."
+    )]
+    pub response_rate: Option<usize>,
+    #[arg(
+        long = "response-trailer",
+        env = "ECHO_RESPONSE_TRAILERS",
+        value_delimiter = ',',
+        value_parser = shaping::parse_header_pair,
+        long_help = "Trailer to attach to every response, as a `name=value` pair. May be given multiple times.\n\nAdditional trailers may be requested per-request via the `X-Echo-Trailer` header.\n\nTrailers are only sent over HTTP/2 (and later) connections, and are ignored for HTTP/1.x requests (whose responses are neither chunked for them nor announce them via a `Trailer` header)."
+    )]
+    pub response_trailers: Vec<(HeaderName, HeaderValue)>,
+    #[arg(
+        long = "throttle-quota",
+        env = "ECHO_THROTTLE_QUOTA",
+        long_help = "Respond with 429 once more than the given number of requests arrive within a period, e.g. '100/1m' or '5/s'."
+    )]
+    pub throttle_quota: Option<schedule::Quota>,
+    #[arg(
+        long = "throttle-schedule",
+        env = "ECHO_THROTTLE_SCHEDULE",
+        long_help = "Alternate between serving requests normally and responding with 429, e.g. '30s/10s' to serve normally for 30 seconds then throttle for 10."
+    )]
+    pub throttle_schedule: Option<schedule::Cycle>,
+    #[arg(
+        long = "rate-limit",
+        env = "ECHO_RATE_LIMIT",
+        long_help = "Respond with 429 once a client (by IP address) sends requests faster than the given rate, e.g. '100/s' or '600/1m'.\n\nRequests are admitted by token bucket, so short bursts (up to `--burst`) above the rate are tolerated. Up to 100,000 clients are tracked at once, beyond which any others are refused until idle clients are forgotten (at most every 10 seconds)."
+    )]
+    pub rate_limit: Option<schedule::Quota>,
+    #[arg(
+        long = "global-rate-limit",
+        env = "ECHO_GLOBAL_RATE_LIMIT",
+        long_help = "Respond with 429 once requests from all clients combined arrive faster than the given rate, e.g. '1000/s'."
+    )]
+    pub global_rate_limit: Option<schedule::Quota>,
+    #[arg(
+        long = "burst",
+        env = "ECHO_RATE_LIMIT_BURST",
+        long_help = "How many requests may arrive at once before `--rate-limit` and `--global-rate-limit` apply (default: the rate's count)."
+    )]
+    pub burst: Option<u64>,
+    #[arg(
+        long = "path-quota",
+        env = "ECHO_PATH_QUOTAS",
+        value_delimiter = ',',
+        long_help = "Respond with 429 once a path (and everything beneath it) has been requested more than the given number of times within a period, as a `path=count/period` pair, e.g. '/expensive=100/day'. May be given multiple times.\n\nResponses on quota'd paths carry `X-RateLimit-Limit`, `X-RateLimit-Remaining`, and `X-RateLimit-Reset` headers. Where quotas overlap, the most specific path's applies."
+    )]
+    pub path_quota: Vec<throttle::PathQuota>,
+    #[arg(
+        long = "retry-after-format",
+        env = "ECHO_RETRY_AFTER_FORMAT",
+        value_enum,
+        default_value_t = throttle::RetryAfterFormat::Seconds
+    )]
+    pub retry_after_format: throttle::RetryAfterFormat,
+    #[arg(
+        long = "fail-window",
+        env = "ECHO_FAIL_WINDOW",
+        long_help = "Fail hard for a while after a threshold, then recover, to exercise downstream circuit breakers.\n\nSettings:\n  after=<n>          requests served normally before failing starts\n  fail=<n|duration>  requests to fail, or how long to fail them for\n  recover=<duration> how long to stay recovered before the cycle repeats (default: forever)\n  status=<code>      status code for failed requests (default: 503)\n\nExample:\n  echo-rs ... --fail-window='after=100;fail=50;recover=30s'"
+    )]
+    pub fail_window: Option<fail_window::FailWindowSpec>,
+    #[arg(
+        long = "warmup",
+        env = "ECHO_WARMUP",
+        value_parser = humantime::parse_duration,
+        long_help = "Perform poorly for a while after startup, e.g. '30s', emulating a JVM-style cold start to exercise load-balancer slow-start and outlier detection.\n\nHow requests are treated in the meantime is set by `--warmup-mode`."
+    )]
+    pub warmup: Option<Duration>,
+    #[arg(
+        long = "warmup-mode",
+        env = "ECHO_WARMUP_MODE",
+        value_enum,
+        default_value_t = warmup::WarmupMode::Slow
+    )]
+    pub warmup_mode: warmup::WarmupMode,
+    #[arg(
+        long = "warmup-latency",
+        env = "ECHO_WARMUP_LATENCY",
+        value_parser = humantime::parse_duration,
+        default_value = "1s",
+        long_help = "The latency added to requests at startup by the `slow` warm-up mode, tapering off to nothing as the warm-up goes on."
+    )]
+    pub warmup_latency: Duration,
+    #[arg(
+        long = "chaos-error-rate",
+        env = "ECHO_CHAOS_ERROR_RATE",
+        value_parser = chaos::parse_rate,
+        default_value_t = 0.0,
+        long_help = "Fraction (0 to 1) of echo requests randomly answered with a 500, 502, 503, or 504 status, e.g. '0.05'."
+    )]
+    pub chaos_error_rate: f64,
+    #[arg(
+        long = "chaos-latency",
+        env = "ECHO_CHAOS_LATENCY",
+        value_parser = humantime::parse_duration,
+        long_help = "Latency added to every echo request, e.g. '100ms'."
+    )]
+    pub chaos_latency: Option<Duration>,
+    #[arg(
+        long = "chaos-latency-jitter",
+        env = "ECHO_CHAOS_LATENCY_JITTER",
+        value_parser = humantime::parse_duration,
+        long_help = "Maximum random variation (in either direction) of the `--chaos-latency`, e.g. '50ms'."
+    )]
+    pub chaos_latency_jitter: Option<Duration>,
+    #[arg(
+        long = "chaos-abort-rate",
+        env = "ECHO_CHAOS_ABORT_RATE",
+        value_parser = chaos::parse_rate,
+        default_value_t = 0.0,
+        long_help = "Fraction (0 to 1) of echo requests whose connection is randomly reset rather than answered, e.g. '0.01'.\n\nInjected faults are counted in `chaos_injected_total`.\n\nA request may opt into a chaos profile of its own (in place of the `--chaos-*` options, set or not) via the `X-Echo-Chaos` header, e.g. 'fault=0.5;delay=200ms..1s;reset=0.1'."
+    )]
+    pub chaos_abort_rate: f64,
+    #[arg(
+        long = "alert-request-rate",
+        env = "ECHO_ALERT_REQUEST_RATE",
+        long_help = "Log a warning when the echo routes are served more than this many requests per second."
+    )]
+    pub alert_request_rate: Option<f64>,
+    #[arg(
+        long = "alert-error-rate",
+        env = "ECHO_ALERT_ERROR_RATE",
+        value_parser = chaos::parse_rate,
+        long_help = "Log a warning when more than this fraction (0 to 1) of echo requests are answered with an injected fault (by chaos, route rule faults, or a fail window), e.g. '0.1'."
+    )]
+    pub alert_error_rate: Option<f64>,
+    #[arg(
+        long = "alert-latency",
+        env = "ECHO_ALERT_LATENCY",
+        value_parser = humantime::parse_duration,
+        long_help = "Log a warning when the 99th percentile latency of the echo routes exceeds this, e.g. '500ms'."
+    )]
+    pub alert_latency: Option<Duration>,
+    #[arg(
+        long = "alert-interval",
+        env = "ECHO_ALERT_INTERVAL",
+        value_parser = humantime::parse_duration,
+        default_value = "10s",
+        long_help = "How often the request rate, error injection rate, and latency are checked against their `--alert-*` thresholds.\n\nEach check covers the requests served since the one before it. Alerts are logged when a threshold is first crossed, and again (at INFO) when it's no longer exceeded."
+    )]
+    pub alert_interval: Duration,
+    #[arg(
+        long = "admin-token",
+        env = "ECHO_ADMIN_TOKEN",
+        long_help = "Bearer token required to use administrative endpoints (e.g. `POST /_health/toggle`), granted the `admin` role (see `--role-token` for the others).\n\nAdministrative endpoints are disabled unless a token is configured."
+    )]
+    pub admin_token: Option<String>,
+    #[arg(
+        long = "role-token",
+        env = "ECHO_ROLE_TOKENS",
+        value_delimiter = ',',
+        long_help = "Bearer token granted a role, as `role=token`, e.g. 'viewer=s3cr3t'. May be given multiple times. The `--admin-token` is granted the `admin` role.\n\nRoles:\n  viewer    read captures and runtime settings (i.e. make `GET` requests of administrative endpoints)\n  operator  also change runtime settings, and clear or replay captures\n  admin     also shut the server down (via `POST /_quitquitquit`)\n\nOnce any role tokens are configured, the capture APIs (`/_echo/requests`, `/_echo/views`, and `/_echo/tail`) and the rest of the introspection and reset endpoints (`/_requests/unmatched`, `/_assertions`, `/_counters`, `/_inflight`, `/_scenarios`, `/_traffic`, and `/_webhooks`) require them too."
+    )]
+    pub role_token: Vec<admin::RoleToken>,
+    #[arg(
+        long = "audit-log",
+        env = "ECHO_AUDIT_LOG",
+        long_help = "Append a record of every administrative action (i.e. every request, other than a read, made of an administrative endpoint, whether permitted or not) to the given file, as JSON lines, each with the ID of the token used (e.g. 'tok_5e884898da28') and a timestamp.\n\nToken IDs are logged at startup."
+    )]
+    pub audit_log: Option<PathBuf>,
+    #[arg(
+        long = "flap-readiness",
+        env = "ECHO_FLAP_READINESS",
+        long_help = "Alternate `/readyz` between ready and not-ready, e.g. '30s/10s' to report ready for 30 seconds then not-ready for 10."
+    )]
+    pub flap_readiness: Option<schedule::Cycle>,
+    #[arg(
+        long = "drain-timeout",
+        env = "ECHO_DRAIN_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        default_value = "30s",
+        long_help = "How long in-flight requests are given to complete when shutting down (e.g. via `POST /_quitquitquit`)."
+    )]
+    pub drain_timeout: Duration,
+    #[arg(
+        long = "request-timeout",
+        env = "ECHO_REQUEST_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        long_help = "Respond with 408 to any request that takes longer than this to handle (e.g. because of a requested delay).\n\nRequests may take as long as they like if unset."
+    )]
+    pub request_timeout: Option<Duration>,
+    #[arg(
+        long = "header-read-timeout",
+        env = "ECHO_HEADER_READ_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        long_help = "Close HTTP/1.x connections that take longer than this to send a complete request head, e.g. '10s'.\n\nThe time is counted from when a connection starts waiting for its next request, so this also bounds how long idle connections are kept open."
+    )]
+    pub header_read_timeout: Option<Duration>,
+    #[arg(
+        long = "body-read-timeout",
+        env = "ECHO_BODY_READ_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        long_help = "Respond with 408 to any request whose body hasn't been received in full this long after its head, e.g. '30s'. Timeouts are counted in `request_body_timeouts_total`."
+    )]
+    pub body_read_timeout: Option<Duration>,
+    #[arg(
+        long = "keep-alive-timeout",
+        env = "ECHO_KEEP_ALIVE_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        long_help = "Close connections that have gone this long without a request outstanding, e.g. '60s'. Such closures are counted in `http_connections_idle_closed_total`.\n\nConnections are kept open for as long as their clients like if unset."
+    )]
+    pub keep_alive_timeout: Option<Duration>,
+    #[arg(
+        long = "max-concurrency",
+        env = "ECHO_MAX_CONCURRENCY",
+        long_help = "Respond with 503 to requests arriving while this many are already being handled, rather than queueing them, so load generators see back-pressure. Rejections are counted in `concurrency_limit_rejected_total`."
+    )]
+    pub max_concurrency: Option<NonZeroUsize>,
+    #[arg(
+        long = "cors-allow-origins",
+        env = "ECHO_CORS_ALLOW_ORIGINS",
+        value_delimiter = ',',
+        long_help = "Origins (e.g. 'http://localhost:3000') allowed to read responses from browsers, or '*' for any. Preflight (OPTIONS) requests are then answered rather than echoed.\n\nCORS isn't handled at all unless an origin is given."
+    )]
+    pub cors_allow_origins: Vec<String>,
+    #[arg(
+        long = "cors-allow-methods",
+        env = "ECHO_CORS_ALLOW_METHODS",
+        value_delimiter = ',',
+        long_help = "Methods cross-origin requests may use, or '*' for any. Defaults to whichever a preflight asks for."
+    )]
+    pub cors_allow_methods: Vec<String>,
+    #[arg(
+        long = "cors-allow-headers",
+        env = "ECHO_CORS_ALLOW_HEADERS",
+        value_delimiter = ',',
+        long_help = "Headers cross-origin requests may carry, or '*' for any. Defaults to whichever a preflight asks for."
+    )]
+    pub cors_allow_headers: Vec<String>,
+    #[arg(
+        long = "cors-allow-credentials",
+        env = "ECHO_CORS_ALLOW_CREDENTIALS",
+        default_value_t = false,
+        long_help = "Allow cross-origin requests to carry credentials (cookies, `Authorization` headers, or client certificates), which requires the allowed origins (and methods and headers, if given) to be listed rather than '*'."
+    )]
+    pub cors_allow_credentials: bool,
+    #[arg(
+        long = "cors-max-age",
+        env = "ECHO_CORS_MAX_AGE",
+        value_parser = humantime::parse_duration,
+        long_help = "How long browsers may cache preflight responses, e.g. '10m'."
+    )]
+    pub cors_max_age: Option<Duration>,
+    #[arg(
+        long = "max-body-size",
+        env = "ECHO_MAX_BODY_SIZE",
+        value_parser = body::parse_size,
+        long_help = "Respond with 413 to any request whose body is larger than this, e.g. '10MiB', rather than buffering it. Rejections are counted in `request_body_rejected_total`.\n\nBodies echoed back are otherwise limited to 2MiB, while bodies relayed by proxies, mirrors, and stubs aren't limited at all."
+    )]
+    pub max_body_size: Option<usize>,
+    #[arg(
+        long = "max-header-bytes",
+        env = "ECHO_MAX_HEADER_BYTES",
+        value_parser = body::parse_size,
+        long_help = "Respond with 431 to any request whose headers add up to more than this, e.g. '8KiB', counting each as it's written on the wire (`name: value\\r\\n`). Rejections are counted in `request_headers_rejected_total`."
+    )]
+    pub max_header_bytes: Option<usize>,
+    #[arg(
+        long = "max-header-count",
+        env = "ECHO_MAX_HEADER_COUNT",
+        long_help = "Respond with 431 to any request carrying more headers than this. Rejections are counted in `request_headers_rejected_total`.\n\nNote that HTTP/1.x requests with more than 100 headers are always rejected (with 431) before they're handled at all."
+    )]
+    pub max_header_count: Option<usize>,
+    #[arg(
+        long = "max-uri-length",
+        env = "ECHO_MAX_URI_LENGTH",
+        long_help = "Respond with 414 to any request whose URI (its path and query string) is longer than this many bytes, logging the length it was. Rejections are counted in `request_uri_rejected_total`."
+    )]
+    pub max_uri_length: Option<usize>,
+    #[arg(
+        long = "shutdown-delay",
+        env = "ECHO_SHUTDOWN_DELAY",
+        value_parser = humantime::parse_duration,
+        default_value = "0s",
+        long_help = "How long to keep serving (while `/readyz` reports not-ready) after being asked to shut down, before draining connections.\n\nGives load balancers (e.g. Kubernetes endpoints during a rolling update) time to stop routing new requests to the server."
+    )]
+    pub shutdown_delay: Duration,
+    #[arg(
+        long = "close-every",
+        env = "ECHO_CLOSE_EVERY",
+        long_help = "Close each HTTP/1.x connection (via `Connection: close`) after every N-th response served on it."
+    )]
+    pub close_every: Option<NonZeroU64>,
+    #[arg(
+        long = "proxy-protocol",
+        env = "ECHO_PROXY_PROTOCOL",
+        default_value_t = false,
+        long_help = "Expect each connection to start with a PROXY protocol (v1 or v2) header, e.g. from HAProxy or an AWS NLB, and report the client address it relays.\n\nConnections without one are served as usual."
+    )]
+    pub proxy_protocol: bool,
+    #[arg(
+        long = "trusted-proxies",
+        env = "ECHO_TRUSTED_PROXIES",
+        value_delimiter = ',',
+        long_help = "Comma-separated networks (e.g. '10.0.0.0/8,::1') of proxies whose `Forwarded` (or else `X-Forwarded-For`) headers are believed.\n\nRequests from them are attributed to the nearest untrusted address they were forwarded for."
+    )]
+    pub trusted_proxies: Vec<client_ip::Cidr>,
+    #[arg(
+        long = "oauth",
+        env = "ECHO_OAUTH",
+        default_value_t = false,
+        long_help = "Serve a mock OAuth2 / OpenID Connect authorization server under `/oauth` (`/authorize`, `/token`, and `/introspect`).\n\nEvery request is approved, and every token minted is a signed (ES256) JWT. The discovery document and signing keys are served at `/.well-known/openid-configuration` and `/jwks.json`."
+    )]
+    pub oauth: bool,
+    #[arg(long = "oauth-issuer", env = "ECHO_OAUTH_ISSUER")]
+    pub oauth_issuer: Option<String>,
+    #[arg(long = "oauth-audience", env = "ECHO_OAUTH_AUDIENCE")]
+    pub oauth_audience: Option<String>,
+    #[arg(
+        long = "oauth-token-ttl",
+        env = "ECHO_OAUTH_TOKEN_TTL",
+        value_parser = humantime::parse_duration,
+        default_value = "1h"
+    )]
+    pub oauth_token_ttl: Duration,
+    #[arg(
+        long = "oauth-claim",
+        env = "ECHO_OAUTH_CLAIMS",
+        value_delimiter = ';',
+        value_parser = oauth::parse_claim,
+        long_help = "Additional claim to include in every minted access token, as a `name=value` pair. May be given multiple times.\n\nValues are interpreted as JSON where possible, and as plain strings otherwise."
+    )]
+    pub oauth_claims: Vec<(String, serde_json::Value)>,
+    #[arg(
+        long = "oauth-signing-key",
+        env = "ECHO_OAUTH_SIGNING_KEY",
+        long_help = "PEM file holding the PKCS#8-encoded P-256 key tokens are signed with.\n\nAn ephemeral key is generated at startup if none is supplied."
+    )]
+    pub oauth_signing_key: Option<PathBuf>,
+    #[arg(
+        long = "soap-wsdl",
+        env = "ECHO_SOAP_WSDL",
+        long_help = "WSDL document to serve at `<soap-path>?wsdl`, enabling SOAP stubbing.\n\nThe document may be a template (see `--response-template`), e.g. to point its service address at the `Host` it was fetched from."
+    )]
+    pub soap_wsdl: Option<PathBuf>,
+    #[arg(
+        long = "soap-action",
+        env = "ECHO_SOAP_ACTIONS",
+        value_delimiter = ';',
+        value_parser = soap::parse_action,
+        long_help = "Envelope template to answer a SOAP action with, as a `name=path` pair, enabling SOAP stubbing. May be given multiple times.\n\nCalls are matched by their `SOAPAction` (or SOAP 1.2 `action`), its last segment, or the name of the first element in the `Body`. Templates may render a whole envelope or just the `Body`'s content, and have `soap` (`action`, `operation`, `version`, and `params`, the operation's element as JSON) available alongside `request`. Calls matching no action, or rendering a `Fault`, are answered with a fault."
+    )]
+    pub soap_actions: Vec<(String, PathBuf)>,
+    #[arg(
+        long = "soap-path",
+        env = "ECHO_SOAP_PATH",
+        default_value = "/soap",
+        long_help = "Path the SOAP endpoint (and its WSDL) is served at."
+    )]
+    pub soap_path: String,
+    #[arg(
+        long = "mdns",
+        env = "ECHO_MDNS",
+        default_value_t = false,
+        long_help = "Advertise the echo server on the local network via mDNS / DNS-SD (as an `_http._tcp` or `_https._tcp` service)."
+    )]
+    pub mdns: bool,
+    #[arg(
+        long = "mdns-name",
+        env = "ECHO_MDNS_NAME",
+        long_help = "Instance name to advertise the echo server under via mDNS.\n\nDefaults to `echo-rs (<hostname>)`."
+    )]
+    pub mdns_name: Option<String>,
+    #[arg(
+        long = "consul-addr",
+        env = "ECHO_CONSUL_ADDR",
+        long_help = "Register the echo server with the Consul agent at the given address (e.g. 'http://127.0.0.1:8500') on startup, and deregister it on shutdown.\n\nA health check against `/healthz` is registered alongside it."
+    )]
+    pub consul_addr: Option<String>,
+    #[arg(long = "consul-token", env = "ECHO_CONSUL_TOKEN")]
+    pub consul_token: Option<String>,
+    #[arg(
+        long = "service-name",
+        env = "ECHO_SERVICE_NAME",
+        default_value = "echo-rs"
+    )]
+    pub service_name: String,
+    #[arg(
+        long = "otlp-endpoint",
+        env = "ECHO_OTLP_ENDPOINT",
+        long_help = "Export a span per request to the OTLP/HTTP collector at the given endpoint, e.g. 'http://localhost:4318' (to which `/v1/traces` is appended).\n\nSpans are parented to the caller's trace, as propagated by W3C `traceparent` or B3 (`b3` or `X-B3-*`) headers, and named after `--service-name`. Spans are exported whatever the log level."
+    )]
+    pub otlp_endpoint: Option<String>,
+    #[arg(
+        long = "access-log",
+        env = "ECHO_ACCESS_LOG",
+        long_help = "Append a line per request to the given file, e.g. '/var/log/echo/access.log', in Common or Combined Log Format (per `--access-log-format`), independently of the server's own logs.\n\nLines are written once each response has been sent, so include its size."
+    )]
+    pub access_log: Option<PathBuf>,
+    #[arg(
+        long = "access-log-format",
+        env = "ECHO_ACCESS_LOG_FORMAT",
+        value_enum,
+        default_value_t = access_log::AccessLogFormat::Combined,
+    )]
+    pub access_log_format: access_log::AccessLogFormat,
+    #[arg(
+        long = "access-log-max-size",
+        env = "ECHO_ACCESS_LOG_MAX_SIZE",
+        value_parser = body::parse_size,
+        long_help = "Rotate the access log once it's grown to this size, e.g. '100MiB', renaming it with the time of its rotation as a suffix (e.g. 'access.log.2024-01-01T000000Z')."
+    )]
+    pub access_log_max_size: Option<usize>,
+    #[arg(
+        long = "access-log-rotate-every",
+        env = "ECHO_ACCESS_LOG_ROTATE_EVERY",
+        value_parser = humantime::parse_duration,
+        long_help = "Rotate the access log once it's been written to for this long, e.g. '1d', renaming it with the time of its rotation as a suffix."
+    )]
+    pub access_log_rotate_every: Option<Duration>,
+    #[arg(
+        long = "service-address",
+        env = "ECHO_SERVICE_ADDRESS",
+        long_help = "Address service registries should direct clients (and health checks) to.\n\nDefaults to the machine's hostname."
+    )]
+    pub service_address: Option<String>,
+    #[arg(
+        long = "consul-check-interval",
+        env = "ECHO_CONSUL_CHECK_INTERVAL",
+        value_parser = humantime::parse_duration,
+        default_value = "10s"
+    )]
+    pub consul_check_interval: Duration,
+    #[arg(
+        long = "kubernetes-metadata",
+        env = "ECHO_KUBERNETES_METADATA",
+        default_value_t = false,
+        long_help = "When running in-cluster, include the pod's namespace, name, node, and service account in echoed payloads and as labels on every metric.\n\nValues are read from the downward API (via the `POD_NAMESPACE`, `POD_NAME`, `NODE_NAME`, and `SERVICE_ACCOUNT` environment variables) with the mounted service account token as a fallback."
+    )]
+    pub kubernetes_metadata: bool,
+    #[arg(
+        long = "redact-echo",
+        env = "ECHO_REDACT_ECHO",
+        value_delimiter = ',',
+        long_help = "Dot-separated paths to values that should be masked in echoed payloads, e.g. 'headers.authorization,body.password'. `*` matches every key (or element) at its level.\n\nAs it holds the request's headers verbatim, the `raw_head` is masked as a whole whenever anything is redacted.\n\nRedaction only applies to responses, what gets logged is unaffected."
+    )]
+    pub redact_echo: Vec<redact::RedactPath>,
+    #[arg(
+        long = "config",
+        env = "ECHO_CONFIG",
+        long_help = "YAML (or JSON, or TOML) configuration file.\n\nIts `settings` supply values for any of these options (by long name) not given on the command line or in the environment. `log-level` and `skip-logging-for` are re-applied whenever the file changes (or on SIGHUP), and its `routes` and `hosts` are reloaded per `--stubs-reload-interval`.\n\nEnvironment variables are substituted into it (and into stub files) as `${VAR}`, `${VAR:-default}` (if unset or empty), or `${VAR-default}` (if unset), while `$${` is a literal `${`.\n\nAn existing invocation (and any rules files) can be converted to one by appending `config migrate` to it.\n\nExample:\n  settings:\n    port: 8081\n    skip-logging-for: [health, metrics]\n  routes:\n    - path: /api/**\n      status: 503\n      delay: 250ms\n      headers: {retry-after: '5'}\n      mode: mirror      # or `log-only`\n      auth: {bearer: s3cr3t}\n    - path: /flaky      # 503 twice, then 200 (start over via `POST /_scenarios/flaky/reset`)\n      id: flaky\n      responses:\n        - {status: 503, times: 2}\n        - {status: 200, body: ok}\n      cycle: false      # or start over after the last response\n    - path: /**         # a flaky backend for one team's clients only\n      match_clients: [10.1.0.0/16]\n      match_headers: {x-team: '^a$'}\n      fault: {rate: 0.3, status: 502}"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(
+        long = "config-reload-interval",
+        env = "ECHO_CONFIG_RELOAD_INTERVAL",
+        value_parser = humantime::parse_duration,
+        default_value = "30s"
+    )]
+    pub config_reload_interval: Duration,
+    #[arg(
+        long = "stubs-dir",
+        env = "ECHO_STUBS_DIR",
+        long_help = "Directory of stub files (`.yaml`, `.yml`, or `.json`), each holding a route rule or a list of them, loaded in file name order after any in the `--config` file.\n\nThe registered rules can be exported in the same format from `GET /_stubs`."
+    )]
+    pub stubs_dir: Option<PathBuf>,
+    #[arg(
+        long = "upload-dir",
+        env = "ECHO_UPLOAD_DIR",
+        long_help = "Spool directory to accept file uploads into, via `PUT /upload/<name>`, reporting the size and SHA-256 hash of each.\n\nUploads are listed with `GET /upload`, fetched back with `GET /upload/<name>`, and deleted with `DELETE /upload/<name>` (or all at once with `DELETE /upload`). Uploads are subject to `--max-body-size`."
+    )]
+    pub upload_dir: Option<PathBuf>,
+    #[arg(
+        long = "s3",
+        env = "ECHO_S3",
+        default_value_t = false,
+        requires = "upload_dir",
+        long_help = "Emulate a small subset of the S3 API (listing buckets and their objects, and putting, getting, heading, and deleting objects), keeping buckets as directories of the `--upload-dir`.\n\nRequests are taken for S3 calls if they're signed (or presigned) as the AWS SDKs sign them, or carry an `x-amz-content-sha256` header, and must be path-style (e.g. `PUT /<bucket>/<key>`). Calls are echoed (and so logged) like any other request, but answered as S3 would."
+    )]
+    pub s3: bool,
+    #[arg(
+        long = "stubs-reload-interval",
+        env = "ECHO_STUBS_RELOAD_INTERVAL",
+        value_parser = humantime::parse_duration,
+        default_value = "2s",
+        long_help = "How often the `--config` file and `--stubs-dir` are checked for changes, upon which every route rule is reloaded and swapped in at once (though virtual hosts' certificates aren't).\n\nRules that fail to load are logged and reported by `GET /_stubs/status` (along with counts in `stub_reloads_total`), while the current rules are kept."
+    )]
+    pub stubs_reload_interval: Duration,
+    #[arg(
+        long = "full-echo-head-options",
+        env = "ECHO_FULL_ECHO_HEAD_OPTIONS",
+        default_value_t = false,
+        long_help = "Answer OPTIONS requests with a full echo (rather than a bare `Allow` header), and HEAD requests exactly as they're handled by the echo routes."
+    )]
+    pub full_echo_head_options: bool,
+    #[arg(
+        long = "compress-responses",
+        env = "ECHO_COMPRESS_RESPONSES",
+        default_value_t = false,
+        long_help = "Compress echo responses with gzip, brotli, or zstd, as negotiated via the request's `Accept-Encoding` header."
+    )]
+    pub compress_responses: bool,
+    #[arg(
+        long = "utility-endpoints",
+        env = "ECHO_UTILITY_ENDPOINTS",
+        default_value_t = false,
+        long_help = "Answer httpbin-style utility paths rather than echoing them:\n  /status/{code}     respond with the given status code\n  /delay/{seconds}   echo the request after the given delay (of up to a minute)\n  /bytes/{n}         respond with n random bytes\n  /stream/{n}        respond with n copies of the echo, one per line\n  /redirect/{n}      redirect n times before echoing the request"
+    )]
+    pub utility_endpoints: bool,
+    #[arg(
+        long = "traffic-accounting",
+        env = "ECHO_TRAFFIC_ACCOUNTING",
+        default_value_t = false,
+        long_help = "Count the body bytes received from and sent to each client (in total, and by path), so data-transfer assertions can be made against the server itself.\n\nCounts are reported by `GET /_traffic` (or for a single client, by `GET /_traffic/<ip>`), and reset by `DELETE /_traffic`. Up to 10,000 clients, and 100 paths per client, are counted separately, beyond which the rest are counted together as `other`.\n\nTotals across every client are exported as `client_received_bytes_total` and `client_sent_bytes_total`."
+    )]
+    pub traffic_accounting: bool,
+    #[arg(
+        long = "webhook-deliveries",
+        env = "ECHO_WEBHOOK_DELIVERIES",
+        default_value_t = false,
+        long_help = "Track retried webhook deliveries, matched by their producer's delivery ID header (`X-GitHub-Delivery`, `X-Gitlab-Event-UUID`, `X-Shopify-Webhook-Id`, `svix-id`, `webhook-id`, or `I-Twilio-Idempotency-Token`, plus any `--webhook-delivery-header`s), recording when each attempt was made, what it was answered with, and how long the producer backed off in between.\n\nDeliveries are listed by `GET /_webhooks`, each delivery's attempts reported by `GET /_webhooks/<id>`, and all of them forgotten by `DELETE /_webhooks`. Attempts are counted in `webhook_delivery_attempts_total`, and backoffs recorded in `webhook_retry_backoff_seconds`."
+    )]
+    pub webhook_deliveries: bool,
+    #[arg(
+        long = "webhook-delivery-header",
+        env = "ECHO_WEBHOOK_DELIVERY_HEADERS",
+        value_delimiter = ',',
+        requires = "webhook_deliveries",
+        long_help = "Additional header identifying webhook deliveries (e.g. 'x-acme-delivery-id'), reported as their producer. May be given multiple times."
+    )]
+    pub webhook_delivery_header: Vec<String>,
+    #[arg(
+        long = "self-traffic",
+        env = "ECHO_SELF_TRAFFIC",
+        long_help = "Generate synthetic requests against the server itself (or `--self-traffic-target`) at the given rate, e.g. '1/s' or '6/1m', as a built-in heartbeat for validating that dashboards and alerting pipelines are live.\n\nEach is a `POST` to `/heartbeat` (echoed, logged, and counted like any other request) carrying its sequence number in the `X-Echo-Self-Traffic` header. Their outcomes are counted (by status) in `self_traffic_requests_total`, timed in `self_traffic_duration_seconds`, and the time of the latest success is exported as `self_traffic_last_success_timestamp_seconds`."
+    )]
+    pub self_traffic: Option<schedule::Quota>,
+    #[arg(
+        long = "self-traffic-target",
+        env = "ECHO_SELF_TRAFFIC_TARGET",
+        requires = "self_traffic",
+        long_help = "URL synthetic requests are made to (rather than the server's own `/heartbeat`), e.g. 'http://localhost:3000/ping'."
+    )]
+    pub self_traffic_target: Option<String>,
+    #[arg(
+        long = "strict-stubs",
+        env = "ECHO_STRICT_STUBS",
+        default_value_t = false,
+        long_help = "Reject requests matching none of the configured route rules (with the `--strict-stubs-status`) rather than silently echoing them, so broken test setups fail loudly.\n\nEither way, such requests are reported by `/_requests/unmatched` and counted in `route_unmatched_requests_total` (whenever route rules are configured, or strict mode is on)."
+    )]
+    pub strict_stubs: bool,
+    #[arg(
+        long = "strict-stubs-status",
+        env = "ECHO_STRICT_STUBS_STATUS",
+        value_parser = clap::value_parser!(u16).range(400..600),
+        default_value_t = 501
+    )]
+    pub strict_stubs_status: u16,
+    #[arg(
+        long = "sample-requests",
+        env = "ECHO_SAMPLE_REQUESTS",
+        value_delimiter = ',',
+        long_help = "Conditions under which a request is sampled as an exemplar (logged under the `echo_rs::exemplar` target and counted in `http_requests_sampled_total`). A request is sampled if it meets any of them.\n\nConditions:\n  all              every request\n  errors           requests answered with a 5xx status\n  failures         requests answered with a 4xx or 5xx status\n  slow><duration>  requests slower than the threshold, e.g. 'slow>250ms'\n  ratio=<0..1>     a random fraction of requests, e.g. 'ratio=0.01'\n\nExample:\n  echo-rs ... --sample-requests='errors,slow>1s,ratio=0.001'"
+    )]
+    pub sample_requests: Vec<sampling::SampleWhen>,
+    #[arg(
+        long = "proxy-upstream",
+        env = "ECHO_PROXY_UPSTREAM",
+        value_delimiter = ',',
+        long_help = "Relay requests to the given upstream(s) (e.g. 'http://localhost:3000') rather than echoing them, logging both sides of each exchange.\n\nSeveral upstreams may be given, each optionally weighted for `--proxy-balance=weighted` (e.g. 'http://a:3000=3,http://b:3000'). Per-upstream request counts, latencies, and in-flight requests are exported as `proxy_upstream_*` metrics."
+    )]
+    pub proxy_upstream: Vec<proxy::UpstreamSpec>,
+    #[arg(
+        long = "mirror-to",
+        env = "ECHO_MIRROR_TO",
+        value_delimiter = ',',
+        value_parser = mirror::parse_target,
+        long_help = "Asynchronously replay a copy of every received request to the given target(s) (e.g. 'http://localhost:3000'), while still answering the caller as usual. May be given multiple times.\n\nEach target may be followed by `;`-separated settings, e.g. 'http://localhost:3000;path=/hooks/**;methods=POST|PUT;delivery=at-least-once':\n  path: only mirror requests whose path matches this glob\n  methods: only mirror requests with one of these methods\n  delivery: `fire-and-forget` (the default), or `at-least-once`, which queues copies under `--mirror-queue-dir` before answering, then delivers them in order, retrying until they're accepted (i.e. answered with anything but a 5xx, 408, or 429), even across restarts\n\nDelivery attempts are counted (by target and status) in `mirror_requests_total`, and the copies yet to be delivered in `mirror_queue_depth`."
+    )]
+    pub mirror_to: Vec<mirror::MirrorTarget>,
+    #[arg(
+        long = "mirror-timeout",
+        env = "ECHO_MIRROR_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        default_value = "10s",
+        long_help = "How long a mirrored request may take before it's abandoned (and counted as an error)."
+    )]
+    pub mirror_timeout: Duration,
+    #[arg(
+        long = "mirror-queue-dir",
+        env = "ECHO_MIRROR_QUEUE_DIR",
+        default_value_os_t = env::temp_dir().join("echo-rs-mirror"),
+        long_help = "Directory under which copies bound for `delivery=at-least-once` mirror targets are queued (in a subdirectory per target) until they're delivered."
+    )]
+    pub mirror_queue_dir: PathBuf,
+    #[arg(
+        long = "mirror-max-attempts",
+        env = "ECHO_MIRROR_MAX_ATTEMPTS",
+        long_help = "How many times a copy bound for a `delivery=at-least-once` mirror target is sent before it's given up on (and dead-lettered, if `--mirror-dead-letter-file` is set). Retried indefinitely by default."
+    )]
+    pub mirror_max_attempts: Option<u32>,
+    #[arg(
+        long = "mirror-dead-letter-file",
+        env = "ECHO_MIRROR_DEAD_LETTER_FILE",
+        long_help = "Keep copies that mirror targets fail to accept in the given file (one JSON object per line), rather than dropping them: those a fire-and-forget target fails (or answers with a 4xx or 5xx), and those an at-least-once target rejects (with a 4xx) or runs out of attempts for. Counted in `mirror_dead_letters_total`.\n\nWhen an admin token is configured, they're listed by `GET /_mirror/dead-letters`, and handed back to their targets by `POST /_mirror/dead-letters/redrive`."
+    )]
+    pub mirror_dead_letter_file: Option<PathBuf>,
+    #[arg(
+        long = "dns-record",
+        env = "ECHO_DNS_RECORDS",
+        value_delimiter = ',',
+        long_help = "Record answered by the DNS-over-HTTPS endpoint (`/dns-query`), as a `name=type:value` pair, e.g. 'example.com=A:192.0.2.1'. May be given multiple times.\n\nA, AAAA, CNAME, and TXT records are supported. Queries for names without any records are answered with NXDOMAIN."
+    )]
+    pub dns_record: Vec<doh::DnsRecord>,
+    #[arg(
+        long = "proxy-balance",
+        env = "ECHO_PROXY_BALANCE",
+        value_enum,
+        default_value_t = proxy::Balance::RoundRobin,
+        long_help = "How requests are spread across several upstreams: in turn, to whichever has the fewest requests in flight, or in turn in proportion to their weights."
+    )]
+    pub proxy_balance: proxy::Balance,
+    #[arg(
+        long = "proxy-forwarded-headers",
+        env = "ECHO_PROXY_FORWARDED_HEADERS",
+        value_enum,
+        default_value_t = proxy::ForwardedHeaders::Both,
+        long_help = "Which forwarding headers to add to relayed requests: `X-Forwarded-For`/`-Proto`/`-Host`, RFC 7239 `Forwarded`, both, or none."
+    )]
+    pub proxy_forwarded_headers: proxy::ForwardedHeaders,
+    #[arg(
+        long = "proxy-health-path",
+        env = "ECHO_PROXY_HEALTH_PATH",
+        default_value = "/",
+        long_help = "Path each proxy upstream is actively health-checked at, where any response other than a 4xx or 5xx counts as healthy. Requests are only relayed to healthy upstreams while there are any.\n\nUpstream health is reported by `/readyz` (which fails while every upstream is down, unless falling back to echoing) and by the `proxy_upstream_up` gauge."
+    )]
+    pub proxy_health_path: String,
+    #[arg(
+        long = "proxy-health-interval",
+        env = "ECHO_PROXY_HEALTH_INTERVAL",
+        value_parser = humantime::parse_duration,
+        default_value = "10s"
+    )]
+    pub proxy_health_interval: Duration,
+    #[arg(
+        long = "proxy-health-timeout",
+        env = "ECHO_PROXY_HEALTH_TIMEOUT",
+        value_parser = humantime::parse_duration,
+        default_value = "2s"
+    )]
+    pub proxy_health_timeout: Duration,
+    #[arg(
+        long = "proxy-fallback-to-echo",
+        env = "ECHO_PROXY_FALLBACK_TO_ECHO",
+        default_value_t = false,
+        long_help = "Echo requests (rather than relaying them) while every proxy upstream is failing its health checks."
+    )]
+    pub proxy_fallback_to_echo: bool,
+    #[arg(
+        long = "proxy-cache",
+        env = "ECHO_PROXY_CACHE",
+        default_value_t = false,
+        long_help = "Keep upstream responses in an in-memory cache, answering repeated requests from it as an intermediary (shared) cache would, to exercise clients' cache-busting behavior.\n\nOnly responses to `GET` requests with an explicit lifetime (from `s-maxage`, `max-age`, or `Expires`) are stored, unless marked `no-store`, `no-cache`, or `private` (or varying by `*`). Stored responses are selected by the request headers named in their `Vary`, and requests marked `no-store` bypass the cache, while those marked `no-cache` (or `max-age=0`) are relayed and their responses stored afresh. Successful unsafe requests (e.g. `POST` or `DELETE`) evict whatever was stored for their URI.\n\nRelayed responses are marked with `X-Cache: HIT`, `MISS`, or `BYPASS` (and cached ones with their `Age`), and lookups are counted by result in `proxy_cache_requests_total`."
+    )]
+    pub proxy_cache: bool,
+    #[arg(
+        long = "proxy-cache-entries",
+        env = "ECHO_PROXY_CACHE_ENTRIES",
+        default_value_t = 1024,
+        long_help = "The most responses the proxy cache stores at once, beyond which the longest-stored are evicted."
+    )]
+    pub proxy_cache_entries: usize,
+    #[arg(
+        long = "proxy-cache-max-body",
+        env = "ECHO_PROXY_CACHE_MAX_BODY",
+        value_parser = body::parse_size,
+        default_value = "1MiB",
+        long_help = "The largest response body the proxy cache stores, e.g. '256KiB'."
+    )]
+    pub proxy_cache_max_body: usize,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What `echo-rs` does, besides serving (the default)
+#[derive(Clone, Debug, clap::Subcommand)]
+enum Command {
+    /// Serve as usual, additionally recording every echoed request to an NDJSON file
+    Record {
+        #[arg(
+            long = "out",
+            env = "ECHO_RECORD_OUT",
+            long_help = "File to record to, one echo (plus the request as it was received, so it can be replayed) per line.\n\nRecordings are appended to, if the file already exists."
+        )]
+        out: PathBuf,
+    },
+    /// Re-send the requests recorded to an NDJSON file (by `record`) to another server
+    Replay {
+        /// The recording to replay
+        file: PathBuf,
+        #[arg(
+            long = "target",
+            long_help = "Base URL to re-send the recorded requests to, e.g. 'https://api.example.com'."
+        )]
+        target: String,
+        #[arg(
+            long = "rate",
+            long_help = "The most requests to re-send per second, e.g. '2' or '0.5'.\n\nRequests are re-sent one after another, as fast as they're answered, if neither this nor `--realtime` is given."
+        )]
+        rate: Option<f64>,
+        #[arg(
+            long = "realtime",
+            default_value_t = false,
+            long_help = "Keep the time between requests as it was when they were recorded."
+        )]
+        realtime: bool,
+        #[arg(
+            long = "insecure",
+            default_value_t = false,
+            long_help = "Don't verify the target's TLS certificate."
+        )]
+        insecure: bool,
+    },
+    /// Work with `echo-rs` configuration files
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+/// What to do with configuration files
+#[derive(Clone, Debug, clap::Subcommand)]
+enum ConfigCommand {
+    /// Print the configuration file equivalent to the rest of the invocation, e.g.
+    /// `echo-rs --port 8081 --chaos-error-rate 0.1 config migrate --rules stubs.yaml`
+    Migrate {
+        #[arg(
+            long = "rules",
+            long_help = "Rules (i.e. stub) file, holding a route rule or a list of them, whose rules are added to the configuration file's `routes`. May be given multiple times.\n\nBody files are taken as relative to the rules file, as they would be in `--stubs-dir`."
+        )]
+        rules: Vec<PathBuf>,
+        #[arg(
+            long = "format",
+            value_enum,
+            default_value_t = config::ConfigFormat::Yaml,
+            long_help = "Format to print the configuration file in."
+        )]
+        format: config::ConfigFormat,
+    },
+}
+
+/// Settings that a reloaded configuration file applies without a restart
+const RELOADABLE_SETTINGS: &[&str] = &["log-level", "skip-logging-for"];
+
+/// Extend the `RUST_LOG` filter with the given level for `echo-rs` itself,
+/// unless it already mentions it
+fn log_filter(rust_log: &str, level: tracing::Level) -> String {
+    let mut log_conf = rust_log.to_owned();
+
+    if !log_conf.to_ascii_lowercase().contains("echo_rs") {
+        if !log_conf.is_empty() {
+            log_conf.insert(log_conf.len(), ',');
+        }
+
+        log_conf.extend(format!("echo_rs={}", level.as_str()).chars());
+    }
+
+    log_conf
+}
+
+#[tracing::instrument(skip_all, parent = None)]
+/// Parse user-supplied patterns for URLs that should not be logged
+fn parse_unlogged_patterns(value: &str) -> Vec<Regex> {
+    let mut patterns: Vec<Regex> = Vec::new();
+
+    if !value.is_empty() {
+        patterns.extend(Regex::new("[,;] ?").unwrap().split(value).flat_map(
+            |pat| match Regex::new(pat) {
+                Ok(pattern) => Some(pattern),
+                Err(_) => {
+                    tracing::warn!("Declining to add bad filter pattern: {pat}");
+                    None
+                }
+            },
+        ));
+    }
+
+    patterns
+}
+
+/// A server listening on the inherited socket (if there is one), or else
+/// bound to the given host and port, along with the address it listens at
+fn bind(
+    host: &str,
+    port: usize,
+    inherited: Option<TcpListener>,
+) -> anyhow::Result<(axum_server::Server, String)> {
+    Ok(match inherited {
+        None => {
+            let addr = format!("{host}:{port}").parse::<SocketAddr>()?;
+            (axum_server::bind(addr), addr.to_string())
+        }
+        Some(listener) => {
+            let addr = activation::describe(&listener);
+            (
+                axum_server::from_tcp(listener),
+                format!("{addr} (inherited)"),
+            )
+        }
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn serve_app(
+    host: &str,
+    port: usize,
+    inherited: Option<TcpListener>,
+    tls_config: Option<RustlsConfig>,
+    conn_options: conn::ConnOptions,
+    app: Router,
+    handle: Handle,
+) -> anyhow::Result<()> {
+    const LOG_LINE: &str = "`echo-rs` server listening at";
+
+    let (mut proto, (server, addr)) = ("http".to_string(), bind(host, port, inherited)?);
+
+    let server = match conn_options.header_read_timeout {
+        None => server,
+        Some(timeout) => {
+            server.http_config(HttpConfig::new().http1_header_read_timeout(timeout).build())
+        }
+    };
+
+    match tls_config {
+        Some(tls_config) => {
+            proto.push('s');
+
+            tracing::info!("{LOG_LINE}: {proto}://{addr}");
+
+            server
+                .acceptor(conn::EchoAcceptor::new(
+                    RustlsAcceptor::new(tls_config),
+                    conn_options,
+                ))
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap();
+        }
+        _ => {
+            tracing::info!("{LOG_LINE}: {proto}://{addr}");
+
+            server
+                .acceptor(conn::EchoAcceptor::new(DefaultAcceptor, conn_options))
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+    };
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn serve_auxiliary(
+    serving: &str,
+    host: &str,
+    port: usize,
+    inherited: Option<TcpListener>,
+    tls_config: Option<RustlsConfig>,
+    app: Router,
+    handle: Handle,
+) -> anyhow::Result<()> {
+    let (mut proto, (server, addr)) = ("http".to_string(), bind(host, port, inherited)?);
+
+    match tls_config {
+        Some(tls_config) => {
+            proto.push('s');
+
+            tracing::info!("Serving {serving} at: {proto}://{addr}");
+
+            server
+                .acceptor(RustlsAcceptor::new(tls_config))
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .unwrap();
+        }
+        _ => {
+            tracing::info!("Serving {serving} at: {proto}://{addr}");
+
+            server
+                .handle(handle)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Run the `echo-rs` server, as configured by the command line,
+/// the environment, and the configuration file
+#[tracing::instrument]
+pub async fn run() -> anyhow::Result<()> {
+    let command = <Args as clap::CommandFactory>::command();
+    let matches = command.clone().get_matches();
+    let options = config::Options::unset(&command, &matches);
+    let mut args = <Args as clap::FromArgMatches>::from_arg_matches(&matches)?;
+
+    // every option given on the command line or in the environment becomes a
+    // setting, along with those of any configuration file already in use
+    if let Some(Command::Config {
+        action: ConfigCommand::Migrate { rules, format },
+    }) = &args.command
+    {
+        print!("{}", config::migrate(&command, &matches, rules, *format)?);
+        return Ok(());
+    }
+
+    let config = match args.config.as_ref() {
+        Some(path) => {
+            let config = config::Config::load(path)?;
+
+            // settings from the file only fill in what the command line and environment don't
+            config.export(&command, &options)?;
+            args = <Args as clap::Parser>::parse();
+
+            config
+        }
+        None => config::Config::default(),
+    };
+
+    if let Some(time) = args.frozen_time {
+        clock::install(clock::FrozenClock(time));
+    }
+
+    let rust_log = env::var("RUST_LOG").unwrap_or_default();
+
+    env::set_var("RUST_LOG", log_filter(&rust_log, args.log_level));
+
+    let (log_filter_layer, log_reload) = tracing_subscriber::reload::Layer::new(
+        tracing_subscriber::EnvFilter::try_from_env("RUST_LOG")
+            .unwrap_or(tracing_subscriber::EnvFilter::from_default_env()),
+    );
+
+    let otel = args
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| otel::layer(endpoint, &args.service_name))
+        .transpose()?;
+
+    // the log filter only applies to the logs, so spans it would
+    // discard can still be exported
+    let subscriber = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .event_format(logging::EventFormat::new(
+                    args.log_schema,
+                    args.log_format,
+                    args.service_name.clone(),
+                ))
+                .with_filter(log_filter_layer),
+        )
+        .with(otel);
+
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    if let Some(Command::Replay {
+        file,
+        target,
+        rate,
+        realtime,
+        insecure,
+    }) = args.command.clone()
+    {
+        let settings = recording::ReplaySettings {
+            target,
+            rate,
+            realtime,
+            insecure,
+        };
+
+        return recording::replay(file, settings).await;
+    }
+
+    let url_filters = Arc::new(RwLock::new(parse_unlogged_patterns(&args.unlogged)));
+    let log_only = Arc::new(AtomicBool::new(false));
+
+    let log_control = Arc::new(log_control::LogControl::new(
+        args.log_level,
+        move |level| {
+            log_reload.reload(tracing_subscriber::EnvFilter::new(log_filter(
+                &rust_log, level,
+            )))?;
+
+            Ok(())
+        },
+        url_filters.clone(),
+        log_only.clone(),
+    ));
+
+    if let Some(path) = args.config.clone() {
+        let (log_control, mut settings) = (log_control.clone(), config.settings.clone());
+
+        let reloader = config::Reloader::new(path, move |config: config::Config| {
+            config.export(&command, &options)?;
+
+            let args = <Args as clap::Parser>::try_parse()?;
+
+            for name in settings
+                .keys()
+                .chain(config.settings.keys())
+                .collect::<BTreeSet<&String>>()
+            {
+                if options.contains(name)
+                    && !RELOADABLE_SETTINGS.contains(&name.as_str())
+                    && settings.get(name) != config.settings.get(name)
+                {
+                    tracing::warn!("Setting {name:?} changed, but only takes effect on restart");
+                }
+            }
+
+            log_control.set_level(args.log_level)?;
+            log_control.set_patterns(parse_unlogged_patterns(&args.unlogged));
+
+            settings = config.settings;
+
+            Ok(())
+        });
+
+        tokio::spawn(reloader.watch(args.config_reload_interval));
+    }
+
+    let shaping = shaping::Shaping {
+        ttfb_delay: args.ttfb_delay,
+        body_delay: args.body_delay,
+        bytes_per_second: args.response_rate,
+        trailers: args.response_trailers.iter().cloned().collect(),
+    };
+
+    let throttle = throttle::Throttle::new(
+        args.throttle_quota,
+        args.throttle_schedule,
+        args.retry_after_format,
+    );
+
+    let rate_limiter = ratelimit::RateLimiter::new(
+        args.rate_limit,
+        args.global_rate_limit,
+        args.burst,
+        args.retry_after_format,
+    );
+
+    let quotas = throttle::PathQuotas::new(args.path_quota.clone(), args.retry_after_format);
+
+    let fail_window = args
+        .fail_window
+        .map(|spec| Arc::new(fail_window::FailWindow::new(spec)));
+
+    let warmup = warmup::Warmup::new(
+        args.warmup,
+        args.warmup_mode,
+        args.warmup_latency,
+        args.retry_after_format,
+    );
+
+    let admin_token = admin::AdminToken::new(
+        args.admin_token
+            .iter()
+            .map(|token| admin::RoleToken::new(admin::Role::Admin, token))
+            .chain(args.role_token.iter().cloned())
+            .collect(),
+    );
+
+    if args.admin_port.is_some() && admin_token.is_none() {
+        anyhow::bail!("`--admin-port` requires `--admin-token` (or `--role-token`)");
+    }
+
+    if args.audit_log.is_some() && admin_token.is_none() {
+        anyhow::bail!("`--audit-log` requires `--admin-token` (or `--role-token`)");
+    }
+
+    let audit_log = args
+        .audit_log
+        .clone()
+        .map(audit::AuditLog::open)
+        .transpose()?
+        .map(Arc::new);
+    let admin_token = admin_token.map(|token| token.with_audit(audit_log));
+
+    // captures (and the like) are only restricted once roles are handed out, so as not to lock out existing setups
+    let capture_token = admin_token.clone().filter(|_| !args.role_token.is_empty());
+
+    let logging_admin = admin_token
+        .clone()
+        .map(|token| log_control::router(log_control.clone(), token));
+
+    let shutdown = shutdown::Shutdown::new(args.drain_timeout).with_delay(args.shutdown_delay);
+
+    let conn_options = conn::ConnOptions {
+        raw_dump: args.raw_dump,
+        close_every: args.close_every,
+        proxy_protocol: args.proxy_protocol,
+        keep_alive_timeout: args.keep_alive_timeout,
+        header_read_timeout: args.header_read_timeout,
+    };
+
+    let kubernetes = if !args.kubernetes_metadata {
+        None
+    } else {
+        let metadata = kube::KubeMetadata::discover();
+
+        if metadata.is_none() {
+            tracing::warn!("Kubernetes metadata requested, but none could be found");
+        }
+
+        metadata
+    };
+
+    let unmatched = Arc::new(unmatched::UnmatchedRequests::default());
+
+    let stubs = match args.stubs_dir.as_ref() {
+        Some(dir) => stubs::load_dir(dir)?,
+        None => Vec::new(),
+    };
+
+    let mut routes = routes::RouteRules::new(
+        config.routes.into_iter().chain(stubs).collect(),
+        config.hosts.clone(),
+    )?
+    .with_unmatched(
+        unmatched.clone(),
+        args.strict_stubs
+            .then(|| StatusCode::from_u16(args.strict_stubs_status))
+            .transpose()?,
+    );
+
+    let stubs_reloader = (args.config.is_some() || args.stubs_dir.is_some()).then(|| {
+        routes = routes.clone().reloadable();
+        stubs::Reloader::new(args.config.clone(), args.stubs_dir.clone(), routes.clone())
+    });
+
+    let stubs_status = stubs_reloader.as_ref().map(stubs::Reloader::status);
+
+    if let Some(reloader) = stubs_reloader {
+        tokio::spawn(reloader.watch(args.stubs_reload_interval));
+    }
+
+    let collapser = args.collapse_duplicate_logs.map(|window| {
+        let collapser = Arc::new(collapse::LogCollapser::default());
+        tokio::spawn(collapser.clone().summarize_every(window));
+        collapser
+    });
+
+    let history =
+        history::RequestHistory::new(args.history_size, args.history_views.clone()).map(Arc::new);
+
+    let tail = Arc::new(tail::RequestTail::default());
+    let assertions = Arc::new(assertions::Assertions::default());
+
+    let templates = routes.templates();
+
+    let response_template = args
+        .response_template
+        .as_ref()
+        .map(|path| {
+            let source = std::fs::read_to_string(path)
+                .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+            templates
+                .validate(&source)
+                .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+            anyhow::Ok(Arc::new(source))
+        })
+        .transpose()?;
+
+    let soap = (args.soap_wsdl.is_some() || !args.soap_actions.is_empty())
+        .then(|| {
+            soap::SoapStubs::load(
+                args.soap_wsdl.as_ref(),
+                &args.soap_actions,
+                templates.clone(),
+            )
+            .map(Arc::new)
+        })
+        .transpose()?;
+
+    let spool = args
+        .upload_dir
+        .clone()
+        .map(uploads::Spool::new)
+        .transpose()?
+        .map(Arc::new);
+
+    let recorder = match args.command.clone() {
+        Some(Command::Record { out }) => Some(Arc::new(recording::Recorder::open(out).await?)),
+        _ => None,
+    };
+
+    let state = EchoState {
+        url_filters,
+        sequencer: Arc::default(),
+        kubernetes: kubernetes.clone(),
+        redactions: Arc::new(args.redact_echo.clone()),
+        parsers: Arc::default(),
+        schema: args.echo_schema,
+        layout: Arc::new(layout::Layout {
+            case: args.echo_field_case,
+            renames: args.echo_rename.clone(),
+            wrap: args.echo_wrap.clone(),
+        }),
+        collapser: collapser.clone(),
+        history: history.clone(),
+        recorder,
+        tail: tail.clone(),
+        assertions: assertions.clone(),
+        templates,
+        response_template,
+        structured_logs: logging::is_structured(args.log_schema, args.log_format),
+        pad_response_to: args.pad_response_to,
+        canonical_json: args.canonical_json,
+        timestamp_tolerance: Some(args.timestamp_tolerance),
+        jwks: args
+            .jwks_url
+            .clone()
+            .map(jwt::Jwks::new)
+            .transpose()?
+            .map(Arc::new),
+    };
+
+    let scenarios = Arc::new(scenarios::Scenarios {
+        sequencer: state.sequencer.clone(),
+        counters: routes.template_counters(),
+        fail_window: fail_window.clone(),
+        routes: routes.clone(),
+    });
+
+    let latency = Arc::new(latency::LatencyRecorder::with_rules(routes.ids()));
+
+    let counters = Arc::new(counters::RequestCounters::default());
+    let traffic = args
+        .traffic_accounting
+        .then(|| Arc::new(traffic::TrafficAccounts::default()));
+
+    let webhooks = args
+        .webhook_deliveries
+        .then(|| webhooks::WebhookDeliveries::new(&args.webhook_delivery_header))
+        .transpose()?
+        .map(Arc::new);
+
+    let inflight = Arc::new(inflight::InflightRequests::default());
+
+    let upstreams = args
+        .proxy_upstream
+        .iter()
+        .cloned()
+        .map(|spec| Arc::new(proxy::Upstream::new(spec)))
+        .collect::<Vec<Arc<proxy::Upstream>>>();
+
+    for upstream in upstreams.iter().cloned() {
+        tokio::spawn(proxy::health_check(
+            upstream,
+            proxy::HealthCheck {
+                path: args.proxy_health_path.clone(),
+                interval: args.proxy_health_interval,
+                timeout: args.proxy_health_timeout,
+            },
+        ));
+    }
+
+    if let Some(rate) = args.self_traffic {
+        if rate.limit == 0 {
+            anyhow::bail!("`--self-traffic` must be a non-zero rate");
+        }
+
+        let tls = args.tls_key.is_some() && args.tls_cert.is_some();
+
+        tokio::spawn(self_traffic::generate(self_traffic::SelfTraffic {
+            rate,
+            url: args.self_traffic_target.clone().unwrap_or_else(|| {
+                history::local_url(&args.host, args.port, tls) + self_traffic::SELF_TRAFFIC_PATH
+            }),
+            // the server's own certificate needn't be trusted to make requests to it
+            insecure: args.self_traffic_target.is_none(),
+        }));
+    }
+
+    let transforms = config
+        .transforms
+        .into_iter()
+        .map(transform::Transform::try_from)
+        .collect::<anyhow::Result<Vec<transform::Transform>>>()?;
+
+    let thresholds = alerts::Thresholds {
+        request_rate: args.alert_request_rate,
+        error_rate: args.alert_error_rate,
+        latency: args.alert_latency,
+    };
+
+    let alerts = thresholds.is_enabled().then(|| {
+        let watcher = Arc::new(alerts::Watcher::new(thresholds));
+        tokio::spawn(watcher.clone().watch(args.alert_interval));
+        watcher
+    });
+
+    let mirror = mirror::Mirror::new(
+        args.mirror_to.clone(),
+        mirror::MirrorSettings {
+            timeout: args.mirror_timeout,
+            queue_dir: args.mirror_queue_dir.clone(),
+            dead_letter_file: args.mirror_dead_letter_file.clone(),
+            max_attempts: args.mirror_max_attempts,
+        },
+    )?
+    .map(Arc::new);
+
+    let features = EchoFeatures {
+        shaping,
+        throttle,
+        rate_limiter,
+        quotas,
+        fail_window,
+        warmup,
+        chaos: chaos::Chaos {
+            error_rate: args.chaos_error_rate,
+            abort_rate: args.chaos_abort_rate,
+            latency: args.chaos_latency,
+            latency_jitter: args.chaos_latency_jitter,
+        },
+        routes: routes.clone(),
+        full_echo_head_options: args.full_echo_head_options,
+        compress_responses: args.compress_responses,
+        utility_endpoints: args.utility_endpoints,
+        sampler: sampling::Sampler::new(args.sample_requests.clone()),
+        metric_paths: metrics::PathLabels::new(
+            args.metrics_path_label,
+            &args.metrics_path_template,
+            args.metrics_max_paths,
+        )?,
+        proxy: (!upstreams.is_empty())
+            .then(|| {
+                proxy::Proxy::new(
+                    upstreams.clone(),
+                    args.proxy_balance,
+                    args.proxy_forwarded_headers,
+                    args.tls_key.is_some() && args.tls_cert.is_some(),
+                    args.proxy_fallback_to_echo,
+                    transforms,
+                    args.proxy_cache.then(|| {
+                        cache::ResponseCache::new(
+                            args.proxy_cache_entries,
+                            args.proxy_cache_max_body,
+                        )
+                    }),
+                )
+            })
+            .transpose()?,
+        latency: latency.clone(),
+        counters: counters.clone(),
+        alerts,
+        inflight: inflight.clone(),
+        mirror: mirror.clone(),
+        webhooks: webhooks.clone(),
+        log_only,
+    };
+
+    let capabilities = capabilities::Capabilities {
+        service: args.service_name.clone(),
+        version: env!("CARGO_PKG_VERSION"),
+        schema: args.echo_schema,
+        tls: args.tls_key.is_some() && args.tls_cert.is_some(),
+        mtls: capabilities::Feature::new(args.tls_client_ca.as_ref().map(|_| {
+            capabilities::ClientCertificates {
+                optional: args.tls_client_optional,
+            }
+        })),
+        http3: args.http3,
+        grpc: capabilities::Feature::new(Some(capabilities::Grpc {
+            methods: vec!["Unary", "ServerStream", "ClientStream", "BidiStream"],
+            stream_count: args.grpc_stream_count,
+            stream_interval: args.grpc_stream_interval.map(capabilities::duration),
+        })),
+        websocket: capabilities::Feature::new(Some(capabilities::WebSocket {
+            paths: vec!["/_ws", "/ws/rooms/{room}"],
+        })),
+        chaos: capabilities::Feature::new(features.chaos.is_enabled().then(|| {
+            capabilities::Chaos {
+                error_rate: args.chaos_error_rate,
+                abort_rate: args.chaos_abort_rate,
+                latency: args.chaos_latency.map(capabilities::duration),
+                latency_jitter: args.chaos_latency_jitter.map(capabilities::duration),
+            }
+        })),
+        capture: capabilities::Feature::new(history.as_ref().map(|_| {
+            capabilities::Capture {
+                size: args.history_size,
+                views: args
+                    .history_views
+                    .iter()
+                    .map(history::SavedView::name)
+                    .collect(),
+            }
+        })),
+        stubbing: capabilities::Feature::new((!routes.is_empty()).then(|| {
+            capabilities::Stubbing {
+                rules: routes.ids(),
+                strict: routes.is_strict(),
+            }
+        })),
+        proxy: capabilities::Feature::new((!upstreams.is_empty()).then_some(capabilities::Proxy {
+            upstreams: upstreams.len(),
+            fallback_to_echo: args.proxy_fallback_to_echo,
+            cache: args.proxy_cache,
+        })),
+        mirror: capabilities::Feature::new(mirror.as_ref().map(|_| capabilities::Mirror {
+            targets: args.mirror_to.len(),
+        })),
+        oauth: args.oauth,
+        soap: capabilities::Feature::new(soap.as_ref().map(|stubs| capabilities::Soap {
+            path: args.soap_path.clone(),
+            wsdl: stubs.has_wsdl(),
+            actions: stubs.actions(),
+        })),
+        uploads: capabilities::Feature::new(spool.as_ref().map(|spool| capabilities::Uploads {
+            dir: spool.dir().display().to_string(),
+        })),
+        s3: args.s3,
+        traffic: args.traffic_accounting,
+        webhooks: args.webhook_deliveries,
+        admin: admin_token.is_some(),
+        metrics: capabilities::Feature::new(args.metrics.then(|| capabilities::Metrics {
+            port: args.metrics_port,
+            tls: args.metrics_use_tls && args.tls_key.is_some() && args.tls_cert.is_some(),
+            auth: args.metrics_auth.is_some(),
+        })),
+    };
+
+    tracing::info!(
+        "`echo-rs` {} capabilities: {}",
+        capabilities.version,
+        capabilities.enabled().join(", ")
+    );
+
+    let echo = echo_router(state, features).await?;
+
+    // batched sub-requests are handed straight to the echo service
+    let app = echo
+        .clone()
+        .merge(batch::router(echo))
+        .merge(capabilities::router(Arc::new(capabilities)))
+        .merge(latency::router(latency))
+        .merge(counters::router(counters.clone(), capture_token.clone()))
+        .merge(inflight::router(inflight, capture_token.clone()))
+        .merge(history::router(
+            history,
+            history::Replayer::new(
+                &args.host,
+                args.port,
+                args.tls_key.is_some() && args.tls_cert.is_some(),
+            )?,
+            capture_token.clone(),
+            admin_token.clone(),
+        ))
+        .merge(tail::router(tail, capture_token.clone()))
+        .merge(unmatched::router(unmatched, capture_token.clone()))
+        .merge(assertions::router(assertions, capture_token.clone()))
+        .merge(scenarios::router(scenarios, capture_token.clone()))
+        .merge(stubs::router(routes, stubs_status, admin_token.clone()))
+        .merge(negotiate::router())
+        .merge(ping::router())
+        .merge(ws::router())
+        .merge(grpc::router(grpc::StreamDefaults {
+            count: args.grpc_stream_count,
+            interval: args.grpc_stream_interval,
+        }))
+        .merge(clock::router(admin_token.clone()))
+        .merge(mirror::router(mirror, admin_token.clone()))
+        .merge(doh::router(Arc::new(doh::Resolver::new(
+            args.dns_record.clone(),
+        ))))
+        .merge(health::router(
+            Arc::new(
+                health::Health::new(args.flap_readiness)
+                    .with_upstreams(upstreams, !args.proxy_fallback_to_echo)
+                    .with_shutdown(shutdown.clone()),
+            ),
+            admin_token.clone(),
+        ))
+        .merge(shutdown::router(
+            shutdown.clone(),
+            admin_token.map(|token| token.requiring(admin::Role::Admin)),
+        ));
+
+    let app = if !args.oauth {
+        app
+    } else {
+        let key = match args.oauth_signing_key.as_ref() {
+            Some(path) => jwt::SigningKey::from_pem_file(path)?,
+            None => jwt::SigningKey::generate()?,
+        };
+
+        let settings = oauth::OAuthSettings {
+            issuer: args.oauth_issuer.clone(),
+            audience: args.oauth_audience.clone(),
+            token_ttl: args.oauth_token_ttl,
+            claims: args.oauth_claims.iter().cloned().collect(),
+        };
+
+        app.merge(oauth::router(Arc::new(oauth::AuthServer::new(
+            key,
+            settings,
+            args.tls_key.is_some() && args.tls_cert.is_some(),
+        ))))
+    };
+
+    let app = match soap.as_ref() {
+        None => app,
+        Some(stubs) => app.merge(soap::router(&args.soap_path, stubs.clone())),
+    };
+
+    let app = match spool.as_ref() {
+        None => app,
+        Some(spool) => app.merge(uploads::router(spool.clone())),
+    };
+
+    let app = match traffic.as_ref() {
+        None => app,
+        Some(accounts) => app.merge(traffic::router(accounts.clone(), capture_token.clone())),
+    };
+
+    let app = match webhooks {
+        None => app,
+        Some(webhooks) => app.merge(webhooks::router(webhooks, capture_token.clone())),
+    };
+
+    let app = match spool.as_ref().filter(|_| args.s3) {
+        None => app,
+        Some(spool) => app.layer(middleware::from_fn_with_state(
+            Arc::new(s3::S3::new(spool.clone())),
+            s3::serve,
+        )),
+    };
+
+    let app = match args.max_body_size {
+        None => app,
+        Some(limit) => {
+            app.layer(DefaultBodyLimit::max(limit))
+                .layer(middleware::from_fn_with_state(
+                    body::BodyLimit(limit),
+                    body::limit,
+                ))
+        }
+    };
+
+    let app = match args.body_read_timeout {
+        None => app,
+        Some(timeout) => app.layer(middleware::from_fn_with_state(
+            body::ReadTimeout(timeout),
+            body::read_timeout,
+        )),
+    };
+
+    let header_limits = header_limits::HeaderLimits {
+        max_bytes: args.max_header_bytes,
+        max_count: args.max_header_count,
+    };
+
+    let app = if !header_limits.is_enabled() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(
+            header_limits,
+            header_limits::enforce,
+        ))
+    };
+
+    let app = match args.max_uri_length {
+        None => app,
+        Some(max) => app.layer(middleware::from_fn_with_state(
+            max,
+            header_limits::enforce_uri_length,
+        )),
+    };
+
+    // shedding load before anything else is done with the request
+    let app = match args.max_concurrency {
+        None => app,
+        Some(max) => app.layer(middleware::from_fn_with_state(
+            concurrency::ConcurrencyLimit::new(max),
+            concurrency::limit,
+        )),
+    };
+
+    // outermost, so that every failure (including those of the layers above) is structured
+    let app = app.layer(middleware::from_fn_with_state(
+        args.request_timeout,
+        errors::structure,
+    ));
+
+    // ... bar CORS handling, so that failures are readable cross-origin (and preflights answered)
+    let cors = cors::CorsSettings {
+        origins: args.cors_allow_origins.clone(),
+        methods: args.cors_allow_methods.clone(),
+        headers: args.cors_allow_headers.clone(),
+        credentials: args.cors_allow_credentials,
+        max_age: args.cors_max_age,
+    };
+
+    let app = match cors.layer()? {
+        None => app,
+        Some(layer) => app.layer(layer),
+    };
+
+    // ... and the access log, so that it records the responses actually sent
+    let app = match args.access_log.clone() {
+        None => app,
+        Some(path) => app.layer(middleware::from_fn_with_state(
+            Arc::new(access_log::AccessLog::open(
+                path,
+                args.access_log_format,
+                access_log::Rotation {
+                    max_size: args.access_log_max_size.map(|size| size as u64),
+                    every: args.access_log_rotate_every,
+                },
+            )?),
+            access_log::record,
+        )),
+    };
+
+    // ... and tracing, so that the spans cover the whole of each request
+    let app = if args.otlp_endpoint.is_none() {
+        app
+    } else {
+        app.layer(middleware::from_fn(otel::trace))
+    };
+
+    // ... and traffic accounting, so that it counts the bytes actually sent
+    let app = match traffic {
+        None => app,
+        Some(accounts) => app.layer(middleware::from_fn_with_state(accounts, traffic::account)),
+    };
+
+    // ... and client identification, so that every layer sees the real client
+    let app = if !args.proxy_protocol && args.trusted_proxies.is_empty() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(
+            client_ip::TrustedProxies(args.trusted_proxies.clone().into()),
+            client_ip::identify,
+        ))
+    };
+
+    // ... and the request ID, so that it's on every log line and span
+    let app = app.layer(middleware::from_fn(request_id::assign));
+
+    if let Some(port) = args.tcp_port {
+        let listener = l4::bind_tcp(format!("{}:{port}", args.host).parse()?).await?;
+        tokio::spawn(l4::serve_tcp(listener));
+    }
+
+    if let Some(port) = args.udp_port {
+        let socket = l4::bind_udp(format!("{}:{port}", args.host).parse()?).await?;
+        tokio::spawn(l4::serve_udp(socket));
+    }
+
+    let _advertisement = if !args.mdns {
+        None
+    } else {
+        Some(mdns::Advertisement::new(
+            args.mdns_name.as_deref(),
+            u16::try_from(args.port)?,
+            args.tls_key.is_some() && args.tls_cert.is_some(),
+        )?)
+    };
+
+    let tls_files = match (args.tls_key.as_ref(), args.tls_cert.as_ref()) {
+        (Some(key), Some(cert)) => Some(tls::TlsFiles {
+            cert: cert.clone(),
+            key: key.clone(),
+        }),
+        _ => {
+            if config.hosts.iter().any(|host| host.tls.is_some()) {
+                tracing::warn!("Ignoring virtual host certificates, as TLS is not enabled");
+            }
+
+            None
+        }
+    };
+
+    let tls_hosts = config
+        .hosts
+        .iter()
+        .filter_map(|host| Some((host.matcher(), host.tls.clone()?)))
+        .map(|(matcher, files)| Ok((matcher?, files)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let client_auth = args.tls_client_ca.clone().map(|ca| tls::ClientAuth {
+        ca,
+        optional: args.tls_client_optional,
+    });
+
+    if client_auth.is_some() && tls_files.is_none() {
+        anyhow::bail!("Client certificates require TLS (`--tls-key` and `--tls-cert`)");
+    }
+
+    let tls_config = tls_files
+        .as_ref()
+        .map(|files| tls::server_config(files, tls_hosts.clone(), client_auth.as_ref()))
+        .transpose()?
+        .map(|config| RustlsConfig::from_config(Arc::new(config)));
+
+    let metrics_tls_config = match tls_files.as_ref() {
+        Some(files) if args.metrics && args.metrics_use_tls => {
+            Some(RustlsConfig::from_pem_file(&files.cert, &files.key).await?)
+        }
+        _ => None,
+    };
+
+    let mut reloader = tls_files
+        .clone()
+        .zip(tls_config.clone())
+        .map(|(files, config)| {
+            tls::Reloader::new(files, tls_hosts.clone(), client_auth.clone(), config)
+        })
+        .map(|reloader| match metrics_tls_config.clone() {
+            Some(config) => reloader.with_metrics(config),
+            None => reloader,
+        });
+
+    let app = match (args.http3, tls_files.as_ref()) {
+        (false, _) => app,
+        (true, None) => anyhow::bail!("HTTP/3 requires TLS (`--tls-key` and `--tls-cert`)"),
+        (true, Some(files)) => {
+            let quic = tls::quic_config(files, tls_hosts, client_auth.as_ref())?;
+            let addr = format!("{}:{}", args.host, args.port).parse::<SocketAddr>()?;
+
+            let app = app.layer(middleware::from_fn_with_state(
+                http3::alt_svc(addr.port()),
+                http3::advertise,
+            ));
+
+            let endpoint = http3::bind(addr, quic)?;
+
+            reloader = reloader.map(|reloader| reloader.with_quic(endpoint.clone()));
+
+            tokio::spawn(http3::serve(endpoint, app.clone()));
+
+            app
+        }
+    };
+
+    let registration = match args.consul_addr.as_ref() {
+        None => None,
+        Some(addr) => Some(
+            consul::Registration::register(
+                consul::ConsulSettings {
+                    addr: addr.clone(),
+                    token: args.consul_token.clone(),
+                    service_name: args.service_name.clone(),
+                    service_address: args.service_address.clone().unwrap_or_else(|| {
+                        gethostname::gethostname().to_string_lossy().into_owned()
+                    }),
+                    check_interval: args.consul_check_interval,
+                },
+                u16::try_from(args.port)?,
+                args.tls_key.is_some() && args.tls_cert.is_some(),
+            )
+            .await?,
+        ),
+    };
+
+    let inherited = activation::Inherited::adopt(args.inherit_fd)?;
+
+    tokio::spawn(shutdown.clone().on_signal());
+
+    if let Some(reloader) = reloader {
+        tokio::spawn(reloader.watch(args.tls_reload_interval));
+    }
+
+    let mut listeners = args
+        .listener
+        .iter()
+        .map(|listener| {
+            let (host, tls_config, handle) =
+                (args.host.clone(), tls_config.clone(), shutdown.handle());
+            let app = app.clone().layer(Extension(listener.profile));
+            let port = usize::from(listener.port);
+
+            tokio::spawn(async move {
+                serve_app(&host, port, None, tls_config, conn_options, app, handle).await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if let (Some(port), Some(admin)) = (args.admin_port, logging_admin.clone()) {
+        let (host, tls_config, handle) = (
+            args.host.clone(),
+            metrics_tls_config.clone(),
+            shutdown.handle(),
+        );
+
+        listeners.push(tokio::spawn(async move {
+            serve_auxiliary(
+                "the admin API",
+                &host,
+                port,
+                None,
+                tls_config,
+                admin,
+                handle,
+            )
+            .await
+        }));
+    }
+
+    let app = app.layer(Extension(args.profile));
+
+    #[cfg(windows)]
+    if let Some(path) = args.pipe.clone() {
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = crate::pipe::serve(path, app).await {
+                tracing::error!("Named pipe listener failed: {error}");
+            }
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(port) = args.vsock_port {
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = crate::vsock::serve(port, app).await {
+                tracing::error!("Vsock listener failed: {error}");
+            }
+        });
+    }
+
+    let mut served = if !args.metrics {
+        serve_app(
+            &args.host,
+            args.port,
+            inherited.echo,
+            tls_config.clone(),
+            conn_options,
+            app,
+            shutdown.handle(),
+        )
+        .await
+    } else {
+        let mut labels = kubernetes
+            .as_ref()
+            .map(kube::KubeMetadata::labels)
+            .unwrap_or_default();
+
+        // the last value given for a label is the one that counts
+        for (name, value) in args.metrics_labels.iter().cloned() {
+            labels.retain(|(label, _)| *label != name);
+            labels.push((name, value));
+        }
+
+        let metrics_app = metrics::router(labels, &args.metrics_buckets)?;
+
+        let metrics_app = match logging_admin {
+            Some(admin) if args.admin_port.is_none() => metrics_app.merge(admin),
+            _ => metrics_app,
+        };
+
+        let metrics_app = match args.metrics_auth.clone() {
+            None => metrics_app,
+            Some(auth) => metrics_app.layer(middleware::from_fn_with_state(
+                Arc::new(auth),
+                admin::require_credentials,
+            )),
+        };
+
+        let (echo_server, metrics_server) = tokio::join!(
+            serve_app(
+                &args.host,
+                args.port,
+                inherited.echo,
+                tls_config,
+                conn_options,
+                app,
+                shutdown.handle(),
+            ),
+            serve_auxiliary(
+                "Prometheus metrics",
+                &args.host,
+                args.metrics_port,
+                inherited.metrics,
+                metrics_tls_config,
+                metrics_app,
+                shutdown.handle(),
+            )
+        );
+        echo_server.and(metrics_server)
+    };
+
+    for listener in listeners {
+        served = served.and(listener.await?);
+    }
+
+    if let Some(registration) = registration {
+        if let Err(error) = registration.deregister().await {
+            tracing::warn!("Failed to deregister from Consul: {error}");
+        }
+    }
+
+    // nothing is left in flight, so whatever is still pending can be written out
+    if let Some(collapser) = collapser {
+        collapser.flush();
+    }
+
+    tracing::info!(
+        "`echo-rs` server stopped after serving {} request(s)",
+        counters.total()
+    );
+
+    if args.otlp_endpoint.is_some() {
+        tokio::task::spawn_blocking(otel::shutdown).await?;
+    }
+
+    served
+}
@@ -0,0 +1,181 @@
+// Client Identification Behind Proxies
+
+// Standard Library Imports
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+// Third Party Imports
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+
+// Crate-Level Imports
+use crate::proxy_protocol::ProxiedClient;
+
+/// An IP network, e.g. `10.0.0.0/8` (or, without a prefix length, a single address)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = value
+            .trim()
+            .split_once('/')
+            .map_or((value.trim(), None), |(address, prefix)| {
+                (address, Some(prefix))
+            });
+
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|error| format!("invalid network address {address:?}: {error}"))?;
+
+        let max = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            None => max,
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| format!("invalid prefix length {prefix:?} (must be 0-{max})"))?,
+        };
+
+        Ok(Self { network, prefix })
+    }
+}
+
+impl Cidr {
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        let mask = |bits: u32| {
+            u128::MAX
+                .checked_shl(bits - u32::from(self.prefix))
+                .unwrap_or(0)
+        };
+
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = mask(32) as u32;
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = mask(128);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The proxies whose `Forwarded` / `X-Forwarded-For` headers are believed
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TrustedProxies(pub(crate) Arc<[Cidr]>);
+
+impl TrustedProxies {
+    fn trust(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+/// An address as it's given by a forwarding header, e.g. `192.0.2.1`,
+/// `192.0.2.1:4711`, or `[2001:db8::17]:4711` (with port 0 if none is given)
+fn forwarded_address(value: &str) -> Option<SocketAddr> {
+    let value = value.trim().trim_matches('"');
+
+    value.parse::<SocketAddr>().ok().or_else(|| {
+        value
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, 0))
+    })
+}
+
+/// The addresses the request was forwarded for, nearest the client first, per
+/// its RFC 7239 `Forwarded` header (or else its `X-Forwarded-For` header)
+fn forwarded_chain(headers: &HeaderMap) -> Vec<SocketAddr> {
+    let values = |name: header::HeaderName| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::to_owned)
+            .collect::<Vec<String>>()
+    };
+
+    let forwarded = values(header::FORWARDED)
+        .iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| forwarded_address(value))?
+            })
+        })
+        .collect::<Vec<SocketAddr>>();
+
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    values(header::HeaderName::from_static("x-forwarded-for"))
+        .iter()
+        .filter_map(|value| forwarded_address(value))
+        .collect()
+}
+
+/// Identify the client a request really came from, i.e. the address relayed
+/// by the connection's PROXY protocol header (if any), or else its peer, or
+/// (if that's a trusted proxy) the nearest untrusted address it was forwarded
+/// for, and have every later layer and handler see that as its address
+#[tracing::instrument(skip_all)]
+pub(crate) async fn identify<B>(
+    State(trusted): State<TrustedProxies>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ProxiedClient>()
+        .map(|ProxiedClient(client)| *client)
+        .or_else(|| {
+            req.extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| *peer)
+        });
+
+    let Some(mut client) = peer else {
+        return next.run(req).await;
+    };
+
+    if trusted.trust(client.ip()) {
+        let chain = forwarded_chain(req.headers());
+
+        // walked back from the nearest hop, through every trusted proxy
+        client = chain
+            .iter()
+            .rev()
+            .find(|hop| !trusted.trust(hop.ip()))
+            .or(chain.first())
+            .copied()
+            .unwrap_or(client);
+    }
+
+    tracing::debug!("Identified client {client}");
+    req.extensions_mut().insert(ConnectInfo(client));
+
+    next.run(req).await
+}
@@ -0,0 +1,158 @@
+// Injectable Time Source
+
+// Standard Library Imports
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+// Third Party Imports
+use axum::{
+    extract::Json,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+};
+
+/// Where the server's idea of the current (wall-clock) time comes from
+pub(crate) trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Whether the clock stands still
+    fn is_frozen(&self) -> bool {
+        false
+    }
+}
+
+/// The operating system's clock
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock stopped at a fixed instant, so that anything timestamped
+/// (echo payloads, logs, tokens, ...) is reproducible byte-for-byte
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FrozenClock(pub(crate) SystemTime);
+
+impl Clock for FrozenClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+
+    fn is_frozen(&self) -> bool {
+        true
+    }
+}
+
+/// The clock in use, or the system's if none has been installed
+static CLOCK: RwLock<Option<Arc<dyn Clock>>> = RwLock::new(None);
+
+/// Tell the time by the given clock from now on
+pub(crate) fn install(clock: impl Clock + 'static) {
+    *CLOCK.write().unwrap() = Some(Arc::new(clock));
+}
+
+fn current() -> Arc<dyn Clock> {
+    CLOCK
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| Arc::new(SystemClock))
+}
+
+/// The current time, according to the installed clock
+pub(crate) fn now() -> SystemTime {
+    current().now()
+}
+
+/// Timestamps text log lines by the installed clock
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct LogTimer;
+
+impl FormatTime for LogTimer {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        write!(w, "{}", humantime::format_rfc3339_micros(now()))
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct TimeReport {
+    now: String,
+    frozen: bool,
+}
+
+impl TimeReport {
+    fn current() -> Self {
+        let clock = current();
+
+        Self {
+            now: humantime::format_rfc3339_nanos(clock.now()).to_string(),
+            frozen: clock.is_frozen(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct FreezeRequest {
+    time: String,
+}
+
+#[tracing::instrument]
+pub(crate) fn router(admin_token: Option<AdminToken>) -> Router {
+    let mut router = Router::new().route("/_time", routing::get(report));
+
+    if let Some(token) = admin_token {
+        router = router.merge(
+            Router::new()
+                .route("/_time", routing::put(freeze).delete(unfreeze))
+                .route_layer(middleware::from_fn_with_state(token, admin::require_token)),
+        );
+    }
+
+    router
+}
+
+/// The server's current time, and whether it's frozen
+#[tracing::instrument(skip_all)]
+async fn report() -> Json<TimeReport> {
+    Json(TimeReport::current())
+}
+
+/// Stop the clock at the given (RFC 3339) time
+#[tracing::instrument(skip_all)]
+async fn freeze(Json(request): Json<FreezeRequest>) -> Response {
+    match humantime::parse_rfc3339_weak(&request.time) {
+        Ok(time) => {
+            install(FrozenClock(time));
+            tracing::info!("Clock frozen at {}", request.time);
+            Json(TimeReport::current()).into_response()
+        }
+        Err(error) => Failure::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid time {:?}: {error}", request.time),
+        )
+        .into_response(),
+    }
+}
+
+/// Go back to telling the time by the system's clock
+#[tracing::instrument(skip_all)]
+async fn unfreeze() -> Json<TimeReport> {
+    install(SystemClock);
+    tracing::info!("Clock unfrozen");
+    Json(TimeReport::current())
+}
@@ -0,0 +1,108 @@
+// Duplicate Request Log Collapsing
+
+// Standard Library Imports
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+// Third Party Imports
+use tokio::time::MissedTickBehavior;
+
+/// What makes requests duplicates of each other
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct RequestKey {
+    method: String,
+    path: String,
+    client: IpAddr,
+}
+
+/// A run of identical consecutive requests
+#[derive(Debug)]
+struct Run {
+    key: RequestKey,
+    /// Requests in the run not yet accounted for in the log
+    suppressed: u64,
+}
+
+impl Run {
+    /// Log how many requests were suppressed since the run (or its last summary) began
+    fn summarize(&mut self) {
+        if self.suppressed == 0 {
+            return;
+        }
+
+        let RequestKey {
+            method,
+            path,
+            client,
+        } = &self.key;
+
+        tracing::info!(
+            repeated = self.suppressed,
+            "{method} {path} from {client} repeated {} more time(s)",
+            self.suppressed
+        );
+
+        metrics::counter!("log_collapsed_requests_total", self.suppressed);
+
+        self.suppressed = 0;
+    }
+}
+
+/// Collapses identical consecutive requests (same method, path, and client)
+/// into a single summarized log line with a count
+#[derive(Debug, Default)]
+pub(crate) struct LogCollapser {
+    run: Mutex<Option<Run>>,
+}
+
+impl LogCollapser {
+    /// Whether the request should be logged, which it shouldn't if it's
+    /// a repeat of the one before it
+    pub(crate) fn admit(&self, method: &str, path: &str, client: IpAddr) -> bool {
+        let key = RequestKey {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            client,
+        };
+
+        let mut run = self.run.lock().unwrap();
+
+        match run.as_mut() {
+            Some(run) if run.key == key => {
+                run.suppressed += 1;
+                false
+            }
+            previous => {
+                if let Some(previous) = previous {
+                    previous.summarize();
+                }
+
+                *run = Some(Run { key, suppressed: 0 });
+                true
+            }
+        }
+    }
+
+    /// Summarize the current run right away, e.g. before exiting
+    pub(crate) fn flush(&self) {
+        if let Some(run) = self.run.lock().unwrap().as_mut() {
+            run.summarize();
+        }
+    }
+
+    /// Summarize the current run every `window`, so long runs still show up
+    /// in the log while they last, forever
+    pub(crate) async fn summarize_every(self: Arc<Self>, window: Duration) {
+        let mut ticker = tokio::time::interval(window);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            self.flush();
+        }
+    }
+}
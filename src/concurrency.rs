@@ -0,0 +1,57 @@
+// Concurrency Limiting
+
+// Standard Library Imports
+use std::{num::NonZeroUsize, sync::Arc};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+// Crate-Level Imports
+use crate::errors::Failure;
+
+/// The most requests handled at once
+#[derive(Clone, Debug)]
+pub(crate) struct ConcurrencyLimit {
+    max: NonZeroUsize,
+    permits: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    pub(crate) fn new(max: NonZeroUsize) -> Self {
+        Self {
+            max,
+            permits: Arc::new(Semaphore::new(max.get())),
+        }
+    }
+}
+
+/// Answer with 503 (rather than queueing) any request arriving while the limit is reached
+#[tracing::instrument(skip_all)]
+pub(crate) async fn limit<B>(
+    State(limit): State<ConcurrencyLimit>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Ok(_permit) = limit.permits.try_acquire() else {
+        tracing::debug!(
+            "Rejecting {} {}: concurrency limit reached",
+            req.method(),
+            req.uri()
+        );
+        metrics::increment_counter!("concurrency_limit_rejected_total");
+
+        return Failure::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("more than {} requests in flight", limit.max),
+        )
+        .into_response();
+    };
+
+    next.run(req).await
+}
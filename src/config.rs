@@ -0,0 +1,385 @@
+// Configuration File
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+// Third Party Imports
+use clap::{parser::ValueSource, ArgAction};
+use serde_yaml::{Number, Value};
+use tokio::{signal, time::MissedTickBehavior};
+
+// Crate-Level Imports
+use crate::{
+    routes::{RouteRuleSpec, VirtualHostSpec},
+    stubs,
+    transform::TransformSpec,
+};
+
+/// Format a migrated configuration file is written in
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub(crate) enum ConfigFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+/// The contents of an `echo-rs` configuration file (YAML, JSON, or TOML)
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Values for command line options, by their (long) name, for any
+    /// option that's given neither on the command line nor in the environment
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) settings: BTreeMap<String, Value>,
+    /// Behaviors attached to path patterns, first match wins
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) routes: Vec<RouteRuleSpec>,
+    /// Per-`Host` behaviors (and certificates), first match wins
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) hosts: Vec<VirtualHostSpec>,
+    /// Transformations applied to proxied requests, every match in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) transforms: Vec<TransformSpec>,
+}
+
+impl Config {
+    /// Read and parse the configuration file at the given path
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .and_then(|contents| interpolate(&contents))
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+        Self::parse(path, &contents)
+    }
+
+    fn parse(path: &Path, contents: &str) -> anyhow::Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(contents).map_err(anyhow::Error::from),
+            _ => serde_yaml::from_str(contents).map_err(anyhow::Error::from),
+        }
+        .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))
+    }
+
+    /// Export the settings for the given options as the environment variables
+    /// they're read from, clearing those of any that are no longer set
+    pub(crate) fn export(&self, command: &clap::Command, options: &Options) -> anyhow::Result<()> {
+        if let Some(unknown) = self.settings.keys().find(|name| {
+            !command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(name))
+        }) {
+            anyhow::bail!("unknown setting {unknown:?}");
+        }
+
+        let exported = options
+            .iter()
+            .map(|(name, var)| {
+                let value = self.settings.get(name).map(|value| setting(name, value));
+                Ok((var, value.transpose()?))
+            })
+            .collect::<anyhow::Result<Vec<(&String, Option<String>)>>>()?;
+
+        for (var, value) in exported {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Command line options (by long name) left for the configuration file to
+/// set, and the environment variables their values are read from
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Options(BTreeMap<String, String>);
+
+impl Options {
+    /// Every option given neither on the command line nor in the environment
+    pub(crate) fn unset(command: &clap::Command, matches: &clap::ArgMatches) -> Self {
+        Self(
+            command
+                .get_arguments()
+                .filter(|arg| {
+                    matches!(
+                        matches.value_source(arg.get_id().as_str()),
+                        None | Some(ValueSource::DefaultValue)
+                    )
+                })
+                .filter_map(|arg| {
+                    Some((
+                        arg.get_long()?.to_owned(),
+                        arg.get_env()?.to_str()?.to_owned(),
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+/// Convert an invocation (the options it gave on the command line or in the environment,
+/// plus the configuration file it names, if any) and any standalone rules (i.e. stub)
+/// files into a single configuration file, leaving `${VAR}` substitutions unexpanded
+pub(crate) fn migrate(
+    command: &clap::Command,
+    matches: &clap::ArgMatches,
+    rules: &[PathBuf],
+    format: ConfigFormat,
+) -> anyhow::Result<String> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path)
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))
+    };
+
+    let mut config = match matches.get_one::<PathBuf>("config") {
+        Some(path) => Config::parse(path, &read(path)?)?,
+        None => Config::default(),
+    };
+
+    // settings given on the command line (or in the environment) win over the file's
+    for arg in command.get_arguments() {
+        let (id, Some(name)) = (arg.get_id().as_str(), arg.get_long()) else {
+            continue;
+        };
+
+        if name == "config"
+            || !matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        {
+            continue;
+        }
+
+        let values = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok_or_else(|| anyhow::anyhow!("option `--{name}`: value isn't valid UTF-8"))
+                    .map(scalar)
+            })
+            .collect::<anyhow::Result<Vec<Value>>>()?;
+
+        let repeatable =
+            matches!(arg.get_action(), ArgAction::Append) || arg.get_value_delimiter().is_some();
+
+        // settings given on the command line (or in the environment) win over the file's
+        let value = match (repeatable, values.as_slice()) {
+            (false, [value]) => value.clone(),
+            _ => Value::Sequence(values),
+        };
+
+        config.settings.insert(name.to_owned(), value);
+    }
+
+    for path in rules {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.routes.extend(stubs::parse(path, &read(path)?, dir)?);
+    }
+
+    Ok(match format {
+        ConfigFormat::Yaml => serde_yaml::to_string(&config)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(&config)? + "\n",
+        ConfigFormat::Toml => toml::to_string_pretty(&config)?,
+    })
+}
+
+/// A command line value as the YAML scalar it'd be written as in a configuration file
+fn scalar(value: &str) -> Value {
+    if let Ok(value) = value.parse::<bool>() {
+        return Value::Bool(value);
+    }
+
+    // only where that's exactly how the value was written (e.g. not `010`)
+    let number = value
+        .parse::<u64>()
+        .map(Number::from)
+        .or_else(|_| value.parse::<i64>().map(Number::from))
+        .or_else(|_| value.parse::<f64>().map(Number::from))
+        .ok()
+        .filter(|number| number.to_string() == value);
+
+    match number {
+        Some(number) => Value::Number(number),
+        None => Value::String(value.to_owned()),
+    }
+}
+
+/// Substitute environment variables into a configuration (or stub) file's
+/// contents, where `${VAR}` is the variable's value (and an error if it's
+/// unset), `${VAR:-default}` is the default if it's unset or empty, and
+/// `${VAR-default}` the default only if it's unset, while `$${` is a literal `${`
+pub(crate) fn interpolate(contents: &str) -> std::io::Result<String> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let mut interpolated = String::with_capacity(contents.len());
+    let mut rest = contents;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            interpolated.push_str(&rest[..start - 1]);
+            interpolated.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        interpolated.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| invalid(format!("unterminated `${{` at {:?}", &rest[start..])))?;
+
+        let expression = &rest[start + 2..end];
+
+        let (name, default) = match expression.find([':', '-']) {
+            None => (expression, None),
+            Some(split) => match expression[split..].strip_prefix(":-") {
+                Some(default) => (&expression[..split], Some((default, true))),
+                None => match expression[split..].strip_prefix('-') {
+                    Some(default) => (&expression[..split], Some((default, false))),
+                    None => {
+                        return Err(invalid(format!("invalid substitution `${{{expression}}}`")))
+                    }
+                },
+            },
+        };
+
+        let name = name.trim();
+
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|char| char == '_' || char.is_ascii_alphanumeric())
+        {
+            return Err(invalid(format!(
+                "invalid variable name in `${{{expression}}}`"
+            )));
+        }
+
+        let value = match (env::var(name).ok(), default) {
+            (Some(value), Some((default, true))) if value.is_empty() => default.to_owned(),
+            (Some(value), _) => value,
+            (None, Some((default, _))) => default.to_owned(),
+            (None, None) => {
+                return Err(invalid(format!(
+                    "environment variable {name:?} is not set (use `${{{name}:-default}}` to give a default)"
+                )))
+            }
+        };
+
+        interpolated.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+
+    interpolated.push_str(rest);
+
+    Ok(interpolated)
+}
+
+/// Render a setting's value the way it'd be given on the command line
+fn setting(name: &str, value: &Value) -> anyhow::Result<String> {
+    match value {
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::String(value) => Ok(value.clone()),
+        Value::Sequence(values) => Ok(values
+            .iter()
+            .map(|value| setting(name, value))
+            .collect::<anyhow::Result<Vec<String>>>()?
+            .join(",")),
+        _ => anyhow::bail!("setting {name:?}: expected a scalar, or a list of them"),
+    }
+}
+
+/// Re-reads the configuration file whenever it changes (or the process gets
+/// `SIGHUP`), handing each successfully loaded revision over to be applied
+pub(crate) struct Reloader<F> {
+    path: PathBuf,
+    apply: F,
+}
+
+impl<F> Reloader<F>
+where
+    F: FnMut(Config) -> anyhow::Result<()>,
+{
+    pub(crate) fn new(path: PathBuf, apply: F) -> Self {
+        Self { path, apply }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// Check for changes every `interval`, reloading when there are any, forever
+    pub(crate) async fn watch(mut self, interval: Duration) {
+        #[cfg(unix)]
+        let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+            .map_err(|error| tracing::warn!("Unable to listen for SIGHUP: {error}"))
+            .ok();
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        let mut modified = self.modified();
+
+        loop {
+            #[cfg(unix)]
+            let hungup = async {
+                match hangup.as_mut() {
+                    Some(hangup) => hangup.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            #[cfg(not(unix))]
+            let hungup = std::future::pending::<Option<()>>();
+
+            let reason = tokio::select! {
+                _ = ticker.tick() => {
+                    let current = self.modified();
+
+                    if current == modified {
+                        continue;
+                    }
+
+                    modified = current;
+                    "configuration file changed"
+                }
+                _ = hungup => "SIGHUP",
+            };
+
+            match Config::load(&self.path).and_then(|config| (self.apply)(config)) {
+                Ok(()) => {
+                    tracing::info!("Reloaded {} ({reason})", self.path.display());
+                    metrics::increment_counter!("config_reloads_total", "result" => "success");
+                }
+                Err(error) => {
+                    tracing::warn!("Keeping current configuration, reload failed: {error}");
+                    metrics::increment_counter!("config_reloads_total", "result" => "failure");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,683 @@
+// Connection-Level Instrumentation
+
+// Standard Library Imports
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU64,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::Bytes,
+    http::{header, HeaderValue, Request, Response, Version},
+};
+use axum_server::accept::Accept;
+use http_body::{Body as HttpBody, SizeHint};
+use hyper::server::conn::AddrStream;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{Instant, Sleep},
+};
+use tokio_rustls::server::TlsStream;
+use tower::Service;
+
+// Crate-Level Imports
+use crate::{
+    proxy_protocol::{ProxiedClient, ProxiedStream},
+    tls::ClientCertificate,
+};
+
+/// Upper bound on the size of a single captured request head
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// The HTTP/2 connection preface, which disables head capture
+const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Source of connection identifiers, unique for the lifetime of the process
+static CONNECTION_IDS: AtomicU64 = AtomicU64::new(1);
+
+/// The raw request head (request line + headers) exactly as it was read off the wire
+#[derive(Clone, Debug)]
+pub(crate) struct RawHead(pub(crate) Bytes);
+
+/// The connection a request arrived on, and its position in that connection's lifetime
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct ConnectionInfo {
+    /// Process-unique identifier of the connection
+    pub(crate) id: u64,
+    /// 1-based sequence number of the request on the connection
+    pub(crate) request: u64,
+    /// The application protocol negotiated via TLS ALPN (e.g. `h2`), if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) alpn_protocol: Option<String>,
+    #[serde(flatten)]
+    pub(crate) socket: SocketInfo,
+}
+
+/// Low-level details of the socket a connection was accepted on
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct SocketInfo {
+    /// The local address the connection arrived on (i.e. which of the host's addresses it was made to)
+    pub(crate) local_address: SocketAddr,
+    /// `ipv4` or `ipv6`, with IPv4 clients of dual-stack listeners counted as the former
+    pub(crate) family: &'static str,
+    /// The IPv6 scope (i.e. interface index) of link-local addresses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) scope_id: Option<u32>,
+    /// The IPv6 flow label the client's address was reported with, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) flow_label: Option<u32>,
+    /// Whether the listener was a TLS one (rather than plaintext)
+    pub(crate) tls: bool,
+}
+
+impl SocketInfo {
+    fn of(local: SocketAddr, peer: SocketAddr, tls: bool) -> Self {
+        let (scope_id, flow_label) = match (local, peer) {
+            (SocketAddr::V6(local), SocketAddr::V6(peer)) => (
+                Some(local.scope_id()).filter(|scope| *scope != 0),
+                Some(peer.flowinfo() & 0x000f_ffff).filter(|label| *label != 0),
+            ),
+            _ => (None, None),
+        };
+
+        Self {
+            local_address: local,
+            family: match local.ip().to_canonical() {
+                IpAddr::V4(_) => "ipv4",
+                IpAddr::V6(_) => "ipv6",
+            },
+            scope_id,
+            flow_label,
+            tls,
+        }
+    }
+}
+
+/// Streams that may be TLS ones (and so have been authenticated with
+/// a client certificate, and had an application protocol negotiated)
+pub(crate) trait PeerCertificate {
+    fn peer_certificate(&self) -> Option<ClientCertificate>;
+
+    fn alpn_protocol(&self) -> Option<String>;
+
+    fn is_tls(&self) -> bool;
+}
+
+impl PeerCertificate for ProxiedStream {
+    fn peer_certificate(&self) -> Option<ClientCertificate> {
+        None
+    }
+
+    fn alpn_protocol(&self) -> Option<String> {
+        None
+    }
+
+    fn is_tls(&self) -> bool {
+        false
+    }
+}
+
+impl<S> PeerCertificate for TlsStream<S> {
+    fn is_tls(&self) -> bool {
+        true
+    }
+
+    fn peer_certificate(&self) -> Option<ClientCertificate> {
+        ClientCertificate::from_chain(self.get_ref().1.peer_certificates())
+    }
+
+    fn alpn_protocol(&self) -> Option<String> {
+        self.get_ref()
+            .1
+            .alpn_protocol()
+            .map(|protocol| String::from_utf8_lossy(protocol).into_owned())
+    }
+}
+
+/// Request heads captured from a connection, waiting to be claimed by their requests
+type HeadQueue = Arc<Mutex<VecDeque<Bytes>>>;
+
+/// Connection-level behavior toggles
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ConnOptions {
+    /// Capture each request's raw head as it comes off the wire
+    pub(crate) raw_dump: bool,
+    /// Close HTTP/1.x connections after every N-th response
+    pub(crate) close_every: Option<NonZeroU64>,
+    /// Expect connections to start with a PROXY protocol header
+    pub(crate) proxy_protocol: bool,
+    /// Close connections that have waited this long for their next request
+    pub(crate) keep_alive_timeout: Option<Duration>,
+    /// Close HTTP/1.x connections that take longer than this to send a request head
+    pub(crate) header_read_timeout: Option<Duration>,
+}
+
+/// Acceptor wrapping another [`Accept`] implementation with
+/// connection-level instrumentation
+#[derive(Clone, Debug)]
+pub(crate) struct EchoAcceptor<A> {
+    inner: A,
+    options: ConnOptions,
+}
+
+impl<A> EchoAcceptor<A> {
+    pub(crate) fn new(inner: A, options: ConnOptions) -> Self {
+        Self { inner, options }
+    }
+}
+
+impl<A, S> Accept<AddrStream, S> for EchoAcceptor<A>
+where
+    A: Accept<ProxiedStream, S> + Clone + Send + 'static,
+    A::Stream: PeerCertificate,
+    A::Future: Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = WireTap<A::Stream>;
+    type Service = ConnService<A::Service>;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, stream: AddrStream, service: S) -> Self::Future {
+        let heads = self.options.raw_dump.then(HeadQueue::default);
+        let (close_every, proxy_protocol) = (self.options.close_every, self.options.proxy_protocol);
+        let keep_alive_timeout = self.options.keep_alive_timeout;
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            let (local, peer) = (stream.local_addr(), stream.remote_addr());
+
+            let (stream, proxied) = match proxy_protocol {
+                true => ProxiedStream::accept(stream).await?,
+                false => (ProxiedStream::direct(stream), None),
+            };
+
+            let (stream, service) = inner.accept(stream, service).await?;
+            let client_certificate = stream.peer_certificate();
+            let alpn_protocol = stream.alpn_protocol();
+            let socket = SocketInfo::of(local, peer, stream.is_tls());
+            let outstanding = Arc::<AtomicU64>::default();
+
+            Ok((
+                WireTap::new(
+                    stream,
+                    heads.clone(),
+                    keep_alive_timeout
+                        .map(|timeout| IdleTimeout::new(timeout, outstanding.clone())),
+                ),
+                ConnService {
+                    inner: service,
+                    id: CONNECTION_IDS.fetch_add(1, Ordering::Relaxed),
+                    served: Arc::default(),
+                    outstanding,
+                    heads,
+                    close_every,
+                    client_certificate,
+                    alpn_protocol,
+                    socket,
+                    proxied,
+                },
+            ))
+        })
+    }
+}
+
+/// Service wrapper attaching per-connection details to each request
+#[derive(Clone, Debug)]
+pub(crate) struct ConnService<S> {
+    inner: S,
+    id: u64,
+    served: Arc<AtomicU64>,
+    /// How many of the connection's requests are yet to be answered in full
+    outstanding: Arc<AtomicU64>,
+    heads: Option<HeadQueue>,
+    close_every: Option<NonZeroU64>,
+    client_certificate: Option<ClientCertificate>,
+    alpn_protocol: Option<String>,
+    socket: SocketInfo,
+    proxied: Option<SocketAddr>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for ConnService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Send + 'static,
+{
+    type Response = Response<Sending<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let request = self.served.fetch_add(1, Ordering::Relaxed) + 1;
+
+        req.extensions_mut().insert(ConnectionInfo {
+            id: self.id,
+            request,
+            alpn_protocol: self.alpn_protocol.clone(),
+            socket: self.socket.clone(),
+        });
+
+        // only HTTP/1.x has a notion of closing the connection after a response
+        let close = req.version() < Version::HTTP_2
+            && self
+                .close_every
+                .is_some_and(|every| request.is_multiple_of(every.get()));
+
+        if let Some(certificate) = self.client_certificate.clone() {
+            req.extensions_mut().insert(certificate);
+        }
+
+        if let Some(client) = self.proxied {
+            req.extensions_mut().insert(ProxiedClient(client));
+        }
+
+        if let Some(head) = self
+            .heads
+            .as_ref()
+            .and_then(|heads| heads.lock().unwrap().pop_front())
+        {
+            req.extensions_mut().insert(RawHead(head));
+        }
+
+        let outstanding = Outstanding::new(self.outstanding.clone());
+        let response = self.inner.call(req);
+
+        Box::pin(async move {
+            let mut response = response.await?;
+
+            if close {
+                response
+                    .headers_mut()
+                    .insert(header::CONNECTION, HeaderValue::from_static("close"));
+            }
+
+            Ok(response.map(|body| Sending {
+                inner: body,
+                _outstanding: outstanding,
+            }))
+        })
+    }
+}
+
+/// Counts a request as outstanding on its connection until it's been answered in full
+#[derive(Debug)]
+struct Outstanding(Arc<AtomicU64>);
+
+impl Outstanding {
+    fn new(count: Arc<AtomicU64>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for Outstanding {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A response body that keeps its request outstanding until it's been sent
+#[derive(Debug)]
+pub(crate) struct Sending<B> {
+    inner: B,
+    _outstanding: Outstanding,
+}
+
+impl<B: HttpBody + Unpin> HttpBody for Sending<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_data(cx)
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<hyper::HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Closes a connection once it's gone too long without a request outstanding
+#[derive(Debug)]
+struct IdleTimeout {
+    timeout: Duration,
+    outstanding: Arc<AtomicU64>,
+    deadline: Pin<Box<Sleep>>,
+}
+
+impl IdleTimeout {
+    fn new(timeout: Duration, outstanding: Arc<AtomicU64>) -> Self {
+        Self {
+            timeout,
+            outstanding,
+            deadline: Box::pin(tokio::time::sleep(timeout)),
+        }
+    }
+
+    /// Restart the countdown, the connection having just been used
+    fn touch(&mut self) {
+        self.deadline.as_mut().reset(Instant::now() + self.timeout);
+    }
+
+    /// Whether the connection has been idle for too long
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        if self.outstanding.load(Ordering::Relaxed) > 0 {
+            self.touch();
+            return false;
+        }
+
+        self.deadline.as_mut().poll(cx).is_ready()
+    }
+}
+
+/// Keeps `http_connections_open` up to date for as long as a connection is open
+#[derive(Debug)]
+struct OpenConnection;
+
+impl OpenConnection {
+    fn open() -> Self {
+        metrics::increment_gauge!("http_connections_open", 1.0);
+        Self
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        metrics::decrement_gauge!("http_connections_open", 1.0);
+    }
+}
+
+/// Stream wrapper that copies each request head out of the bytes read from it
+#[derive(Debug)]
+pub(crate) struct WireTap<S> {
+    inner: S,
+    framer: Option<HeadFramer>,
+    idle: Option<IdleTimeout>,
+    _open: OpenConnection,
+}
+
+impl<S> WireTap<S> {
+    fn new(inner: S, heads: Option<HeadQueue>, idle: Option<IdleTimeout>) -> Self {
+        Self {
+            inner,
+            framer: heads.map(HeadFramer::new),
+            idle,
+            _open: OpenConnection::open(),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for WireTap<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let this = &mut *self;
+        let polled = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let (Poll::Ready(Ok(())), Some(framer)) = (&polled, this.framer.as_mut()) {
+            if !framer.feed(&buf.filled()[filled..]) {
+                this.framer = None;
+            }
+        }
+
+        if let Some(idle) = this.idle.as_mut() {
+            if polled.is_ready() {
+                idle.touch();
+            } else if idle.poll_expired(cx) {
+                tracing::debug!(
+                    "Closing connection idle for {}",
+                    humantime::format_duration(idle.timeout)
+                );
+                metrics::increment_counter!("http_connections_idle_closed_total");
+
+                // reads as the client having hung up, which the server takes gracefully
+                this.idle = None;
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        polled
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for WireTap<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let polled = Pin::new(&mut self.inner).poll_write(cx, buf);
+
+        if let (Poll::Ready(_), Some(idle)) = (&polled, self.idle.as_mut()) {
+            idle.touch();
+        }
+
+        polled
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let polled = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+
+        if let (Poll::Ready(_), Some(idle)) = (&polled, self.idle.as_mut()) {
+            idle.touch();
+        }
+
+        polled
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Where in an HTTP/1.x message the next byte read belongs
+#[derive(Debug)]
+enum Frame {
+    Head,
+    Body(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd(u8),
+    Trailers,
+}
+
+/// Minimal HTTP/1.x message framer, just smart enough to tell
+/// request heads apart from the bodies that follow them
+#[derive(Debug)]
+struct HeadFramer {
+    frame: Frame,
+    line: Vec<u8>,
+    heads: HeadQueue,
+}
+
+impl HeadFramer {
+    fn new(heads: HeadQueue) -> Self {
+        Self {
+            frame: Frame::Head,
+            line: Vec::new(),
+            heads,
+        }
+    }
+
+    /// Consume freshly read bytes, returning `false` once the stream can no longer be framed
+    fn feed(&mut self, mut data: &[u8]) -> bool {
+        while !data.is_empty() {
+            match self.frame {
+                Frame::Head => {
+                    if self.line.is_empty() {
+                        // tolerate stray line breaks between pipelined requests
+                        let skip = data
+                            .iter()
+                            .take_while(|byte| matches!(byte, b'\r' | b'\n'))
+                            .count();
+                        data = &data[skip..];
+
+                        if data.is_empty() {
+                            break;
+                        }
+                    }
+
+                    let start = self.line.len().saturating_sub(3);
+                    self.line.extend_from_slice(data);
+
+                    if self
+                        .line
+                        .starts_with(&H2_PREFACE[..self.line.len().min(H2_PREFACE.len())])
+                    {
+                        if self.line.len() >= H2_PREFACE.len() {
+                            return false;
+                        }
+                        break;
+                    }
+
+                    let Some(end) =
+                        find(&self.line[start..], b"\r\n\r\n").map(|pos| start + pos + 4)
+                    else {
+                        return self.line.len() <= MAX_HEAD_SIZE;
+                    };
+
+                    let consumed = data.len() - (self.line.len() - end);
+                    data = &data[consumed..];
+
+                    let head = Bytes::from(std::mem::take(&mut self.line)).slice(..end);
+                    let frame = body_framing(&head);
+
+                    self.heads.lock().unwrap().push_back(head);
+
+                    match frame {
+                        Some(frame) => self.frame = frame,
+                        None => return false,
+                    }
+                }
+                Frame::Body(remaining) => {
+                    let take = remaining.min(data.len() as u64);
+                    data = &data[take as usize..];
+                    self.frame = match remaining - take {
+                        0 => Frame::Head,
+                        left => Frame::Body(left),
+                    };
+                }
+                Frame::ChunkSize | Frame::Trailers => {
+                    let Some(pos) = data.iter().position(|byte| *byte == b'\n') else {
+                        self.line.extend_from_slice(data);
+                        return self.line.len() <= MAX_HEAD_SIZE;
+                    };
+
+                    self.line.extend_from_slice(&data[..pos]);
+                    data = &data[pos + 1..];
+
+                    let line = std::mem::take(&mut self.line);
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim();
+
+                    self.frame = match self.frame {
+                        Frame::Trailers if line.is_empty() => Frame::Head,
+                        Frame::Trailers => Frame::Trailers,
+                        _ => {
+                            let size = line.split(';').next().unwrap_or_default().trim();
+
+                            match u64::from_str_radix(size, 16) {
+                                Ok(0) => Frame::Trailers,
+                                Ok(size) => Frame::ChunkData(size),
+                                Err(_) => return false,
+                            }
+                        }
+                    };
+                }
+                Frame::ChunkData(remaining) => {
+                    let take = remaining.min(data.len() as u64);
+                    data = &data[take as usize..];
+                    self.frame = match remaining - take {
+                        0 => Frame::ChunkEnd(2),
+                        left => Frame::ChunkData(left),
+                    };
+                }
+                Frame::ChunkEnd(remaining) => {
+                    let take = (remaining as usize).min(data.len());
+                    data = &data[take..];
+                    self.frame = match remaining as usize - take {
+                        0 => Frame::ChunkSize,
+                        left => Frame::ChunkEnd(left as u8),
+                    };
+                }
+            }
+        }
+
+        true
+    }
+}
+
+/// Determine how the body following the supplied head is framed, if
+/// the connection can still be framed after it at all
+fn body_framing(head: &[u8]) -> Option<Frame> {
+    let (mut length, mut chunked) = (0u64, false);
+
+    for line in String::from_utf8_lossy(head).split("\r\n").skip(1) {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => length = value.trim().parse().ok()?,
+            "transfer-encoding" => chunked = value.to_ascii_lowercase().contains("chunked"),
+            // upgraded connections stop speaking HTTP/1.x
+            "upgrade" => return None,
+            _ => {}
+        }
+    }
+
+    Some(match (chunked, length) {
+        (true, _) => Frame::ChunkSize,
+        (false, 0) => Frame::Head,
+        (false, length) => Frame::Body(length),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,127 @@
+// Consul Service Registration
+
+// Standard Library Imports
+use std::time::Duration;
+
+// Third Party Imports
+use serde_json::json;
+
+/// Header carrying the Consul ACL token
+const TOKEN_HEADER: &str = "x-consul-token";
+
+/// Settings for registering with a Consul agent
+#[derive(Clone, Debug)]
+pub(crate) struct ConsulSettings {
+    /// Base URL of the Consul agent's HTTP API, e.g. `http://127.0.0.1:8500`
+    pub(crate) addr: String,
+    /// ACL token to authenticate with, if any
+    pub(crate) token: Option<String>,
+    /// The service name to register under
+    pub(crate) service_name: String,
+    /// The address Consul (and its clients) should reach the service at
+    pub(crate) service_address: String,
+    /// How often Consul should check the service's health
+    pub(crate) check_interval: Duration,
+}
+
+/// A live Consul service registration
+#[derive(Debug)]
+pub(crate) struct Registration {
+    client: reqwest::Client,
+    settings: ConsulSettings,
+    id: String,
+}
+
+impl Registration {
+    /// Register the service (and an HTTP health check against `/healthz`) with the agent
+    pub(crate) async fn register(
+        settings: ConsulSettings,
+        port: u16,
+        tls: bool,
+    ) -> anyhow::Result<Self> {
+        let client = reqwest::Client::new();
+
+        let id = format!(
+            "{}-{}-{port}",
+            settings.service_name, settings.service_address
+        );
+
+        let health = format!(
+            "{}://{}/healthz",
+            if tls { "https" } else { "http" },
+            socket_addr(&settings.service_address, port)
+        );
+
+        // as Go's `time.ParseDuration` reads it (which `humantime`'s `1m 30s` isn't)
+        let interval = format!("{}ms", settings.check_interval.as_millis());
+
+        let payload = json!({
+            "ID": id,
+            "Name": settings.service_name,
+            "Address": settings.service_address,
+            "Port": port,
+            "Tags": ["echo-rs", if tls { "https" } else { "http" }],
+            "Meta": {"version": env!("CARGO_PKG_VERSION")},
+            "Check": {
+                "HTTP": health,
+                "Interval": interval,
+                "TLSSkipVerify": tls,
+                "DeregisterCriticalServiceAfter": "1m",
+            },
+        });
+
+        let registration = Self {
+            client,
+            settings,
+            id,
+        };
+
+        registration
+            .request("/v1/agent/service/register")
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        tracing::info!(
+            "Registered with Consul at {} as {:?}",
+            registration.settings.addr,
+            registration.id
+        );
+
+        Ok(registration)
+    }
+
+    /// Remove the service from the agent's catalog
+    pub(crate) async fn deregister(self) -> anyhow::Result<()> {
+        self.request(&format!("/v1/agent/service/deregister/{}", self.id))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        tracing::info!("Deregistered {:?} from Consul", self.id);
+
+        Ok(())
+    }
+
+    fn request(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.put(format!(
+            "{}{path}",
+            self.settings.addr.trim_end_matches('/')
+        ));
+
+        match self.settings.token.as_deref() {
+            Some(token) => request.header(TOKEN_HEADER, token),
+            None => request,
+        }
+    }
+}
+
+/// Join a host and port, bracketing IPv6 literals
+fn socket_addr(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
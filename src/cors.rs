@@ -0,0 +1,85 @@
+// Cross-Origin Resource Sharing
+
+// Standard Library Imports
+use std::time::Duration;
+
+// Third Party Imports
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+/// Which cross-origin requests browsers are told to allow
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CorsSettings {
+    /// Origins allowed (`*` for any), or none to disable CORS handling altogether
+    pub(crate) origins: Vec<String>,
+    /// Methods allowed (`*` for any), or none for whichever a preflight asks for
+    pub(crate) methods: Vec<String>,
+    /// Request headers allowed (`*` for any), or none for whichever a preflight asks for
+    pub(crate) headers: Vec<String>,
+    pub(crate) credentials: bool,
+    /// How long browsers may cache preflight responses
+    pub(crate) max_age: Option<Duration>,
+}
+
+impl CorsSettings {
+    /// Answer preflight requests (rather than echoing them), and mark
+    /// every other response as readable from the allowed origins
+    pub(crate) fn layer(&self) -> anyhow::Result<Option<CorsLayer>> {
+        if self.origins.is_empty() {
+            return Ok(None);
+        }
+
+        let any = |values: &[String]| values.iter().any(|value| value == "*");
+
+        // browsers refuse credentialed responses that allow any origin, method, or header
+        if self.credentials && (any(&self.origins) || any(&self.methods) || any(&self.headers)) {
+            anyhow::bail!("CORS credentials can't be allowed along with `*` origins, methods, or headers (list them instead)");
+        }
+
+        let origins = match any(&self.origins) {
+            true => AllowOrigin::any(),
+            false => AllowOrigin::list(
+                self.origins
+                    .iter()
+                    .map(|origin| HeaderValue::try_from(origin.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|error| anyhow::anyhow!("invalid CORS origin: {error}"))?,
+            ),
+        };
+
+        let methods = match (self.methods.is_empty(), any(&self.methods)) {
+            (true, _) => AllowMethods::mirror_request(),
+            (_, true) => AllowMethods::any(),
+            _ => AllowMethods::list(
+                self.methods
+                    .iter()
+                    .map(|method| Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|error| anyhow::anyhow!("invalid CORS method: {error}"))?,
+            ),
+        };
+
+        let headers = match (self.headers.is_empty(), any(&self.headers)) {
+            (true, _) => AllowHeaders::mirror_request(),
+            (_, true) => AllowHeaders::any(),
+            _ => AllowHeaders::list(
+                self.headers
+                    .iter()
+                    .map(|name| HeaderName::try_from(name.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|error| anyhow::anyhow!("invalid CORS header: {error}"))?,
+            ),
+        };
+
+        let layer = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.credentials);
+
+        Ok(Some(match self.max_age {
+            Some(max_age) => layer.max_age(max_age),
+            None => layer,
+        }))
+    }
+}
@@ -0,0 +1,147 @@
+// Plain-JSON Request Counters
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+// Third Party Imports
+use axum::{
+    extract::{Json, State},
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing, Router,
+};
+
+// Crate-Level Imports
+use crate::admin::{self, AdminToken};
+
+/// How many distinct paths are counted separately, before any others are counted together
+const MAX_PATHS: usize = 1_000;
+
+/// How many distinct methods each path's requests are counted by, before any others are
+/// counted together (as methods are arbitrary tokens, and so just as unbounded as paths)
+const MAX_METHODS: usize = 20;
+
+/// What paths (or methods) beyond those counted separately are counted as
+const OVERFLOW: &str = "other";
+
+/// Request counts, keyed by path, then method, then response status
+type CountTree = BTreeMap<String, BTreeMap<String, BTreeMap<u16, u64>>>;
+
+/// Per-path / per-method / per-status request counts
+#[derive(Debug, Default)]
+pub(crate) struct RequestCounters {
+    counts: Mutex<CountTree>,
+}
+
+impl RequestCounters {
+    /// How many requests have been counted (since the last reset)
+    pub(crate) fn total(&self) -> u64 {
+        total(&self.counts.lock().unwrap())
+    }
+}
+
+fn total(counts: &CountTree) -> u64 {
+    counts
+        .values()
+        .flat_map(BTreeMap::values)
+        .flat_map(BTreeMap::values)
+        .sum()
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct CountersReport {
+    total: u64,
+    paths: CountTree,
+}
+
+#[tracing::instrument]
+pub(crate) fn router(counters: Arc<RequestCounters>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_counters", routing::get(report).delete(reset))
+        .with_state(counters);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn report(State(counters): State<Arc<RequestCounters>>) -> Json<CountersReport> {
+    let paths = counters.counts.lock().unwrap().clone();
+
+    Json(CountersReport {
+        total: total(&paths),
+        paths,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn reset(State(counters): State<Arc<RequestCounters>>) -> StatusCode {
+    counters.counts.lock().unwrap().clear();
+
+    StatusCode::NO_CONTENT
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn count<B>(
+    State(counters): State<Arc<RequestCounters>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let (method, path) = (req.method().to_string(), req.uri().path().to_owned());
+
+    let response = next.run(req).await;
+
+    let mut counts = counters.counts.lock().unwrap();
+
+    let path = bounded(&counts, path, MAX_PATHS);
+    let methods = counts.entry(path).or_default();
+
+    let method = bounded(methods, method, MAX_METHODS);
+
+    *methods
+        .entry(method)
+        .or_default()
+        .entry(response.status().as_u16())
+        .or_default() += 1;
+
+    drop(counts);
+
+    response
+}
+
+/// The key to count under, i.e. the given one, unless it's new and there
+/// are already as many as are allowed (keeping memory use in bounds)
+fn bounded<V>(counts: &BTreeMap<String, V>, key: String, max: usize) -> String {
+    if counts.contains_key(&key) || counts.len() < max {
+        key
+    } else {
+        OVERFLOW.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_new_keys_together_once_full() {
+        let mut counts = BTreeMap::<String, u64>::new();
+
+        for path in ["/a", "/b", "/c", "/a", "/d"] {
+            *counts.entry(bounded(&counts, path.into(), 2)).or_default() += 1;
+        }
+
+        assert_eq!(
+            counts,
+            BTreeMap::from([("/a".into(), 2), ("/b".into(), 1), (OVERFLOW.into(), 2)])
+        );
+    }
+}
@@ -0,0 +1,532 @@
+// DNS-over-HTTPS Echo
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    sync::Arc,
+};
+
+// Third Party Imports
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+// Crate-Level Imports
+use crate::errors::Failure;
+
+/// Media type of wire-format DNS messages (RFC 8484)
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// TTL of every answer
+const TTL: u32 = 60;
+
+/// Header flags
+const QR: u16 = 0x8000;
+const OPCODE_AND_RD: u16 = 0x7900;
+const RD: u16 = 0x0100;
+const RA: u16 = 0x0080;
+
+/// The `IN` (internet) class
+const CLASS_IN: u16 = 1;
+
+/// Response codes
+const NOERROR: u16 = 0;
+const NXDOMAIN: u16 = 3;
+
+/// Record types that can be answered with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Txt,
+}
+
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            Self::A => 1,
+            Self::Cname => 5,
+            Self::Txt => 16,
+            Self::Aaaa => 28,
+        }
+    }
+}
+
+/// Human-readable name of a record type (code)
+fn type_name(code: u16) -> String {
+    match code {
+        1 => "A".into(),
+        2 => "NS".into(),
+        5 => "CNAME".into(),
+        6 => "SOA".into(),
+        12 => "PTR".into(),
+        15 => "MX".into(),
+        16 => "TXT".into(),
+        28 => "AAAA".into(),
+        33 => "SRV".into(),
+        65 => "HTTPS".into(),
+        255 => "ANY".into(),
+        other => format!("TYPE{other}"),
+    }
+}
+
+/// Lowercase, fully-qualified form of a domain name
+fn normalize(name: &str) -> String {
+    format!(
+        "{}.",
+        name.trim().trim_end_matches('.').to_ascii_lowercase()
+    )
+}
+
+/// The data of a configured record
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Txt(String),
+}
+
+impl RecordData {
+    fn kind(&self) -> RecordType {
+        match self {
+            Self::A(_) => RecordType::A,
+            Self::Aaaa(_) => RecordType::Aaaa,
+            Self::Cname(_) => RecordType::Cname,
+            Self::Txt(_) => RecordType::Txt,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::A(address) => address.octets().to_vec(),
+            Self::Aaaa(address) => address.octets().to_vec(),
+            Self::Cname(target) => encode_name(target),
+            Self::Txt(text) => text
+                .as_bytes()
+                .chunks(255)
+                .flat_map(|chunk| std::iter::once(chunk.len() as u8).chain(chunk.iter().copied()))
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for RecordData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::A(address) => write!(f, "{address}"),
+            Self::Aaaa(address) => write!(f, "{address}"),
+            Self::Cname(target) => write!(f, "{target}"),
+            Self::Txt(text) => write!(f, "{text:?}"),
+        }
+    }
+}
+
+/// A record answered for queries of its name, given as `name=TYPE:value`,
+/// e.g. `example.com=A:192.0.2.1` or `example.com=TXT:hello`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DnsRecord {
+    name: String,
+    data: RecordData,
+}
+
+impl FromStr for DnsRecord {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, record) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<name>=<type>:<value>`, got {value:?}"))?;
+
+        let (kind, data) = record
+            .split_once(':')
+            .ok_or_else(|| format!("expected `<name>=<type>:<value>`, got {value:?}"))?;
+
+        let data = match kind.trim().to_ascii_uppercase().as_str() {
+            "A" => RecordData::A(
+                data.trim()
+                    .parse()
+                    .map_err(|error| format!("{data:?}: {error}"))?,
+            ),
+            "AAAA" => RecordData::Aaaa(
+                data.trim()
+                    .parse()
+                    .map_err(|error| format!("{data:?}: {error}"))?,
+            ),
+            "CNAME" => RecordData::Cname(normalize(data)),
+            "TXT" => RecordData::Txt(data.to_owned()),
+            other => return Err(format!("{other:?}: expected one of A, AAAA, CNAME, or TXT")),
+        };
+
+        Ok(Self {
+            name: normalize(name),
+            data,
+        })
+    }
+}
+
+/// A question in a (parsed) DNS query
+#[derive(Clone, Debug, serde::Serialize)]
+struct Question {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    class: u16,
+    #[serde(skip)]
+    code: u16,
+}
+
+/// A record in an answer
+#[derive(Clone, Debug, serde::Serialize)]
+struct Answer {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    ttl: u32,
+    data: String,
+}
+
+/// A (parsed) DNS query, and how it was answered
+#[derive(Clone, Debug, serde::Serialize)]
+struct DnsEcho {
+    id: u16,
+    opcode: u16,
+    recursion_desired: bool,
+    questions: Vec<Question>,
+    status: &'static str,
+    answers: Vec<Answer>,
+}
+
+/// Reads a DNS message, tracking the position within it
+struct Reader<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self
+            .message
+            .get(self.position..self.position + 2)
+            .ok_or("message truncated")?;
+
+        self.position += 2;
+
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Read a (possibly compressed) domain name
+    fn name(&mut self) -> Result<String, String> {
+        let mut labels = Vec::new();
+        let mut position = self.position;
+        let mut jumped = false;
+
+        // every jump has to go backwards, which bounds how many there can be
+        let mut limit = position;
+
+        loop {
+            let length = *self.message.get(position).ok_or("message truncated")? as usize;
+
+            match length {
+                0 => {
+                    position += 1;
+                    break;
+                }
+                pointer if pointer & 0xC0 == 0xC0 => {
+                    let low = *self.message.get(position + 1).ok_or("message truncated")? as usize;
+                    let target = ((pointer & 0x3F) << 8) | low;
+
+                    if target >= limit {
+                        return Err("invalid name compression pointer".into());
+                    }
+
+                    if !jumped {
+                        self.position = position + 2;
+                        jumped = true;
+                    }
+
+                    (position, limit) = (target, target);
+                }
+                length if length > 63 => return Err("invalid label length".into()),
+                length => {
+                    let label = self
+                        .message
+                        .get(position + 1..position + 1 + length)
+                        .ok_or("message truncated")?;
+
+                    labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+                    position += 1 + length;
+                }
+            }
+        }
+
+        if !jumped {
+            self.position = position;
+        }
+
+        Ok(format!("{}.", labels.join(".")))
+    }
+}
+
+/// The header fields and questions of a DNS query
+struct DnsQuery {
+    id: u16,
+    flags: u16,
+    questions: Vec<Question>,
+}
+
+fn parse(message: &[u8]) -> Result<DnsQuery, String> {
+    let mut reader = Reader {
+        message,
+        position: 0,
+    };
+
+    let id = reader.u16()?;
+    let flags = reader.u16()?;
+    let count = reader.u16()?;
+
+    if flags & QR != 0 {
+        return Err("message is a response, not a query".into());
+    }
+
+    // the answer, authority, and additional counts
+    reader.position += 6;
+
+    let questions = (0..count)
+        .map(|_| {
+            let name = reader.name()?;
+            let code = reader.u16()?;
+
+            Ok(Question {
+                name,
+                kind: type_name(code),
+                class: reader.u16()?,
+                code,
+            })
+        })
+        .collect::<Result<Vec<Question>, String>>()?;
+
+    Ok(DnsQuery {
+        id,
+        flags,
+        questions,
+    })
+}
+
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut encoded = name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|label| !label.is_empty())
+        .flat_map(|label| {
+            let label = &label.as_bytes()[..label.len().min(63)];
+            std::iter::once(label.len() as u8).chain(label.iter().copied())
+        })
+        .collect::<Vec<u8>>();
+
+    encoded.push(0);
+    encoded
+}
+
+/// Answers DNS queries from the configured records
+#[derive(Debug, Default)]
+pub(crate) struct Resolver {
+    records: HashMap<String, Vec<RecordData>>,
+}
+
+impl Resolver {
+    pub(crate) fn new(records: Vec<DnsRecord>) -> Self {
+        let mut resolver = Self::default();
+
+        for record in records {
+            resolver
+                .records
+                .entry(record.name)
+                .or_default()
+                .push(record.data);
+        }
+
+        resolver
+    }
+
+    /// The records answering the question (following configured CNAMEs),
+    /// or `None` if the name isn't known at all
+    fn answer(&self, question: &Question) -> Option<Vec<(String, &RecordData)>> {
+        let mut name = question.name.clone();
+        let mut answers = Vec::new();
+
+        self.records.get(&name)?;
+
+        // configured CNAME chains could loop, so they're only followed so far
+        for _ in 0..8 {
+            let Some(records) = self.records.get(&name) else {
+                break;
+            };
+
+            let matching = records
+                .iter()
+                .filter(|data| data.kind().code() == question.code || question.code == 255)
+                .collect::<Vec<&RecordData>>();
+
+            if !matching.is_empty() || question.code == RecordType::Cname.code() {
+                answers.extend(matching.into_iter().map(|data| (name.clone(), data)));
+                break;
+            }
+
+            let Some(cname) = records.iter().find(|data| data.kind() == RecordType::Cname) else {
+                break;
+            };
+
+            answers.push((name.clone(), cname));
+
+            let RecordData::Cname(target) = cname else {
+                unreachable!()
+            };
+
+            name = target.clone();
+        }
+
+        Some(answers)
+    }
+
+    fn resolve(&self, message: &[u8]) -> Result<(DnsEcho, Vec<u8>), String> {
+        let query = parse(message)?;
+
+        let answers = query
+            .questions
+            .iter()
+            .filter(|question| question.class == CLASS_IN)
+            .map(|question| self.answer(question))
+            .collect::<Vec<Option<Vec<(String, &RecordData)>>>>();
+
+        let rcode = match answers.iter().any(Option::is_none) && !query.questions.is_empty() {
+            true => NXDOMAIN,
+            false => NOERROR,
+        };
+
+        let answers = answers
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect::<Vec<(String, &RecordData)>>();
+
+        let mut response = Vec::with_capacity(512);
+
+        response.extend(query.id.to_be_bytes());
+        response.extend((QR | (query.flags & OPCODE_AND_RD) | RA | rcode).to_be_bytes());
+        response.extend((query.questions.len() as u16).to_be_bytes());
+        response.extend((answers.len() as u16).to_be_bytes());
+        response.extend([0, 0, 0, 0]);
+
+        for question in query.questions.iter() {
+            response.extend(encode_name(&question.name));
+            response.extend(question.code.to_be_bytes());
+            response.extend(question.class.to_be_bytes());
+        }
+
+        for (name, data) in answers.iter() {
+            let rdata = data.encode();
+
+            response.extend(encode_name(name));
+            response.extend(data.kind().code().to_be_bytes());
+            response.extend(CLASS_IN.to_be_bytes());
+            response.extend(TTL.to_be_bytes());
+            response.extend((rdata.len() as u16).to_be_bytes());
+            response.extend(rdata);
+        }
+
+        let echo = DnsEcho {
+            id: query.id,
+            opcode: (query.flags >> 11) & 0xF,
+            recursion_desired: query.flags & RD != 0,
+            status: match rcode {
+                NXDOMAIN => "NXDOMAIN",
+                _ => "NOERROR",
+            },
+            answers: answers
+                .iter()
+                .map(|(name, data)| Answer {
+                    name: name.clone(),
+                    kind: type_name(data.kind().code()),
+                    ttl: TTL,
+                    data: data.to_string(),
+                })
+                .collect(),
+            questions: query.questions,
+        };
+
+        Ok((echo, response))
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(resolver: Arc<Resolver>) -> Router {
+    Router::new()
+        .route("/dns-query", routing::get(query).post(query))
+        .with_state(resolver)
+}
+
+/// Answer a DoH query (RFC 8484), passed as the base64url-encoded `?dns=`
+/// parameter of a `GET` or as the body of a `POST`, in wire format if the
+/// client accepts it or as the parsed query (and its answers) otherwise
+#[tracing::instrument(skip_all)]
+async fn query(
+    State(resolver): State<Arc<Resolver>>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let message = match params.get("dns") {
+        Some(encoded) => match URL_SAFE_NO_PAD.decode(encoded.trim_end_matches('=')) {
+            Ok(message) => message,
+            Err(error) => {
+                return Failure::new(StatusCode::BAD_REQUEST, format!("dns: {error}"))
+                    .into_response()
+            }
+        },
+        None if body.is_empty() => {
+            return Failure::new(StatusCode::BAD_REQUEST, "missing DNS query").into_response()
+        }
+        None => {
+            let content_type = headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+
+            if !content_type.starts_with(DNS_MESSAGE) {
+                return Failure::new(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    format!("expected {DNS_MESSAGE}"),
+                )
+                .into_response();
+            }
+
+            body.to_vec()
+        }
+    };
+
+    let (echo, response) = match resolver.resolve(&message) {
+        Ok(resolved) => resolved,
+        Err(error) => return Failure::new(StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    tracing::info!("{echo:?}");
+
+    let wire_format = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains(DNS_MESSAGE));
+
+    match wire_format {
+        true => ([(header::CONTENT_TYPE, DNS_MESSAGE)], response).into_response(),
+        false => Json(echo).into_response(),
+    }
+}
@@ -0,0 +1,144 @@
+// Structured Error Responses
+
+// Standard Library Imports
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Full},
+    extract::State,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::task::AbortHandle;
+use tracing::Instrument;
+
+// Crate-Level Imports
+use crate::request_id::RequestId;
+
+/// A failure to handle a request, answered with a plain-text message
+/// that [`structure`] turns into an [`ErrorBody`]
+#[derive(Clone, Debug)]
+pub(crate) struct Failure {
+    status: StatusCode,
+    message: String,
+}
+
+impl Failure {
+    pub(crate) fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for Failure {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.message.clone()).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Set once a request has run out of time, so that its abandonment
+/// isn't mistaken for the client's
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TimedOut(Arc<AtomicBool>);
+
+impl TimedOut {
+    pub(crate) fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The body of every failure response, so clients can assert on a single shape
+#[derive(Clone, Debug, serde::Serialize)]
+struct ErrorBody<'a> {
+    code: u16,
+    message: &'a str,
+    request_id: &'a str,
+}
+
+/// Aborts the request's handler if the client goes away (or it times out) first
+struct AbortOnDrop(AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Answer with 408 if handling a request takes longer than the timeout (if
+/// any) and with 500 if its handler panics, and render every [`Failure`] as
+/// a JSON [`ErrorBody`] (carrying the request's ID)
+#[tracing::instrument(skip_all)]
+pub(crate) async fn structure<B: Send + 'static>(
+    State(timeout): State<Option<Duration>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.clone())
+        .unwrap_or_default();
+
+    let timed_out = TimedOut::default();
+    req.extensions_mut().insert(timed_out.clone());
+
+    let mut handler = tokio::spawn(next.run(req).in_current_span());
+    let _abort = AbortOnDrop(handler.abort_handle());
+
+    let outcome = match timeout {
+        None => (&mut handler).await,
+        Some(timeout) => match tokio::time::timeout(timeout, &mut handler).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                timed_out.0.store(true, Ordering::Relaxed);
+                handler.abort();
+
+                Ok(Failure::new(
+                    StatusCode::REQUEST_TIMEOUT,
+                    format!(
+                        "request not handled within {}",
+                        humantime::format_duration(timeout)
+                    ),
+                )
+                .into_response())
+            }
+        },
+    };
+
+    let mut response = outcome.unwrap_or_else(|error| {
+        tracing::error!("Request handler failed: {error}");
+        Failure::new(StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
+    });
+
+    if let Some(failure) = response.extensions_mut().remove::<Failure>() {
+        let body = serde_json::to_vec(&ErrorBody {
+            code: response.status().as_u16(),
+            message: &failure.message,
+            request_id: &request_id,
+        })
+        .unwrap_or_default();
+
+        let (mut parts, _) = response.into_parts();
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+
+        response = Response::from_parts(parts, body::boxed(Full::from(body)));
+    }
+
+    response
+}
@@ -0,0 +1,240 @@
+// Circuit-Breaker Simulation
+
+// Standard Library Imports
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::{chaos, schedule::parse_period};
+
+/// How long the failing phase of a [`FailWindowSpec`] lasts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FailFor {
+    /// Fail exactly this many requests
+    Requests(u64),
+    /// Fail every request for this long
+    Period(Duration),
+}
+
+/// Configuration for a recurring "fail hard" window, e.g. `after=100;fail=50;recover=30s`
+///
+/// - `after`: the number of requests served normally before failing starts
+/// - `fail`: how many requests to fail (or, given a duration, for how long to fail them)
+/// - `recover`: how long to serve normally after failing before the cycle starts over
+///   (omit to recover permanently)
+/// - `status`: the status code failed requests receive (default: 503)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FailWindowSpec {
+    pub(crate) after: u64,
+    pub(crate) fail: FailFor,
+    pub(crate) recover: Option<Duration>,
+    pub(crate) status: StatusCode,
+}
+
+impl FromStr for FailWindowSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (mut after, mut fail, mut recover, mut status) =
+            (None, None, None, StatusCode::SERVICE_UNAVAILABLE);
+
+        for setting in value
+            .split(';')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (key, value) = setting
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| format!("expected `key=value`, got {setting:?}"))?;
+
+            match key {
+                "after" => {
+                    after = Some(value.parse().map_err(|error| format!("after: {error}"))?);
+                }
+                "fail" => {
+                    fail = Some(match value.parse::<u64>() {
+                        Ok(0) => return Err("fail: must fail at least 1 request".into()),
+                        Ok(count) => FailFor::Requests(count),
+                        Err(_) => FailFor::Period(parse_period(value)?),
+                    });
+                }
+                "recover" => recover = Some(parse_period(value)?),
+                "status" => {
+                    status = value
+                        .parse::<u16>()
+                        .ok()
+                        .and_then(|code| StatusCode::from_u16(code).ok())
+                        .ok_or_else(|| format!("invalid status code: {value:?}"))?;
+                }
+                _ => return Err(format!("unknown fail-window setting: {key:?}")),
+            }
+        }
+
+        Ok(Self {
+            after: after.ok_or("missing required `after` setting")?,
+            fail: fail.ok_or("missing required `fail` setting")?,
+            recover,
+            status,
+        })
+    }
+}
+
+/// Which part of the fail window cycle the server is currently in
+#[derive(Clone, Copy, Debug)]
+enum Phase {
+    Healthy { served: u64 },
+    Failing { failed: u64, since: Instant },
+    Recovered { since: Instant },
+}
+
+/// Live state of a [`FailWindowSpec`]
+#[derive(Debug)]
+pub(crate) struct FailWindow {
+    spec: FailWindowSpec,
+    phase: Mutex<Phase>,
+}
+
+impl FailWindow {
+    pub(crate) fn new(spec: FailWindowSpec) -> Self {
+        Self {
+            spec,
+            phase: Mutex::new(Phase::Healthy { served: 0 }),
+        }
+    }
+
+    /// Start the cycle over, as if no requests had been served
+    pub(crate) fn reset(&self) {
+        *self.phase.lock().unwrap() = Phase::Healthy { served: 0 };
+    }
+
+    /// Advance the cycle by one request, returning whether that request should fail
+    fn should_fail(&self) -> bool {
+        let mut phase = self.phase.lock().unwrap();
+        let now = Instant::now();
+
+        // settle any time-based phase transitions that are due
+        loop {
+            *phase = match *phase {
+                Phase::Failing { since, .. } if matches!(self.spec.fail, FailFor::Period(period) if now - since >= period) =>
+                {
+                    tracing::info!("Fail window closed, recovering");
+                    Phase::Recovered { since: now }
+                }
+                Phase::Recovered { since }
+                    if self
+                        .spec
+                        .recover
+                        .is_some_and(|recover| now - since >= recover) =>
+                {
+                    Phase::Healthy { served: 0 }
+                }
+                _ => break,
+            };
+        }
+
+        let (next, fail) = match *phase {
+            Phase::Healthy { served } if served >= self.spec.after => {
+                tracing::info!(
+                    "Fail window opened after {served} requests, failing with {}",
+                    self.spec.status
+                );
+                (
+                    Phase::Failing {
+                        failed: 1,
+                        since: now,
+                    },
+                    true,
+                )
+            }
+            Phase::Healthy { served } => (Phase::Healthy { served: served + 1 }, false),
+            Phase::Failing { failed, since } => match self.spec.fail {
+                FailFor::Requests(limit) if failed >= limit => {
+                    tracing::info!("Fail window closed after {failed} requests, recovering");
+                    (Phase::Recovered { since: now }, false)
+                }
+                _ => (
+                    Phase::Failing {
+                        failed: failed + 1,
+                        since,
+                    },
+                    true,
+                ),
+            },
+            recovered @ Phase::Recovered { .. } => (recovered, false),
+        };
+
+        *phase = next;
+
+        fail
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce<B>(
+    State(window): State<Arc<FailWindow>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if window.should_fail() {
+        chaos::injected(window.spec.status.into_response())
+    } else {
+        next.run(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(
+            "after=100; fail=50; recover=30s".parse(),
+            Ok(FailWindowSpec {
+                after: 100,
+                fail: FailFor::Requests(50),
+                recover: Some(Duration::from_secs(30)),
+                status: StatusCode::SERVICE_UNAVAILABLE,
+            })
+        );
+        assert_eq!(
+            "after=0;fail=1m;status=500".parse(),
+            Ok(FailWindowSpec {
+                after: 0,
+                fail: FailFor::Period(Duration::from_secs(60)),
+                recover: None,
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        for value in [
+            "fail=1",
+            "after=1",
+            "after=1;fail=0",
+            "after=-1;fail=1",
+            "after=1;fail=1;status=abc",
+            "after=1;fail=1;bogus=1",
+            "after",
+        ] {
+            assert!(
+                value.parse::<FailWindowSpec>().is_err(),
+                "{value:?} should be rejected"
+            );
+        }
+    }
+}
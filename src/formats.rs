@@ -0,0 +1,180 @@
+// Echo Payload Formats
+
+// Third Party Imports
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use quick_xml::escape::escape;
+use serde_json::Value;
+
+// Crate-Level Imports
+use crate::{errors::Failure, negotiate::choose_media_type};
+
+/// Query parameter selecting the echo's format, in place of the `Accept` header
+pub(crate) const FORMAT_PARAM: &str = "format";
+
+/// Media types the echo can be rendered as, in order of preference
+const OFFERED: &str = "application/json,application/yaml,application/x-yaml,text/yaml,\
+                       application/xml,text/xml,application/msgpack,application/x-msgpack,\
+                       application/vnd.msgpack,text/plain";
+
+/// What the echo is rendered as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum EchoFormat {
+    #[default]
+    Json,
+    Yaml,
+    Xml,
+    MessagePack,
+    /// One `dotted.path: value` line per field
+    Text,
+}
+
+impl EchoFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "json" | "application/json" => Some(Self::Json),
+            "yaml" | "yml" | "application/yaml" | "application/x-yaml" | "text/yaml" => {
+                Some(Self::Yaml)
+            }
+            "xml" | "application/xml" | "text/xml" => Some(Self::Xml),
+            "msgpack"
+            | "messagepack"
+            | "application/msgpack"
+            | "application/x-msgpack"
+            | "application/vnd.msgpack" => Some(Self::MessagePack),
+            "text" | "txt" | "plain" | "text/plain" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// The format asked for by the `?format=` parameter or, failing
+    /// that, the `Accept` header - JSON unless something else is
+    pub(crate) fn negotiate(headers: &HeaderMap, format: Option<&str>) -> Result<Self, String> {
+        if let Some(format) = format {
+            return Self::from_name(format).ok_or_else(|| {
+                format!(
+                    "unknown format {format:?} (expected one of: json, yaml, xml, msgpack, text)"
+                )
+            });
+        }
+
+        Ok(choose_media_type(headers, OFFERED)
+            .as_deref()
+            .and_then(Self::from_name)
+            .unwrap_or_default())
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml",
+            Self::Xml => "application/xml",
+            Self::MessagePack => "application/msgpack",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// Respond with the (serialized) echo in this format
+    pub(crate) fn respond(self, echo: Value) -> Response {
+        let body = match self {
+            Self::Json => return Json(echo).into_response(),
+            Self::Yaml => serde_yaml::to_string(&echo)
+                .map(String::into_bytes)
+                .map_err(|error| error.to_string()),
+            Self::Xml => Ok(xml(&echo).into_bytes()),
+            Self::MessagePack => rmp_serde::to_vec_named(&echo).map_err(|error| error.to_string()),
+            Self::Text => Ok(text(&echo).into_bytes()),
+        };
+
+        match body {
+            Ok(body) => ([(header::CONTENT_TYPE, self.content_type())], body).into_response(),
+            Err(error) => Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error).into_response(),
+        }
+    }
+}
+
+/// Whether the given text can be used as-is as an XML element name
+fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '-' | '.'))
+        && !name.to_ascii_lowercase().starts_with("xml")
+}
+
+/// Render the echo as an XML document, with arrays as repeated `<item>`s and
+/// fields whose names aren't valid element names as `<field name="...">`s
+fn xml(echo: &Value) -> String {
+    fn element(out: &mut String, name: &str, value: &Value) {
+        let (open, close) = match is_xml_name(name) {
+            true => (name.to_owned(), name.to_owned()),
+            false => (
+                format!("field name=\"{}\"", escape(name)),
+                "field".to_owned(),
+            ),
+        };
+
+        match value {
+            Value::Null => out.push_str(&format!("<{open}/>")),
+            Value::Object(fields) => {
+                out.push_str(&format!("<{open}>"));
+
+                for (name, value) in fields {
+                    element(out, name, value);
+                }
+
+                out.push_str(&format!("</{close}>"));
+            }
+            Value::Array(items) => {
+                out.push_str(&format!("<{open}>"));
+
+                for item in items {
+                    element(out, "item", item);
+                }
+
+                out.push_str(&format!("</{close}>"));
+            }
+            Value::String(text) => out.push_str(&format!("<{open}>{}</{close}>", escape(text))),
+            other => out.push_str(&format!("<{open}>{other}</{close}>")),
+        }
+    }
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    element(&mut out, "echo", echo);
+    out.push('\n');
+    out
+}
+
+/// Render the echo as `dotted.path: value` lines
+fn text(echo: &Value) -> String {
+    fn lines(out: &mut String, path: &str, value: &Value) {
+        let nested = |key: &str| match path.is_empty() {
+            true => key.to_owned(),
+            false => format!("{path}.{key}"),
+        };
+
+        match value {
+            Value::Object(fields) if !fields.is_empty() => {
+                for (name, value) in fields {
+                    lines(out, &nested(name), value);
+                }
+            }
+            Value::Array(items) if !items.is_empty() => {
+                for (index, item) in items.iter().enumerate() {
+                    lines(out, &nested(&index.to_string()), item);
+                }
+            }
+            Value::String(text) => out.push_str(&format!("{path}: {text}\n")),
+            other => out.push_str(&format!("{path}: {other}\n")),
+        }
+    }
+
+    let mut out = String::new();
+    lines(&mut out, "", echo);
+    out
+}
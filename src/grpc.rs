@@ -0,0 +1,562 @@
+// gRPC Echo Service
+
+// Standard Library Imports
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Body, Bytes, HttpBody},
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Request},
+    response::Response,
+    routing, Router,
+};
+use tokio::{sync::mpsc, time::Instant};
+
+// Crate-Level Imports
+use crate::shaping::{header_duration, parse_header_pair};
+
+/// Request metadata overriding how many times each message is streamed back
+pub(crate) const STREAM_COUNT_HEADER: &str = "x-echo-stream-count";
+
+/// Request metadata overriding the delay before each response message
+pub(crate) const STREAM_INTERVAL_HEADER: &str = "x-echo-stream-interval";
+
+/// Request metadata setting the status code the call ends with
+pub(crate) const STATUS_HEADER: &str = "x-echo-grpc-status";
+
+/// Request metadata setting the `grpc-message` the call ends with
+pub(crate) const MESSAGE_HEADER: &str = "x-echo-grpc-message";
+
+/// Request metadata delaying the response, e.g. `250ms`
+pub(crate) const DELAY_HEADER: &str = "x-echo-grpc-delay";
+
+/// Request metadata adding a `name=value` trailer to the response
+pub(crate) const TRAILER_HEADER: &str = "x-echo-grpc-trailer";
+
+/// Trailer reporting how many messages the client sent
+const RECEIVED_TRAILER: &str = "x-echo-messages-received";
+
+/// The (length-prefixed) framing of each message, per the gRPC-over-HTTP/2 spec
+const FRAME_HEADER_LEN: usize = 5;
+
+/// The largest message accepted, matching gRPC's default receive limit
+const MAX_MESSAGE_LEN: usize = 4 * 1024 * 1024;
+
+/// Messages are echoed exactly as sent (whatever their type), so any
+/// method whose request and response types match can be called this way
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Method {
+    Unary,
+    ServerStream,
+    ClientStream,
+    BidiStream,
+}
+
+impl Method {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Unary => "Unary",
+            Self::ServerStream => "ServerStream",
+            Self::ClientStream => "ClientStream",
+            Self::BidiStream => "BidiStream",
+        }
+    }
+}
+
+/// Server-wide streaming defaults, overridable per-call via request metadata
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StreamDefaults {
+    /// How many times each message is sent back by streaming methods
+    pub(crate) count: u32,
+    /// Delay before each response message is sent
+    pub(crate) interval: Option<Duration>,
+}
+
+impl Default for StreamDefaults {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            interval: None,
+        }
+    }
+}
+
+/// How an individual call plays out
+#[derive(Clone, Debug)]
+struct CallSettings {
+    count: u32,
+    interval: Option<Duration>,
+    deadline: Option<Instant>,
+    /// Delay before the response (head) is sent
+    delay: Option<Duration>,
+    /// The status to end the call with, if it isn't otherwise cut short
+    status: Option<Status>,
+    /// Additional trailers to end the call with
+    trailers: HeaderMap,
+}
+
+impl CallSettings {
+    fn new(defaults: StreamDefaults, headers: &HeaderMap) -> Result<Self, Status> {
+        let count = match headers.get(STREAM_COUNT_HEADER) {
+            None => defaults.count,
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| {
+                    Status::invalid_argument(format!("invalid `{STREAM_COUNT_HEADER}` metadata"))
+                })?,
+        };
+
+        let deadline = headers
+            .get("grpc-timeout")
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(parse_timeout)
+                    .map(|timeout| Instant::now() + timeout)
+                    .ok_or_else(|| Status::invalid_argument("invalid `grpc-timeout` metadata"))
+            })
+            .transpose()?;
+
+        let status = headers
+            .get(STATUS_HEADER)
+            .map(|value| {
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.trim().parse::<u8>().ok())
+                    .filter(|code| *code <= Status::MAX_CODE)
+                    .ok_or_else(|| {
+                        Status::invalid_argument(format!("invalid `{STATUS_HEADER}` metadata"))
+                    })
+            })
+            .transpose()?
+            .map(|code| {
+                let message = headers
+                    .get(MESSAGE_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default();
+
+                Status::new(code, message)
+            });
+
+        let trailers = headers
+            .get_all(TRAILER_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|pair| {
+                parse_header_pair(pair).map_err(|error| {
+                    Status::invalid_argument(format!(
+                        "invalid `{TRAILER_HEADER}` metadata: {error}"
+                    ))
+                })
+            })
+            .collect::<Result<HeaderMap, Status>>()?;
+
+        Ok(Self {
+            count,
+            interval: header_duration(headers, STREAM_INTERVAL_HEADER).or(defaults.interval),
+            deadline,
+            delay: header_duration(headers, DELAY_HEADER),
+            status,
+            trailers,
+        })
+    }
+}
+
+/// Parse a `grpc-timeout` value, e.g. `100m` (milliseconds) or `5S` (seconds)
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let (amount, unit) = value.split_at(value.len().checked_sub(1)?);
+    let amount: u64 = amount.parse().ok().filter(|_| amount.len() <= 8)?;
+
+    Some(match unit {
+        "H" => Duration::from_secs(amount * 3600),
+        "M" => Duration::from_secs(amount * 60),
+        "S" => Duration::from_secs(amount),
+        "m" => Duration::from_millis(amount),
+        "u" => Duration::from_micros(amount),
+        "n" => Duration::from_nanos(amount),
+        _ => return None,
+    })
+}
+
+/// The outcome of a call, as reported in its trailers
+#[derive(Clone, Debug)]
+struct Status {
+    code: u8,
+    message: String,
+}
+
+impl Status {
+    const OK: u8 = 0;
+    const INVALID_ARGUMENT: u8 = 3;
+    const DEADLINE_EXCEEDED: u8 = 4;
+    const RESOURCE_EXHAUSTED: u8 = 8;
+    const UNIMPLEMENTED: u8 = 12;
+    const INTERNAL: u8 = 13;
+    /// The highest status code defined, i.e. UNAUTHENTICATED
+    const MAX_CODE: u8 = 16;
+
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn ok() -> Self {
+        Self::new(Self::OK, "")
+    }
+
+    fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_ARGUMENT, message)
+    }
+
+    fn trailers(&self, received: u64, extra: HeaderMap) -> HeaderMap {
+        let mut trailers = extra;
+
+        trailers.insert("grpc-status", HeaderValue::from(u16::from(self.code)));
+        trailers.insert(RECEIVED_TRAILER, HeaderValue::from(received));
+
+        if !self.message.is_empty() {
+            if let Ok(message) = HeaderValue::try_from(percent_encode(&self.message)) {
+                trailers.insert("grpc-message", message);
+            }
+        }
+
+        trailers
+    }
+}
+
+/// Percent-encode a `grpc-message`, as the spec requires
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'%' => (byte as char).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// The messages of a request, as they arrive
+#[derive(Debug)]
+struct Messages {
+    body: Body,
+    buffer: Vec<u8>,
+    received: u64,
+}
+
+impl Messages {
+    fn new(body: Body) -> Self {
+        Self {
+            body,
+            buffer: Vec::new(),
+            received: 0,
+        }
+    }
+
+    /// The next message, or `None` once the client has finished sending them
+    async fn next(&mut self) -> Result<Option<Bytes>, Status> {
+        loop {
+            if self.buffer.len() >= FRAME_HEADER_LEN {
+                let compressed = self.buffer[0] != 0;
+                let len = u32::from_be_bytes([
+                    self.buffer[1],
+                    self.buffer[2],
+                    self.buffer[3],
+                    self.buffer[4],
+                ]) as usize;
+
+                if compressed {
+                    return Err(Status::new(
+                        Status::UNIMPLEMENTED,
+                        "compressed messages are not supported",
+                    ));
+                }
+
+                if len > MAX_MESSAGE_LEN {
+                    return Err(Status::new(
+                        Status::RESOURCE_EXHAUSTED,
+                        format!("message of {len} bytes exceeds the {MAX_MESSAGE_LEN} byte limit"),
+                    ));
+                }
+
+                if self.buffer.len() >= FRAME_HEADER_LEN + len {
+                    let frame: Vec<u8> = self.buffer.drain(..FRAME_HEADER_LEN + len).collect();
+                    self.received += 1;
+                    return Ok(Some(Bytes::copy_from_slice(&frame[FRAME_HEADER_LEN..])));
+                }
+            }
+
+            match self.body.data().await {
+                Some(Ok(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Some(Err(error)) => {
+                    return Err(Status::new(
+                        Status::INTERNAL,
+                        format!("failed to read request: {error}"),
+                    ))
+                }
+                None if self.buffer.is_empty() => return Ok(None),
+                None => return Err(Status::invalid_argument("truncated request message")),
+            }
+        }
+    }
+}
+
+/// A piece of a response, as it's produced
+#[derive(Debug)]
+enum Frame {
+    Message(Bytes),
+    Trailers(HeaderMap),
+}
+
+/// Sends a call's response messages, each after the configured interval
+#[derive(Debug)]
+struct Responder {
+    frames: mpsc::Sender<Frame>,
+    interval: Option<Duration>,
+    sent: u64,
+}
+
+impl Responder {
+    /// Send the message back, returning whether the client is still listening
+    async fn send(&mut self, message: &Bytes) -> bool {
+        if let Some(interval) = self.interval {
+            tokio::time::sleep(interval).await;
+        }
+
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + message.len());
+        frame.push(0);
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(message);
+
+        self.sent += 1;
+        self.frames.send(Frame::Message(frame.into())).await.is_ok()
+    }
+
+    /// Send the message back as many times as configured
+    async fn send_repeatedly(&mut self, message: &Bytes, count: u32) -> bool {
+        for _ in 0..count {
+            if !self.send(message).await {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Play out a call, up to (but not including) its trailers
+async fn run(
+    method: Method,
+    settings: &CallSettings,
+    messages: &mut Messages,
+    responder: &mut Responder,
+) -> Result<(), Status> {
+    match method {
+        Method::Unary | Method::ServerStream => {
+            let message = messages
+                .next()
+                .await?
+                .ok_or_else(|| Status::invalid_argument("expected a request message"))?;
+
+            // anything else sent is read (and counted), but otherwise ignored
+            while messages.next().await?.is_some() {}
+
+            let count = if method == Method::Unary {
+                1
+            } else {
+                settings.count
+            };
+
+            responder.send_repeatedly(&message, count).await;
+        }
+        Method::ClientStream => {
+            let mut last = None;
+
+            while let Some(message) = messages.next().await? {
+                last = Some(message);
+            }
+
+            let message =
+                last.ok_or_else(|| Status::invalid_argument("expected a request message"))?;
+
+            responder.send(&message).await;
+        }
+        Method::BidiStream => {
+            while let Some(message) = messages.next().await? {
+                if !responder.send_repeatedly(&message, settings.count).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Response body streaming a call's messages, then its trailers
+#[derive(Debug)]
+struct CallBody {
+    frames: mpsc::Receiver<Frame>,
+    trailers: Option<HeaderMap>,
+    finished: bool,
+}
+
+impl HttpBody for CallBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+
+        match self.frames.poll_recv(cx) {
+            Poll::Ready(Some(Frame::Message(message))) => Poll::Ready(Some(Ok(message))),
+            Poll::Ready(Some(Frame::Trailers(trailers))) => {
+                self.trailers = Some(trailers);
+                self.finished = true;
+                Poll::Ready(None)
+            }
+            Poll::Ready(None) => {
+                self.finished = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(defaults: StreamDefaults) -> Router {
+    Router::new()
+        .route(
+            "/echo.v1.Echo/Unary",
+            routing::post(|state, req| call(Method::Unary, state, req)),
+        )
+        .route(
+            "/echo.v1.Echo/ServerStream",
+            routing::post(|state, req| call(Method::ServerStream, state, req)),
+        )
+        .route(
+            "/echo.v1.Echo/ClientStream",
+            routing::post(|state, req| call(Method::ClientStream, state, req)),
+        )
+        .route(
+            "/echo.v1.Echo/BidiStream",
+            routing::post(|state, req| call(Method::BidiStream, state, req)),
+        )
+        .with_state(defaults)
+}
+
+/// Echo a call's messages back (repeated, delayed, and/or cut short by its
+/// deadline as configured), reporting its outcome in the response trailers
+#[tracing::instrument(skip_all, fields(method = method.name()))]
+async fn call(
+    method: Method,
+    State(defaults): State<StreamDefaults>,
+    req: Request<Body>,
+) -> Response {
+    let settings = CallSettings::new(defaults, req.headers());
+    let mut messages = Messages::new(req.into_body());
+    let (frames, receiver) = mpsc::channel(16);
+
+    // the response is held back as asked, but never beyond the call's deadline
+    if let Ok(CallSettings {
+        delay: Some(delay),
+        deadline,
+        ..
+    }) = settings.as_ref()
+    {
+        let until = Instant::now() + *delay;
+        tokio::time::sleep_until(deadline.map_or(until, |deadline| deadline.min(until))).await;
+    }
+
+    tokio::spawn(async move {
+        let mut responder = Responder {
+            frames: frames.clone(),
+            interval: settings
+                .as_ref()
+                .ok()
+                .and_then(|settings| settings.interval),
+            sent: 0,
+        };
+
+        let (status, trailers) = match settings {
+            Err(status) => (status, HeaderMap::new()),
+            Ok(settings) => {
+                let exceeded = || Status::new(Status::DEADLINE_EXCEEDED, "deadline exceeded");
+                let call = run(method, &settings, &mut messages, &mut responder);
+
+                let outcome = match settings.deadline {
+                    None => call.await,
+                    // e.g. having been spent delaying the response
+                    Some(deadline) if deadline <= Instant::now() => Err(exceeded()),
+                    Some(deadline) => tokio::time::timeout_at(deadline, call)
+                        .await
+                        .unwrap_or_else(|_| Err(exceeded())),
+                };
+
+                let status = outcome
+                    .err()
+                    .or_else(|| settings.status.clone())
+                    .unwrap_or_else(Status::ok);
+
+                (status, settings.trailers.clone())
+            }
+        };
+
+        tracing::info!(
+            "gRPC {} call finished with status {} ({} messages received, {} sent)",
+            method.name(),
+            status.code,
+            messages.received,
+            responder.sent
+        );
+
+        metrics::increment_counter!(
+            "grpc_calls_total",
+            "method" => method.name(),
+            "status" => status.code.to_string()
+        );
+
+        let _ = frames
+            .send(Frame::Trailers(
+                status.trailers(messages.received, trailers),
+            ))
+            .await;
+    });
+
+    let mut response = Response::new(body::boxed(CallBody {
+        frames: receiver,
+        trailers: None,
+        finished: false,
+    }));
+
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/grpc"),
+    );
+
+    response
+}
@@ -0,0 +1,102 @@
+// Request Header and URI Limits
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::{HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::errors::Failure;
+
+/// The most (and largest) headers a request may carry
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct HeaderLimits {
+    /// The most bytes the request's headers may add up to, counting
+    /// each one as it would be written on the wire (`name: value\r\n`)
+    pub(crate) max_bytes: Option<usize>,
+    /// The most headers the request may carry
+    pub(crate) max_count: Option<usize>,
+}
+
+impl HeaderLimits {
+    /// Whether any limit was actually configured
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.max_count.is_some()
+    }
+
+    /// Why the headers exceed the limits, if they do (along with which limit they exceed)
+    fn check(&self, headers: &HeaderMap) -> Option<(&'static str, String)> {
+        let count = headers.len();
+
+        if let Some(max) = self.max_count.filter(|max| count > *max) {
+            return Some((
+                "count",
+                format!("request carries {count} headers, more than the {max} allowed"),
+            ));
+        }
+
+        let bytes = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len() + 4)
+            .sum::<usize>();
+
+        self.max_bytes.filter(|max| bytes > *max).map(|max| {
+            (
+                "bytes",
+                format!("request headers total {bytes} bytes, more than the {max} allowed"),
+            )
+        })
+    }
+}
+
+/// Reject requests whose headers exceed the limits with 431
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce<B>(
+    State(limits): State<HeaderLimits>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some((limit, reason)) = limits.check(req.headers()) else {
+        return next.run(req).await;
+    };
+
+    tracing::debug!("Rejecting {} {}: {reason}", req.method(), req.uri().path());
+
+    metrics::increment_counter!("request_headers_rejected_total", "limit" => limit);
+
+    Failure::new(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, reason).into_response()
+}
+
+/// Reject requests whose URI (i.e. path and query) is longer than the limit with 414
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce_uri_length<B>(
+    State(max): State<usize>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let length = req
+        .uri()
+        .path_and_query()
+        .map_or(0, |target| target.as_str().len());
+
+    if length <= max {
+        return next.run(req).await;
+    }
+
+    tracing::info!(
+        "Rejecting {} {}: URI is {length} bytes long, more than the {max} allowed",
+        req.method(),
+        req.uri().path()
+    );
+
+    metrics::increment_counter!("request_uri_rejected_total");
+
+    Failure::new(
+        StatusCode::URI_TOO_LONG,
+        format!("request URI is {length} bytes long, more than the {max} allowed"),
+    )
+    .into_response()
+}
@@ -0,0 +1,214 @@
+// Health Checks
+
+// Standard Library Imports
+use std::{
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+// Third Party Imports
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    middleware, routing, Router,
+};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+    proxy::Upstream,
+    schedule::Cycle,
+    shutdown::Shutdown,
+};
+
+/// Whether (and how) the `/healthz` and `/readyz` endpoints should currently be failing
+#[derive(Debug)]
+pub(crate) struct Health {
+    /// The status code `/healthz` fails with, or zero while healthy
+    failing: AtomicU16,
+    /// When the server started, for tracking where in the readiness cycle it is
+    started: Instant,
+    /// Alternating ready / not-ready phases for `/readyz`
+    flap: Option<Cycle>,
+    /// Proxy upstreams reported by `/readyz`
+    upstreams: Vec<Arc<Upstream>>,
+    /// Whether `/readyz` fails while no upstream is healthy
+    upstreams_required: bool,
+    /// Shutdown coordination, `/readyz` fails once the server starts shutting down
+    shutdown: Option<Shutdown>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct UpstreamReport {
+    url: String,
+    healthy: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct HealthReport {
+    healthy: bool,
+    status: u16,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draining: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    upstreams: Vec<UpstreamReport>,
+}
+
+impl Health {
+    pub(crate) fn new(flap: Option<Cycle>) -> Self {
+        Self {
+            failing: AtomicU16::new(0),
+            started: Instant::now(),
+            flap,
+            upstreams: vec![],
+            upstreams_required: false,
+            shutdown: None,
+        }
+    }
+
+    /// Report not-ready via `/readyz` once the server starts shutting down
+    pub(crate) fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// Report on the given proxy upstreams via `/readyz`, optionally
+    /// reporting not-ready while none of them are healthy
+    pub(crate) fn with_upstreams(mut self, upstreams: Vec<Arc<Upstream>>, required: bool) -> Self {
+        self.upstreams = upstreams;
+        self.upstreams_required = required;
+        self
+    }
+
+    fn readiness(&self) -> (StatusCode, HealthReport) {
+        let flapped_down = self
+            .flap
+            .and_then(|flap| flap.down_remaining(self.started.elapsed()))
+            .is_some();
+
+        let upstreams = self
+            .upstreams
+            .iter()
+            .map(|upstream| UpstreamReport {
+                url: upstream.url.clone(),
+                healthy: upstream.is_healthy(),
+            })
+            .collect::<Vec<UpstreamReport>>();
+
+        let upstreams_down = self.upstreams_required
+            && !upstreams.is_empty()
+            && !upstreams.iter().any(|upstream| upstream.healthy);
+
+        let draining = self.shutdown.as_ref().is_some_and(Shutdown::is_draining);
+
+        let ready = !flapped_down && !upstreams_down && !draining;
+
+        let status = if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        (
+            status,
+            HealthReport {
+                healthy: ready,
+                status: status.as_u16(),
+                draining,
+                upstreams,
+            },
+        )
+    }
+
+    fn report(&self) -> (StatusCode, HealthReport) {
+        let status = match self.failing.load(Ordering::Relaxed) {
+            0 => StatusCode::OK,
+            code => StatusCode::from_u16(code).unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+        };
+
+        (
+            status,
+            HealthReport {
+                healthy: status.is_success(),
+                status: status.as_u16(),
+                draining: false,
+                upstreams: vec![],
+            },
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+struct ToggleParams {
+    status: Option<u16>,
+}
+
+#[tracing::instrument]
+pub(crate) fn router(health: Arc<Health>, admin_token: Option<AdminToken>) -> Router {
+    let mut router = Router::new()
+        .route("/healthz", routing::get(healthz))
+        .route("/readyz", routing::get(readyz))
+        .with_state(health.clone());
+
+    if let Some(token) = admin_token {
+        router = router.merge(
+            Router::new()
+                .route("/_health/toggle", routing::post(toggle))
+                .with_state(health)
+                .route_layer(middleware::from_fn_with_state(token, admin::require_token)),
+        );
+    }
+
+    router
+}
+
+#[tracing::instrument(skip_all)]
+async fn healthz(State(health): State<Arc<Health>>) -> (StatusCode, Json<HealthReport>) {
+    let (status, report) = health.report();
+
+    (status, Json(report))
+}
+
+#[tracing::instrument(skip_all)]
+async fn readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<HealthReport>) {
+    let (status, report) = health.readiness();
+
+    (status, Json(report))
+}
+
+/// Flip `/healthz` between healthy and failing (with the requested status, default 503)
+#[tracing::instrument(skip_all)]
+async fn toggle(
+    State(health): State<Arc<Health>>,
+    Query(params): Query<ToggleParams>,
+) -> Result<Json<HealthReport>, Failure> {
+    let status = match params.status {
+        None => StatusCode::SERVICE_UNAVAILABLE,
+        Some(code) => StatusCode::from_u16(code)
+            .ok()
+            .filter(|status| !status.is_success())
+            .ok_or_else(|| {
+                Failure::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid failure status: {code}"),
+                )
+            })?,
+    };
+
+    let toggled = match health.failing.load(Ordering::Relaxed) {
+        0 => status.as_u16(),
+        _ => 0,
+    };
+
+    health.failing.store(toggled, Ordering::Relaxed);
+
+    let (_, report) = health.report();
+
+    tracing::info!("Health check toggled: {report:?}");
+
+    Ok(Json(report))
+}
@@ -0,0 +1,350 @@
+// Per-Request Response Hints
+
+// Standard Library Imports
+use std::{collections::HashMap, time::Duration};
+
+// Third Party Imports
+use axum::{
+    body::{self, Full},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+// Crate-Level Imports
+use crate::{body::parse_size, errors::Failure, shaping::parse_header_pair, utility::MAX_DELAY};
+
+/// Request header (or query parameter) setting the response status
+pub(crate) const STATUS_HEADER: &str = "x-echo-status";
+pub(crate) const STATUS_PARAM: &str = "echo_status";
+
+/// Request header (or query parameter) delaying the response, e.g. `250ms` (at most a minute)
+pub(crate) const DELAY_HEADER: &str = "x-echo-delay";
+pub(crate) const DELAY_PARAM: &str = "echo_delay";
+
+/// Request header (or query parameter) adding a `name=value` header to the response
+pub(crate) const HEADER_HEADER: &str = "x-echo-header";
+pub(crate) const HEADER_PARAM: &str = "echo_header";
+
+/// Request header (or query parameter) adding a `Set-Cookie` header to the
+/// response, e.g. `session=abc; Path=/; HttpOnly`, so cookie handling can be
+/// round-tripped (by sending the cookie back, and seeing it under `cookies`)
+pub(crate) const SET_COOKIE_HEADER: &str = "x-echo-set-cookie";
+pub(crate) const SET_COOKIE_PARAM: &str = "echo_set_cookie";
+
+/// Request header (or query parameter) supplying a template the echo is rendered with
+pub(crate) const TEMPLATE_HEADER: &str = "x-echo-template";
+pub(crate) const TEMPLATE_PARAM: &str = "echo_template";
+
+/// Request header (or query parameter) padding the echo to a fixed size, e.g. `4KiB`
+pub(crate) const PAD_HEADER: &str = "x-echo-pad";
+pub(crate) const PAD_PARAM: &str = "echo_pad";
+
+/// Request header (or query parameter) adding the body's canonical
+/// JSON form (and its hash) to the echo, e.g. `true`
+pub(crate) const CANONICAL_HEADER: &str = "x-echo-canonical";
+pub(crate) const CANONICAL_PARAM: &str = "echo_canonical";
+
+/// Request header (or query parameter) asserting something of the request, as
+/// an expression evaluated against its echo, e.g. `body.id == 42`
+pub(crate) const ASSERT_HEADER: &str = "x-echo-assert";
+pub(crate) const ASSERT_PARAM: &str = "echo_assert";
+
+/// Request header (or query parameter) capping the size of the response, e.g. `1KiB`
+///
+/// The echo's body is cut short (or emptied, should the rest of the echo alone not fit) so
+/// that the JSON echo still parses, then whatever's sent is cut at the limit regardless, so
+/// other formats, templates, and echoes too big even without a body arrive cut mid-document.
+pub(crate) const MAX_BYTES_HEADER: &str = "x-echo-max-response-bytes";
+pub(crate) const MAX_BYTES_PARAM: &str = "echo_max_response_bytes";
+
+/// Response header marking a truncated response with its full (i.e. untruncated) size
+pub(crate) const TRUNCATED_HEADER: &str = "x-echo-truncated";
+
+/// The largest size an echo will be padded to
+pub(crate) const MAX_PAD: usize = 10 * 1024 * 1024;
+
+/// How the client asked for the echo's response to be shaped
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Hints {
+    pub(crate) status: Option<StatusCode>,
+    pub(crate) delay: Option<Duration>,
+    pub(crate) headers: Vec<(HeaderName, HeaderValue)>,
+    pub(crate) set_cookies: Vec<HeaderValue>,
+    pub(crate) template: Option<String>,
+    pub(crate) pad_to: Option<usize>,
+    pub(crate) max_bytes: Option<usize>,
+    pub(crate) canonical: bool,
+    pub(crate) assertions: Vec<String>,
+}
+
+impl Hints {
+    /// Collect the hints from the request's headers and query parameters,
+    /// preferring the former, and ignoring (with a warning) invalid ones
+    pub(crate) fn from_request(headers: &HeaderMap, params: &HashMap<String, String>) -> Self {
+        let hint = |header: &'static str, param: &'static str| {
+            headers
+                .get(header)
+                .and_then(|value| value.to_str().ok())
+                .or_else(|| params.get(param).map(String::as_str))
+                .map(|value| (header, value.trim()))
+        };
+
+        let status = hint(STATUS_HEADER, STATUS_PARAM).and_then(|(name, value)| {
+            value
+                .parse::<u16>()
+                .map_err(|error| error.to_string())
+                .and_then(|code| StatusCode::from_u16(code).map_err(|error| error.to_string()))
+                .and_then(|status| match status.is_informational() {
+                    true => Err("informational statuses can't end a response".to_owned()),
+                    false => Ok(status),
+                })
+                .map_err(|error| {
+                    tracing::warn!("Ignoring invalid `{name}` value {value:?}: {error}")
+                })
+                .ok()
+        });
+
+        let delay = hint(DELAY_HEADER, DELAY_PARAM).and_then(|(name, value)| {
+            humantime::parse_duration(value)
+                .map(|delay| delay.min(MAX_DELAY))
+                .map_err(|error| {
+                    tracing::warn!("Ignoring invalid `{name}` value {value:?}: {error}")
+                })
+                .ok()
+        });
+
+        let pad_to = hint(PAD_HEADER, PAD_PARAM).and_then(|(name, value)| {
+            parse_size(value)
+                .map_err(|error| {
+                    tracing::warn!("Ignoring invalid `{name}` value {value:?}: {error}")
+                })
+                .ok()
+        });
+
+        let max_bytes = hint(MAX_BYTES_HEADER, MAX_BYTES_PARAM).and_then(|(name, value)| {
+            parse_size(value)
+                .map_err(|error| {
+                    tracing::warn!("Ignoring invalid `{name}` value {value:?}: {error}")
+                })
+                .ok()
+        });
+
+        let canonical = hint(CANONICAL_HEADER, CANONICAL_PARAM).is_some_and(|(_, value)| {
+            !matches!(
+                value.to_ascii_lowercase().as_str(),
+                "0" | "false" | "no" | "off"
+            )
+        });
+
+        // templates are taken verbatim, leading and trailing whitespace included
+        let template = headers
+            .get(TEMPLATE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+            .or_else(|| params.get(TEMPLATE_PARAM).cloned());
+
+        // repeatable as headers, but not as query parameters
+        let repeated =
+            |header: &'static str, param: &'static str| match headers.contains_key(header) {
+                true => headers
+                    .get_all(header)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .collect::<Vec<&str>>(),
+                false => params.get(param).map(String::as_str).into_iter().collect(),
+            };
+
+        let set_cookies = repeated(SET_COOKIE_HEADER, SET_COOKIE_PARAM)
+            .into_iter()
+            .filter_map(|cookie| {
+                HeaderValue::try_from(cookie.trim())
+                    .map_err(|error| {
+                        tracing::warn!("Ignoring invalid `{SET_COOKIE_HEADER}`: {error}")
+                    })
+                    .ok()
+            })
+            .collect();
+
+        let assertions = repeated(ASSERT_HEADER, ASSERT_PARAM)
+            .into_iter()
+            .map(str::trim)
+            .filter(|assertion| !assertion.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        let headers = repeated(HEADER_HEADER, HEADER_PARAM)
+            .into_iter()
+            .filter_map(|pair| {
+                parse_header_pair(pair)
+                    .map_err(|error| tracing::warn!("Ignoring invalid `{HEADER_HEADER}`: {error}"))
+                    .ok()
+            })
+            .collect();
+
+        Self {
+            status,
+            delay,
+            headers,
+            set_cookies,
+            template,
+            pad_to,
+            max_bytes,
+            canonical,
+            assertions,
+        }
+    }
+
+    /// Apply the requested status and headers to the response
+    pub(crate) fn apply(self, mut response: Response) -> Response {
+        if let Some(status) = self.status {
+            *response.status_mut() = status;
+        }
+
+        for (name, value) in self.headers {
+            response.headers_mut().append(name, value);
+        }
+
+        for cookie in self.set_cookies {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+
+        response
+    }
+}
+
+/// Pad the response's body with trailing spaces (which leave JSON, YAML, and
+/// text echoes intact) until it's (at least) the given number of bytes long
+pub(crate) async fn pad(response: Response, size: usize) -> Response {
+    let (mut parts, body) = response.into_parts();
+
+    let mut bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => Vec::from(bytes),
+        Err(error) => {
+            return Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+                .into_response()
+        }
+    };
+
+    bytes.resize(bytes.len().max(size.min(MAX_PAD)), b' ');
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    Response::from_parts(parts, body::boxed(Full::from(bytes)))
+}
+
+/// Cut the response's body short at (at most) the given number of bytes, returning
+/// its full size if it had to be
+pub(crate) async fn cap(response: Response, max_bytes: usize) -> (Response, Option<usize>) {
+    let (mut parts, body) = response.into_parts();
+
+    let mut bytes = match hyper::body::to_bytes(body).await {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            let failure = Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string());
+            return (failure.into_response(), None);
+        }
+    };
+
+    let original = bytes.len();
+
+    if original > max_bytes {
+        bytes.truncate(max_bytes);
+        parts.headers.remove(header::CONTENT_LENGTH);
+    }
+
+    let response = Response::from_parts(parts, body::boxed(Full::from(bytes)));
+
+    (response, (original > max_bytes).then_some(original))
+}
+
+/// Cut the echo's body short so that the (JSON-serialized) echo fits in (at most)
+/// the given number of bytes, noting its full size in its `truncated` field (and
+/// returning it) if it had to be, so that the echo remains parseable
+pub(crate) fn truncate(echo: &mut Value, max_bytes: usize) -> Option<usize> {
+    let size = |echo: &Value| serde_json::to_vec(echo).map_or(0, |bytes| bytes.len());
+
+    let original = size(echo);
+
+    if original <= max_bytes {
+        return None;
+    }
+
+    // bodies that aren't text are cut short as they're serialized
+    let body = match echo["body"].take() {
+        Value::String(text) => text,
+        other => other.to_string(),
+    };
+
+    echo["truncated"] = json!({"original_bytes": original, "body_bytes": body.len()});
+    echo["body"] = Value::String(String::new());
+
+    let mut budget = max_bytes.saturating_sub(size(echo));
+
+    // escaping can make the body take up more room than its length, so trim until it fits
+    loop {
+        let mut end = budget.min(body.len());
+
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        echo["body"] = Value::String(body[..end].to_owned());
+
+        let over = size(echo).saturating_sub(max_bytes);
+
+        if over == 0 || end == 0 {
+            return Some(original);
+        }
+
+        budget = end.saturating_sub(over);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hints(params: &[(&str, &str)]) -> Hints {
+        let params = params
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        Hints::from_request(&HeaderMap::new(), &params)
+    }
+
+    #[test]
+    fn status_must_end_a_response() {
+        assert_eq!(
+            hints(&[(STATUS_PARAM, "404")]).status,
+            Some(StatusCode::NOT_FOUND)
+        );
+        assert_eq!(hints(&[(STATUS_PARAM, "200")]).status, Some(StatusCode::OK));
+        assert_eq!(hints(&[(STATUS_PARAM, "101")]).status, None);
+        assert_eq!(hints(&[(STATUS_PARAM, "100")]).status, None);
+        assert_eq!(hints(&[(STATUS_PARAM, "abc")]).status, None);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let delay = |value| hints(&[(DELAY_PARAM, value)]).delay;
+
+        assert_eq!(delay("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(delay("1h"), Some(MAX_DELAY));
+        assert_eq!(delay("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn cap_cuts_whatever_was_rendered() {
+        let (response, cut) = cap("a: 1\nb: 2\n".into_response(), 5).await;
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        assert_eq!(cut, Some(10));
+        assert_eq!(&bytes[..], b"a: 1\n");
+
+        let (_, cut) = cap("fits".into_response(), 5).await;
+
+        assert_eq!(cut, None);
+    }
+}
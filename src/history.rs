@@ -0,0 +1,980 @@
+// Echoed-Request History
+
+// Standard Library Imports
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    io::{self, Write},
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    body::{Body, Bytes},
+    extract::{Json, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use serde_json::Value;
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+    jwt::unix_now,
+    proxy::strip_hop_by_hop,
+};
+
+/// Request header identifying the captured request a replay is a copy of
+const REPLAY_HEADER: &str = "x-echo-replay-of";
+
+/// A captured request, as it was received (i.e. before redaction), so it can be replayed
+#[derive(Debug)]
+pub(crate) struct OriginalRequest {
+    pub(crate) method: Method,
+    pub(crate) uri: Uri,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+/// An echoed request, as it was echoed (i.e. after redaction)
+#[derive(Clone, Debug, serde::Serialize)]
+struct CapturedRequest {
+    id: u64,
+    /// Seconds since the unix epoch
+    received_at: u64,
+    #[serde(skip)]
+    method: String,
+    #[serde(skip)]
+    path: String,
+    /// Status code of the echo's response
+    status: u16,
+    #[serde(flatten)]
+    echo: Value,
+    #[serde(skip)]
+    original: Arc<OriginalRequest>,
+}
+
+/// The most recently echoed requests, oldest first
+#[derive(Debug)]
+pub(crate) struct RequestHistory {
+    capacity: usize,
+    next_id: AtomicU64,
+    requests: Mutex<VecDeque<CapturedRequest>>,
+    views: RwLock<BTreeMap<String, CaptureQuery>>,
+}
+
+/// What a query condition compares
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Method,
+    Path,
+    Status,
+    Client,
+}
+
+/// How a query condition compares it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Equals,
+    NotEquals,
+    Contains,
+    AtLeast,
+    AtMost,
+    Above,
+    Below,
+}
+
+impl Operator {
+    /// Longest first, so that e.g. `>=` isn't taken for `>`
+    const ALL: [(&'static str, Self); 7] = [
+        ("!=", Self::NotEquals),
+        (">=", Self::AtLeast),
+        ("<=", Self::AtMost),
+        ("=", Self::Equals),
+        ("~", Self::Contains),
+        (">", Self::Above),
+        ("<", Self::Below),
+    ];
+
+    fn symbol(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(_, operator)| *operator == self)
+            .map_or("=", |(symbol, _)| symbol)
+    }
+
+    fn is_ordering(self) -> bool {
+        matches!(
+            self,
+            Self::AtLeast | Self::AtMost | Self::Above | Self::Below
+        )
+    }
+}
+
+/// A condition on a captured request, e.g. `method=POST`, `path~/stripe`, or `status>=400`
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Condition {
+    field: Field,
+    operator: Operator,
+    value: String,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+
+        let split = value
+            .find(|char: char| "!=<>~".contains(char))
+            .ok_or_else(|| format!("expected e.g. `status>=400`, got {value:?}"))?;
+
+        let (field, rest) = value.split_at(split);
+
+        let field = match field.trim() {
+            "method" => Field::Method,
+            "path" => Field::Path,
+            "status" => Field::Status,
+            "client" => Field::Client,
+            other => return Err(format!("unknown capture field: {other:?}")),
+        };
+
+        let (symbol, operator) = Operator::ALL
+            .iter()
+            .find(|(symbol, _)| rest.starts_with(symbol))
+            .copied()
+            .ok_or_else(|| format!("unknown operator in {value:?}"))?;
+
+        let value = rest[symbol.len()..].trim().to_owned();
+
+        if field == Field::Status && value.parse::<u16>().is_err() {
+            return Err(format!("invalid status code: {value:?}"));
+        }
+
+        if operator.is_ordering() && field != Field::Status {
+            return Err(format!("`{symbol}` only applies to `status`"));
+        }
+
+        Ok(Self {
+            field,
+            operator,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = match self.field {
+            Field::Method => "method",
+            Field::Path => "path",
+            Field::Status => "status",
+            Field::Client => "client",
+        };
+
+        write!(f, "{field}{}{}", self.operator.symbol(), self.value)
+    }
+}
+
+impl Condition {
+    fn matches(&self, request: &CapturedRequest) -> bool {
+        if self.field == Field::Status {
+            let expected = self.value.parse::<u16>().unwrap_or_default();
+
+            return match self.operator {
+                Operator::Equals => request.status == expected,
+                Operator::NotEquals => request.status != expected,
+                Operator::Contains => request.status.to_string().contains(&self.value),
+                Operator::AtLeast => request.status >= expected,
+                Operator::AtMost => request.status <= expected,
+                Operator::Above => request.status > expected,
+                Operator::Below => request.status < expected,
+            };
+        }
+
+        let actual = match self.field {
+            Field::Method => request.method.as_str(),
+            Field::Path => request.path.as_str(),
+            _ => request.echo["client"].as_str().unwrap_or_default(),
+        };
+
+        let equal = match self.field {
+            Field::Method => actual.eq_ignore_ascii_case(&self.value),
+            _ => actual == self.value,
+        };
+
+        match self.operator {
+            Operator::NotEquals => !equal,
+            Operator::Contains => actual.contains(&self.value),
+            _ => equal,
+        }
+    }
+}
+
+/// Conditions a captured request must all meet, e.g. `method=POST;path~/stripe;status>=400`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CaptureQuery(Vec<Condition>);
+
+impl FromStr for CaptureQuery {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .split(';')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(Condition::from_str)
+            .collect::<Result<Vec<Condition>, String>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for CaptureQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conditions = self
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+
+        f.write_str(&conditions.join(";"))
+    }
+}
+
+impl CaptureQuery {
+    fn matches(&self, request: &CapturedRequest) -> bool {
+        self.0.iter().all(|condition| condition.matches(request))
+    }
+}
+
+/// A saved view, i.e. a named query over the captured requests, e.g.
+/// `stripe-failures=method=POST;path~/stripe;status>=400`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct SavedView {
+    name: String,
+    query: CaptureQuery,
+}
+
+impl SavedView {
+    pub(crate) fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl FromStr for SavedView {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, query) = value
+            .split_once('=')
+            .map(|(name, query)| (name.trim(), query))
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or_else(|| format!("expected `name=query`, got {value:?}"))?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            query: query.parse()?,
+        })
+    }
+}
+
+/// Which of the captured requests to list
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+struct HistoryFilter {
+    method: Option<String>,
+    path: Option<String>,
+    /// Name of a saved view
+    view: Option<String>,
+    /// Cursor (i.e. the id of a captured request) only those captured after are listed
+    since: Option<u64>,
+    /// Time (as an RFC 3339 timestamp, or seconds since the unix epoch)
+    /// only those captured at or after are listed
+    since_time: Option<String>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, request: &CapturedRequest) -> bool {
+        self.method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(&request.method))
+            && self.path.as_ref().is_none_or(|path| *path == request.path)
+            && self.since.is_none_or(|since| request.id > since)
+    }
+
+    /// The filter as a predicate, along with the named view (if any) and starting time
+    fn predicate(
+        &self,
+        history: &RequestHistory,
+    ) -> Result<impl Fn(&CapturedRequest) -> bool + '_, Failure> {
+        let view = match self.view.as_deref() {
+            Some(name) => history.view(name)?,
+            None => CaptureQuery::default(),
+        };
+
+        let since_time = match self.since_time.as_deref().map(str::trim) {
+            None => None,
+            Some(time) => Some(time.parse::<u64>().or_else(|_| {
+                humantime::parse_rfc3339_weak(time)
+                    .map(|time| {
+                        time.duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs()
+                    })
+                    .map_err(|error| {
+                        Failure::new(
+                            StatusCode::BAD_REQUEST,
+                            format!("invalid `since_time` {time:?}: {error}"),
+                        )
+                    })
+            })?),
+        };
+
+        Ok(move |request: &CapturedRequest| {
+            self.matches(request)
+                && view.matches(request)
+                && since_time.is_none_or(|since| request.received_at >= since)
+        })
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct HistoryReport {
+    count: usize,
+    /// The id of the newest request listed, to list only those captured after it next time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cursor: Option<u64>,
+    requests: Vec<CapturedRequest>,
+}
+
+/// How exported captures are compressed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn compress(self, ndjson: Vec<u8>) -> io::Result<Vec<u8>> {
+        match self {
+            Self::None => Ok(ndjson),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&ndjson)?;
+                encoder.finish()
+            }
+            Self::Zstd => zstd::bulk::compress(&ndjson, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::None => "application/x-ndjson",
+            Self::Gzip => "application/gzip",
+            Self::Zstd => "application/zstd",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::None => "ndjson",
+            Self::Gzip => "ndjson.gz",
+            Self::Zstd => "ndjson.zst",
+        }
+    }
+}
+
+/// How the captured requests are exported
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+struct ExportOptions {
+    #[serde(default)]
+    compression: Compression,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct ViewSummary {
+    name: String,
+    query: String,
+    /// How many of the captured requests are in view
+    count: usize,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct ViewsReport {
+    count: usize,
+    views: Vec<ViewSummary>,
+}
+
+impl RequestHistory {
+    /// A history keeping up to `capacity` requests (if it's to keep any at all)
+    /// and offering the given saved views of them
+    pub(crate) fn new(capacity: usize, views: Vec<SavedView>) -> Option<Self> {
+        (capacity > 0).then(|| Self {
+            capacity,
+            next_id: AtomicU64::new(1),
+            requests: Mutex::new(VecDeque::with_capacity(capacity)),
+            views: RwLock::new(
+                views
+                    .into_iter()
+                    .map(|view| (view.name, view.query))
+                    .collect(),
+            ),
+        })
+    }
+
+    pub(crate) fn record(
+        &self,
+        method: &str,
+        path: &str,
+        status: StatusCode,
+        echo: Value,
+        original: OriginalRequest,
+    ) {
+        let mut requests = self.requests.lock().unwrap();
+
+        if requests.len() >= self.capacity {
+            requests.pop_front();
+        }
+
+        requests.push_back(CapturedRequest {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            received_at: unix_now(),
+            method: method.to_owned(),
+            path: path.to_owned(),
+            status: status.as_u16(),
+            echo,
+            original: Arc::new(original),
+        });
+    }
+
+    fn original(&self, id: u64) -> Option<Arc<OriginalRequest>> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|request| request.id == id)
+            .map(|request| request.original.clone())
+    }
+
+    /// The captured requests matching the given predicate, oldest first
+    fn select(&self, predicate: impl Fn(&CapturedRequest) -> bool) -> HistoryReport {
+        let requests = self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| predicate(request))
+            .cloned()
+            .collect::<Vec<CapturedRequest>>();
+
+        HistoryReport {
+            count: requests.len(),
+            cursor: requests.last().map(|request| request.id),
+            requests,
+        }
+    }
+
+    fn view(&self, name: &str) -> Result<CaptureQuery, Failure> {
+        self.views
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Failure::new(StatusCode::NOT_FOUND, format!("no saved view {name:?}")))
+    }
+}
+
+/// Re-sends captured requests, to a given target or (by default) the server itself
+#[derive(Debug)]
+pub(crate) struct Replayer {
+    client: reqwest::Client,
+    /// Replays to the server itself, whose own certificate needn't be trusted
+    local_client: reqwest::Client,
+    local: String,
+}
+
+impl Replayer {
+    /// Replay requests to the server listening at the given host
+    /// and port (or to loopback, if it listens on every address)
+    pub(crate) fn new(host: &str, port: usize, tls: bool) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+            local_client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .danger_accept_invalid_certs(true)
+                .build()?,
+            local: local_url(host, port, tls),
+        })
+    }
+
+    fn client_for(&self, target: &str) -> &reqwest::Client {
+        if target == self.local {
+            &self.local_client
+        } else {
+            &self.client
+        }
+    }
+}
+
+/// Base URL of the server listening at the given host and port
+/// (or of loopback, if it listens on every address)
+pub(crate) fn local_url(host: &str, port: usize, tls: bool) -> String {
+    let host = match host
+        .trim_matches(|char| char == '[' || char == ']')
+        .parse::<IpAddr>()
+    {
+        Ok(IpAddr::V4(ip)) if ip.is_unspecified() => "127.0.0.1".to_owned(),
+        Ok(IpAddr::V6(ip)) if ip.is_unspecified() => "[::1]".to_owned(),
+        Ok(IpAddr::V6(ip)) => format!("[{ip}]"),
+        _ => host.to_owned(),
+    };
+
+    format!("{}://{host}:{port}", if tls { "https" } else { "http" })
+}
+
+/// Where a captured request should be replayed to
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+struct ReplayParams {
+    /// Base URL, e.g. `http://localhost:3000` (the server itself, if omitted)
+    target: Option<String>,
+}
+
+/// What became of a replayed request
+#[derive(Clone, Debug, serde::Serialize)]
+struct ReplayReport {
+    id: u64,
+    url: String,
+    status: u16,
+    headers: BTreeMap<String, String>,
+    body: Value,
+}
+
+#[tracing::instrument]
+pub(crate) fn router(
+    history: Option<Arc<RequestHistory>>,
+    replayer: Replayer,
+    capture_token: Option<AdminToken>,
+    replay_token: Option<AdminToken>,
+) -> Router {
+    let Some(history) = history else {
+        return Router::new();
+    };
+
+    let router = Router::new()
+        .route("/_echo/requests", routing::get(list).delete(clear))
+        .route("/_echo/requests/export", routing::get(export))
+        .route("/_echo/views", routing::get(list_views))
+        .route(
+            "/_echo/views/:name",
+            routing::get(show_view).put(save_view).delete(drop_view),
+        )
+        .with_state(history.clone())
+        .merge({
+            let replays = Router::new()
+                .route("/_echo/requests/:id/replay", routing::post(replay))
+                .with_state((history, Arc::new(replayer)));
+
+            // once captures are restricted, replaying them anywhere is too
+            if capture_token.is_some() {
+                replays
+            } else {
+                replays.route_layer(middleware::from_fn_with_state(replay_token, guard_target))
+            }
+        });
+
+    match capture_token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn list(
+    State(history): State<Arc<RequestHistory>>,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Json<HistoryReport>, Failure> {
+    Ok(Json(history.select(filter.predicate(&history)?)))
+}
+
+/// The captured requests as NDJSON (one per line, oldest first), optionally
+/// compressed, and marked with the cursor to export only newer ones next time
+#[tracing::instrument(skip_all)]
+async fn export(
+    State(history): State<Arc<RequestHistory>>,
+    Query(filter): Query<HistoryFilter>,
+    Query(options): Query<ExportOptions>,
+) -> Result<Response, Failure> {
+    let report = history.select(filter.predicate(&history)?);
+    let (first, cursor) = (
+        report.requests.first().map(|request| request.id),
+        report.cursor.or(filter.since),
+    );
+
+    let compression = options.compression;
+
+    let exported = tokio::task::spawn_blocking(move || {
+        let mut ndjson = Vec::new();
+
+        for request in &report.requests {
+            serde_json::to_writer(&mut ndjson, request)?;
+            ndjson.push(b'\n');
+        }
+
+        compression.compress(ndjson)
+    })
+    .await
+    .map_err(io::Error::other)
+    .and_then(|exported| exported)
+    .map_err(|error| Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))?;
+
+    let name = match (first, report.cursor) {
+        (Some(first), Some(last)) => format!("captures-{first}-{last}"),
+        _ => "captures".to_owned(),
+    };
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, compression.content_type().to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{name}.{}\"",
+                    compression.extension()
+                ),
+            ),
+        ],
+        exported,
+    )
+        .into_response();
+
+    response
+        .headers_mut()
+        .insert("x-echo-count", HeaderValue::from(report.count));
+
+    if let Some(cursor) = cursor {
+        response
+            .headers_mut()
+            .insert("x-echo-cursor", HeaderValue::from(cursor));
+    }
+
+    Ok(response)
+}
+
+#[tracing::instrument(skip_all)]
+async fn list_views(State(history): State<Arc<RequestHistory>>) -> Json<ViewsReport> {
+    let views = history
+        .views
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, query)| (name.clone(), query.clone()))
+        .collect::<Vec<(String, CaptureQuery)>>();
+
+    let views = views
+        .into_iter()
+        .map(|(name, query)| ViewSummary {
+            count: history.select(|request| query.matches(request)).count,
+            query: query.to_string(),
+            name,
+        })
+        .collect::<Vec<ViewSummary>>();
+
+    Json(ViewsReport {
+        count: views.len(),
+        views,
+    })
+}
+
+/// The captured requests in the named view
+#[tracing::instrument(skip_all)]
+async fn show_view(
+    State(history): State<Arc<RequestHistory>>,
+    Path(name): Path<String>,
+) -> Result<Json<HistoryReport>, Failure> {
+    let query = history.view(&name)?;
+
+    Ok(Json(history.select(|request| query.matches(request))))
+}
+
+/// Save (or replace) a view, given its query as the request body
+#[tracing::instrument(skip_all)]
+async fn save_view(
+    State(history): State<Arc<RequestHistory>>,
+    Path(name): Path<String>,
+    query: String,
+) -> Result<StatusCode, Failure> {
+    let query = query
+        .parse::<CaptureQuery>()
+        .map_err(|error| Failure::new(StatusCode::BAD_REQUEST, error))?;
+
+    tracing::info!("Saving view {name:?} of captured requests: {query}");
+
+    Ok(match history.views.write().unwrap().insert(name, query) {
+        Some(_) => StatusCode::NO_CONTENT,
+        None => StatusCode::CREATED,
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn drop_view(
+    State(history): State<Arc<RequestHistory>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, Failure> {
+    history
+        .views
+        .write()
+        .unwrap()
+        .remove(&name)
+        .map(|_| StatusCode::NO_CONTENT)
+        .ok_or_else(|| Failure::new(StatusCode::NOT_FOUND, format!("no saved view {name:?}")))
+}
+
+#[tracing::instrument(skip_all)]
+async fn clear(State(history): State<Arc<RequestHistory>>) -> StatusCode {
+    history.requests.lock().unwrap().clear();
+
+    StatusCode::NO_CONTENT
+}
+
+/// Require an `operator` token to replay captures to a given target (as they
+/// may carry other clients' credentials), refusing to if no tokens are configured
+#[tracing::instrument(skip_all)]
+async fn guard_target(
+    State(token): State<Option<AdminToken>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let targeted = req.uri().query().is_some_and(|query| {
+        serde_urlencoded::from_str::<ReplayParams>(query)
+            .map_or(true, |params| params.target.is_some())
+    });
+
+    match token {
+        _ if !targeted => next.run(req).await,
+        Some(token) => {
+            admin::require_token(State(token.requiring(admin::Role::Operator)), req, next).await
+        }
+        None => Failure::new(
+            StatusCode::FORBIDDEN,
+            "replaying to a `target` requires `--admin-token` (or `--role-token`)",
+        )
+        .into_response(),
+    }
+}
+
+/// Re-send a captured request, as it was received, to the given target (or to
+/// the server itself, to run it through the configured routes and stubs again)
+#[tracing::instrument(skip_all)]
+async fn replay(
+    State((history, replayer)): State<(Arc<RequestHistory>, Arc<Replayer>)>,
+    Path(id): Path<u64>,
+    Query(params): Query<ReplayParams>,
+) -> Response {
+    let Some(original) = history.original(id) else {
+        return Failure::new(
+            StatusCode::NOT_FOUND,
+            format!("no captured request with id {id}"),
+        )
+        .into_response();
+    };
+
+    let target = params
+        .target
+        .as_deref()
+        .unwrap_or(&replayer.local)
+        .trim_end_matches('/');
+
+    if !(target.starts_with("http://") || target.starts_with("https://")) {
+        return Failure::new(
+            StatusCode::BAD_REQUEST,
+            format!("replay target must be an http(s) URL, got {target:?}"),
+        )
+        .into_response();
+    }
+
+    let url = format!(
+        "{target}{}",
+        original
+            .uri
+            .path_and_query()
+            .map_or("/", |path| path.as_str())
+    );
+
+    let mut headers = strip_hop_by_hop(&original.headers);
+    // the target's own host (and the copy's length) apply instead
+    headers.remove(header::HOST);
+    headers.remove(header::CONTENT_LENGTH);
+    headers.insert(
+        HeaderName::from_static(REPLAY_HEADER),
+        HeaderValue::from(id),
+    );
+
+    tracing::info!("Replaying captured request {id} to {url}");
+
+    let response = match replayer
+        .client_for(target)
+        .request(original.method.clone(), &url)
+        .headers(headers)
+        .body(original.body.clone())
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(error) => {
+            return Failure::new(
+                StatusCode::BAD_GATEWAY,
+                format!("failed to replay request {id} to {url}: {error}"),
+            )
+            .into_response()
+        }
+    };
+
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_owned())))
+        .collect();
+
+    let body = match response.bytes().await {
+        Ok(body) => serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned())),
+        Err(error) => {
+            return Failure::new(
+                StatusCode::BAD_GATEWAY,
+                format!("failed to read the reply to request {id} from {url}: {error}"),
+            )
+            .into_response()
+        }
+    };
+
+    Json(ReplayReport {
+        id,
+        url,
+        status,
+        headers,
+        body,
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captured(method: &str, path: &str, status: u16) -> CapturedRequest {
+        CapturedRequest {
+            id: 1,
+            received_at: 0,
+            method: method.to_owned(),
+            path: path.to_owned(),
+            status,
+            echo: serde_json::json!({"client": "127.0.0.1:1234"}),
+            original: Arc::new(OriginalRequest {
+                method: Method::GET,
+                uri: Uri::from_static("/"),
+                headers: HeaderMap::new(),
+                body: Bytes::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let parsed = |value: &str| value.parse::<Condition>().unwrap();
+
+        assert_eq!(
+            parsed("status>=400"),
+            Condition {
+                field: Field::Status,
+                operator: Operator::AtLeast,
+                value: "400".into(),
+            }
+        );
+        assert_eq!(
+            parsed(" path ~ /x "),
+            Condition {
+                field: Field::Path,
+                operator: Operator::Contains,
+                value: "/x".into(),
+            }
+        );
+        assert_eq!(
+            parsed("method!=GET"),
+            Condition {
+                field: Field::Method,
+                operator: Operator::NotEquals,
+                value: "GET".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        for value in [
+            "path>=x",
+            "status=abc",
+            "host=example.com",
+            "status",
+            "status!400",
+        ] {
+            assert!(
+                value.parse::<Condition>().is_err(),
+                "{value:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn queries_round_trip() {
+        let query = "method=POST;path~/stripe;status>=400;client!=10.0.0.1";
+
+        assert_eq!(query.parse::<CaptureQuery>().unwrap().to_string(), query);
+        assert_eq!(
+            " method=POST ; ; status<500 "
+                .parse::<CaptureQuery>()
+                .unwrap()
+                .to_string(),
+            "method=POST;status<500"
+        );
+        assert!("method=POST;bogus=1".parse::<CaptureQuery>().is_err());
+    }
+
+    #[test]
+    fn queries_match_captured_requests() {
+        let query = "method=post;path~/stripe;status>=400"
+            .parse::<CaptureQuery>()
+            .unwrap();
+
+        assert!(query.matches(&captured("POST", "/stripe/webhook", 500)));
+        assert!(!query.matches(&captured("POST", "/stripe/webhook", 200)));
+        assert!(!query.matches(&captured("GET", "/stripe/webhook", 500)));
+        assert!(!query.matches(&captured("POST", "/paypal", 500)));
+        assert!(CaptureQuery::default().matches(&captured("GET", "/", 200)));
+    }
+
+    #[test]
+    fn parses_saved_views() {
+        let view = "failures=status>=400;path~/x".parse::<SavedView>().unwrap();
+
+        assert_eq!(view.name(), "failures");
+        assert_eq!(view.query.to_string(), "status>=400;path~/x");
+        assert!("=status>=400".parse::<SavedView>().is_err());
+        assert!("no-query-here".parse::<SavedView>().is_err());
+    }
+}
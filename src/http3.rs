@@ -0,0 +1,156 @@
+// HTTP/3 (QUIC) Serving
+
+// Standard Library Imports
+use std::{net::SocketAddr, sync::Arc};
+
+// Third Party Imports
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, State},
+    http::{header, HeaderValue, Request, Version},
+    middleware::Next,
+    response::Response,
+    Router,
+};
+use h3::{quic::BidiStream, server::RequestStream};
+use hyper::body::{Buf, Bytes};
+use tower::ServiceExt;
+
+// Crate-Level Imports
+use crate::tls::ClientCertificate;
+
+/// Advertise the HTTP/3 endpoint to clients connected over HTTP/1.1 or HTTP/2
+#[tracing::instrument(skip_all)]
+pub(crate) async fn advertise<B>(
+    State(alt_svc): State<HeaderValue>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut response = next.run(req).await;
+
+    response
+        .headers_mut()
+        .insert(header::ALT_SVC, alt_svc.clone());
+
+    response
+}
+
+/// The `Alt-Svc` header value advertising HTTP/3 on the given port
+pub(crate) fn alt_svc(port: u16) -> HeaderValue {
+    HeaderValue::try_from(format!("h3=\":{port}\"; ma=86400"))
+        .expect("formatted alt-svc value is valid")
+}
+
+/// Bind the HTTP/3 endpoint to the given (UDP) address
+pub(crate) fn bind(addr: SocketAddr, tls: rustls::ServerConfig) -> anyhow::Result<quinn::Endpoint> {
+    let endpoint = quinn::Endpoint::server(quinn::ServerConfig::with_crypto(Arc::new(tls)), addr)
+        .map_err(|error| anyhow::anyhow!("http/3 endpoint on {addr}: {error}"))?;
+
+    tracing::info!("`echo-rs` HTTP/3 server listening at: https://{addr} (udp)");
+
+    Ok(endpoint)
+}
+
+/// Serve the app over HTTP/3, forever
+#[tracing::instrument(skip_all)]
+pub(crate) async fn serve(endpoint: quinn::Endpoint, app: Router) {
+    while let Some(connecting) = endpoint.accept().await {
+        tokio::spawn(connection(connecting, app.clone()));
+    }
+}
+
+async fn connection(connecting: quinn::Connecting, app: Router) {
+    let conn = match connecting.await {
+        Ok(conn) => conn,
+        Err(error) => {
+            tracing::debug!("HTTP/3 handshake failed: {error}");
+            return;
+        }
+    };
+
+    let client = conn.remote_address();
+    let certificate = conn
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<rustls::Certificate>>().ok())
+        .and_then(|chain| ClientCertificate::from_chain(Some(&chain)));
+
+    let mut conn =
+        match h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(conn)).await {
+            Ok(conn) => conn,
+            Err(error) => {
+                tracing::debug!("HTTP/3 connection from {client} failed: {error}");
+                return;
+            }
+        };
+
+    loop {
+        match conn.accept().await {
+            Ok(Some((req, stream))) => {
+                let (app, certificate) = (app.clone(), certificate.clone());
+
+                tokio::spawn(async move {
+                    if let Err(error) = request(req, stream, app, client, certificate).await {
+                        tracing::debug!("HTTP/3 request from {client} failed: {error}");
+                    }
+                });
+            }
+            Ok(None) => break,
+            Err(error) => {
+                tracing::debug!("HTTP/3 connection from {client} closed: {error}");
+                break;
+            }
+        }
+    }
+}
+
+async fn request<S>(
+    req: Request<()>,
+    mut stream: RequestStream<S, Bytes>,
+    app: Router,
+    client: SocketAddr,
+    certificate: Option<ClientCertificate>,
+) -> anyhow::Result<()>
+where
+    S: BidiStream<Bytes>,
+{
+    let mut body = Vec::new();
+
+    while let Some(mut chunk) = stream.recv_data().await? {
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            body.extend_from_slice(bytes);
+            let read = bytes.len();
+            chunk.advance(read);
+        }
+    }
+
+    let (mut parts, ()) = req.into_parts();
+    parts.version = Version::HTTP_3;
+    parts.extensions.insert(ConnectInfo(client));
+
+    if let Some(certificate) = certificate {
+        parts.extensions.insert(certificate);
+    }
+
+    let response = app
+        .oneshot(Request::from_parts(parts, Body::from(body)))
+        .await?;
+
+    let (parts, mut body) = response.into_parts();
+
+    stream
+        .send_response(Response::from_parts(parts, ()))
+        .await?;
+
+    while let Some(chunk) = body.data().await {
+        stream.send_data(chunk?).await?;
+    }
+
+    if let Some(trailers) = body.trailers().await? {
+        stream.send_trailers(trailers).await?;
+    }
+
+    stream.finish().await?;
+
+    Ok(())
+}
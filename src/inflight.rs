@@ -0,0 +1,149 @@
+// In-Flight Request Visibility
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Instant, SystemTime},
+};
+
+// Third Party Imports
+use axum::{
+    extract::{ConnectInfo, Json, State},
+    http::Request,
+    middleware::{self, Next},
+    response::Response,
+    routing, Router,
+};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    clock,
+};
+
+/// A request that's currently being handled
+#[derive(Clone, Debug)]
+struct Inflight {
+    method: String,
+    path: String,
+    client: Option<SocketAddr>,
+    started: Instant,
+    started_at: SystemTime,
+}
+
+/// The requests currently being handled by the echo routes
+#[derive(Debug, Default)]
+pub(crate) struct InflightRequests {
+    next_id: AtomicU64,
+    requests: Mutex<BTreeMap<u64, Inflight>>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct InflightRequest {
+    method: String,
+    path: String,
+    client: Option<String>,
+    started_at: String,
+    /// Milliseconds since the request arrived
+    elapsed: f64,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct InflightReport {
+    count: usize,
+    requests: Vec<InflightRequest>,
+}
+
+/// Removes its request from the in-flight requests once dropped,
+/// whether the request completed or its client went away
+struct Tracked<'a> {
+    id: u64,
+    requests: &'a InflightRequests,
+}
+
+impl Drop for Tracked<'_> {
+    fn drop(&mut self) {
+        self.requests.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl InflightRequests {
+    fn track<B>(&self, req: &Request<B>) -> Tracked<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.requests.lock().unwrap().insert(
+            id,
+            Inflight {
+                method: req.method().to_string(),
+                path: req.uri().path().to_owned(),
+                client: req
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(client)| *client),
+                started: Instant::now(),
+                started_at: clock::now(),
+            },
+        );
+
+        Tracked { id, requests: self }
+    }
+
+    fn report(&self) -> InflightReport {
+        let mut requests = self
+            .requests
+            .lock()
+            .unwrap()
+            .values()
+            .map(|request| InflightRequest {
+                method: request.method.clone(),
+                path: request.path.clone(),
+                client: request.client.map(|client| client.to_string()),
+                started_at: humantime::format_rfc3339_millis(request.started_at).to_string(),
+                elapsed: request.started.elapsed().as_secs_f64() * 1_000.0,
+            })
+            .collect::<Vec<InflightRequest>>();
+
+        // the longest-running (i.e. most likely stuck) requests first
+        requests.sort_by(|a, b| b.elapsed.total_cmp(&a.elapsed));
+
+        InflightReport {
+            count: requests.len(),
+            requests,
+        }
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(inflight: Arc<InflightRequests>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_inflight", routing::get(report))
+        .with_state(inflight);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn report(State(inflight): State<Arc<InflightRequests>>) -> Json<InflightReport> {
+    Json(inflight.report())
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn track<B>(
+    State(inflight): State<Arc<InflightRequests>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let _tracked = inflight.track(&req);
+
+    next.run(req).await
+}
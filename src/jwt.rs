@@ -0,0 +1,398 @@
+// JSON Web Tokens
+
+// Standard Library Imports
+use std::{
+    fmt,
+    path::Path,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+// Third Party Imports
+use axum::http::{header, HeaderMap};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+    signature::{
+        self, EcdsaKeyPair, KeyPair, RsaPublicKeyComponents, UnparsedPublicKey,
+        ECDSA_P256_SHA256_FIXED, ECDSA_P256_SHA256_FIXED_SIGNING,
+    },
+};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+// Crate-Level Imports
+use crate::clock;
+
+/// The JWS algorithm every token minted by `echo-rs` is signed with
+pub(crate) const ALGORITHM: &str = "ES256";
+
+/// Seconds since the unix epoch
+pub(crate) fn unix_now() -> u64 {
+    clock::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A random, url-safe identifier with the given number of bytes of entropy
+pub(crate) fn random_id(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+
+    SystemRandom::new()
+        .fill(&mut buf)
+        .expect("system randomness is available");
+
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// Split a compact JWS into its (decoded) header and claims, without verifying it
+pub(crate) fn decode(token: &str) -> Result<(Value, Value), String> {
+    let mut parts = token.trim().splitn(3, '.');
+
+    let (Some(header), Some(claims), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err("not a compact JWS (expected three dot-separated segments)".into());
+    };
+
+    let segment = |name: &str, value: &str| {
+        URL_SAFE_NO_PAD
+            .decode(value.trim_end_matches('='))
+            .map_err(|error| format!("{name}: {error}"))
+            .and_then(|bytes| {
+                serde_json::from_slice::<Value>(&bytes).map_err(|error| format!("{name}: {error}"))
+            })
+    };
+
+    Ok((segment("header", header)?, segment("claims", claims)?))
+}
+
+/// ECDSA P-256 key used to sign (and verify) tokens
+pub(crate) struct SigningKey {
+    pair: EcdsaKeyPair,
+    kid: String,
+}
+
+impl fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningKey {
+    /// Generate a fresh, ephemeral signing key
+    pub(crate) fn generate() -> anyhow::Result<Self> {
+        let document =
+            EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+                .map_err(|error| anyhow::anyhow!("failed to generate signing key: {error}"))?;
+
+        Self::from_pkcs8(document.as_ref())
+    }
+
+    /// Load a PKCS#8-encoded P-256 key from a PEM file
+    pub(crate) fn from_pem_file(path: &Path) -> anyhow::Result<Self> {
+        let pem = std::fs::read(path)?;
+
+        let der = rustls_pemfile::pkcs8_private_keys(&mut pem.as_slice())?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("no PKCS#8 private key in {}", path.display()))?;
+
+        Self::from_pkcs8(&der)
+    }
+
+    fn from_pkcs8(der: &[u8]) -> anyhow::Result<Self> {
+        let pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, der, &SystemRandom::new())
+                .map_err(|error| anyhow::anyhow!("invalid signing key: {error}"))?;
+
+        let fingerprint = digest::digest(&digest::SHA256, pair.public_key().as_ref());
+        let kid = URL_SAFE_NO_PAD.encode(&fingerprint.as_ref()[..12]);
+
+        Ok(Self { pair, kid })
+    }
+
+    /// The public half of the key as a JSON Web Key (RFC 7517)
+    pub(crate) fn jwk(&self) -> Value {
+        // Uncompressed SEC1 point: 0x04 || x || y
+        let point = self.pair.public_key().as_ref();
+
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "use": "sig",
+            "alg": ALGORITHM,
+            "kid": self.kid,
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+        })
+    }
+
+    /// Mint a compact JWS carrying the supplied claims
+    pub(crate) fn sign(&self, claims: &Value) -> anyhow::Result<String> {
+        let header = json!({"alg": ALGORITHM, "typ": "JWT", "kid": self.kid});
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?),
+        );
+
+        let signature = self
+            .pair
+            .sign(&SystemRandom::new(), signing_input.as_bytes())
+            .map_err(|error| anyhow::anyhow!("failed to sign token: {error}"))?;
+
+        Ok(format!(
+            "{signing_input}.{}",
+            URL_SAFE_NO_PAD.encode(signature.as_ref())
+        ))
+    }
+
+    /// Verify a token's signature and expiry, returning its claims if it's valid
+    pub(crate) fn verify(&self, token: &str) -> Result<Value, String> {
+        let token = token.trim();
+
+        let (signing_input, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| "not a compact JWS".to_string())?;
+
+        let (header, claims) = decode(token)?;
+
+        if header.get("alg").and_then(Value::as_str) != Some(ALGORITHM) {
+            return Err(format!("unsupported algorithm: {}", header["alg"]));
+        }
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|error| format!("signature: {error}"))?;
+
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, self.pair.public_key().as_ref())
+            .verify(signing_input.as_bytes(), &signature)
+            .map_err(|_| "signature verification failed".to_string())?;
+
+        if claims
+            .get("exp")
+            .and_then(Value::as_u64)
+            .is_some_and(|exp| exp <= unix_now())
+        {
+            return Err("token has expired".into());
+        }
+
+        Ok(claims)
+    }
+}
+
+/// How long fetched JWKS keys are used before they're fetched again
+const JWKS_TTL: Duration = Duration::from_secs(300);
+
+/// How soon the JWKS may be fetched again for a token signed by a key it lacks
+const JWKS_REFETCH_AFTER: Duration = Duration::from_secs(30);
+
+/// The JWT a request carried as its `Authorization: Bearer` credentials, as it's echoed
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct BearerToken {
+    header: Value,
+    claims: Value,
+    /// Whether the token's signature (and expiry) checked out against
+    /// the JWKS, if one was configured to check it against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification_error: Option<String>,
+}
+
+/// Decode the JWT carried by the request's `Authorization: Bearer` header (if
+/// it carries one), verifying it against the JWKS (if one's given)
+pub(crate) async fn inspect(headers: &HeaderMap, jwks: Option<&Jwks>) -> Option<BearerToken> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?.trim();
+
+    let (scheme, token) = value.split_once(' ')?;
+
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    // opaque (i.e. non-JWT) tokens are simply echoed with the rest of the headers
+    let (header, claims) = decode(token).ok()?;
+
+    let outcome = match jwks {
+        None => None,
+        Some(jwks) => Some(jwks.verify(token.trim(), &header, &claims).await),
+    };
+
+    Some(BearerToken {
+        header,
+        claims,
+        verified: outcome.as_ref().map(Result::is_ok),
+        verification_error: outcome.and_then(Result::err),
+    })
+}
+
+/// Public keys, fetched from a JSON Web Key Set (RFC 7517) URL, that bearer tokens are verified with
+#[derive(Debug)]
+pub(crate) struct Jwks {
+    url: String,
+    client: reqwest::Client,
+    keys: RwLock<Option<(Instant, Vec<Value>)>>,
+}
+
+impl Jwks {
+    pub(crate) fn new(url: String) -> anyhow::Result<Self> {
+        Ok(Self {
+            url,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            keys: RwLock::new(None),
+        })
+    }
+
+    async fn fetch(&self) -> Result<Vec<Value>, String> {
+        let set = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|error| format!("failed to fetch JWKS from {}: {error}", self.url))?
+            .json::<Value>()
+            .await
+            .map_err(|error| format!("invalid JWKS from {}: {error}", self.url))?;
+
+        match set.get("keys").and_then(Value::as_array) {
+            Some(keys) => Ok(keys.clone()),
+            None => Err(format!("invalid JWKS from {}: no `keys`", self.url)),
+        }
+    }
+
+    /// The keys the token may have been signed with, fetched anew if they're
+    /// stale (or if none of them match the token's `kid` and they're not fresh)
+    async fn keys(&self, kid: Option<&str>) -> Result<Vec<Value>, String> {
+        let matching = |keys: &[Value]| {
+            keys.iter()
+                .filter(|key| {
+                    kid.is_none_or(|kid| key.get("kid").and_then(Value::as_str) == Some(kid))
+                })
+                .cloned()
+                .collect::<Vec<Value>>()
+        };
+
+        if let Some((fetched, keys)) = self.keys.read().await.as_ref() {
+            let age = fetched.elapsed();
+            let keys = matching(keys);
+
+            if age < JWKS_TTL && !(keys.is_empty() && age >= JWKS_REFETCH_AFTER) {
+                return Ok(keys);
+            }
+        }
+
+        let keys = self.fetch().await?;
+        let matched = matching(&keys);
+
+        *self.keys.write().await = Some((Instant::now(), keys));
+
+        Ok(matched)
+    }
+
+    async fn verify(&self, token: &str, header: &Value, claims: &Value) -> Result<(), String> {
+        let (signing_input, signature) = token
+            .rsplit_once('.')
+            .ok_or_else(|| "not a compact JWS".to_string())?;
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature.trim_end_matches('='))
+            .map_err(|error| format!("signature: {error}"))?;
+
+        let algorithm = header
+            .get("alg")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let keys = self.keys(header.get("kid").and_then(Value::as_str)).await?;
+
+        if keys.is_empty() {
+            return Err("no matching key in the JWKS".into());
+        }
+
+        if !keys
+            .iter()
+            .any(|key| verify_with(key, algorithm, signing_input.as_bytes(), &signature).is_ok())
+        {
+            return Err(
+                verify_with(&keys[0], algorithm, signing_input.as_bytes(), &signature)
+                    .err()
+                    .unwrap_or_else(|| "signature verification failed".into()),
+            );
+        }
+
+        let now = unix_now();
+
+        if claims
+            .get("exp")
+            .and_then(Value::as_u64)
+            .is_some_and(|exp| exp <= now)
+        {
+            return Err("token has expired".into());
+        }
+
+        if claims
+            .get("nbf")
+            .and_then(Value::as_u64)
+            .is_some_and(|nbf| nbf > now)
+        {
+            return Err("token is not yet valid".into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Verify a JWS signature with a JSON Web Key, per the token's algorithm
+fn verify_with(jwk: &Value, algorithm: &str, input: &[u8], signature: &[u8]) -> Result<(), String> {
+    let member = |name: &str| {
+        jwk.get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("JWK has no `{name}`"))
+            .and_then(|value| {
+                URL_SAFE_NO_PAD
+                    .decode(value.trim_end_matches('='))
+                    .map_err(|error| format!("JWK `{name}`: {error}"))
+            })
+    };
+
+    let point = || -> Result<Vec<u8>, String> {
+        // Uncompressed SEC1 point: 0x04 || x || y
+        Ok([vec![0x04], member("x")?, member("y")?].concat())
+    };
+
+    let verified = match algorithm {
+        "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512" => {
+            let parameters: &signature::RsaParameters = match algorithm {
+                "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                _ => &signature::RSA_PSS_2048_8192_SHA512,
+            };
+
+            RsaPublicKeyComponents {
+                n: member("n")?,
+                e: member("e")?,
+            }
+            .verify(parameters, input, signature)
+        }
+        "ES256" => {
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point()?).verify(input, signature)
+        }
+        "ES384" => UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, point()?)
+            .verify(input, signature),
+        "EdDSA" => {
+            UnparsedPublicKey::new(&signature::ED25519, member("x")?).verify(input, signature)
+        }
+        algorithm => return Err(format!("unsupported algorithm: {algorithm:?}")),
+    };
+
+    verified.map_err(|_| "signature verification failed".into())
+}
@@ -0,0 +1,106 @@
+// Kubernetes Metadata
+
+// Standard Library Imports
+use std::{env, fs, path::Path};
+
+// Third Party Imports
+use serde_json::Value;
+
+// Crate-Level Imports
+use crate::jwt;
+
+/// Where Kubernetes mounts the pod's service account credentials
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Where (and under which names) the pod's identity is
+/// exposed via the downward API, in order of preference
+const NAMESPACE_VARS: &[&str] = &["POD_NAMESPACE", "K8S_NAMESPACE"];
+const POD_VARS: &[&str] = &["POD_NAME", "K8S_POD_NAME"];
+const NODE_VARS: &[&str] = &["NODE_NAME", "K8S_NODE_NAME"];
+const SERVICE_ACCOUNT_VARS: &[&str] = &["SERVICE_ACCOUNT", "K8S_SERVICE_ACCOUNT"];
+
+/// Identity of the pod `echo-rs` is running in
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub(crate) struct KubeMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) pod: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) node: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) service_account: Option<String>,
+}
+
+impl KubeMetadata {
+    /// Gather whatever can be learned about the pod from the downward API
+    /// and its mounted service account, or `None` if not running in-cluster
+    pub(crate) fn discover() -> Option<Self> {
+        let dir = Path::new(SERVICE_ACCOUNT_DIR);
+
+        let in_cluster = env::var_os("KUBERNETES_SERVICE_HOST").is_some() || dir.exists();
+
+        let token = fs::read_to_string(dir.join("token"))
+            .ok()
+            .and_then(|token| jwt::decode(&token).ok())
+            .map(|(_, claims)| claims)
+            .unwrap_or_default();
+
+        // projected tokens carry the pod's identity under `kubernetes.io`
+        let claim = |pointer: &str| {
+            token
+                .pointer(pointer)
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+        };
+
+        let metadata = Self {
+            namespace: from_env(NAMESPACE_VARS)
+                .or_else(|| {
+                    fs::read_to_string(dir.join("namespace"))
+                        .ok()
+                        .map(|namespace| namespace.trim().to_owned())
+                })
+                .or_else(|| claim("/kubernetes.io/namespace")),
+            pod: from_env(POD_VARS)
+                .or_else(|| claim("/kubernetes.io/pod/name"))
+                .or_else(|| in_cluster.then(|| env::var("HOSTNAME").ok()).flatten()),
+            node: from_env(NODE_VARS).or_else(|| claim("/kubernetes.io/node/name")),
+            service_account: from_env(SERVICE_ACCOUNT_VARS)
+                .or_else(|| claim("/kubernetes.io/serviceaccount/name"))
+                .or_else(|| {
+                    token
+                        .get("kubernetes.io/serviceaccount/service-account.name")
+                        .and_then(Value::as_str)
+                        .map(str::to_owned)
+                }),
+        };
+
+        if metadata == Self::default() {
+            None
+        } else {
+            Some(metadata)
+        }
+    }
+
+    /// The metadata as (Prometheus-friendly) metric labels
+    pub(crate) fn labels(&self) -> Vec<(String, String)> {
+        [
+            ("namespace", &self.namespace),
+            ("pod", &self.pod),
+            ("node", &self.node),
+            ("service_account", &self.service_account),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_owned(), value.clone()?)))
+        .collect()
+    }
+}
+
+/// The first non-empty value among the named environment variables
+fn from_env(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
@@ -0,0 +1,109 @@
+// Raw TCP & UDP Echo (RFC 862)
+
+// Standard Library Imports
+use std::net::SocketAddr;
+
+// Third Party Imports
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+};
+
+/// The largest datagram a UDP socket can receive
+const MAX_DATAGRAM: usize = 65_535;
+
+fn record(protocol: &'static str, received: usize, sent: usize) {
+    metrics::counter!("l4_bytes_received_total", received as u64, "protocol" => protocol);
+    metrics::counter!("l4_bytes_sent_total", sent as u64, "protocol" => protocol);
+}
+
+/// Bind a TCP echo listener to the given address
+pub(crate) async fn bind_tcp(addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|error| anyhow::anyhow!("tcp echo listener on {addr}: {error}"))?;
+
+    tracing::info!("TCP echo listening on: {}", listener.local_addr()?);
+
+    Ok(listener)
+}
+
+/// Bind a UDP echo socket to the given address
+pub(crate) async fn bind_udp(addr: SocketAddr) -> anyhow::Result<UdpSocket> {
+    let socket = UdpSocket::bind(addr)
+        .await
+        .map_err(|error| anyhow::anyhow!("udp echo socket on {addr}: {error}"))?;
+
+    tracing::info!("UDP echo listening on: {}", socket.local_addr()?);
+
+    Ok(socket)
+}
+
+/// Echo every byte received on each accepted connection back to its sender
+#[tracing::instrument(skip_all)]
+pub(crate) async fn serve_tcp(listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                metrics::increment_counter!("l4_connections_total", "protocol" => "tcp");
+                tokio::spawn(echo_stream(stream, peer));
+            }
+            Err(error) => tracing::warn!("Failed to accept TCP echo connection: {error}"),
+        }
+    }
+}
+
+async fn echo_stream(mut stream: TcpStream, peer: SocketAddr) {
+    let (mut buf, mut total) = (vec![0u8; 16 * 1024], 0usize);
+
+    loop {
+        let read = match stream.read(&mut buf).await {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) => {
+                tracing::debug!("TCP echo read from {peer} failed: {error}");
+                break;
+            }
+        };
+
+        if let Err(error) = stream.write_all(&buf[..read]).await {
+            tracing::debug!("TCP echo write to {peer} failed: {error}");
+            record("tcp", read, 0);
+            break;
+        }
+
+        record("tcp", read, read);
+        total += read;
+    }
+
+    tracing::info!("TCP echo: {peer} ({total} bytes)");
+}
+
+/// Echo every datagram received back to its sender
+#[tracing::instrument(skip_all)]
+pub(crate) async fn serve_udp(socket: UdpSocket) {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+
+    loop {
+        let (received, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(error) => {
+                tracing::warn!("Failed to receive UDP echo datagram: {error}");
+                continue;
+            }
+        };
+
+        let sent = match socket.send_to(&buf[..received], peer).await {
+            Ok(sent) => sent,
+            Err(error) => {
+                tracing::debug!("UDP echo send to {peer} failed: {error}");
+                0
+            }
+        };
+
+        metrics::increment_counter!("l4_datagrams_total", "protocol" => "udp");
+        record("udp", received, sent);
+
+        tracing::info!("UDP echo: {peer} ({received} bytes)");
+    }
+}
@@ -0,0 +1,145 @@
+// In-Process Latency Percentiles
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+// Third Party Imports
+use axum::{
+    extract::{Json, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+    routing, Router,
+};
+use hdrhistogram::Histogram;
+
+// Crate-Level Imports
+use crate::routes::MatchedRule;
+
+/// The largest latency (in microseconds) tracked with full precision - one hour
+pub(crate) const MAX_TRACKED_MICROS: u64 = 60 * 60 * 1_000_000;
+
+pub(crate) fn histogram() -> Histogram<u64> {
+    Histogram::new_with_bounds(1, MAX_TRACKED_MICROS, 3).expect("static histogram bounds are valid")
+}
+
+/// Request latencies recorded since startup, overall and per matched route rule
+#[derive(Debug)]
+pub(crate) struct LatencyRecorder {
+    histogram: Mutex<Histogram<u64>>,
+    rules: Mutex<BTreeMap<String, Histogram<u64>>>,
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self {
+            histogram: Mutex::new(histogram()),
+            rules: Mutex::default(),
+        }
+    }
+}
+
+/// Summary of the recorded latencies, in milliseconds
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+struct LatencySummary {
+    count: u64,
+    min: f64,
+    mean: f64,
+    max: f64,
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    p999: f64,
+}
+
+/// Summary of the recorded latencies, overall and per route rule
+#[derive(Clone, Debug, serde::Serialize)]
+struct LatencyReport {
+    #[serde(flatten)]
+    overall: LatencySummary,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    rules: BTreeMap<String, LatencySummary>,
+}
+
+impl LatencyRecorder {
+    /// A recorder reporting on the given route rules, even if they're never matched
+    pub(crate) fn with_rules<I: IntoIterator<Item = String>>(rules: I) -> Self {
+        Self {
+            rules: Mutex::new(rules.into_iter().map(|id| (id, histogram())).collect()),
+            ..Self::default()
+        }
+    }
+
+    fn report(&self) -> LatencyReport {
+        LatencyReport {
+            overall: summarize(&self.histogram.lock().unwrap()),
+            rules: self
+                .rules
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(id, histogram)| (id.clone(), summarize(histogram)))
+                .collect(),
+        }
+    }
+}
+
+fn summarize(histogram: &Histogram<u64>) -> LatencySummary {
+    let millis = |micros: u64| micros as f64 / 1_000.0;
+
+    LatencySummary {
+        count: histogram.len(),
+        min: millis(histogram.min()),
+        mean: histogram.mean() / 1_000.0,
+        max: millis(histogram.max()),
+        p50: millis(histogram.value_at_quantile(0.5)),
+        p90: millis(histogram.value_at_quantile(0.9)),
+        p99: millis(histogram.value_at_quantile(0.99)),
+        p999: millis(histogram.value_at_quantile(0.999)),
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(recorder: Arc<LatencyRecorder>) -> Router {
+    Router::new()
+        .route("/_latency", routing::get(latency))
+        .with_state(recorder)
+}
+
+#[tracing::instrument(skip_all)]
+async fn latency(State(recorder): State<Arc<LatencyRecorder>>) -> Json<LatencyReport> {
+    Json(recorder.report())
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn record<B>(
+    State(recorder): State<Arc<LatencyRecorder>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    let micros = u64::try_from(start.elapsed().as_micros())
+        .unwrap_or(u64::MAX)
+        .clamp(1, MAX_TRACKED_MICROS);
+
+    recorder.histogram.lock().unwrap().saturating_record(micros);
+
+    if let Some(MatchedRule(rule)) = response.extensions().get::<MatchedRule>() {
+        recorder
+            .rules
+            .lock()
+            .unwrap()
+            .entry(rule.clone())
+            .or_insert_with(histogram)
+            .saturating_record(micros);
+    }
+
+    response
+}
@@ -0,0 +1,163 @@
+// Echo Payload Layout
+
+// Standard Library Imports
+use std::str::FromStr;
+
+// Third Party Imports
+use serde_json::{Map, Value};
+
+/// Fields whose contents are the request's own, and so are never re-cased
+const VERBATIM: &[&str] = &["headers", "params", "body"];
+
+/// How the echo payload's own field names are cased
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum FieldCase {
+    /// `parse_error`, `raw_head`, ...
+    #[default]
+    Snake,
+    /// `parseError`, `rawHead`, ...
+    Camel,
+}
+
+impl FieldCase {
+    fn apply(self, name: &str) -> String {
+        match self {
+            Self::Snake => name.to_owned(),
+            Self::Camel => name
+                .split('_')
+                .enumerate()
+                .map(|(index, word)| match (index, word.chars().next()) {
+                    (0, _) | (_, None) => word.to_owned(),
+                    (_, Some(first)) => first.to_uppercase().chain(word.chars().skip(1)).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A field of the echo payload moved to another (dot-separated) path,
+/// given as `from=to`, e.g. `method=http.request.method`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FieldRename {
+    from: Vec<String>,
+    to: Vec<String>,
+}
+
+impl FromStr for FieldRename {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let path = |path: &str| {
+            let segments = path
+                .trim()
+                .split('.')
+                .map(|segment| segment.trim().to_owned())
+                .collect::<Vec<String>>();
+
+            match segments.iter().any(String::is_empty) {
+                true => Err(format!("{value:?}: empty path segment")),
+                false => Ok(segments),
+            }
+        };
+
+        let (from, to) = value
+            .split_once('=')
+            .ok_or_else(|| format!("{value:?}: expected `from=to`"))?;
+
+        Ok(Self {
+            from: path(from)?,
+            to: path(to)?,
+        })
+    }
+}
+
+/// Field naming (and nesting) applied to echo payloads, so they
+/// can match a downstream log schema (e.g. ECS) as-is
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Layout {
+    pub(crate) case: FieldCase,
+    pub(crate) renames: Vec<FieldRename>,
+    pub(crate) wrap: Option<String>,
+}
+
+impl Layout {
+    /// Lay out the (serialized) echo, where renames refer to the original
+    /// field names and their targets are used exactly as given
+    pub(crate) fn apply(&self, mut echo: Value) -> Value {
+        let moved = self
+            .renames
+            .iter()
+            .filter_map(|rename| Some((&rename.to, take(&mut echo, &rename.from)?)))
+            .collect::<Vec<(&Vec<String>, Value)>>();
+
+        if self.case != FieldCase::Snake {
+            recase(&mut echo, self.case);
+        }
+
+        for (path, value) in moved {
+            put(&mut echo, path, value);
+        }
+
+        match self.wrap.as_ref() {
+            Some(key) => Value::Object(Map::from_iter([(key.clone(), echo)])),
+            None => echo,
+        }
+    }
+}
+
+/// Remove (and return) the value at the given path, if there is one
+fn take(value: &mut Value, path: &[String]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+
+    parents
+        .iter()
+        .try_fold(value, |value, segment| value.get_mut(segment))?
+        .as_object_mut()?
+        .remove(last)
+}
+
+/// Insert the value at the given path, creating (or replacing
+/// non-object values with) objects along the way
+fn put(value: &mut Value, path: &[String], inserted: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let parent = parents.iter().fold(value, |value, segment| {
+        if !value.is_object() {
+            *value = Value::Object(Map::new());
+        }
+
+        value
+            .as_object_mut()
+            .unwrap()
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(Map::new()))
+    });
+
+    if !parent.is_object() {
+        *parent = Value::Object(Map::new());
+    }
+
+    parent
+        .as_object_mut()
+        .unwrap()
+        .insert(last.clone(), inserted);
+}
+
+fn recase(value: &mut Value, case: FieldCase) {
+    let Value::Object(map) = value else {
+        return;
+    };
+
+    *map = std::mem::take(map)
+        .into_iter()
+        .map(|(name, mut value)| {
+            if !VERBATIM.contains(&name.as_str()) {
+                recase(&mut value, case);
+            }
+
+            (case.apply(&name), value)
+        })
+        .collect();
+}
@@ -0,0 +1,671 @@
+#![forbid(unsafe_code)]
+#![deny(missing_docs, missing_debug_implementations)]
+
+//! # `echo-rs` - a simple echo server
+//!
+//! Besides running as the `echo-rs` binary, the echo service can be mounted
+//! inside another axum app, which has to be served with connection info:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! use std::net::SocketAddr;
+//!
+//! let echo = echo_rs::echo_router(Default::default(), Default::default()).await?;
+//! let app = axum::Router::new().nest("/debug/echo", echo);
+//!
+//! axum::Server::bind(&"[::]:8080".parse()?)
+//!     .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    net::SocketAddr,
+    sync::{atomic::AtomicBool, Arc, RwLock},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::Bytes,
+    extract::{
+        rejection::{BytesRejection, QueryRejection},
+        ConnectInfo, Extension, MatchedPath, OriginalUri, Path, Query, State,
+    },
+    http::{header, HeaderMap, Method, StatusCode, Version},
+    middleware,
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use base64::Engine;
+use regex_lite::Regex;
+use tower_http::compression::CompressionLayer;
+
+pub(crate) mod aborts;
+pub(crate) mod access_log;
+pub(crate) mod activation;
+pub(crate) mod admin;
+pub(crate) mod alerts;
+pub(crate) mod assertions;
+pub(crate) mod audit;
+pub(crate) mod batch;
+/// Parsing of request bodies, extensible with parsers of your own
+pub mod body;
+pub(crate) mod cache;
+pub(crate) mod canonical;
+pub(crate) mod capabilities;
+pub(crate) mod chaos;
+/// The `echo-rs` binary's command line interface
+pub mod cli;
+pub(crate) mod client_ip;
+pub(crate) mod clock;
+pub(crate) mod collapse;
+pub(crate) mod concurrency;
+pub(crate) mod config;
+pub(crate) mod conn;
+pub(crate) mod consul;
+pub(crate) mod cors;
+pub(crate) mod counters;
+pub(crate) mod doh;
+pub(crate) mod errors;
+pub(crate) mod fail_window;
+pub(crate) mod formats;
+pub(crate) mod grpc;
+pub(crate) mod header_limits;
+pub(crate) mod health;
+pub(crate) mod hints;
+pub(crate) mod history;
+pub(crate) mod http3;
+pub(crate) mod inflight;
+pub(crate) mod jwt;
+pub(crate) mod kube;
+pub(crate) mod l4;
+pub(crate) mod latency;
+pub(crate) mod layout;
+pub(crate) mod listeners;
+pub(crate) mod log_control;
+pub(crate) mod logging;
+pub(crate) mod mdns;
+pub(crate) mod methods;
+/// Prometheus metrics for the echo service
+pub mod metrics;
+pub(crate) mod mirror;
+pub(crate) mod negotiate;
+pub(crate) mod normalize;
+pub(crate) mod oauth;
+pub(crate) mod otel;
+pub(crate) mod parsers;
+pub(crate) mod ping;
+#[cfg(windows)]
+pub(crate) mod pipe;
+pub(crate) mod projection;
+pub(crate) mod proxy;
+pub(crate) mod proxy_protocol;
+pub(crate) mod ratelimit;
+pub(crate) mod recording;
+pub(crate) mod redact;
+pub(crate) mod request_id;
+pub(crate) mod routes;
+pub(crate) mod s3;
+pub(crate) mod sampling;
+pub(crate) mod scenarios;
+pub(crate) mod schedule;
+pub(crate) mod schema;
+pub(crate) mod self_traffic;
+pub(crate) mod sequence;
+pub(crate) mod shaping;
+pub(crate) mod shutdown;
+pub(crate) mod soap;
+pub(crate) mod stubs;
+pub(crate) mod tail;
+pub(crate) mod template;
+pub(crate) mod throttle;
+pub(crate) mod timestamps;
+pub(crate) mod tls;
+pub(crate) mod traffic;
+pub(crate) mod transform;
+pub(crate) mod unmatched;
+pub(crate) mod uploads;
+pub(crate) mod utility;
+#[cfg(target_os = "linux")]
+pub(crate) mod vsock;
+pub(crate) mod warmup;
+pub(crate) mod webhooks;
+pub(crate) mod ws;
+
+/// Shared state of the echo handler
+#[derive(Clone, Debug, Default)]
+pub struct EchoState {
+    url_filters: Arc<RwLock<Vec<Regex>>>,
+    sequencer: Arc<sequence::Sequencer>,
+    kubernetes: Option<kube::KubeMetadata>,
+    redactions: Arc<Vec<redact::RedactPath>>,
+    parsers: Arc<body::ParserRegistry>,
+    schema: schema::EchoSchema,
+    layout: Arc<layout::Layout>,
+    collapser: Option<Arc<collapse::LogCollapser>>,
+    history: Option<Arc<history::RequestHistory>>,
+    recorder: Option<Arc<recording::Recorder>>,
+    tail: Arc<tail::RequestTail>,
+    assertions: Arc<assertions::Assertions>,
+    templates: Arc<template::Templates>,
+    response_template: Option<Arc<String>>,
+    structured_logs: bool,
+    pad_response_to: Option<usize>,
+    jwks: Option<Arc<jwt::Jwks>>,
+    canonical_json: bool,
+    timestamp_tolerance: Option<Duration>,
+}
+
+impl EchoState {
+    /// Parse request bodies with the given parsers, in place of the built-in ones
+    pub fn with_parsers(self, parsers: body::ParserRegistry) -> Self {
+        Self {
+            parsers: Arc::new(parsers),
+            ..self
+        }
+    }
+}
+
+/// Optional behaviors layered over the echo routes (none, by default)
+#[derive(Debug, Default)]
+pub struct EchoFeatures {
+    shaping: shaping::Shaping,
+    throttle: Option<throttle::Throttle>,
+    rate_limiter: Option<ratelimit::RateLimiter>,
+    quotas: Option<throttle::PathQuotas>,
+    fail_window: Option<Arc<fail_window::FailWindow>>,
+    warmup: Option<warmup::Warmup>,
+    chaos: chaos::Chaos,
+    routes: routes::RouteRules,
+    full_echo_head_options: bool,
+    compress_responses: bool,
+    utility_endpoints: bool,
+    sampler: sampling::Sampler,
+    metric_paths: metrics::PathLabels,
+    proxy: Option<proxy::Proxy>,
+    latency: Arc<latency::LatencyRecorder>,
+    counters: Arc<counters::RequestCounters>,
+    alerts: Option<Arc<alerts::Watcher>>,
+    inflight: Arc<inflight::InflightRequests>,
+    mirror: Option<Arc<mirror::Mirror>>,
+    webhooks: Option<Arc<webhooks::WebhookDeliveries>>,
+    log_only: Arc<AtomicBool>,
+}
+
+/// A request, as it's echoed back (and logged)
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Echo {
+    schema_version: schema::EchoSchema,
+    client: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    method: String,
+    version: String,
+    path: String,
+    headers: schema::EchoHeaders,
+    #[serde(skip_serializing_if = "schema::EchoCookies::is_empty")]
+    cookies: schema::EchoCookies,
+    params: schema::EchoParams,
+    body: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_error: Option<body::ParseError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical_body: Option<canonical::CanonicalBody>,
+    sequence: sequence::Sequence,
+    connection: Option<conn::ConnectionInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_head: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_certificate: Option<tls::ClientCertificate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kubernetes: Option<kube::KubeMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    virtual_host: Option<String>,
+    normalization: normalize::Normalization,
+    route: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth: Option<jwt::BearerToken>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    signed_timestamps: Vec<timestamps::SignedTimestamp>,
+}
+
+#[tracing::instrument(skip_all, parent = None)]
+#[allow(clippy::too_many_arguments)]
+async fn serialize_request(
+    State(state): State<EchoState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    method: Method,
+    version: Version,
+    OriginalUri(uri): OriginalUri,
+    path: Option<Path<String>>,
+    params: Result<Query<Vec<(String, String)>>, QueryRejection>,
+    headers: HeaderMap,
+    connection: Option<Extension<conn::ConnectionInfo>>,
+    raw_head: Option<Extension<conn::RawHead>>,
+    client_certificate: Option<Extension<tls::ClientCertificate>>,
+    virtual_host: Option<Extension<routes::VirtualHostName>>,
+    matched_path: Option<MatchedPath>,
+    matched_rule: Option<Extension<routes::MatchedRule>>,
+    request_id: Option<Extension<request_id::RequestId>>,
+    body: Result<Bytes, BytesRejection>,
+) -> Response {
+    let pairs = match params {
+        Ok(Query(pairs)) => pairs,
+        Err(rejection) => {
+            return errors::Failure::new(rejection.status(), rejection.body_text()).into_response()
+        }
+    };
+
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) => {
+            return errors::Failure::new(rejection.status(), rejection.body_text()).into_response()
+        }
+    };
+
+    let normalization = normalize::Normalization::of(&uri, &headers);
+
+    // kept as received (if it's to be kept at all), so it can be replayed
+    let original =
+        (state.history.is_some() || state.recorder.is_some()).then(|| history::OriginalRequest {
+            method: method.clone(),
+            uri,
+            headers: headers.clone(),
+            body: body.clone(),
+        });
+
+    // the last of any repeated parameter's values is the one that counts
+    let params = pairs.iter().cloned().collect::<HashMap<_, _>>();
+
+    let mut path = path.map(|value| value.0).unwrap_or_default();
+
+    if !path.starts_with('/') {
+        // path extractor sometimes omits leading slash
+        path.insert(0, '/');
+    }
+
+    let mut hints = hints::Hints::from_request(&headers, &params);
+
+    let (content_encoding, body) = match body::decode(
+        headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok()),
+        &body,
+    ) {
+        Ok(Some((encoding, decoded))) => (Some(encoding), Bytes::from(decoded)),
+        Ok(None) => (None, body),
+        Err(error) => {
+            tracing::debug!("Echoing body as received: {error}");
+            (None, body)
+        }
+    };
+
+    let canonical_body =
+        (state.canonical_json || hints.canonical).then(|| canonical::CanonicalBody::of(&body));
+
+    let (body, parse_error) = state.parsers.parse(
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok()),
+        &body,
+    );
+
+    let format = formats::EchoFormat::negotiate(
+        &headers,
+        params.get(formats::FORMAT_PARAM).map(String::as_str),
+    );
+
+    let auth = jwt::inspect(&headers, state.jwks.as_deref()).await;
+    let signed_timestamps = timestamps::check(
+        &headers,
+        &params,
+        state
+            .timestamp_tolerance
+            .unwrap_or(timestamps::DEFAULT_TOLERANCE),
+    );
+
+    let cookies = state.schema.cookies(&headers);
+    let headers = state.schema.headers(&headers);
+
+    let sequence = state.sequencer.next(&path, client.ip());
+    let client_ip = client.ip();
+
+    let (client, method) = (client.to_string(), method.to_string());
+
+    let connection = connection.map(|Extension(info)| info);
+
+    let raw_head = raw_head.map(|Extension(conn::RawHead(head))| {
+        base64::engine::general_purpose::STANDARD.encode(head)
+    });
+
+    let req = Echo {
+        schema_version: state.schema,
+        client,
+        request_id: request_id.map(|Extension(request_id::RequestId(id))| id),
+        method,
+        version: format!("{version:?}"),
+        path,
+        headers,
+        cookies,
+        params: state.schema.params(&pairs),
+        body,
+        parse_error,
+        content_encoding,
+        canonical_body,
+        sequence,
+        connection,
+        raw_head,
+        client_certificate: client_certificate.map(|Extension(certificate)| certificate),
+        kubernetes: state.kubernetes.clone(),
+        virtual_host: virtual_host.map(|Extension(routes::VirtualHostName(name))| name),
+        normalization,
+        route: matched_path.map_or_else(
+            || metrics::FALLBACK_ROUTE.to_owned(),
+            |path| path.as_str().to_owned(),
+        ),
+        rule: matched_rule.map(|Extension(routes::MatchedRule(rule))| rule),
+        auth,
+        signed_timestamps,
+    };
+
+    if !state
+        .url_filters
+        .read()
+        .unwrap()
+        .iter()
+        .any(|pattern| pattern.is_match(&req.path))
+        && state
+            .collapser
+            .as_ref()
+            .is_none_or(|collapser| collapser.admit(&req.method, &req.path, client_ip))
+    {
+        if state.structured_logs {
+            tracing::info!(
+                echo = %serde_json::to_string(&req).unwrap_or_default(),
+                "Echoing {} {}",
+                req.method,
+                req.path
+            );
+        } else {
+            tracing::info!("{req:?}");
+        }
+    }
+
+    let mut echo = match serde_json::to_value(&req) {
+        Ok(echo) => echo,
+        Err(error) => {
+            return errors::Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+                .into_response()
+        }
+    };
+
+    // checked against the echo as received, i.e. before anything's redacted
+    let rejection = state
+        .assertions
+        .check(&state.templates, &hints.assertions, &echo)
+        .await
+        .err();
+    let rejected = rejection.is_some();
+
+    redact::apply(&state.redactions, &mut echo);
+
+    // recorded once it's been answered, along with the status it was answered with
+    let captured = original.map(|original| (echo.clone(), original));
+
+    state.tail.publish(&echo);
+
+    // the client's budget is applied to the echo itself, so what's sent of it still parses
+    let mut truncated = hints
+        .max_bytes
+        .and_then(|max_bytes| hints::truncate(&mut echo, max_bytes));
+
+    if let Some(delay) = hints.delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    let format = match format {
+        Ok(format) => format,
+        Err(error) => return errors::Failure::new(StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    let response = if let Some(rejection) = rejection {
+        rejection.into_response()
+    } else if let Some(template) = hints.template.take() {
+        let rendering = state.templates.offload({
+            let echo = echo.clone();
+            move |templates| templates.render_echo(&template, &echo)
+        });
+
+        match rendering.await {
+            Ok(rendered) => routes::canned(rendered, None),
+            Err(error) => errors::Failure::new(StatusCode::BAD_REQUEST, error).into_response(),
+        }
+    } else if let Some(path) = params.get(projection::JSONPATH_PARAM) {
+        match projection::jsonpath(&echo["body"], path) {
+            Ok(selected) => format.respond(selected),
+            Err(error) => errors::Failure::new(StatusCode::BAD_REQUEST, error).into_response(),
+        }
+    } else if let Some(fields) = params.get(projection::FIELDS_PARAM) {
+        match projection::fields(echo, fields) {
+            Ok(selected) => format.respond(state.layout.apply(selected)),
+            Err(error) => errors::Failure::new(StatusCode::BAD_REQUEST, error).into_response(),
+        }
+    } else if let Some(template) = state.response_template.as_ref() {
+        match state.templates.render_echo(template, &echo) {
+            Ok(rendered) => routes::canned(rendered, None),
+            Err(error) => {
+                errors::Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error).into_response()
+            }
+        }
+    } else {
+        format.respond(state.layout.apply(echo))
+    };
+
+    // ... nor can padding push the echo past it
+    let max_bytes = hints.max_bytes;
+    let pad_to = hints
+        .pad_to
+        .or(state.pad_response_to)
+        .map(|size| max_bytes.map_or(size, |max_bytes| size.min(max_bytes)));
+
+    // a failed assertion's status stands, whatever was asked for
+    let response = match rejected {
+        true => response,
+        false => hints.apply(response),
+    };
+
+    if let Some((echo, original)) = captured {
+        if let Some(recorder) = state.recorder.as_ref() {
+            recorder.record(response.status(), &echo, &original);
+        }
+
+        if let Some(history) = state.history.as_ref() {
+            history.record(&req.method, &req.path, response.status(), echo, original);
+        }
+    }
+
+    let mut response = match pad_to {
+        Some(size) => hints::pad(response, size).await,
+        None => response,
+    };
+
+    // whatever was rendered is held to the budget too, even if it's cut mid-document
+    if let Some(max_bytes) = max_bytes {
+        let (capped, cut) = hints::cap(response, max_bytes).await;
+        response = capped;
+        truncated = truncated.or(cut);
+    }
+
+    if let Some(original) = truncated {
+        response
+            .headers_mut()
+            .insert(hints::TRUNCATED_HEADER, original.into());
+    }
+
+    response
+}
+
+/// Build the echo service's router, answering every method on every path
+#[tracing::instrument]
+pub async fn echo_router(state: EchoState, features: EchoFeatures) -> anyhow::Result<Router> {
+    let EchoFeatures {
+        shaping,
+        throttle,
+        rate_limiter,
+        quotas,
+        fail_window,
+        warmup,
+        chaos,
+        routes,
+        full_echo_head_options,
+        compress_responses,
+        utility_endpoints,
+        sampler,
+        metric_paths,
+        proxy,
+        latency,
+        counters,
+        alerts,
+        inflight,
+        mirror,
+        webhooks,
+        log_only,
+    } = features;
+
+    let mut router = Router::new()
+        .route(
+            "/",
+            routing::get(serialize_request)
+                .put(serialize_request)
+                .head(serialize_request)
+                .post(serialize_request)
+                .patch(serialize_request)
+                .delete(serialize_request)
+                .trace(serialize_request)
+                .options(serialize_request),
+        )
+        .with_state(state.clone())
+        .route(
+            "/*key",
+            routing::get(serialize_request)
+                .put(serialize_request)
+                .head(serialize_request)
+                .post(serialize_request)
+                .patch(serialize_request)
+                .delete(serialize_request)
+                .trace(serialize_request)
+                .options(serialize_request),
+        )
+        .with_state(state.clone())
+        .fallback(serialize_request)
+        .with_state(state);
+
+    // compressed beneath the shaping, so it's the bytes on the wire that are shaped
+    if compress_responses {
+        router = router.layer(CompressionLayer::new());
+    }
+
+    router = router.layer(middleware::from_fn_with_state(
+        shaping,
+        shaping::shape_response,
+    ));
+
+    if !full_echo_head_options {
+        router = router.layer(middleware::from_fn(methods::head_and_options));
+    }
+
+    if let Some(proxy) = proxy {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(proxy),
+            proxy::relay,
+        ));
+    }
+
+    if !routes.is_empty() || routes.is_strict() || routes.is_reloadable() {
+        router = router.layer(middleware::from_fn_with_state(routes, routes::apply));
+    }
+
+    if utility_endpoints {
+        router = router.layer(middleware::from_fn(utility::serve));
+    }
+
+    if let Some(throttle) = throttle {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(throttle),
+            throttle::enforce,
+        ));
+    }
+
+    if let Some(limiter) = rate_limiter {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(limiter),
+            ratelimit::enforce,
+        ));
+    }
+
+    if let Some(quotas) = quotas {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(quotas),
+            throttle::enforce_quotas,
+        ));
+    }
+
+    if let Some(fail_window) = fail_window {
+        router = router.layer(middleware::from_fn_with_state(
+            fail_window,
+            fail_window::enforce,
+        ));
+    }
+
+    if let Some(warmup) = warmup {
+        router = router.layer(middleware::from_fn_with_state(
+            Arc::new(warmup),
+            warmup::enforce,
+        ));
+    }
+
+    // always in place, as requests may opt into chaos of their own
+    router = router.layer(middleware::from_fn_with_state(
+        Arc::new(chaos),
+        chaos::inject,
+    ));
+
+    if let Some(alerts) = alerts {
+        router = router.layer(middleware::from_fn_with_state(alerts, alerts::record));
+    }
+
+    if let Some(mirror) = mirror {
+        router = router.layer(middleware::from_fn_with_state(mirror, mirror::mirror));
+    }
+
+    // outside the chaos, so injected faults are recorded as the producer saw them
+    if let Some(webhooks) = webhooks {
+        router = router.layer(middleware::from_fn_with_state(webhooks, webhooks::track));
+    }
+
+    Ok(router
+        .layer(middleware::from_fn_with_state(
+            log_only,
+            listeners::log_only,
+        ))
+        .layer(middleware::from_fn_with_state(counters, counters::count))
+        .layer(middleware::from_fn_with_state(inflight, inflight::track))
+        .layer(middleware::from_fn(aborts::detect))
+        .route_layer(middleware::from_fn_with_state(latency, latency::record))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::new(metrics::RequestMetrics {
+                sampler,
+                paths: metric_paths,
+            }),
+            metrics::track_metrics,
+        )))
+}
@@ -0,0 +1,90 @@
+// Per-Listener Behavior Profiles
+
+// Standard Library Imports
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// What requests arriving on a listener are subjected to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Profile {
+    /// Every configured behavior
+    #[default]
+    Echo,
+    /// Log (and count) requests, but answer them without a body
+    LogOnly,
+    /// Mirror requests to the `--mirror-to` targets, without injecting chaos
+    Mirror,
+    /// Inject the configured chaos, without mirroring requests
+    Chaos,
+}
+
+impl Profile {
+    /// The profile of the listener the request arrived on
+    pub(crate) fn of<B>(req: &Request<B>) -> Self {
+        req.extensions().get::<Self>().copied().unwrap_or_default()
+    }
+
+    pub(crate) fn mirrors(self) -> bool {
+        matches!(self, Self::Echo | Self::Mirror)
+    }
+
+    pub(crate) fn injects_chaos(self) -> bool {
+        matches!(self, Self::Echo | Self::Chaos)
+    }
+}
+
+/// An additional listener, given as a `port=profile` pair
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Listener {
+    pub(crate) port: u16,
+    pub(crate) profile: Profile,
+}
+
+impl FromStr for Listener {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (port, profile) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected `port=profile`, got {value:?}"))?;
+
+        Ok(Self {
+            port: port
+                .trim()
+                .parse()
+                .map_err(|error| format!("invalid port {port:?}: {error}"))?,
+            profile: <Profile as clap::ValueEnum>::from_str(profile.trim(), true)?,
+        })
+    }
+}
+
+/// Answer requests arriving on `log-only` listeners (or on any listener,
+/// while log-only mode is switched on) without a body, once they've been
+/// handled (and so logged) as usual
+#[tracing::instrument(skip_all)]
+pub(crate) async fn log_only<B>(
+    State(everywhere): State<Arc<AtomicBool>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if Profile::of(&req) != Profile::LogOnly && !everywhere.load(Ordering::Relaxed) {
+        return next.run(req).await;
+    }
+
+    let _ = next.run(req).await;
+
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,239 @@
+// Runtime Logging Controls
+
+// Standard Library Imports
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use regex_lite::Regex;
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+};
+
+/// Applies a new log level to the running subscriber
+type ApplyLevel = Box<dyn Fn(tracing::Level) -> anyhow::Result<()> + Send + Sync>;
+
+/// The logging settings that can be changed while the server runs
+pub(crate) struct LogControl {
+    level: RwLock<tracing::Level>,
+    apply_level: ApplyLevel,
+    /// Patterns of the URLs that aren't logged (shared with the echo handler)
+    url_filters: Arc<RwLock<Vec<Regex>>>,
+    /// Whether every request is answered as if on a `log-only` listener
+    log_only: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for LogControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogControl")
+            .field("level", &self.level)
+            .field("url_filters", &self.url_filters)
+            .field("log_only", &self.log_only)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LogControl {
+    pub(crate) fn new(
+        level: tracing::Level,
+        apply_level: impl Fn(tracing::Level) -> anyhow::Result<()> + Send + Sync + 'static,
+        url_filters: Arc<RwLock<Vec<Regex>>>,
+        log_only: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            level: RwLock::new(level),
+            apply_level: Box::new(apply_level),
+            url_filters,
+            log_only,
+        }
+    }
+
+    pub(crate) fn set_level(&self, level: tracing::Level) -> anyhow::Result<()> {
+        (self.apply_level)(level)?;
+        *self.level.write().unwrap() = level;
+
+        Ok(())
+    }
+
+    /// Replace every pattern of the URLs that aren't logged
+    pub(crate) fn set_patterns(&self, patterns: Vec<Regex>) {
+        *self.url_filters.write().unwrap() = patterns;
+    }
+
+    fn report(&self) -> LoggingReport {
+        LoggingReport {
+            level: self.level.read().unwrap().as_str().to_ascii_lowercase(),
+            skip_logging_for: self
+                .url_filters
+                .read()
+                .unwrap()
+                .iter()
+                .map(|pattern| pattern.as_str().to_owned())
+                .collect(),
+            log_only: self.log_only.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The logging settings currently in effect
+#[derive(Clone, Debug, serde::Serialize)]
+struct LoggingReport {
+    level: String,
+    skip_logging_for: Vec<String>,
+    log_only: bool,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct LevelRequest {
+    level: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct PatternRequest {
+    pattern: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct LogOnlyRequest {
+    enabled: bool,
+}
+
+#[tracing::instrument]
+pub(crate) fn router(control: Arc<LogControl>, admin_token: AdminToken) -> Router {
+    Router::new()
+        .route("/_logging", routing::get(report))
+        .route("/_logging/level", routing::put(set_level))
+        .route(
+            "/_logging/skip",
+            routing::post(add_pattern).delete(remove_pattern),
+        )
+        .route("/_logging/log-only", routing::put(set_log_only))
+        .route_layer(middleware::from_fn_with_state(
+            admin_token,
+            admin::require_token,
+        ))
+        .with_state(control)
+}
+
+/// The logging settings currently in effect
+#[tracing::instrument(skip_all)]
+async fn report(State(control): State<Arc<LogControl>>) -> Json<LoggingReport> {
+    Json(control.report())
+}
+
+/// Change the level `echo-rs` itself logs at
+#[tracing::instrument(skip_all)]
+async fn set_level(
+    State(control): State<Arc<LogControl>>,
+    Json(request): Json<LevelRequest>,
+) -> Response {
+    let level = match request.level.parse::<tracing::Level>() {
+        Ok(level) => level,
+        Err(error) => {
+            return Failure::new(
+                StatusCode::BAD_REQUEST,
+                format!("invalid log level {:?}: {error}", request.level),
+            )
+            .into_response()
+        }
+    };
+
+    if let Err(error) = control.set_level(level) {
+        return Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response();
+    }
+
+    tracing::warn!("Log level changed to {level}");
+
+    Json(control.report()).into_response()
+}
+
+/// Stop logging requests to URLs matching the given pattern
+#[tracing::instrument(skip_all)]
+async fn add_pattern(
+    State(control): State<Arc<LogControl>>,
+    Json(request): Json<PatternRequest>,
+) -> Response {
+    let pattern = match Regex::new(&request.pattern) {
+        Ok(pattern) => pattern,
+        Err(error) => {
+            return Failure::new(
+                StatusCode::BAD_REQUEST,
+                format!("invalid pattern {:?}: {error}", request.pattern),
+            )
+            .into_response()
+        }
+    };
+
+    {
+        let mut patterns = control.url_filters.write().unwrap();
+
+        if !patterns
+            .iter()
+            .any(|existing| existing.as_str() == pattern.as_str())
+        {
+            patterns.push(pattern);
+        }
+    }
+
+    Json(control.report()).into_response()
+}
+
+/// Resume logging requests to URLs matching the given pattern
+#[tracing::instrument(skip_all)]
+async fn remove_pattern(
+    State(control): State<Arc<LogControl>>,
+    Json(request): Json<PatternRequest>,
+) -> Response {
+    let removed = {
+        let mut patterns = control.url_filters.write().unwrap();
+        let before = patterns.len();
+
+        patterns.retain(|pattern| pattern.as_str() != request.pattern);
+        patterns.len() < before
+    };
+
+    if !removed {
+        return Failure::new(
+            StatusCode::NOT_FOUND,
+            format!("no such pattern: {:?}", request.pattern),
+        )
+        .into_response();
+    }
+
+    Json(control.report()).into_response()
+}
+
+/// Answer every request (on every listener) as if on a `log-only` listener, or stop
+#[tracing::instrument(skip_all)]
+async fn set_log_only(
+    State(control): State<Arc<LogControl>>,
+    Json(request): Json<LogOnlyRequest>,
+) -> Json<LoggingReport> {
+    control.log_only.store(request.enabled, Ordering::Relaxed);
+
+    tracing::warn!(
+        "Log-only mode {}",
+        if request.enabled {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
+
+    Json(control.report())
+}
@@ -0,0 +1,267 @@
+// Structured Log Schemas
+
+// Standard Library Imports
+use std::{fmt, time::UNIX_EPOCH};
+
+// Third Party Imports
+use serde_json::{json, Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{
+        format::{Compact, Format, Full, Pretty, Writer},
+        FmtContext, FormatEvent, FormatFields,
+    },
+    registry::LookupSpan,
+};
+
+// Crate-Level Imports
+use crate::clock;
+
+/// Version of the Elastic Common Schema records conform to
+const ECS_VERSION: &str = "8.11.0";
+
+/// Layout of each log record
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum LogSchema {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// Elastic Common Schema JSON documents
+    Ecs,
+    /// OpenTelemetry log data model JSON records
+    Otel,
+}
+
+/// Layout of each `text` schema log record
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum LogFormat {
+    /// One line per record, with every enclosing span
+    #[default]
+    Full,
+    /// Several indented lines per record
+    Pretty,
+    /// One short line per record, without span fields
+    Compact,
+    /// One JSON object per line
+    Json,
+}
+
+/// Whether records are written as JSON, and so should carry
+/// echoes as fields rather than in their messages
+pub(crate) fn is_structured(schema: LogSchema, format: LogFormat) -> bool {
+    schema != LogSchema::Text || format == LogFormat::Json
+}
+
+/// Formats each event according to the configured log schema
+#[derive(Debug)]
+pub(crate) struct EventFormat {
+    schema: LogSchema,
+    format: LogFormat,
+    service: String,
+    text: Format<Full, clock::LogTimer>,
+    pretty: Format<Pretty, clock::LogTimer>,
+    compact: Format<Compact, clock::LogTimer>,
+}
+
+impl EventFormat {
+    pub(crate) fn new(schema: LogSchema, format: LogFormat, service: String) -> Self {
+        Self {
+            schema,
+            format,
+            service,
+            text: Format::default().with_timer(clock::LogTimer),
+            pretty: Format::default().pretty().with_timer(clock::LogTimer),
+            compact: Format::default().compact().with_timer(clock::LogTimer),
+        }
+    }
+
+    fn json(&self, event: &Event<'_>, fields: Fields, spans: Vec<&str>) -> Value {
+        let meta = event.metadata();
+
+        let mut record = json!({
+            "timestamp": humantime::format_rfc3339_micros(clock::now()).to_string(),
+            "level": meta.level().as_str(),
+            "target": meta.target(),
+            "message": fields.message,
+        });
+
+        if !fields.attributes.is_empty() {
+            record["fields"] = Value::Object(fields.attributes);
+        }
+
+        if !spans.is_empty() {
+            record["spans"] = json!(spans);
+        }
+
+        record
+    }
+
+    fn ecs(&self, event: &Event<'_>, fields: Fields, spans: Vec<&str>) -> Value {
+        let meta = event.metadata();
+
+        let mut record = json!({
+            "@timestamp": humantime::format_rfc3339_micros(clock::now()).to_string(),
+            "log.level": meta.level().as_str().to_ascii_lowercase(),
+            "log.logger": meta.target(),
+            "message": fields.message,
+            "ecs.version": ECS_VERSION,
+            "service.name": self.service,
+            "process.pid": std::process::id(),
+        });
+
+        if let (Some(file), Some(line)) = (meta.file(), meta.line()) {
+            record["log.origin.file.name"] = json!(file);
+            record["log.origin.file.line"] = json!(line);
+        }
+
+        // anything else is a custom field, kept under the application's own namespace
+        let mut custom = fields.attributes;
+
+        if !spans.is_empty() {
+            custom.insert("spans".into(), json!(spans));
+        }
+
+        if !custom.is_empty() {
+            record["echo_rs"] = Value::Object(custom);
+        }
+
+        record
+    }
+
+    fn otel(&self, event: &Event<'_>, fields: Fields, spans: Vec<&str>) -> Value {
+        let meta = event.metadata();
+
+        let (severity, number) = match *meta.level() {
+            Level::TRACE => ("TRACE", 1),
+            Level::DEBUG => ("DEBUG", 5),
+            Level::INFO => ("INFO", 9),
+            Level::WARN => ("WARN", 13),
+            Level::ERROR => ("ERROR", 17),
+        };
+
+        let timestamp = clock::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string();
+
+        let mut attributes = fields.attributes;
+
+        if let (Some(file), Some(line)) = (meta.file(), meta.line()) {
+            attributes.insert("code.filepath".into(), json!(file));
+            attributes.insert("code.lineno".into(), json!(line));
+        }
+
+        if !spans.is_empty() {
+            attributes.insert("echo_rs.spans".into(), json!(spans));
+        }
+
+        json!({
+            "Timestamp": timestamp,
+            "ObservedTimestamp": timestamp,
+            "SeverityText": severity,
+            "SeverityNumber": number,
+            "Body": fields.message,
+            "Resource": {
+                "service.name": self.service,
+                "process.pid": std::process::id(),
+            },
+            "InstrumentationScope": {"Name": meta.target()},
+            "Attributes": attributes,
+        })
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for EventFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let spans = || {
+            ctx.event_scope()
+                .map(|scope| scope.from_root().map(|span| span.name()).collect())
+                .unwrap_or_default()
+        };
+
+        let record = match (self.schema, self.format) {
+            (LogSchema::Text, LogFormat::Full) => {
+                return self.text.format_event(ctx, writer, event)
+            }
+            (LogSchema::Text, LogFormat::Pretty) => {
+                return self.pretty.format_event(ctx, writer, event)
+            }
+            (LogSchema::Text, LogFormat::Compact) => {
+                return self.compact.format_event(ctx, writer, event)
+            }
+            (LogSchema::Text, LogFormat::Json) => self.json(event, Fields::of(event), spans()),
+            (LogSchema::Ecs, _) => self.ecs(event, Fields::of(event), spans()),
+            (LogSchema::Otel, _) => self.otel(event, Fields::of(event), spans()),
+        };
+
+        writeln!(writer, "{record}")
+    }
+}
+
+/// An event's message, and every other field it was recorded with
+#[derive(Debug, Default)]
+struct Fields {
+    message: String,
+    attributes: Map<String, Value>,
+}
+
+impl Fields {
+    fn of(event: &Event<'_>) -> Self {
+        let mut fields = Self::default();
+        event.record(&mut fields);
+        fields
+    }
+
+    fn insert(&mut self, field: &Field, value: Value) {
+        match (field.name(), value) {
+            ("message", Value::String(message)) => self.message = message,
+            // echoes are logged pre-serialized, so they can be recorded as objects
+            ("echo", Value::String(echo)) => {
+                let echo = serde_json::from_str(&echo).unwrap_or(Value::String(echo));
+                self.attributes.insert("echo".to_owned(), echo);
+            }
+            (name, value) => {
+                self.attributes.insert(name.to_owned(), value);
+            }
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, json!(format!("{value:?}")));
+    }
+}
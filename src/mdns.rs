@@ -0,0 +1,78 @@
+// mDNS / DNS-SD Advertisement
+
+// Standard Library Imports
+use std::{collections::HashMap, fmt, time::Duration};
+
+// Third Party Imports
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+/// How long to wait for the goodbye packets to go out when withdrawing
+const WITHDRAW_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A live DNS-SD advertisement of the echo service, withdrawn when dropped
+pub(crate) struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl fmt::Debug for Advertisement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Advertisement")
+            .field("fullname", &self.fullname)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Advertisement {
+    /// Advertise the service as `<instance>._http._tcp.local.` (or `_https`)
+    /// on every local interface, following address changes as they happen
+    pub(crate) fn new(instance: Option<&str>, port: u16, tls: bool) -> anyhow::Result<Self> {
+        let host = gethostname::gethostname().to_string_lossy().into_owned();
+        let host = host.split('.').next().unwrap_or("echo-rs").to_owned();
+
+        let instance = instance
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("echo-rs ({host})"));
+
+        let service_type = if tls {
+            "_https._tcp.local."
+        } else {
+            "_http._tcp.local."
+        };
+
+        let properties = HashMap::from([
+            ("path".to_owned(), "/".to_owned()),
+            ("version".to_owned(), env!("CARGO_PKG_VERSION").to_owned()),
+        ]);
+
+        let info = ServiceInfo::new(
+            service_type,
+            &instance,
+            &format!("{host}.local."),
+            "",
+            port,
+            properties,
+        )?
+        .enable_addr_auto();
+
+        let fullname = info.get_fullname().to_owned();
+
+        let daemon = ServiceDaemon::new()?;
+
+        daemon.register(info)?;
+
+        tracing::info!("Advertising `echo-rs` via mDNS as {fullname:?}");
+
+        Ok(Self { daemon, fullname })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_timeout(WITHDRAW_TIMEOUT);
+        }
+
+        let _ = self.daemon.shutdown();
+    }
+}
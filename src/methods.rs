@@ -0,0 +1,47 @@
+// HEAD and OPTIONS Handling
+
+// Third Party Imports
+use axum::{
+    body::{self, Empty, HttpBody},
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+/// The methods every echo route answers
+pub(crate) const ALLOWED: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS, TRACE";
+
+/// Answer HEAD requests with the headers (including the `Content-Length`) a GET
+/// would get but no body, and OPTIONS requests with an accurate `Allow` header
+/// rather than a full echo. The request is still handled (and so logged) as usual.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn head_and_options<B>(req: Request<B>, next: Next<B>) -> Response {
+    let method = req.method().clone();
+
+    let mut response = next.run(req).await;
+
+    if method == Method::HEAD {
+        if let Some(length) = response.body().size_hint().exact() {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+        }
+
+        return response.map(|_| body::boxed(Empty::new()));
+    }
+
+    if method == Method::OPTIONS && response.status().is_success() {
+        let mut response = response.map(|_| body::boxed(Empty::new()));
+
+        *response.status_mut() = StatusCode::NO_CONTENT;
+
+        let headers = response.headers_mut();
+        headers.remove(header::CONTENT_TYPE);
+        headers.remove(header::CONTENT_LENGTH);
+        headers.insert(header::ALLOW, HeaderValue::from_static(ALLOWED));
+
+        return response;
+    }
+
+    response
+}
@@ -1,63 +1,402 @@
 // Prometheus Metrics
 
 // Standard Library Imports
-use std::{future::ready, time::Instant};
+use std::{
+    collections::HashSet,
+    future::ready,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
 
 // Third Party Imports
 use axum::{
-    extract::MatchedPath, http::Request, middleware::Next, response::IntoResponse, routing, Router,
+    body::{self, Body, BoxBody, Bytes, HttpBody},
+    extract::{MatchedPath, State},
+    http::{HeaderMap, Request},
+    middleware::{self, Next},
+    response::Response,
+    routing, Router,
+};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use regex_lite::Regex;
+use tokio_stream::StreamExt;
+
+// Crate-Level Imports
+use crate::{
+    routes::{self, MatchedRule},
+    sampling::Sampler,
 };
-use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 
+/// What requests not matched by any route are reported as having matched
+pub(crate) const FALLBACK_ROUTE: &str = "fallback";
+
+/// What requests are reported as having matched once `path` label values run out
+pub(crate) const OVERFLOW_PATH: &str = "other";
+
+/// Default `http_requests_duration_seconds` buckets, in seconds
+const EXPONENTIAL_SECONDS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// `http_request_size_bytes` and `http_response_size_bytes` buckets, in bytes
+const EXPONENTIAL_BYTES: &[f64] = &[
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+];
+
+/// Where the `path` label of request metrics comes from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PathLabel {
+    /// The route the request matched (e.g. `/*key` for echoed requests)
+    #[default]
+    Route,
+    /// The first `--metrics-path-template` the request's path matches,
+    /// or the route it matched if none do
+    Template,
+    /// The request's path, verbatim
+    Raw,
+}
+
+/// Parse a static label attached to every exported series, e.g. `env=staging`
+pub(crate) fn parse_label(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value
+        .split_once('=')
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .ok_or_else(|| format!("expected `name=value`, got {value:?}"))?;
+
+    let valid = name.chars().enumerate().all(|(index, char)| {
+        char == '_' || char.is_ascii_alphabetic() || index > 0 && char.is_ascii_digit()
+    });
+
+    if name.is_empty() || !valid || name.starts_with("__") {
+        return Err(format!("invalid label name: {name:?}"));
+    }
+
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+/// Derives the `path` label of request metrics, keeping the number of
+/// distinct values (and so of series) within bounds
+#[derive(Debug)]
+pub(crate) struct PathLabels {
+    mode: PathLabel,
+    templates: Vec<(String, Regex)>,
+    max: usize,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl Default for PathLabels {
+    fn default() -> Self {
+        Self {
+            mode: PathLabel::Route,
+            templates: Vec::new(),
+            max: usize::MAX,
+            seen: Mutex::default(),
+        }
+    }
+}
+
+impl PathLabels {
+    pub(crate) fn new(mode: PathLabel, templates: &[String], max: usize) -> anyhow::Result<Self> {
+        Ok(Self {
+            mode,
+            templates: templates
+                .iter()
+                .map(|template| Ok((template.clone(), routes::glob(template)?)))
+                .collect::<anyhow::Result<_>>()?,
+            max,
+            seen: Mutex::default(),
+        })
+    }
+
+    /// The label for a request to the path that matched the route (if any)
+    fn label(&self, route: Option<&str>, path: &str) -> String {
+        let route = || route.unwrap_or(FALLBACK_ROUTE).to_owned();
+
+        let label = match self.mode {
+            PathLabel::Route => return route(),
+            PathLabel::Raw => path.to_owned(),
+            PathLabel::Template => self
+                .templates
+                .iter()
+                .find(|(_, pattern)| pattern.is_match(path))
+                .map_or_else(route, |(template, _)| template.clone()),
+        };
+
+        let mut seen = self.seen.lock().unwrap();
+
+        if seen.contains(&label) {
+            label
+        } else if seen.len() < self.max {
+            seen.insert(label.clone());
+            metrics::gauge!("http_path_labels", seen.len() as f64);
+            label
+        } else {
+            OVERFLOW_PATH.to_owned()
+        }
+    }
+}
+
+/// What request metrics are labelled with, and which requests are sampled
+#[derive(Debug, Default)]
+pub(crate) struct RequestMetrics {
+    pub(crate) sampler: Sampler,
+    pub(crate) paths: PathLabels,
+}
+
+/// Keeps `http_requests_in_flight` up to date for as long as a request is being handled
+struct InFlight;
+
+impl InFlight {
+    fn start() -> Self {
+        metrics::increment_gauge!("http_requests_in_flight", 1.0);
+        Self
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics::decrement_gauge!("http_requests_in_flight", 1.0);
+    }
+}
+
+/// Install the Prometheus recorder (with the given global labels and, if
+/// any are given, latency buckets), and serve what it records from `/metrics`
 #[tracing::instrument]
-pub(crate) fn router() -> Router {
-    let recorder_handle = setup_metrics_recorder();
-    Router::new().route(
+pub fn router(global_labels: Vec<(String, String)>, buckets: &[f64]) -> Result<Router, BuildError> {
+    let recorder_handle = setup_metrics_recorder(global_labels, buckets)?;
+    Ok(Router::new().route(
         "/metrics",
-        routing::get(move || ready(recorder_handle.render())),
-    )
+        routing::get(move || ready(scrape(&recorder_handle))),
+    ))
+}
+
+/// Render what's been recorded, then record how that went (i.e. the
+/// scrape count, how long it took, and how many series each metric
+/// has), so it's reported by the next scrape
+fn scrape(handle: &PrometheusHandle) -> String {
+    let start = Instant::now();
+    let rendered = handle.render();
+    let elapsed = start.elapsed();
+
+    let mut series = Vec::<(&str, usize)>::new();
+
+    for line in rendered.lines() {
+        if let Some(family) = line
+            .strip_prefix("# TYPE ")
+            .and_then(|rest| rest.split_whitespace().next())
+        {
+            series.push((family, 0));
+        } else if !(line.is_empty() || line.starts_with('#')) {
+            if let Some((_, count)) = series.last_mut() {
+                *count += 1;
+            }
+        }
+    }
+
+    for (family, count) in series {
+        metrics::gauge!("metrics_series", count as f64, "metric" => family.to_owned());
+    }
+
+    metrics::increment_counter!("metrics_scrapes_total");
+    metrics::gauge!(
+        "metrics_last_scrape_duration_seconds",
+        elapsed.as_secs_f64()
+    );
+
+    rendered
 }
 
+/// Install the Prometheus recorder, with the given global labels and
+/// (if any are given, rather than the defaults) latency buckets, failing if the
+/// buckets are empty or another recorder has already been installed
 #[tracing::instrument]
-pub(crate) fn setup_metrics_recorder() -> PrometheusHandle {
-    const EXPONENTIAL_SECONDS: &[f64] = &[
-        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
-    ];
+pub fn setup_metrics_recorder(
+    global_labels: Vec<(String, String)>,
+    buckets: &[f64],
+) -> Result<PrometheusHandle, BuildError> {
+    let buckets = match buckets {
+        [] => EXPONENTIAL_SECONDS,
+        buckets => buckets,
+    };
 
-    PrometheusBuilder::new()
+    global_labels
+        .into_iter()
+        .fold(PrometheusBuilder::new(), |builder, (name, value)| {
+            builder.add_global_label(name, value)
+        })
         .set_buckets_for_metric(
             Matcher::Full("http_requests_duration_seconds".to_string()),
-            EXPONENTIAL_SECONDS,
-        )
-        .unwrap()
+            buckets,
+        )?
+        .set_buckets_for_metric(
+            Matcher::Full("route_rule_duration_seconds".to_string()),
+            buckets,
+        )?
+        .set_buckets_for_metric(
+            Matcher::Suffix("_size_bytes".to_string()),
+            EXPONENTIAL_BYTES,
+        )?
         .install_recorder()
-        .unwrap()
+}
+
+/// Track request counts, latencies, and sizes (`http_requests_total`,
+/// `http_requests_duration_seconds`, `http_request_size_bytes`, and
+/// `http_response_size_bytes`) for the router's routes
+pub fn layer<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.route_layer(middleware::from_fn_with_state(
+        Arc::new(RequestMetrics::default()),
+        track_metrics,
+    ))
 }
 
 #[tracing::instrument(skip_all)]
 #[allow(clippy::let_with_type_underscore)]
-pub(crate) async fn track_metrics<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+pub(crate) async fn track_metrics(
+    State(tracking): State<Arc<RequestMetrics>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
     let start = Instant::now();
-    let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
-        matched_path.as_str().to_owned()
-    } else {
-        req.uri().path().to_owned()
-    };
+    // raw paths make for unbounded label cardinality, so they're only used if asked for
+    let path = tracking.paths.label(
+        req.extensions()
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str),
+        req.uri().path(),
+    );
     let method = req.method().clone();
 
+    // the request body is counted as it's read, however much of it that is
+    let received = Arc::new(AtomicU64::new(0));
+    let req = req.map(|body| {
+        let received = received.clone();
+
+        Body::wrap_stream(body.map(move |chunk| {
+            if let Ok(chunk) = chunk.as_ref() {
+                received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+            chunk
+        }))
+    });
+
+    let in_flight = InFlight::start();
     let response = next.run(req).await;
+    drop(in_flight);
 
-    let latency = start.elapsed().as_secs_f64();
-    let status = response.status().as_u16().to_string();
+    let elapsed = start.elapsed();
+    let latency = elapsed.as_secs_f64();
+    let status = response.status().as_u16();
+    let sampled = tracking.sampler.samples(response.status(), elapsed);
 
-    let labels = [
+    let mut labels = vec![
         ("method", method.to_string()),
         ("path", path),
-        ("status", status),
+        ("status", status.to_string()),
+        ("status_class", format!("{}xx", status / 100)),
     ];
 
+    if let Some(MatchedRule(rule)) = response.extensions().get::<MatchedRule>() {
+        labels.push(("rule", rule.clone()));
+    }
+
     metrics::increment_counter!("http_requests_total", &labels);
     metrics::histogram!("http_requests_duration_seconds", latency, &labels);
 
-    response
+    if sampled {
+        metrics::increment_counter!("http_requests_sampled_total", &labels);
+
+        tracing::info!(
+            target: "echo_rs::exemplar",
+            exemplar = true,
+            latency_seconds = latency,
+            "{labels:?}"
+        );
+    }
+
+    let sizes = SizeLabels {
+        method: method.to_string(),
+        status: status.to_string(),
+    };
+
+    response.map(|inner| {
+        body::boxed(SizedBody {
+            inner,
+            received,
+            sent: 0,
+            labels: sizes,
+        })
+    })
+}
+
+/// What request and response size metrics are labelled with
+#[derive(Debug)]
+struct SizeLabels {
+    method: String,
+    status: String,
+}
+
+/// Response body wrapper counting the bytes sent, and recording the size of
+/// the request (as read) and the response (as sent) once it's been dropped
+#[derive(Debug)]
+struct SizedBody {
+    inner: BoxBody,
+    received: Arc<AtomicU64>,
+    sent: u64,
+    labels: SizeLabels,
+}
+
+impl HttpBody for SizedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_data(cx);
+
+        if let Poll::Ready(Some(Ok(data))) = &polled {
+            self.sent += data.len() as u64;
+        }
+
+        polled
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for SizedBody {
+    fn drop(&mut self) {
+        let received = self.received.load(Ordering::Relaxed);
+        let labels = [
+            ("method", self.labels.method.clone()),
+            ("status", self.labels.status.clone()),
+        ];
+
+        metrics::histogram!("http_request_size_bytes", received as f64, &labels);
+        metrics::histogram!("http_response_size_bytes", self.sent as f64, &labels);
+        metrics::counter!("http_request_bytes_total", received, &labels);
+        metrics::counter!("http_response_bytes_total", self.sent, &labels);
+    }
 }
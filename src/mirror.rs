@@ -0,0 +1,731 @@
+// Traffic Mirroring
+
+// Standard Library Imports
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Third Party Imports
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex_lite::Regex;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{Mutex, Notify},
+};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    body::buffer,
+    clock,
+    errors::Failure,
+    listeners::Profile,
+    proxy::strip_hop_by_hop,
+    routes,
+};
+
+/// The longest an at-least-once target's queue waits before retrying a failed delivery
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// How a target's copies are delivered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    /// Sent once, in the background, whatever becomes of them
+    #[default]
+    FireAndForget,
+    /// Queued on disk before the original request is answered, then sent
+    /// (in order, and retried until they're accepted, or the attempts
+    /// allowed run out) by a background worker
+    AtLeastOnce,
+}
+
+/// A mirror target, e.g. `http://localhost:3000;path=/hooks/**;methods=POST|PUT;delivery=at-least-once`
+///
+/// - `path`: only mirror requests whose path matches this glob
+/// - `methods`: only mirror requests with one of these (`|`-separated) methods
+/// - `delivery`: `fire-and-forget` (the default) or `at-least-once`
+#[derive(Clone, Debug)]
+pub(crate) struct MirrorTarget {
+    url: String,
+    path: Option<Regex>,
+    methods: Vec<Method>,
+    delivery: Delivery,
+}
+
+impl MirrorTarget {
+    fn accepts(&self, method: &Method, path: &str) -> bool {
+        (self.methods.is_empty() || self.methods.contains(method))
+            && self.path.as_ref().is_none_or(|glob| glob.is_match(path))
+    }
+}
+
+/// Parse a mirror target, which must be an http(s) URL, optionally
+/// followed by `;`-separated `key=value` filters and delivery settings
+pub(crate) fn parse_target(value: &str) -> Result<MirrorTarget, String> {
+    let mut settings = value.split(';').map(str::trim);
+    let url = settings
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .to_owned();
+
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(format!("mirror target must be an http(s) URL, got {url:?}"));
+    }
+
+    let mut target = MirrorTarget {
+        url,
+        path: None,
+        methods: Vec::new(),
+        delivery: Delivery::default(),
+    };
+
+    for setting in settings.filter(|setting| !setting.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| format!("expected `key=value`, got {setting:?}"))?;
+
+        match key {
+            "path" => target.path = Some(routes::glob(value).map_err(|error| error.to_string())?),
+            "methods" => {
+                target.methods = value
+                    .split('|')
+                    .map(|method| {
+                        Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes())
+                            .map_err(|_| format!("invalid method: {method:?}"))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "delivery" => {
+                target.delivery = match value {
+                    "fire-and-forget" => Delivery::FireAndForget,
+                    "at-least-once" => Delivery::AtLeastOnce,
+                    _ => return Err(format!("unknown delivery mode: {value:?}")),
+                }
+            }
+            _ => return Err(format!("unknown mirror target setting: {key:?}")),
+        }
+    }
+
+    Ok(target)
+}
+
+/// A mirrored request, as it's queued (or dead-lettered) on disk
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct QueuedCopy {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    /// Base64-encoded
+    body: String,
+}
+
+impl QueuedCopy {
+    fn new(method: &Method, path: &str, headers: &HeaderMap, body: &Bytes) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_owned(),
+            headers: headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            body: STANDARD.encode(body),
+        }
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        target: &str,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in &self.headers {
+            headers.append(HeaderName::try_from(name)?, HeaderValue::try_from(value)?);
+        }
+
+        Ok(client
+            .request(
+                Method::from_bytes(self.method.as_bytes())?,
+                format!("{target}{}", self.path),
+            )
+            .headers(headers)
+            .body(STANDARD.decode(&self.body)?))
+    }
+}
+
+/// Whether a target answering with the status is worth retrying, i.e. it's
+/// a server error (or a timeout, or throttling) rather than a rejection
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// A copy that couldn't be delivered, and why
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct DeadLetter {
+    target: String,
+    failure: String,
+    time: String,
+    #[serde(flatten)]
+    copy: QueuedCopy,
+}
+
+/// Copies that couldn't be delivered, kept (one JSON object per line) until they're re-driven
+#[derive(Debug)]
+struct DeadLetters {
+    path: PathBuf,
+    file: Mutex<()>,
+}
+
+impl DeadLetters {
+    /// Keep a copy the target failed to accept
+    async fn add(&self, target: &str, failure: String, copy: QueuedCopy) {
+        tracing::warn!(
+            "Dead-lettering {} {target}{}: {failure}",
+            copy.method,
+            copy.path
+        );
+
+        metrics::increment_counter!("mirror_dead_letters_total", "target" => target.to_owned());
+
+        let letter = DeadLetter {
+            target: target.to_owned(),
+            failure,
+            time: humantime::format_rfc3339_millis(clock::now()).to_string(),
+            copy,
+        };
+
+        let mut line = serde_json::to_vec(&letter).expect("dead letters are serializable");
+        line.push(b'\n');
+
+        let _file = self.file.lock().await;
+
+        let written = async {
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?
+                .write_all(&line)
+                .await
+        };
+
+        if let Err(error) = written.await {
+            tracing::error!("Failed to write dead letter to {:?}: {error}", self.path);
+        }
+    }
+
+    /// The dead letters kept so far, skipping (with a warning) any that can't be read
+    async fn load(&self) -> io::Result<Vec<DeadLetter>> {
+        let contents = match tokio::fs::read(&self.path).await {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+
+        Ok(contents
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                serde_json::from_slice(line)
+                    .map_err(|error| tracing::warn!("Skipping unreadable dead letter: {error}"))
+                    .ok()
+            })
+            .collect())
+    }
+
+    async fn list(&self) -> io::Result<Vec<DeadLetter>> {
+        let _file = self.file.lock().await;
+        self.load().await
+    }
+
+    /// Take every dead letter kept so far, leaving none behind
+    async fn take(&self) -> io::Result<Vec<DeadLetter>> {
+        let _file = self.file.lock().await;
+        let letters = self.load().await?;
+
+        if !letters.is_empty() {
+            tokio::fs::write(&self.path, b"").await?;
+        }
+
+        Ok(letters)
+    }
+}
+
+/// An at-least-once target's backlog of copies, one file apiece
+/// (named so that they sort in the order they were queued)
+#[derive(Debug)]
+struct Queue {
+    dir: PathBuf,
+    next: AtomicU64,
+    queued: Notify,
+}
+
+impl Queue {
+    /// Open (or create) the target's queue under the given directory,
+    /// picking up any copies left undelivered by a previous run
+    fn open(root: &Path, target: &str) -> io::Result<Self> {
+        let name = target
+            .chars()
+            .map(|char| {
+                if char.is_ascii_alphanumeric() {
+                    char
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let dir = root.join(name);
+
+        std::fs::create_dir_all(&dir)?;
+
+        let backlog = std::fs::read_dir(&dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .count();
+
+        metrics::gauge!("mirror_queue_depth", backlog as f64, "target" => target.to_owned());
+
+        if backlog > 0 {
+            tracing::info!("Resuming delivery of {backlog} queued copies to {target}");
+        }
+
+        Ok(Self {
+            dir,
+            next: AtomicU64::new(0),
+            queued: Notify::new(),
+        })
+    }
+
+    /// Queue a copy (written aside, then renamed into place, so it's never read half-written)
+    async fn push(&self, target: &str, copy: &QueuedCopy) -> io::Result<()> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let name = format!(
+            "{stamp:020}-{:010}",
+            self.next.fetch_add(1, Ordering::Relaxed)
+        );
+        let staged = self.dir.join(format!(".{name}.tmp"));
+
+        tokio::fs::write(&staged, serde_json::to_vec(copy)?).await?;
+        tokio::fs::rename(&staged, self.dir.join(format!("{name}.json"))).await?;
+
+        metrics::increment_gauge!("mirror_queue_depth", 1.0, "target" => target.to_owned());
+        self.queued.notify_one();
+
+        Ok(())
+    }
+
+    /// The queued copies, oldest first
+    async fn backlog(&self) -> io::Result<Vec<PathBuf>> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        let mut backlog = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.path().extension().is_some_and(|ext| ext == "json") {
+                backlog.push(entry.path());
+            }
+        }
+
+        backlog.sort();
+
+        Ok(backlog)
+    }
+
+    /// Deliver the queued copies to the target, in order, forever
+    async fn drain(self: Arc<Self>, courier: Courier, target: String) {
+        loop {
+            let backlog = match self.backlog().await {
+                Ok(backlog) => backlog,
+                Err(error) => {
+                    tracing::warn!("Failed to read mirror queue {:?}: {error}", self.dir);
+                    tokio::time::sleep(MAX_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            if backlog.is_empty() {
+                self.queued.notified().await;
+                continue;
+            }
+
+            for file in backlog {
+                if let Some((failure, copy)) = courier.deliver(&target, &file).await {
+                    courier.undeliverable(&target, failure, copy).await;
+                }
+
+                if let Err(error) = tokio::fs::remove_file(&file).await {
+                    tracing::warn!("Failed to dequeue delivered copy {file:?}: {error}");
+                }
+
+                metrics::decrement_gauge!("mirror_queue_depth", 1.0, "target" => target.clone());
+            }
+        }
+    }
+}
+
+/// Sends copies to their targets, dead-lettering (if configured) those that can't be delivered
+#[derive(Clone, Debug)]
+struct Courier {
+    client: reqwest::Client,
+    dead_letters: Option<Arc<DeadLetters>>,
+    /// How many times an at-least-once copy is sent before it's given up on
+    max_attempts: Option<u32>,
+}
+
+impl Courier {
+    async fn undeliverable(&self, target: &str, failure: String, copy: QueuedCopy) {
+        match self.dead_letters.as_ref() {
+            Some(dead_letters) => dead_letters.add(target, failure, copy).await,
+            None => tracing::warn!(
+                "Failed to mirror {} {target}{}: {failure}",
+                copy.method,
+                copy.path
+            ),
+        }
+    }
+
+    /// Send a copy to the target once, in the background
+    fn send(&self, target: String, copy: QueuedCopy) {
+        let courier = self.clone();
+
+        tokio::spawn(async move {
+            let failure = match copy.request(&courier.client, &target) {
+                Ok(request) => match request.send().await {
+                    Ok(response) => {
+                        let status = response.status();
+
+                        metrics::increment_counter!(
+                            "mirror_requests_total",
+                            "target" => target.clone(),
+                            "result" => status.as_str().to_owned(),
+                        );
+
+                        (status.is_client_error() || status.is_server_error())
+                            .then(|| format!("answered with {status}"))
+                    }
+                    Err(error) => {
+                        metrics::increment_counter!(
+                            "mirror_requests_total",
+                            "target" => target.clone(),
+                            "result" => "error",
+                        );
+
+                        Some(error.to_string())
+                    }
+                },
+                Err(error) => Some(error.to_string()),
+            };
+
+            match failure {
+                Some(failure) => courier.undeliverable(&target, failure, copy).await,
+                None => tracing::debug!("Mirrored {} {target}{}", copy.method, copy.path),
+            }
+        });
+    }
+
+    /// Send a queued copy to the target, retrying (with exponential back-off)
+    /// until it's either accepted, rejected, or the attempts allowed run out,
+    /// returning the copy (and what became of it) if it wasn't accepted
+    async fn deliver(&self, target: &str, file: &Path) -> Option<(String, QueuedCopy)> {
+        let copy = match tokio::fs::read(file)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<QueuedCopy>(&bytes)?))
+        {
+            Ok(copy) => copy,
+            Err(error) => {
+                tracing::warn!("Discarding unreadable queued copy {file:?}: {error}");
+                return None;
+            }
+        };
+
+        let (mut delay, mut attempts) = (Duration::from_secs(1), 0);
+
+        loop {
+            let request = match copy.request(&self.client, target) {
+                Ok(request) => request,
+                Err(error) => return Some((error.to_string(), copy)),
+            };
+
+            attempts += 1;
+
+            let failure = match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+
+                    metrics::increment_counter!(
+                        "mirror_requests_total",
+                        "target" => target.to_owned(),
+                        "result" => status.as_str().to_owned(),
+                    );
+
+                    if status.is_success() || status.is_redirection() {
+                        tracing::debug!("Mirrored {} {target}{}: {status}", copy.method, copy.path);
+                        return None;
+                    }
+
+                    if !is_retryable(status) {
+                        return Some((format!("rejected with {status}"), copy));
+                    }
+
+                    format!("answered with {status}")
+                }
+                Err(error) => {
+                    metrics::increment_counter!(
+                        "mirror_requests_total",
+                        "target" => target.to_owned(),
+                        "result" => "error",
+                    );
+
+                    error.to_string()
+                }
+            };
+
+            if self.max_attempts.is_some_and(|max| attempts >= max) {
+                return Some((format!("{failure} (after {attempts} attempts)"), copy));
+            }
+
+            tracing::debug!(
+                "Failed to mirror {} {target}{}: {failure}, retrying in {}",
+                copy.method,
+                copy.path,
+                humantime::format_duration(delay)
+            );
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+/// A mirror target, and (if its delivery is at-least-once) its queue
+#[derive(Debug)]
+struct Target {
+    spec: MirrorTarget,
+    queue: Option<Arc<Queue>>,
+}
+
+/// Replays a copy of every (matching) request to each of the mirror targets,
+/// without waiting for their responses
+#[derive(Debug)]
+pub(crate) struct Mirror {
+    courier: Courier,
+    targets: Arc<[Target]>,
+}
+
+/// How mirrored copies are delivered
+#[derive(Clone, Debug)]
+pub(crate) struct MirrorSettings {
+    /// How long a copy may take to be delivered
+    pub(crate) timeout: Duration,
+    /// Where copies bound for at-least-once targets are queued
+    pub(crate) queue_dir: PathBuf,
+    /// Where copies that can't be delivered are kept, if anywhere
+    pub(crate) dead_letter_file: Option<PathBuf>,
+    /// How many times an at-least-once copy is sent before it's given up on
+    pub(crate) max_attempts: Option<u32>,
+}
+
+impl Mirror {
+    /// Create a mirror if any targets were actually configured
+    pub(crate) fn new(
+        targets: Vec<MirrorTarget>,
+        settings: MirrorSettings,
+    ) -> anyhow::Result<Option<Self>> {
+        if targets.is_empty() {
+            return Ok(None);
+        }
+
+        let courier = Courier {
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .timeout(settings.timeout)
+                .build()?,
+            dead_letters: settings.dead_letter_file.map(|path| {
+                Arc::new(DeadLetters {
+                    path,
+                    file: Mutex::new(()),
+                })
+            }),
+            max_attempts: settings.max_attempts,
+        };
+
+        let targets = targets
+            .into_iter()
+            .map(|spec| {
+                let queue = match spec.delivery {
+                    Delivery::FireAndForget => None,
+                    Delivery::AtLeastOnce => {
+                        let queue = Arc::new(Queue::open(&settings.queue_dir, &spec.url).map_err(
+                            |error| {
+                                anyhow::anyhow!(
+                                    "can't open mirror queue in {:?}: {error}",
+                                    settings.queue_dir
+                                )
+                            },
+                        )?);
+
+                        tokio::spawn(queue.clone().drain(courier.clone(), spec.url.clone()));
+
+                        Some(queue)
+                    }
+                };
+
+                anyhow::Ok(Target { spec, queue })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Some(Self { courier, targets }))
+    }
+
+    /// Whether any target wants a copy of the request
+    fn wants(&self, method: &Method, path: &str) -> bool {
+        self.targets
+            .iter()
+            .any(|target| target.spec.accepts(method, path))
+    }
+
+    /// Hand a copy to the target, queueing it (if the target's delivery
+    /// is at-least-once) or else sending it in the background
+    async fn dispatch(&self, target: &str, copy: QueuedCopy) {
+        let queue = self
+            .targets
+            .iter()
+            .find(|candidate| candidate.spec.url == target)
+            .and_then(|target| target.queue.as_ref());
+
+        match queue {
+            None => self.courier.send(target.to_owned(), copy),
+            Some(queue) => {
+                if let Err(error) = queue.push(target, &copy).await {
+                    self.courier
+                        .undeliverable(target, format!("couldn't be queued: {error}"), copy)
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Send a copy of the request to every target that wants one
+    async fn replay(&self, method: &Method, path: &str, headers: &HeaderMap, body: &Bytes) {
+        let filtered = path.split('?').next().unwrap_or(path);
+
+        for target in self
+            .targets
+            .iter()
+            .filter(|target| target.spec.accepts(method, filtered))
+        {
+            self.dispatch(
+                &target.spec.url,
+                QueuedCopy::new(method, path, headers, body),
+            )
+            .await;
+        }
+    }
+}
+
+/// List and re-drive dead-lettered copies, if they're being kept and an admin token is configured
+#[tracing::instrument]
+pub(crate) fn router(mirror: Option<Arc<Mirror>>, admin_token: Option<AdminToken>) -> Router {
+    match (mirror, admin_token) {
+        (Some(mirror), Some(token)) if mirror.courier.dead_letters.is_some() => Router::new()
+            .route("/_mirror/dead-letters", routing::get(dead_letters))
+            .route("/_mirror/dead-letters/redrive", routing::post(redrive))
+            .route_layer(middleware::from_fn_with_state(token, admin::require_token))
+            .with_state(mirror),
+        _ => Router::new(),
+    }
+}
+
+/// The copies dead-lettered so far
+#[tracing::instrument(skip_all)]
+async fn dead_letters(State(mirror): State<Arc<Mirror>>) -> Response {
+    let Some(dead_letters) = mirror.courier.dead_letters.as_ref() else {
+        return Json(Vec::<DeadLetter>::new()).into_response();
+    };
+
+    match dead_letters.list().await {
+        Ok(letters) => Json(letters).into_response(),
+        Err(error) => {
+            Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+/// Hand every dead-lettered copy back to its target (where any that
+/// fail again are dead-lettered again)
+#[tracing::instrument(skip_all)]
+async fn redrive(State(mirror): State<Arc<Mirror>>) -> Response {
+    let Some(dead_letters) = mirror.courier.dead_letters.as_ref() else {
+        return Json(serde_json::json!({"redriven": 0})).into_response();
+    };
+
+    let letters = match dead_letters.take().await {
+        Ok(letters) => letters,
+        Err(error) => {
+            return Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+                .into_response()
+        }
+    };
+
+    tracing::info!("Re-driving {} dead-lettered copies", letters.len());
+
+    let redriven = letters.len();
+
+    for DeadLetter { target, copy, .. } in letters {
+        mirror.dispatch(&target, copy).await;
+    }
+
+    Json(serde_json::json!({"redriven": redriven})).into_response()
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn mirror(
+    State(mirror): State<Arc<Mirror>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !Profile::of(&req).mirrors() || !mirror.wants(req.method(), req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+
+    let body = match buffer(&parts, body).await {
+        Ok(body) => body,
+        Err(rejection) => return rejection,
+    };
+
+    let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+
+    let mut headers = strip_hop_by_hop(&parts.headers);
+    // the targets' own host (and the copy's length) apply instead
+    headers.remove(header::HOST);
+    headers.remove(header::CONTENT_LENGTH);
+
+    mirror.replay(&parts.method, path, &headers, &body).await;
+
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
@@ -0,0 +1,239 @@
+// Content Negotiation Playground
+
+// Standard Library Imports
+use std::collections::BTreeMap;
+
+// Third Party Imports
+use axum::{
+    extract::{Json, Query},
+    http::{header, HeaderMap, HeaderName},
+    routing, Router,
+};
+
+/// Variants offered when the request doesn't name its own
+const DEFAULT_TYPES: &str = "application/json,text/html,text/plain,application/xml";
+const DEFAULT_LANGUAGES: &str = "en,en-US,fr,de,es,ja";
+const DEFAULT_ENCODINGS: &str = "gzip,br,deflate,zstd,identity";
+
+/// A single (parsed) element of an `Accept`-style header
+#[derive(Clone, Debug, serde::Serialize)]
+struct Preference {
+    value: String,
+    q: f32,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    params: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// How one available variant fared against the client's preferences
+#[derive(Clone, Debug, serde::Serialize)]
+struct Candidate {
+    variant: String,
+    q: f32,
+    matched_by: Option<String>,
+}
+
+/// The outcome of negotiating a single dimension
+#[derive(Clone, Debug, serde::Serialize)]
+struct Negotiation {
+    header: Option<String>,
+    parsed: Vec<Preference>,
+    candidates: Vec<Candidate>,
+    chosen: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+struct Offers {
+    types: Option<String>,
+    languages: Option<String>,
+    encodings: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct NegotiationReport {
+    accept: Negotiation,
+    accept_language: Negotiation,
+    accept_encoding: Negotiation,
+}
+
+#[tracing::instrument]
+pub(crate) fn router() -> Router {
+    Router::new().route("/negotiate", routing::get(negotiate))
+}
+
+/// Negotiate against the variants named by `?types=`, `?languages=`, and `?encodings=`
+/// (or a default set of each), reporting how every candidate was scored
+#[tracing::instrument(skip_all)]
+async fn negotiate(Query(offers): Query<Offers>, headers: HeaderMap) -> Json<NegotiationReport> {
+    Json(NegotiationReport {
+        accept: negotiate_one(
+            &headers,
+            header::ACCEPT,
+            offers.types.as_deref().unwrap_or(DEFAULT_TYPES),
+            media_range_match,
+        ),
+        accept_language: negotiate_one(
+            &headers,
+            header::ACCEPT_LANGUAGE,
+            offers.languages.as_deref().unwrap_or(DEFAULT_LANGUAGES),
+            language_range_match,
+        ),
+        accept_encoding: negotiate_one(
+            &headers,
+            header::ACCEPT_ENCODING,
+            offers.encodings.as_deref().unwrap_or(DEFAULT_ENCODINGS),
+            coding_match,
+        ),
+    })
+}
+
+/// The most acceptable of the offered (comma-separated) media types, if any are
+pub(crate) fn choose_media_type(headers: &HeaderMap, offered: &str) -> Option<String> {
+    negotiate_one(headers, header::ACCEPT, offered, media_range_match).chosen
+}
+
+/// Parse an `Accept`-style header into its elements, in the order given
+fn parse(value: &str) -> Vec<Preference> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|element| !element.is_empty())
+        .map(|element| {
+            let mut parts = element.split(';').map(str::trim);
+            let value = parts.next().unwrap_or_default().to_ascii_lowercase();
+
+            let mut preference = Preference {
+                value,
+                q: 1.0,
+                params: BTreeMap::new(),
+                error: None,
+            };
+
+            for param in parts.filter(|param| !param.is_empty()) {
+                let (name, param) = param.split_once('=').unwrap_or((param, ""));
+                let (name, param) = (
+                    name.trim().to_ascii_lowercase(),
+                    param.trim().trim_matches('"'),
+                );
+
+                if name != "q" {
+                    preference.params.insert(name, param.to_owned());
+                    continue;
+                }
+
+                match param.parse::<f32>() {
+                    Ok(q) if (0.0..=1.0).contains(&q) && param.len() <= 5 => preference.q = q,
+                    _ => preference.error = Some(format!("invalid quality value {param:?}")),
+                }
+            }
+
+            preference
+        })
+        .collect()
+}
+
+/// How specifically a media range matches a media type (higher is more specific)
+fn media_range_match(range: &str, variant: &str) -> Option<u8> {
+    let (range_type, range_subtype) = range.split_once('/')?;
+    let (variant_type, variant_subtype) = variant.split_once('/')?;
+
+    match (range_type, range_subtype) {
+        ("*", "*") => Some(0),
+        (range_type, "*") if range_type == variant_type => Some(1),
+        (range_type, range_subtype)
+            if range_type == variant_type && range_subtype == variant_subtype =>
+        {
+            Some(2)
+        }
+        _ => None,
+    }
+}
+
+/// RFC 4647 basic filtering: a range matches a tag equal to it, or one
+/// it's a prefix of (ending at a subtag boundary)
+fn language_range_match(range: &str, variant: &str) -> Option<u8> {
+    if range == "*" {
+        return Some(0);
+    }
+
+    let prefix = variant
+        .strip_prefix(range)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'));
+
+    prefix.then(|| u8::try_from(range.split('-').count()).unwrap_or(u8::MAX))
+}
+
+fn coding_match(range: &str, variant: &str) -> Option<u8> {
+    match range {
+        "*" => Some(0),
+        range if range == variant => Some(1),
+        _ => None,
+    }
+}
+
+fn negotiate_one(
+    headers: &HeaderMap,
+    name: HeaderName,
+    offered: &str,
+    matches: fn(&str, &str) -> Option<u8>,
+) -> Negotiation {
+    let header = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<&str>>();
+
+    let header = (!header.is_empty()).then(|| header.join(", "));
+
+    let parsed = header.as_deref().map(parse).unwrap_or_default();
+
+    let candidates = offered
+        .split(',')
+        .map(|variant| variant.trim().to_ascii_lowercase())
+        .filter(|variant| !variant.is_empty())
+        .map(|variant| {
+            // the most specific matching range determines the variant's quality
+            let best = parsed
+                .iter()
+                .filter_map(|preference| {
+                    matches(&preference.value, &variant)
+                        .map(|specificity| (specificity, preference))
+                })
+                .max_by_key(|(specificity, _)| *specificity)
+                .map(|(_, preference)| preference);
+
+            let q = match best {
+                Some(preference) => preference.q,
+                // an absent header accepts anything, and `identity` is
+                // acceptable unless explicitly excluded
+                None if header.is_none() => 1.0,
+                None if name == header::ACCEPT_ENCODING && variant == "identity" => 0.001,
+                None => 0.0,
+            };
+
+            Candidate {
+                variant,
+                q,
+                matched_by: best.map(|preference| preference.value.clone()),
+            }
+        })
+        .collect::<Vec<Candidate>>();
+
+    // ties go to whichever variant was offered first
+    let chosen = candidates
+        .iter()
+        .filter(|candidate| candidate.q > 0.0)
+        .fold(None::<&Candidate>, |best, candidate| match best {
+            Some(best) if best.q >= candidate.q => Some(best),
+            _ => Some(candidate),
+        })
+        .map(|candidate| candidate.variant.clone());
+
+    Negotiation {
+        header,
+        parsed,
+        candidates,
+        chosen,
+    }
+}
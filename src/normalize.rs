@@ -0,0 +1,114 @@
+// Path & Host Normalization Reporting
+
+// Third Party Imports
+use axum::http::{header, HeaderMap, Uri};
+use unicode_normalization::UnicodeNormalization;
+
+/// A value as it was received, and as an intermediary normalizing it would see it
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Forms {
+    pub(crate) raw: String,
+    pub(crate) normalized: String,
+    /// Whether normalizing changed the value (i.e. whether proxies and backends might disagree on it)
+    pub(crate) differs: bool,
+}
+
+impl Forms {
+    fn new(raw: &str, normalized: String) -> Self {
+        Self {
+            differs: raw != normalized,
+            raw: raw.to_owned(),
+            normalized,
+        }
+    }
+}
+
+/// The raw and normalized forms of the request's path and `Host`
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct Normalization {
+    pub(crate) path: Forms,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) host: Option<Forms>,
+}
+
+impl Normalization {
+    pub(crate) fn of(uri: &Uri, headers: &HeaderMap) -> Self {
+        let path = uri.path();
+
+        // HTTP/2 (and later) requests carry the host as their `:authority`
+        let host = headers
+            .get(header::HOST)
+            .map(|host| String::from_utf8_lossy(host.as_bytes()).into_owned())
+            .or_else(|| {
+                uri.authority()
+                    .map(|authority| authority.as_str().to_owned())
+            });
+
+        Self {
+            path: Forms::new(path, normalize_path(path)),
+            host: host.map(|host| {
+                let normalized = normalize_host(&host);
+                Forms::new(&host, normalized)
+            }),
+        }
+    }
+}
+
+/// Percent-decode the path, NFC-normalize it, and resolve its
+/// dot segments (after decoding, so `%2e%2e` counts as `..`)
+fn normalize_path(path: &str) -> String {
+    let decoded = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+
+    remove_dot_segments(&decoded.nfc().collect::<String>())
+}
+
+/// RFC 3986's `remove_dot_segments`, for an absolute path
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+
+    while let Some(segment) = parts.next() {
+        let last = parts.peek().is_none();
+
+        match segment {
+            "." | ".." => {
+                if segment == ".." {
+                    segments.pop();
+                }
+
+                // a trailing dot segment leaves the path ending in a `/`
+                if last {
+                    segments.push("");
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    format!("/{}", segments.join("/"))
+}
+
+/// Percent-decode the host, decode any punycode (i.e. `xn--`)
+/// labels, then NFC-normalize and lowercase it, keeping any port
+fn normalize_host(host: &str) -> String {
+    let decoded = percent_encoding::percent_decode_str(host).decode_utf8_lossy();
+
+    // IP literals (e.g. `[::1]:8080`) have no labels to decode
+    if decoded.starts_with('[') {
+        return decoded.to_ascii_lowercase();
+    }
+
+    let (name, port) = match decoded.rsplit_once(':') {
+        Some((name, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => (name, Some(port)),
+        _ => (decoded.as_ref(), None),
+    };
+
+    // labels that don't decode are kept as they were
+    let (name, _) = idna::domain_to_unicode(name);
+    let name = name.nfc().collect::<String>().to_lowercase();
+
+    match port {
+        Some(port) => format!("{name}:{port}"),
+        None => name,
+    }
+}
@@ -0,0 +1,483 @@
+// OAuth2 Mock Authorization Server
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    extract::{Form, Json, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing, Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use ring::digest;
+use serde_json::{json, Map, Value};
+
+// Crate-Level Imports
+use crate::jwt::{self, SigningKey};
+
+/// How long an issued authorization code may be exchanged for a token
+const CODE_TTL: Duration = Duration::from_secs(600);
+
+/// How many times longer refresh tokens live than access tokens
+const REFRESH_TTL_FACTOR: u64 = 24;
+
+/// Tunables for the tokens the mock authorization server mints
+#[derive(Clone, Debug)]
+pub(crate) struct OAuthSettings {
+    /// The `iss` claim (defaults to the scheme and `Host` the request was made to)
+    pub(crate) issuer: Option<String>,
+    /// The `aud` claim, if any
+    pub(crate) audience: Option<String>,
+    /// How long access tokens remain valid
+    pub(crate) token_ttl: Duration,
+    /// Additional claims added to every access token
+    pub(crate) claims: Map<String, Value>,
+}
+
+/// An authorization code awaiting exchange
+#[derive(Clone, Debug)]
+struct PendingCode {
+    client_id: String,
+    redirect_uri: String,
+    scope: Option<String>,
+    challenge: Option<(String, String)>,
+    /// The OpenID Connect `nonce` to bind the ID token to
+    nonce: Option<String>,
+    issued: Instant,
+}
+
+/// The mock authorization server's state
+#[derive(Debug)]
+pub(crate) struct AuthServer {
+    key: SigningKey,
+    settings: OAuthSettings,
+    tls: bool,
+    codes: Mutex<HashMap<String, PendingCode>>,
+}
+
+/// Parse a `name=value` claim, treating the value as JSON where it parses as such
+pub(crate) fn parse_claim(value: &str) -> Result<(String, Value), String> {
+    let (name, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=value`, got {value:?}"))?;
+
+    let value = serde_json::from_str(value.trim())
+        .unwrap_or_else(|_| Value::String(value.trim().to_owned()));
+
+    Ok((name.trim().to_owned(), value))
+}
+
+/// An RFC 6749 error response
+#[derive(Debug)]
+struct OAuthError {
+    error: &'static str,
+    description: String,
+}
+
+impl OAuthError {
+    fn new(error: &'static str, description: impl Into<String>) -> Self {
+        Self {
+            error,
+            description: description.into(),
+        }
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let status = match self.error {
+            "invalid_client" => StatusCode::UNAUTHORIZED,
+            "server_error" => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        (
+            status,
+            Json(json!({"error": self.error, "error_description": self.description})),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct AuthorizeParams {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    scope: Option<String>,
+    state: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+    nonce: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct TokenParams {
+    grant_type: String,
+    client_id: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    scope: Option<String>,
+    username: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+struct IntrospectParams {
+    token: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+}
+
+impl AuthServer {
+    pub(crate) fn new(key: SigningKey, settings: OAuthSettings, tls: bool) -> Self {
+        Self {
+            key,
+            settings,
+            tls,
+            codes: Mutex::default(),
+        }
+    }
+
+    /// The issuer identifier tokens are minted under
+    fn issuer(&self, headers: &HeaderMap) -> String {
+        self.settings.issuer.clone().unwrap_or_else(|| {
+            let host = headers
+                .get(header::HOST)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("localhost");
+
+            format!("{}://{host}", if self.tls { "https" } else { "http" })
+        })
+    }
+
+    /// Mint an access token (and refresh token, and ID token, if the
+    /// `openid` scope was granted) for the given subject
+    fn mint(
+        &self,
+        issuer: &str,
+        client_id: &str,
+        subject: &str,
+        scope: Option<String>,
+        nonce: Option<String>,
+    ) -> Result<TokenResponse, OAuthError> {
+        let (now, ttl) = (jwt::unix_now(), self.settings.token_ttl.as_secs().max(1));
+
+        let mut claims = self.settings.claims.clone();
+
+        claims.extend([
+            ("iss".to_owned(), json!(issuer)),
+            ("sub".to_owned(), json!(subject)),
+            ("client_id".to_owned(), json!(client_id)),
+            ("iat".to_owned(), json!(now)),
+            ("nbf".to_owned(), json!(now)),
+            ("exp".to_owned(), json!(now + ttl)),
+            ("jti".to_owned(), json!(jwt::random_id(16))),
+        ]);
+
+        if let Some(audience) = self.settings.audience.as_ref() {
+            claims.insert("aud".to_owned(), json!(audience));
+        }
+
+        if let Some(scope) = scope.as_ref() {
+            claims.insert("scope".to_owned(), json!(scope));
+        }
+
+        let id_claims = scope
+            .as_deref()
+            .is_some_and(|scope| scope.split_whitespace().any(|scope| scope == "openid"))
+            .then(|| {
+                let mut id_claims = json!({
+                    "iss": issuer,
+                    "sub": subject,
+                    "aud": self.settings.audience.as_deref().unwrap_or(client_id),
+                    "azp": client_id,
+                    "iat": now,
+                    "auth_time": now,
+                    "exp": now + ttl,
+                });
+
+                if let Some(nonce) = nonce {
+                    id_claims["nonce"] = json!(nonce);
+                }
+
+                id_claims
+            });
+
+        let refresh_claims = json!({
+            "iss": issuer,
+            "sub": subject,
+            "client_id": client_id,
+            "scope": scope,
+            "iat": now,
+            "exp": now + ttl * REFRESH_TTL_FACTOR,
+            "jti": jwt::random_id(16),
+            "token_use": "refresh",
+        });
+
+        let sign = |claims: &Value| {
+            self.key
+                .sign(claims)
+                .map_err(|error| OAuthError::new("server_error", error.to_string()))
+        };
+
+        Ok(TokenResponse {
+            access_token: sign(&Value::Object(claims))?,
+            token_type: "Bearer",
+            expires_in: ttl,
+            refresh_token: Some(sign(&refresh_claims)?),
+            id_token: id_claims.as_ref().map(sign).transpose()?,
+            scope,
+        })
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(server: Arc<AuthServer>) -> Router {
+    Router::new()
+        .route("/oauth/authorize", routing::get(authorize))
+        .route("/oauth/token", routing::post(token))
+        .route("/oauth/introspect", routing::post(introspect))
+        .route("/.well-known/openid-configuration", routing::get(discovery))
+        .route("/jwks.json", routing::get(jwks))
+        .with_state(server)
+}
+
+/// Auto-approve the authorization request, redirecting straight back with a code
+#[tracing::instrument(skip_all)]
+async fn authorize(
+    State(server): State<Arc<AuthServer>>,
+    Query(params): Query<AuthorizeParams>,
+) -> Redirect {
+    let separator = if params.redirect_uri.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+
+    let mut reply = vec![];
+
+    if params.response_type != "code" {
+        reply.push(("error", "unsupported_response_type".to_owned()));
+    } else {
+        let code = jwt::random_id(24);
+        let mut codes = server.codes.lock().unwrap();
+
+        // codes that were never exchanged would otherwise pile up
+        codes.retain(|_, pending| pending.issued.elapsed() < CODE_TTL);
+        codes.insert(
+            code.clone(),
+            PendingCode {
+                client_id: params.client_id,
+                redirect_uri: params.redirect_uri.clone(),
+                scope: params.scope,
+                challenge: params.code_challenge.map(|challenge| {
+                    (
+                        params
+                            .code_challenge_method
+                            .unwrap_or_else(|| "plain".to_owned()),
+                        challenge,
+                    )
+                }),
+                nonce: params.nonce,
+                issued: Instant::now(),
+            },
+        );
+
+        reply.push(("code", code));
+    }
+
+    if let Some(state) = params.state {
+        reply.push(("state", state));
+    }
+
+    let query = serde_urlencoded::to_string(&reply).unwrap_or_default();
+
+    Redirect::to(&format!("{}{separator}{query}", params.redirect_uri))
+}
+
+/// Pull the client id out of HTTP Basic credentials, if supplied
+fn basic_client_id(headers: &HeaderMap) -> Option<String> {
+    let credentials = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+
+    let decoded = String::from_utf8(STANDARD.decode(credentials.trim()).ok()?).ok()?;
+
+    decoded
+        .split_once(':')
+        .map(|(client_id, _)| client_id.to_owned())
+}
+
+#[tracing::instrument(skip_all)]
+async fn token(
+    State(server): State<Arc<AuthServer>>,
+    headers: HeaderMap,
+    Form(params): Form<TokenParams>,
+) -> Result<Json<TokenResponse>, OAuthError> {
+    let issuer = server.issuer(&headers);
+
+    let client_id = basic_client_id(&headers)
+        .or(params.client_id.clone())
+        .ok_or_else(|| OAuthError::new("invalid_client", "no client credentials supplied"))?;
+
+    let response = match params.grant_type.as_str() {
+        "client_credentials" => server.mint(&issuer, &client_id, &client_id, params.scope, None)?,
+        "password" => {
+            let subject = params.username.unwrap_or_else(|| client_id.clone());
+
+            server.mint(&issuer, &client_id, &subject, params.scope, None)?
+        }
+        "authorization_code" => {
+            let code = params
+                .code
+                .ok_or_else(|| OAuthError::new("invalid_request", "missing `code`"))?;
+
+            let pending = server
+                .codes
+                .lock()
+                .unwrap()
+                .remove(&code)
+                .filter(|pending| pending.issued.elapsed() < CODE_TTL)
+                .ok_or_else(|| OAuthError::new("invalid_grant", "unknown or expired code"))?;
+
+            if pending.client_id != client_id {
+                return Err(OAuthError::new(
+                    "invalid_grant",
+                    "code issued to another client",
+                ));
+            }
+
+            if params
+                .redirect_uri
+                .is_some_and(|uri| uri != pending.redirect_uri)
+            {
+                return Err(OAuthError::new("invalid_grant", "`redirect_uri` mismatch"));
+            }
+
+            if let Some((method, challenge)) = pending.challenge {
+                let verifier = params
+                    .code_verifier
+                    .ok_or_else(|| OAuthError::new("invalid_grant", "missing `code_verifier`"))?;
+
+                let computed = match method.as_str() {
+                    "S256" => URL_SAFE_NO_PAD
+                        .encode(digest::digest(&digest::SHA256, verifier.as_bytes()).as_ref()),
+                    _ => verifier,
+                };
+
+                if computed != challenge {
+                    return Err(OAuthError::new("invalid_grant", "PKCE verification failed"));
+                }
+            }
+
+            server.mint(&issuer, &client_id, "user", pending.scope, pending.nonce)?
+        }
+        "refresh_token" => {
+            let claims = params
+                .refresh_token
+                .as_deref()
+                .ok_or_else(|| OAuthError::new("invalid_request", "missing `refresh_token`"))
+                .and_then(|token| {
+                    server
+                        .key
+                        .verify(token)
+                        .map_err(|error| OAuthError::new("invalid_grant", error))
+                })?;
+
+            if claims["token_use"] != "refresh" {
+                return Err(OAuthError::new("invalid_grant", "not a refresh token"));
+            }
+
+            let subject = claims["sub"].as_str().unwrap_or(&client_id).to_owned();
+            let scope = params
+                .scope
+                .or_else(|| claims["scope"].as_str().map(str::to_owned));
+
+            server.mint(&issuer, &client_id, &subject, scope, None)?
+        }
+        other => {
+            return Err(OAuthError::new(
+                "unsupported_grant_type",
+                format!("unsupported grant type: {other:?}"),
+            ))
+        }
+    };
+
+    tracing::info!(
+        "Minted access token for client {client_id:?} ({})",
+        params.grant_type
+    );
+
+    Ok(Json(response))
+}
+
+/// RFC 7662 token introspection
+#[tracing::instrument(skip_all)]
+async fn introspect(
+    State(server): State<Arc<AuthServer>>,
+    Form(params): Form<IntrospectParams>,
+) -> Json<Value> {
+    Json(match server.key.verify(&params.token) {
+        Ok(Value::Object(mut claims)) => {
+            claims.insert("active".to_owned(), Value::Bool(true));
+            Value::Object(claims)
+        }
+        Ok(_) | Err(_) => json!({"active": false}),
+    })
+}
+
+/// OpenID Connect discovery document
+#[tracing::instrument(skip_all)]
+async fn discovery(State(server): State<Arc<AuthServer>>, headers: HeaderMap) -> Json<Value> {
+    let issuer = server.issuer(&headers);
+
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/oauth/authorize"),
+        "token_endpoint": format!("{issuer}/oauth/token"),
+        "introspection_endpoint": format!("{issuer}/oauth/introspect"),
+        "jwks_uri": format!("{issuer}/jwks.json"),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": [jwt::ALGORITHM],
+        "scopes_supported": ["openid"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+        "grant_types_supported": [
+            "authorization_code",
+            "client_credentials",
+            "password",
+            "refresh_token",
+        ],
+        "code_challenge_methods_supported": ["S256", "plain"],
+    }))
+}
+
+/// The JSON Web Key Set tokens can be verified against
+#[tracing::instrument(skip_all)]
+async fn jwks(State(server): State<Arc<AuthServer>>) -> Json<Value> {
+    Json(json!({"keys": [server.key.jwk()]}))
+}
@@ -0,0 +1,148 @@
+// OpenTelemetry Trace Export
+
+// Third Party Imports
+use axum::{
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace, Resource};
+use tracing::{field::Empty, Instrument, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{filter, registry::LookupSpan, Layer};
+
+// Crate-Level Imports
+use crate::request_id::RequestId;
+
+/// The target of the (one) span exported per request, so that
+/// the rest of the server's spans can be kept to the logs
+const SPAN_TARGET: &str = "echo_rs::otel";
+
+/// Export a span per request to the OTLP/HTTP collector at the given
+/// endpoint (e.g. `http://localhost:4318`), in batches
+pub(crate) fn layer<S>(endpoint: &str, service: &str) -> anyhow::Result<impl Layer<S>>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service.to_owned(),
+        )])))
+        .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+
+    Ok(tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(filter::filter_fn(|metadata| {
+            metadata.target() == SPAN_TARGET
+        })))
+}
+
+/// Flush any spans yet to be exported
+pub(crate) fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Lets propagators read a request's headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// The caller's trace context, from its B3 headers (in either the single
+/// `b3` header or the multi-header `X-B3-*` encoding)
+fn b3_context(headers: &HeaderMap) -> Option<SpanContext> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let (trace_id, span_id, sampled) = match header("b3") {
+        Some(single) => {
+            let mut parts = single.split('-');
+            (parts.next()?, parts.next()?, parts.next())
+        }
+        None => (
+            header("x-b3-traceid")?,
+            header("x-b3-spanid")?,
+            header("x-b3-sampled").or(header("x-b3-flags")),
+        ),
+    };
+
+    // 64-bit trace ids are left-padded to the 128 bits W3C trace contexts use
+    let trace_id = TraceId::from_hex(&format!("{trace_id:0>32}")).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let flags = match sampled {
+        Some("0") => TraceFlags::default(),
+        _ => TraceFlags::SAMPLED,
+    };
+
+    Some(SpanContext::new(
+        trace_id,
+        span_id,
+        flags,
+        true,
+        TraceState::default(),
+    ))
+    .filter(SpanContext::is_valid)
+}
+
+/// The trace context propagated by the caller, preferring W3C `traceparent` to B3
+fn parent(headers: &HeaderMap) -> Context {
+    let context = TraceContextPropagator::new().extract(&HeaderExtractor(headers));
+
+    if context.span().span_context().is_valid() {
+        return context;
+    }
+
+    b3_context(headers).map_or(context, |span| {
+        Context::new().with_remote_span_context(span)
+    })
+}
+
+/// Trace each request as a server span, parented to the caller's trace (if any)
+pub(crate) async fn trace<B>(req: Request<B>, next: Next<B>) -> Response {
+    let span = tracing::info_span!(
+        target: SPAN_TARGET,
+        "request",
+        otel.name = format!("{} {}", req.method(), req.uri().path()),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %req.method(),
+        url.path = req.uri().path(),
+        url.query = req.uri().query(),
+        http.response.status_code = Empty,
+        request_id = req
+            .extensions()
+            .get::<RequestId>()
+            .map(|RequestId(id)| id.as_str()),
+    );
+
+    span.set_parent(parent(req.headers()));
+
+    let response = next.run(req).instrument(span.clone()).await;
+
+    span.record("http.response.status_code", response.status().as_u16());
+
+    if response.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    response
+}
@@ -0,0 +1,499 @@
+// Built-In Body Parsers
+
+// Third Party Imports
+use quick_xml::events::{BytesStart, Event};
+use serde_json::{Map, Value};
+
+// Crate-Level Imports
+use crate::body::BodyParser;
+
+/// How deeply nested a structured body may be (as with `serde_json`'s own limit),
+/// as the values parsed from it are built, serialized, and dropped recursively
+const MAX_DEPTH: usize = 128;
+
+/// Insert a value under the given key, collecting repeated keys into an array
+fn insert_repeated(map: &mut Map<String, Value>, key: String, value: Value) {
+    match map.get_mut(&key) {
+        None => {
+            map.insert(key, value);
+        }
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+    }
+}
+
+/// `application/x-www-form-urlencoded` bodies, as an object
+/// (with repeated keys collected into arrays)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Form;
+
+impl BodyParser for Form {
+    fn name(&self) -> &'static str {
+        "form"
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "application/x-www-form-urlencoded"
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        // decoding is lossy, which would mangle binary bodies sent with the wrong type
+        std::str::from_utf8(body).map_err(|error| error.to_string())?;
+
+        let pairs = serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+            .map_err(|error| error.to_string())?;
+
+        let mut map = Map::new();
+
+        for (key, value) in pairs {
+            insert_repeated(&mut map, key, Value::String(value));
+        }
+
+        Ok(Value::Object(map))
+    }
+}
+
+/// `text/*` bodies, as a string
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Text;
+
+impl BodyParser for Text {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type.starts_with("text/")
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        std::str::from_utf8(body)
+            .map(|text| Value::String(text.to_owned()))
+            .map_err(|error| error.to_string())
+    }
+}
+
+/// The position of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// `multipart/form-data` bodies, as an array of each part's metadata
+/// (along with the value of any part that isn't a file upload)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Multipart;
+
+impl Multipart {
+    /// The `name="value"` parameters of a header value (e.g. `Content-Disposition`)
+    fn parameters(value: &str) -> impl Iterator<Item = (String, String)> + '_ {
+        value.split(';').skip(1).filter_map(|parameter| {
+            let (name, value) = parameter.split_once('=')?;
+
+            Some((
+                name.trim().to_ascii_lowercase(),
+                value.trim().trim_matches('"').to_owned(),
+            ))
+        })
+    }
+
+    fn part(part: &[u8]) -> Result<Value, String> {
+        let (head, content) = match part.strip_prefix(b"\r\n") {
+            Some(content) => (&b""[..], content),
+            None => {
+                let end = find(part, b"\r\n\r\n").ok_or("part has no header terminator")?;
+                (&part[..end], &part[end + 4..])
+            }
+        };
+
+        let head = std::str::from_utf8(head).map_err(|error| error.to_string())?;
+
+        let mut headers = Map::new();
+        let mut metadata = Map::new();
+
+        for line in head.split("\r\n") {
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("malformed part header {line:?}"))?;
+            let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+
+            match name.as_str() {
+                "content-disposition" => {
+                    for (parameter, value) in Self::parameters(value) {
+                        if matches!(parameter.as_str(), "name" | "filename") {
+                            metadata.insert(parameter, Value::String(value));
+                        }
+                    }
+                }
+                "content-type" => {
+                    metadata.insert("content_type".into(), Value::String(value.to_owned()));
+                }
+                _ => {}
+            }
+
+            headers.insert(name, Value::String(value.to_owned()));
+        }
+
+        metadata.insert("size".into(), Value::from(content.len()));
+        metadata.insert("headers".into(), Value::Object(headers));
+
+        if !metadata.contains_key("filename") {
+            if let Ok(value) = std::str::from_utf8(content) {
+                metadata.insert("value".into(), Value::String(value.to_owned()));
+            }
+        }
+
+        Ok(Value::Object(metadata))
+    }
+}
+
+impl BodyParser for Multipart {
+    fn name(&self) -> &'static str {
+        "multipart"
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "multipart/form-data"
+    }
+
+    /// Parts are delimited by whatever boundary the body opens with,
+    /// rather than the one declared in the `Content-Type` header
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        let opening = find(body, b"\r\n").ok_or("body has no opening boundary")?;
+        let boundary = &body[..opening];
+
+        if boundary.len() <= 2 || !boundary.starts_with(b"--") {
+            return Err("body has no opening boundary".into());
+        }
+
+        let delimiter = [&b"\r\n"[..], boundary].concat();
+        let mut rest = &body[opening + 2..];
+        let mut parts = vec![];
+
+        loop {
+            let end = find(rest, &delimiter).ok_or("body has no closing boundary")?;
+            parts.push(Self::part(&rest[..end])?);
+            rest = &rest[end + delimiter.len()..];
+
+            if rest.starts_with(b"--") {
+                break;
+            }
+
+            rest = rest
+                .strip_prefix(b"\r\n")
+                .ok_or("malformed boundary delimiter")?;
+        }
+
+        Ok(Value::Array(parts))
+    }
+}
+
+/// XML bodies, with elements as objects keyed by child element name,
+/// attributes keyed as `@name`, and mixed text content keyed as `#text`
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Xml;
+
+impl Xml {
+    fn element(start: &BytesStart<'_>) -> Result<(String, Map<String, Value>), String> {
+        let name = String::from_utf8_lossy(start.name().as_ref()).into_owned();
+
+        let mut map = Map::new();
+
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|error| error.to_string())?;
+
+            map.insert(
+                format!("@{}", String::from_utf8_lossy(attribute.key.as_ref())),
+                Value::String(
+                    attribute
+                        .unescape_value()
+                        .map_err(|error| error.to_string())?
+                        .into_owned(),
+                ),
+            );
+        }
+
+        Ok((name, map))
+    }
+
+    /// Collapse text-only elements down to their text
+    fn finish(mut map: Map<String, Value>, text: String) -> Value {
+        match (map.is_empty(), text.is_empty()) {
+            (true, true) => Value::Null,
+            (true, false) => Value::String(text),
+            (false, true) => Value::Object(map),
+            (false, false) => {
+                map.insert("#text".to_owned(), Value::String(text));
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+impl BodyParser for Xml {
+    fn name(&self) -> &'static str {
+        "xml"
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        matches!(media_type, "application/xml" | "text/xml") || media_type.ends_with("+xml")
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        let mut reader = quick_xml::Reader::from_reader(body);
+        reader.trim_text(true);
+
+        // the root is a pseudo-element holding the document element
+        let mut stack = vec![(String::new(), Map::new(), String::new())];
+        let mut buf = Vec::new();
+
+        loop {
+            match reader
+                .read_event_into(&mut buf)
+                .map_err(|error| format!("at byte {}: {error}", reader.buffer_position()))?
+            {
+                Event::Start(start) => {
+                    // the root pseudo-element doesn't count
+                    if stack.len() > MAX_DEPTH {
+                        return Err(format!("elements nested deeper than {MAX_DEPTH} levels"));
+                    }
+
+                    let (name, map) = Self::element(&start)?;
+                    stack.push((name, map, String::new()));
+                }
+                Event::Empty(start) => {
+                    let (name, map) = Self::element(&start)?;
+                    let parent = &mut stack.last_mut().expect("root is never popped").1;
+                    insert_repeated(parent, name, Self::finish(map, String::new()));
+                }
+                Event::Text(text) => {
+                    let text = text.unescape().map_err(|error| error.to_string())?;
+                    stack.last_mut().expect("root is never popped").2 += &text;
+                }
+                Event::CData(data) => {
+                    let data = String::from_utf8_lossy(&data.into_inner()).into_owned();
+                    stack.last_mut().expect("root is never popped").2 += &data;
+                }
+                Event::End(_) => {
+                    if stack.len() < 2 {
+                        return Err("unbalanced closing tag".into());
+                    }
+
+                    let (name, map, text) = stack.pop().expect("checked above");
+                    let parent = &mut stack.last_mut().expect("root is never popped").1;
+                    insert_repeated(parent, name, Self::finish(map, text));
+                }
+                Event::Eof => break,
+                Event::Decl(_) | Event::PI(_) | Event::Comment(_) | Event::DocType(_) => {}
+            }
+
+            buf.clear();
+        }
+
+        match stack.pop() {
+            Some((_, document, _)) if stack.is_empty() && !document.is_empty() => {
+                Ok(Value::Object(document))
+            }
+            _ => Err("unexpected end of document".into()),
+        }
+    }
+}
+
+/// CBOR (RFC 8949) bodies
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Cbor;
+
+impl BodyParser for Cbor {
+    fn name(&self) -> &'static str {
+        "cbor"
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        media_type == "application/cbor" || media_type.ends_with("+cbor")
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        ciborium::de::from_reader(body).map_err(|error| error.to_string())
+    }
+}
+
+/// Protocol Buffers bodies, decoded without a schema into an object
+/// keyed by field number (with repeated fields collected into arrays)
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Protobuf;
+
+impl Protobuf {
+    const TOO_DEEP: &'static str = "messages nested too deeply";
+
+    fn varint(body: &mut &[u8]) -> Result<u64, String> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = body
+                .split_first()
+                .ok_or_else(|| "truncated varint".to_string())?;
+            *body = rest;
+
+            value |= u64::from(byte & 0x7f) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err("varint too long".into())
+    }
+
+    fn take<'a>(body: &mut &'a [u8], len: usize) -> Result<&'a [u8], String> {
+        if body.len() < len {
+            return Err("truncated field".into());
+        }
+
+        let (taken, rest) = body.split_at(len);
+        *body = rest;
+
+        Ok(taken)
+    }
+
+    fn message(mut body: &[u8], depth: usize) -> Result<Map<String, Value>, String> {
+        if depth > MAX_DEPTH {
+            return Err(Self::TOO_DEEP.into());
+        }
+
+        let mut map = Map::new();
+
+        while !body.is_empty() {
+            let key = Self::varint(&mut body)?;
+            let (field, wire_type) = (key >> 3, key & 0x7);
+
+            if field == 0 {
+                return Err("invalid field number 0".into());
+            }
+
+            let value = match wire_type {
+                0 => Value::from(Self::varint(&mut body)?),
+                1 => Value::from(u64::from_le_bytes(
+                    Self::take(&mut body, 8)?.try_into().expect("took 8 bytes"),
+                )),
+                2 => {
+                    let len = usize::try_from(Self::varint(&mut body)?)
+                        .map_err(|error| error.to_string())?;
+
+                    Self::length_delimited(Self::take(&mut body, len)?, depth)?
+                }
+                5 => Value::from(u32::from_le_bytes(
+                    Self::take(&mut body, 4)?.try_into().expect("took 4 bytes"),
+                )),
+                other => return Err(format!("unsupported wire type {other}")),
+            };
+
+            insert_repeated(&mut map, field.to_string(), value);
+        }
+
+        Ok(map)
+    }
+
+    /// Length-delimited fields may be strings, bytes, or nested messages,
+    /// so take the most specific interpretation that fits (refusing to
+    /// interpret any as messages nested deeper than is allowed)
+    fn length_delimited(bytes: &[u8], depth: usize) -> Result<Value, String> {
+        if let Ok(text) = std::str::from_utf8(bytes) {
+            if !text
+                .chars()
+                .any(|char| char.is_control() && !char.is_whitespace())
+            {
+                return Ok(Value::String(text.to_owned()));
+            }
+        }
+
+        match Self::message(bytes, depth + 1) {
+            Ok(message) if !message.is_empty() => Ok(Value::Object(message)),
+            Err(error) if error == Self::TOO_DEEP => Err(error),
+            _ => Ok(Value::Array(
+                bytes.iter().map(|byte| Value::from(*byte)).collect(),
+            )),
+        }
+    }
+}
+
+impl BodyParser for Protobuf {
+    fn name(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn accepts(&self, media_type: &str) -> bool {
+        matches!(
+            media_type,
+            "application/protobuf" | "application/x-protobuf" | "application/vnd.google.protobuf"
+        )
+    }
+
+    fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        Self::message(body, 0).map(Value::Object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A protobuf message nesting (in field 1) the given number of messages
+    /// around one holding a single varint field
+    fn nested_message(depth: usize) -> Vec<u8> {
+        (0..depth).fold(vec![0x08, 0x01], |inner, _| {
+            let mut outer = vec![0x0a];
+            let mut len = inner.len();
+
+            while len >= 0x80 {
+                outer.push((len as u8 & 0x7f) | 0x80);
+                len >>= 7;
+            }
+
+            outer.push(len as u8);
+            outer.extend(inner);
+            outer
+        })
+    }
+
+    #[test]
+    fn protobuf_messages() {
+        let body = [0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i', 0x08, 0x01];
+
+        assert_eq!(
+            Protobuf.parse(&body).unwrap(),
+            serde_json::json!({"1": [150, 1], "2": "hi"})
+        );
+        assert!(Protobuf.parse(&[0x08]).is_err());
+        assert!(Protobuf.parse(&[0x00, 0x01]).is_err());
+    }
+
+    #[test]
+    fn protobuf_nesting_is_limited() {
+        assert!(Protobuf.parse(&nested_message(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Protobuf.parse(&nested_message(MAX_DEPTH + 1)),
+            Err(Protobuf::TOO_DEEP.to_owned())
+        );
+    }
+
+    #[test]
+    fn xml_documents() {
+        let body = br#"<a x="1"><b>one</b><b>two</b><c/>text</a>"#;
+
+        assert_eq!(
+            Xml.parse(body).unwrap(),
+            serde_json::json!({"a": {"@x": "1", "b": ["one", "two"], "c": null, "#text": "text"}})
+        );
+        assert!(Xml.parse(b"<a><b></a>").is_err());
+        assert!(Xml.parse(b"").is_err());
+    }
+
+    #[test]
+    fn xml_nesting_is_limited() {
+        let nested = |depth| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+
+        assert!(Xml.parse(nested(MAX_DEPTH).as_bytes()).is_ok());
+        assert!(Xml.parse(nested(MAX_DEPTH + 1).as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,58 @@
+// Clock-Offset / RTT Ping
+
+// Standard Library Imports
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Third Party Imports
+use axum::{
+    extract::{Json, Query},
+    routing, Router,
+};
+
+// Crate-Level Imports
+use crate::clock;
+
+/// The client's own send time, echoed back so it can work out the round trip
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+struct PingParams {
+    t: Option<String>,
+}
+
+/// NTP-style timestamps: the client's send time (as given), and the server's
+/// receive and transmit times, in nanoseconds since the unix epoch
+#[derive(Clone, Debug, serde::Serialize)]
+struct Pong {
+    pong: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_sent: Option<String>,
+    server_received: u128,
+    server_sent: u128,
+    /// The receive time, for humans
+    received_at: String,
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+#[tracing::instrument]
+pub(crate) fn router() -> Router {
+    Router::new().route("/_ping", routing::get(ping).post(ping))
+}
+
+/// A minimal, timestamped answer for measuring round trip times and clock offsets,
+/// where the client passes its own send time as `?t=` to have it echoed back
+#[tracing::instrument(skip_all)]
+async fn ping(Query(params): Query<PingParams>) -> Json<Pong> {
+    let received = clock::now();
+
+    Json(Pong {
+        pong: true,
+        client_sent: params.t,
+        server_received: unix_nanos(received),
+        received_at: humantime::format_rfc3339_nanos(received).to_string(),
+        server_sent: unix_nanos(clock::now()),
+    })
+}
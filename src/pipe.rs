@@ -0,0 +1,43 @@
+// Windows Named Pipe Listener
+
+// Standard Library Imports
+use std::net::{Ipv4Addr, SocketAddr};
+
+// Third Party Imports
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper::server::conn::Http;
+use tokio::net::windows::named_pipe::ServerOptions;
+
+/// Serve the app over the named pipe (e.g. `\\.\pipe\echo-rs`), for as long as the process runs
+#[tracing::instrument(skip(app))]
+pub(crate) async fn serve(path: String, app: Router) -> anyhow::Result<()> {
+    // pipe clients have no address, but the echo handler wants one all the same
+    let app = app.layer(Extension(ConnectInfo(SocketAddr::from((
+        Ipv4Addr::UNSPECIFIED,
+        0,
+    )))));
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)?;
+
+    tracing::info!("`echo-rs` server listening at: {path}");
+
+    loop {
+        server.connect().await?;
+
+        // a new instance has to be waiting before the next client turns up
+        let client = std::mem::replace(&mut server, ServerOptions::new().create(&path)?);
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            if let Err(error) = Http::new()
+                .serve_connection(client, app)
+                .with_upgrades()
+                .await
+            {
+                tracing::debug!("Named pipe connection failed: {error}");
+            }
+        });
+    }
+}
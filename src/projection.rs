@@ -0,0 +1,47 @@
+// Echo Projection
+
+// Third Party Imports
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+/// Query parameter selecting part of the request body via JSONPath (RFC 9535)
+pub(crate) const JSONPATH_PARAM: &str = "jsonpath";
+
+/// Query parameter selecting which top-level fields of the echo to respond with
+pub(crate) const FIELDS_PARAM: &str = "fields";
+
+/// Every node in the body matched by the given JSONPath expression
+pub(crate) fn jsonpath(body: &Value, path: &str) -> Result<Value, String> {
+    let path =
+        JsonPath::parse(path).map_err(|error| format!("invalid JSONPath {path:?}: {error}"))?;
+
+    Ok(Value::Array(
+        path.query(body).all().into_iter().cloned().collect(),
+    ))
+}
+
+/// Only the named (comma-separated) top-level fields of the serialized echo
+pub(crate) fn fields(echo: Value, fields: &str) -> Result<Value, String> {
+    let Value::Object(mut echo) = echo else {
+        return Err("echo is not a JSON object".into());
+    };
+
+    let wanted = fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect::<Vec<&str>>();
+
+    if let Some(unknown) = wanted.iter().find(|field| !echo.contains_key(**field)) {
+        let known = echo.keys().map(String::as_str).collect::<Vec<&str>>();
+
+        return Err(format!(
+            "unknown field {unknown:?} (expected one of: {})",
+            known.join(", ")
+        ));
+    }
+
+    echo.retain(|field, _| wanted.contains(&field.as_str()));
+
+    Ok(Value::Object(echo))
+}
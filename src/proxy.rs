@@ -0,0 +1,501 @@
+// Reverse-Proxy Capture Mode
+
+// Standard Library Imports
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Body, Full},
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::{
+    body::buffer,
+    cache::{Lookup, ResponseCache},
+    errors::Failure,
+    transform::{self, Transform},
+};
+
+/// Headers meaningful only for a single connection, which proxies must not relay
+const HOP_BY_HOP: &[HeaderName] = &[
+    header::CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Which forwarding headers are added to relayed requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ForwardedHeaders {
+    /// `X-Forwarded-For`, `X-Forwarded-Proto`, and `X-Forwarded-Host`
+    XForwarded,
+    /// RFC 7239 `Forwarded`
+    Forwarded,
+    /// Both of the above
+    #[default]
+    Both,
+    /// Neither
+    None,
+}
+
+/// How requests are spread across several upstreams
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Balance {
+    /// Each upstream in turn
+    #[default]
+    RoundRobin,
+    /// Whichever upstream has the fewest requests in flight
+    LeastConnections,
+    /// Each upstream in turn, in proportion to its weight
+    Weighted,
+}
+
+/// An upstream as given on the command line: `<url>[=<weight>]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct UpstreamSpec {
+    pub(crate) url: String,
+    pub(crate) weight: usize,
+}
+
+impl FromStr for UpstreamSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (url, weight) = value
+            .rsplit_once('=')
+            .and_then(|(url, weight)| Some((url, weight.trim().parse::<usize>().ok()?)))
+            .unwrap_or((value, 1));
+
+        let url = url.trim().trim_end_matches('/').to_owned();
+
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!(
+                "proxy upstream must be an http(s) URL, got {url:?}"
+            ));
+        }
+
+        if weight == 0 {
+            return Err(format!(
+                "proxy upstream {url:?} must have a non-zero weight"
+            ));
+        }
+
+        Ok(Self { url, weight })
+    }
+}
+
+/// An upstream server, whether it's passing its health checks,
+/// and how many requests it currently has in flight
+#[derive(Debug)]
+pub(crate) struct Upstream {
+    pub(crate) url: String,
+    weight: usize,
+    healthy: AtomicBool,
+    active: AtomicUsize,
+}
+
+impl Upstream {
+    pub(crate) fn new(spec: UpstreamSpec) -> Self {
+        // upstreams are presumed healthy until checked
+        Self {
+            url: spec.url,
+            weight: spec.weight,
+            healthy: AtomicBool::new(true),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Count a relayed request against the upstream
+    fn record(&self, status: &str, elapsed: Duration) {
+        let labels = [
+            ("upstream", self.url.clone()),
+            ("status", status.to_owned()),
+        ];
+
+        metrics::increment_counter!("proxy_upstream_requests_total", &labels);
+        metrics::histogram!(
+            "proxy_upstream_duration_seconds",
+            elapsed.as_secs_f64(),
+            &labels
+        );
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        if self.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+            if healthy {
+                tracing::info!("Upstream {} is healthy again", self.url);
+            } else {
+                tracing::warn!("Upstream {} is failing its health checks", self.url);
+            }
+        }
+
+        metrics::gauge!(
+            "proxy_upstream_up",
+            if healthy { 1.0 } else { 0.0 },
+            "upstream" => self.url.clone()
+        );
+    }
+}
+
+/// How upstreams are actively health-checked
+#[derive(Clone, Debug)]
+pub(crate) struct HealthCheck {
+    /// Path requested from the upstream, which must answer with a non-error status
+    pub(crate) path: String,
+    /// How often the upstream is checked
+    pub(crate) interval: Duration,
+    /// How long a check may take before it's considered failed
+    pub(crate) timeout: Duration,
+}
+
+/// A request in flight to an upstream, counted against it until dropped
+struct InFlight<'a>(&'a Upstream);
+
+impl<'a> InFlight<'a> {
+    fn start(upstream: &'a Upstream) -> Self {
+        let active = upstream.active.fetch_add(1, Ordering::Relaxed) + 1;
+
+        metrics::gauge!(
+            "proxy_upstream_active_requests",
+            active as f64,
+            "upstream" => upstream.url.clone()
+        );
+
+        Self(upstream)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let active = self.0.active.fetch_sub(1, Ordering::Relaxed) - 1;
+
+        metrics::gauge!(
+            "proxy_upstream_active_requests",
+            active as f64,
+            "upstream" => self.0.url.clone()
+        );
+    }
+}
+
+/// Relays requests to upstream servers, capturing both sides of the exchange
+#[derive(Debug)]
+pub(crate) struct Proxy {
+    client: reqwest::Client,
+    upstreams: Vec<Arc<Upstream>>,
+    balance: Balance,
+    turn: AtomicUsize,
+    forwarded: ForwardedHeaders,
+    tls: bool,
+    fallback_to_echo: bool,
+    transforms: Vec<Transform>,
+    cache: Option<ResponseCache>,
+}
+
+impl Proxy {
+    pub(crate) fn new(
+        upstreams: Vec<Arc<Upstream>>,
+        balance: Balance,
+        forwarded: ForwardedHeaders,
+        tls: bool,
+        fallback_to_echo: bool,
+        transforms: Vec<Transform>,
+        cache: Option<ResponseCache>,
+    ) -> anyhow::Result<Self> {
+        if upstreams.is_empty() {
+            anyhow::bail!("proxy mode requires at least one upstream");
+        }
+
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()?,
+            upstreams,
+            balance,
+            turn: AtomicUsize::new(0),
+            forwarded,
+            tls,
+            fallback_to_echo,
+            transforms,
+            cache,
+        })
+    }
+
+    /// The upstream the next request should be relayed to, preferring healthy ones
+    fn pick(&self) -> Option<&Upstream> {
+        let healthy = self
+            .upstreams
+            .iter()
+            .filter(|upstream| upstream.is_healthy())
+            .collect::<Vec<&Arc<Upstream>>>();
+
+        let candidates = match healthy.is_empty() {
+            true if self.fallback_to_echo => return None,
+            true => self.upstreams.iter().collect(),
+            false => healthy,
+        };
+
+        let turn = self.turn.fetch_add(1, Ordering::Relaxed);
+
+        let picked = match self.balance {
+            Balance::RoundRobin => candidates[turn % candidates.len()],
+            Balance::LeastConnections => candidates
+                .iter()
+                .enumerate()
+                // break ties in turn, so idle upstreams share the load
+                .min_by_key(|(index, upstream)| {
+                    (
+                        upstream.active.load(Ordering::Relaxed),
+                        (index + candidates.len() - turn % candidates.len()) % candidates.len(),
+                    )
+                })
+                .map(|(_, upstream)| *upstream)?,
+            Balance::Weighted => {
+                let total = candidates
+                    .iter()
+                    .map(|upstream| upstream.weight)
+                    .sum::<usize>();
+                let mut slot = turn % total;
+
+                candidates
+                    .iter()
+                    .find(|upstream| match slot.checked_sub(upstream.weight) {
+                        Some(rest) => {
+                            slot = rest;
+                            false
+                        }
+                        None => true,
+                    })
+                    .copied()?
+            }
+        };
+
+        Some(picked)
+    }
+
+    /// Strip hop-by-hop headers, and add the configured forwarding headers
+    fn forward_headers(&self, headers: &HeaderMap, client: SocketAddr) -> HeaderMap {
+        let mut forwarded = strip_hop_by_hop(headers);
+
+        let client = client.ip().to_canonical();
+        let proto = if self.tls { "https" } else { "http" };
+        let host = forwarded.remove(header::HOST);
+
+        if matches!(
+            self.forwarded,
+            ForwardedHeaders::XForwarded | ForwardedHeaders::Both
+        ) {
+            append(&mut forwarded, "x-forwarded-for", &client.to_string());
+
+            if !forwarded.contains_key("x-forwarded-proto") {
+                forwarded.insert("x-forwarded-proto", HeaderValue::from_static(proto));
+            }
+
+            if let Some(host) = host
+                .as_ref()
+                .filter(|_| !forwarded.contains_key("x-forwarded-host"))
+            {
+                forwarded.insert("x-forwarded-host", host.clone());
+            }
+        }
+
+        if matches!(
+            self.forwarded,
+            ForwardedHeaders::Forwarded | ForwardedHeaders::Both
+        ) {
+            // IPv6 addresses must be bracketed, and so quoted
+            let node = if client.is_ipv6() {
+                format!("\"[{client}]\"")
+            } else {
+                client.to_string()
+            };
+
+            let mut element = format!("for={node};proto={proto}");
+
+            if let Some(host) = host.as_ref().and_then(|host| host.to_str().ok()) {
+                element.push_str(&format!(";host=\"{host}\""));
+            }
+
+            append(&mut forwarded, header::FORWARDED.as_str(), &element);
+        }
+
+        forwarded
+    }
+}
+
+/// Copy the headers, minus any hop-by-hop ones (including those named by `Connection`)
+pub(crate) fn strip_hop_by_hop(headers: &HeaderMap) -> HeaderMap {
+    let named = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::try_from(name.trim()).ok())
+        .collect::<Vec<HeaderName>>();
+
+    let mut stripped = headers.clone();
+
+    for name in HOP_BY_HOP.iter().chain(named.iter()) {
+        stripped.remove(name);
+    }
+
+    stripped
+}
+
+/// Append a value to a comma-separated list header, creating it if absent
+fn append(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    let combined = match headers
+        .get(name)
+        .and_then(|existing| existing.to_str().ok())
+    {
+        Some(existing) if !existing.trim().is_empty() => format!("{existing}, {value}"),
+        _ => value.to_owned(),
+    };
+
+    if let Ok(combined) = HeaderValue::try_from(combined) {
+        headers.insert(name, combined);
+    }
+}
+
+/// Periodically check the upstream's health, forever
+#[tracing::instrument(skip_all)]
+pub(crate) async fn health_check(upstream: Arc<Upstream>, check: HealthCheck) {
+    let client = match reqwest::Client::builder().timeout(check.timeout).build() {
+        Ok(client) => client,
+        Err(error) => {
+            tracing::error!("Unable to health-check upstream {}: {error}", upstream.url);
+            return;
+        }
+    };
+
+    let url = format!("{}/{}", upstream.url, check.path.trim_start_matches('/'));
+    let mut interval = tokio::time::interval(check.interval);
+
+    loop {
+        interval.tick().await;
+
+        let healthy = match client.get(&url).send().await {
+            Ok(response) => {
+                !(response.status().is_client_error() || response.status().is_server_error())
+            }
+            Err(error) => {
+                tracing::debug!("Health check against {url} failed: {error}");
+                false
+            }
+        };
+
+        upstream.set_healthy(healthy);
+    }
+}
+
+/// Relay the request upstream in place of echoing it
+#[tracing::instrument(skip_all)]
+pub(crate) async fn relay(
+    State(proxy): State<Arc<Proxy>>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let (parts, body) = req.into_parts();
+
+    let lookup = match proxy.cache.as_ref().map(|cache| cache.lookup(&parts)) {
+        Some(Lookup::Hit(response)) => {
+            tracing::info!("Answered {} {} from the cache", parts.method, parts.uri);
+            return response;
+        }
+        lookup => lookup,
+    };
+
+    let Some(target) = proxy.pick() else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+
+    let body = match buffer(&parts, body).await {
+        Ok(body) => body,
+        Err(rejection) => return rejection,
+    };
+
+    let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+
+    let mut headers = proxy.forward_headers(&parts.headers, client);
+    let relayed = transform::apply(&proxy.transforms, parts.uri.path(), &mut headers);
+    let url = match parts.uri.query() {
+        Some(query) => format!("{}{relayed}?{query}", target.url),
+        None => format!("{}{relayed}", target.url),
+    };
+
+    let in_flight = InFlight::start(target);
+    let start = Instant::now();
+
+    let upstream = proxy
+        .client
+        .request(parts.method.clone(), &url)
+        .headers(headers)
+        .body(body.clone())
+        .send()
+        .await;
+
+    let upstream = match upstream {
+        Ok(upstream) => upstream,
+        Err(error) => {
+            target.record("error", start.elapsed());
+            tracing::warn!("Failed to relay {} {url}: {error}", parts.method);
+            return Failure::new(StatusCode::BAD_GATEWAY, error.to_string()).into_response();
+        }
+    };
+
+    let (status, headers) = (upstream.status(), strip_hop_by_hop(upstream.headers()));
+
+    let response_body = match upstream.bytes().await {
+        Ok(body) => body,
+        Err(error) => {
+            target.record("error", start.elapsed());
+            return Failure::new(StatusCode::BAD_GATEWAY, error.to_string()).into_response();
+        }
+    };
+
+    target.record(status.as_str(), start.elapsed());
+    drop(in_flight);
+
+    tracing::info!(
+        "Proxied {} {path} ({} bytes) -> {url}: {status} ({} bytes)",
+        parts.method,
+        body.len(),
+        response_body.len()
+    );
+
+    if let Some(cache) = proxy.cache.as_ref() {
+        match &lookup {
+            Some(Lookup::Miss) => cache.store(&parts, status, &headers, &response_body),
+            _ => cache.invalidate(&parts, status),
+        }
+    }
+
+    let mut response = Response::new(body::boxed(Full::new(response_body)));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+
+    if let Some(lookup) = lookup {
+        lookup.mark(&mut response);
+    }
+
+    response
+}
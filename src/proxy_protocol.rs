@@ -0,0 +1,239 @@
+// PROXY Protocol (v1 and v2) Support
+
+// Standard Library Imports
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+// Third Party Imports
+use axum::body::Bytes;
+use hyper::server::conn::AddrStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+/// How a v1 (i.e. human-readable) header starts
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The longest a v1 header may be, its terminating CRLF included
+const V1_MAX_LEN: usize = 107;
+
+/// How a v2 (i.e. binary) header starts
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The client address relayed by the PROXY protocol header of the connection a request arrived on
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ProxiedClient(pub(crate) SocketAddr);
+
+/// What the start of a connection holds
+#[derive(Debug, PartialEq, Eq)]
+enum Preamble {
+    /// No PROXY protocol header at all
+    None,
+    /// (What may yet be) the start of a header
+    Incomplete,
+    /// A header of the given length, relaying the given client address (if any)
+    Header(usize, Option<SocketAddr>),
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Parse the PROXY protocol header the bytes start with, if they do
+fn parse(buf: &[u8]) -> io::Result<Preamble> {
+    if buf.len() < V1_PREFIX.len() && V1_PREFIX.starts_with(buf)
+        || buf.len() < V2_SIGNATURE.len() && V2_SIGNATURE.starts_with(buf)
+    {
+        return Ok(Preamble::Incomplete);
+    }
+
+    if buf.starts_with(V1_PREFIX) {
+        return parse_v1(buf);
+    }
+
+    if buf.starts_with(V2_SIGNATURE) {
+        return parse_v2(buf);
+    }
+
+    Ok(Preamble::None)
+}
+
+/// e.g. `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`
+fn parse_v1(buf: &[u8]) -> io::Result<Preamble> {
+    let window = &buf[..buf.len().min(V1_MAX_LEN)];
+
+    let Some(end) = window.windows(2).position(|pair| pair == b"\r\n") else {
+        return match buf.len() < V1_MAX_LEN {
+            true => Ok(Preamble::Incomplete),
+            false => Err(invalid("unterminated PROXY protocol v1 header")),
+        };
+    };
+
+    let line = std::str::from_utf8(&buf[..end])
+        .map_err(|_| invalid("non-ASCII PROXY protocol v1 header"))?;
+
+    let fields = line.split(' ').collect::<Vec<&str>>();
+
+    let client = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => None,
+        ["PROXY", "TCP4" | "TCP6", source, _, port, _] => Some(SocketAddr::new(
+            source
+                .parse::<IpAddr>()
+                .map_err(|_| invalid(format!("invalid PROXY source address {source:?}")))?,
+            port.parse::<u16>()
+                .map_err(|_| invalid(format!("invalid PROXY source port {port:?}")))?,
+        )),
+        _ => {
+            return Err(invalid(format!(
+                "malformed PROXY protocol v1 header {line:?}"
+            )))
+        }
+    };
+
+    Ok(Preamble::Header(end + 2, client))
+}
+
+/// A 16-byte preamble (signature, version and command, address
+/// family, and address length), followed by the addresses
+fn parse_v2(buf: &[u8]) -> io::Result<Preamble> {
+    let Some(&[version_command, family, high, low]) = buf.get(12..16) else {
+        return Ok(Preamble::Incomplete);
+    };
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    let len = 16 + usize::from(u16::from_be_bytes([high, low]));
+
+    let Some(addresses) = buf.get(16..len) else {
+        return Ok(Preamble::Incomplete);
+    };
+
+    // `LOCAL` connections (e.g. the proxy's own health checks) relay no client
+    if version_command & 0x0F == 0 {
+        return Ok(Preamble::Header(len, None));
+    }
+
+    let client = match (family >> 4, addresses) {
+        (1, [a, b, c, d, _, _, _, _, high, low, ..]) => Some(SocketAddr::new(
+            Ipv4Addr::new(*a, *b, *c, *d).into(),
+            u16::from_be_bytes([*high, *low]),
+        )),
+        (2, addresses) if addresses.len() >= 36 => {
+            let mut source = [0u8; 16];
+            source.copy_from_slice(&addresses[..16]);
+
+            Some(SocketAddr::new(
+                Ipv6Addr::from(source).into(),
+                u16::from_be_bytes([addresses[32], addresses[33]]),
+            ))
+        }
+        // `AF_UNSPEC` and `AF_UNIX` addresses say nothing useful about the client
+        _ => None,
+    };
+
+    Ok(Preamble::Header(len, client))
+}
+
+/// A connection, with anything read past its PROXY protocol header still to be read
+#[derive(Debug)]
+pub(crate) struct ProxiedStream {
+    inner: AddrStream,
+    buffered: Bytes,
+}
+
+impl ProxiedStream {
+    /// A connection that isn't expected to start with a PROXY protocol header
+    pub(crate) fn direct(inner: AddrStream) -> Self {
+        Self {
+            inner,
+            buffered: Bytes::new(),
+        }
+    }
+
+    /// Read the PROXY protocol header the connection starts with (if it does),
+    /// returning the client address it relays (if any) along with the connection
+    pub(crate) async fn accept(mut inner: AddrStream) -> io::Result<(Self, Option<SocketAddr>)> {
+        let mut buf = Vec::with_capacity(V1_MAX_LEN);
+
+        loop {
+            match parse(&buf)? {
+                Preamble::Incomplete => {
+                    if inner.read_buf(&mut buf).await? == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                }
+                Preamble::None => {
+                    return Ok((
+                        Self {
+                            inner,
+                            buffered: buf.into(),
+                        },
+                        None,
+                    ))
+                }
+                Preamble::Header(len, client) => {
+                    buf.drain(..len);
+
+                    return Ok((
+                        Self {
+                            inner,
+                            buffered: buf.into(),
+                        },
+                        client,
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl AsyncRead for ProxiedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.buffered.is_empty() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+
+        let len = self.buffered.len().min(buf.remaining());
+        buf.put_slice(&self.buffered.split_to(len));
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ProxiedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
@@ -0,0 +1,240 @@
+// Token-Bucket Rate Limiting
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::{errors::Failure, schedule::Quota, throttle::RetryAfterFormat};
+
+/// How many per-client buckets are kept before idle (i.e. full) ones are forgotten
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// How many per-client buckets are kept at most, beyond which new clients are limited
+/// outright (until idle buckets are next forgotten)
+const MAX_BUCKETS: usize = 100_000;
+
+/// How often (at most) idle buckets are looked for and forgotten
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The longest a limited client is told to wait (e.g. when the rate is zero)
+const MAX_WAIT: Duration = Duration::from_secs(86_400);
+
+/// A bucket of request tokens, refilled continuously at a steady rate
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The rate at which (and the capacity to which) buckets are refilled
+#[derive(Clone, Copy, Debug)]
+struct Refill {
+    per_second: f64,
+    capacity: f64,
+}
+
+impl Refill {
+    fn new(rate: Quota, burst: Option<u64>) -> Self {
+        Self {
+            per_second: rate.limit as f64 / rate.period.as_secs_f64(),
+            capacity: burst.unwrap_or(rate.limit).max(1) as f64,
+        }
+    }
+
+    fn full(&self) -> Bucket {
+        Bucket {
+            tokens: self.capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    fn top_up(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.capacity);
+        bucket.updated = now;
+    }
+
+    /// Take a token from the bucket, or determine how long until one is available
+    fn take(&self, bucket: &mut Bucket) -> Result<(), Duration> {
+        self.top_up(bucket);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(
+                Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.per_second)
+                    .map_or(MAX_WAIT, |wait| wait.min(MAX_WAIT)),
+            )
+        }
+    }
+}
+
+/// Per-client buckets, and when idle ones were last forgotten
+#[derive(Debug)]
+struct Clients {
+    buckets: HashMap<IpAddr, Bucket>,
+    swept: Instant,
+}
+
+impl Default for Clients {
+    fn default() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            swept: Instant::now(),
+        }
+    }
+}
+
+/// Per-client (by IP address) and/or server-wide request rate limits
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    client: Option<(Refill, Mutex<Clients>)>,
+    global: Option<(Refill, Mutex<Bucket>)>,
+    format: RetryAfterFormat,
+}
+
+impl RateLimiter {
+    /// Create a limiter if either a per-client or a global rate was actually configured
+    pub(crate) fn new(
+        client: Option<Quota>,
+        global: Option<Quota>,
+        burst: Option<u64>,
+        format: RetryAfterFormat,
+    ) -> Option<Self> {
+        (client.is_some() || global.is_some()).then(|| Self {
+            client: client.map(|rate| (Refill::new(rate, burst), Mutex::default())),
+            global: global.map(|rate| {
+                let refill = Refill::new(rate, burst);
+                (refill, Mutex::new(refill.full()))
+            }),
+            format,
+        })
+    }
+
+    /// Take a token on behalf of the client (and the server as a whole), returning
+    /// which limit was hit and how long to wait before retrying if it couldn't be
+    fn check(&self, client: Option<IpAddr>) -> Result<(), (&'static str, Duration)> {
+        if let (Some((refill, clients)), Some(client)) = (self.client.as_ref(), client) {
+            let mut clients = clients.lock().unwrap();
+
+            if clients.buckets.len() > MAX_IDLE_BUCKETS && clients.swept.elapsed() >= SWEEP_INTERVAL
+            {
+                clients.buckets.retain(|_, bucket| {
+                    refill.top_up(bucket);
+                    bucket.tokens < refill.capacity
+                });
+                clients.swept = Instant::now();
+            }
+
+            let wait = SWEEP_INTERVAL.saturating_sub(clients.swept.elapsed());
+
+            let bucket = match clients.buckets.len() < MAX_BUCKETS {
+                true => clients
+                    .buckets
+                    .entry(client)
+                    .or_insert_with(|| refill.full()),
+                false => clients.buckets.get_mut(&client).ok_or(("client", wait))?,
+            };
+
+            refill.take(bucket).map_err(|wait| ("client", wait))?;
+        }
+
+        if let Some((refill, bucket)) = self.global.as_ref() {
+            refill
+                .take(&mut bucket.lock().unwrap())
+                .map_err(|wait| ("global", wait))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(client)| client.ip());
+
+    match limiter.check(client) {
+        Ok(()) => next.run(req).await,
+        Err((scope, wait)) => {
+            tracing::debug!(
+                "Rate limited {} {} from {} ({scope} limit), retry in {}",
+                req.method(),
+                req.uri().path(),
+                client.map_or_else(|| "unknown client".to_owned(), |ip| ip.to_string()),
+                humantime::format_duration(wait)
+            );
+
+            metrics::increment_counter!("rate_limited_total", "scope" => scope);
+
+            (
+                [(header::RETRY_AFTER, limiter.format.header_value(wait))],
+                Failure::new(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        let rate = "1/1h".parse::<Quota>().unwrap();
+        RateLimiter::new(Some(rate), None, None, RetryAfterFormat::default()).unwrap()
+    }
+
+    #[test]
+    fn limits_each_client_separately() {
+        let limiter = limiter();
+        let [first, second] = [[10, 0, 0, 1], [10, 0, 0, 2]].map(IpAddr::from);
+
+        assert!(limiter.check(Some(first)).is_ok());
+        assert!(limiter.check(Some(second)).is_ok());
+        assert!(matches!(limiter.check(Some(first)), Err(("client", _))));
+    }
+
+    #[test]
+    fn refuses_new_clients_once_full() {
+        let limiter = limiter();
+
+        if let Some((refill, clients)) = limiter.client.as_ref() {
+            let mut clients = clients.lock().unwrap();
+
+            for n in 0..MAX_BUCKETS as u32 {
+                clients
+                    .buckets
+                    .insert(IpAddr::from(n.to_be_bytes()), refill.full());
+            }
+        }
+
+        // nothing's idle enough to be forgotten, nor is a sweep due yet
+        let refused = limiter.check(Some(IpAddr::from([255, 255, 255, 255])));
+
+        assert!(matches!(refused, Err(("client", wait)) if wait > Duration::ZERO));
+        assert!(limiter.check(Some(IpAddr::from([0, 0, 0, 1]))).is_ok());
+    }
+}
@@ -0,0 +1,393 @@
+// Request Recording & Replay
+
+// Standard Library Imports
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+// Third Party Imports
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::mpsc,
+};
+
+// Crate-Level Imports
+use crate::{clock, history::OriginalRequest, proxy::strip_hop_by_hop};
+
+/// A request, as it was received, in the form it's recorded in
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct RecordedOriginal {
+    method: String,
+    uri: String,
+    /// Every header, in the order it was received
+    headers: Vec<(String, String)>,
+    /// The body, base64-encoded
+    body: String,
+}
+
+impl From<&OriginalRequest> for RecordedOriginal {
+    fn from(original: &OriginalRequest) -> Self {
+        Self {
+            method: original.method.to_string(),
+            uri: original.uri.to_string(),
+            headers: original
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        name.to_string(),
+                        String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                    )
+                })
+                .collect(),
+            body: STANDARD.encode(&original.body),
+        }
+    }
+}
+
+/// One line of a recording: the echo, as it was echoed, alongside the
+/// request as it was received (so it can be replayed faithfully)
+#[derive(Debug, serde::Serialize)]
+struct Recorded<'echo> {
+    /// When the request was received, as an RFC 3339 timestamp
+    received_at: String,
+    /// Status code of the echo's response
+    status: u16,
+    #[serde(flatten)]
+    echo: &'echo Value,
+    original: RecordedOriginal,
+}
+
+/// Appends every echoed request to an NDJSON file
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    lines: mpsc::UnboundedSender<String>,
+}
+
+impl Recorder {
+    /// Record to the given file (appending to it, if it exists)
+    pub(crate) async fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+        let (lines, mut pending) = mpsc::unbounded_channel::<String>();
+
+        tokio::spawn(async move {
+            let mut file = BufWriter::new(file);
+
+            while let Some(line) = pending.recv().await {
+                let mut written = file.write_all(line.as_bytes()).await;
+
+                // write out whatever else is already waiting before flushing
+                while let (Ok(()), Ok(line)) = (&written, pending.try_recv()) {
+                    written = file.write_all(line.as_bytes()).await;
+                }
+
+                if let Err(error) = written.and(file.flush().await) {
+                    tracing::error!("Failed to record to {}: {error}", path.display());
+                }
+            }
+        });
+
+        Ok(Self { lines })
+    }
+
+    pub(crate) fn record(&self, status: StatusCode, echo: &Value, original: &OriginalRequest) {
+        let recorded = Recorded {
+            received_at: humantime::format_rfc3339_millis(clock::now()).to_string(),
+            status: status.as_u16(),
+            echo,
+            original: original.into(),
+        };
+
+        match serde_json::to_string(&recorded) {
+            Ok(line) => {
+                let _ = self.lines.send(line + "\n");
+            }
+            Err(error) => tracing::warn!("Failed to record request: {error}"),
+        }
+    }
+}
+
+/// Just what replaying a line of a recording calls for: the request as it
+/// was received, or else (e.g. for echoes copied out of the logs) the echo
+#[derive(Debug, serde::Deserialize)]
+struct Replayable {
+    received_at: Option<String>,
+    original: Option<RecordedOriginal>,
+    method: Option<String>,
+    path: Option<String>,
+    params: Option<Value>,
+    headers: Option<Value>,
+    body: Option<Value>,
+}
+
+/// A request to re-send
+#[derive(Debug)]
+struct Replay {
+    received_at: Option<SystemTime>,
+    method: Method,
+    path_and_query: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+/// The values of an echoed map of headers or parameters (in either schema)
+fn echoed_pairs(map: Option<Value>) -> Vec<(String, String)> {
+    let Some(Value::Object(map)) = map else {
+        return Vec::new();
+    };
+
+    map.into_iter()
+        .flat_map(|(name, values)| {
+            let values = match values {
+                Value::Array(values) => values,
+                value => vec![value],
+            };
+
+            values.into_iter().map(move |value| {
+                let value = match value {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                };
+
+                (name.clone(), value)
+            })
+        })
+        .collect()
+}
+
+impl TryFrom<Replayable> for Replay {
+    type Error = anyhow::Error;
+
+    fn try_from(line: Replayable) -> anyhow::Result<Self> {
+        let received_at = line
+            .received_at
+            .as_deref()
+            .map(humantime::parse_rfc3339_weak)
+            .transpose()?;
+
+        let (method, path_and_query, headers, body) = match line.original {
+            Some(original) => {
+                let uri = original.uri.parse::<Uri>()?;
+
+                (
+                    original.method,
+                    uri.path_and_query()
+                        .map_or_else(|| "/".to_owned(), ToString::to_string),
+                    original.headers,
+                    STANDARD.decode(original.body)?,
+                )
+            }
+            None => {
+                let method = line
+                    .method
+                    .ok_or_else(|| anyhow::anyhow!("neither `original` nor `method` recorded"))?;
+                let path = line.path.unwrap_or_else(|| "/".to_owned());
+                let query = serde_urlencoded::to_string(echoed_pairs(line.params))?;
+
+                let body = match line.body {
+                    None | Some(Value::Null) => Vec::new(),
+                    Some(Value::String(text)) => text.into_bytes(),
+                    Some(json) => serde_json::to_vec(&json)?,
+                };
+
+                (
+                    method,
+                    if query.is_empty() {
+                        path
+                    } else {
+                        format!("{path}?{query}")
+                    },
+                    echoed_pairs(line.headers),
+                    body,
+                )
+            }
+        };
+
+        let mut map = HeaderMap::new();
+
+        for (name, value) in headers {
+            map.append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(&value)?,
+            );
+        }
+
+        let mut headers = strip_hop_by_hop(&map);
+        // the target's own host (and the copy's length) apply instead
+        headers.remove(header::HOST);
+        headers.remove(header::CONTENT_LENGTH);
+
+        Ok(Self {
+            received_at,
+            method: method.parse()?,
+            path_and_query,
+            headers,
+            body,
+        })
+    }
+}
+
+/// How recorded requests are re-sent
+#[derive(Clone, Debug)]
+pub(crate) struct ReplaySettings {
+    /// Base URL requests are re-sent to, e.g. `https://api.example.com`
+    pub(crate) target: String,
+    /// The most requests to send per second
+    pub(crate) rate: Option<f64>,
+    /// Whether to keep the time between requests as it was when they were recorded
+    pub(crate) realtime: bool,
+    /// Whether to skip verifying the target's certificate
+    pub(crate) insecure: bool,
+}
+
+/// What became of a replayed request
+#[derive(Debug, serde::Serialize)]
+struct ReplayOutcome {
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Re-send every request recorded to the given file, in order, reporting
+/// what became of each (as NDJSON) on stdout
+pub(crate) async fn replay(file: PathBuf, settings: ReplaySettings) -> anyhow::Result<()> {
+    let target = settings.target.trim_end_matches('/');
+
+    if !(target.starts_with("http://") || target.starts_with("https://")) {
+        anyhow::bail!("replay target must be an http(s) URL, got {target:?}");
+    }
+
+    if settings
+        .rate
+        .is_some_and(|rate| !(rate > 0.0 && rate.is_finite()))
+    {
+        anyhow::bail!("replay rate must be a positive number of requests per second");
+    }
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .danger_accept_invalid_certs(settings.insecure)
+        .build()?;
+
+    let mut lines = BufReader::new(
+        tokio::fs::File::open(&file)
+            .await
+            .map_err(|error| anyhow::anyhow!("{}: {error}", file.display()))?,
+    )
+    .lines();
+
+    let started = Instant::now();
+    let spacing = settings
+        .rate
+        .map(|rate| Duration::from_secs_f64(1.0 / rate));
+    let mut first_received = None;
+    let (mut number, mut sent, mut failed) = (0usize, 0u32, 0usize);
+
+    while let Some(line) = lines.next_line().await? {
+        number += 1;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let replay = serde_json::from_str::<Replayable>(&line)
+            .map_err(anyhow::Error::from)
+            .and_then(Replay::try_from);
+
+        let replay = match replay {
+            Ok(replay) => replay,
+            Err(error) => {
+                failed += 1;
+                report(&ReplayOutcome {
+                    line: number,
+                    method: None,
+                    url: None,
+                    status: None,
+                    elapsed_ms: None,
+                    error: Some(format!("unreplayable: {error}")),
+                });
+                continue;
+            }
+        };
+
+        // whichever of the rate and the original timing calls for the longer wait
+        let mut due = started;
+
+        if let Some(spacing) = spacing {
+            due = due.max(started + spacing * sent);
+        }
+
+        if let (true, Some(received_at)) = (settings.realtime, replay.received_at) {
+            let first = *first_received.get_or_insert(received_at);
+            due = due.max(started + received_at.duration_since(first).unwrap_or_default());
+        }
+
+        tokio::time::sleep_until(due.into()).await;
+        sent += 1;
+
+        let url = format!("{target}{}", replay.path_and_query);
+        let sending = Instant::now();
+
+        let outcome = client
+            .request(replay.method.clone(), &url)
+            .headers(replay.headers)
+            .body(replay.body)
+            .send()
+            .await;
+
+        let (status, error) = match outcome {
+            // read in full, so the reported time covers the whole exchange
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                response.bytes().await.err().map(|error| error.to_string()),
+            ),
+            Err(error) => (None, Some(error.to_string())),
+        };
+
+        failed += usize::from(error.is_some());
+
+        report(&ReplayOutcome {
+            line: number,
+            method: Some(replay.method.to_string()),
+            url: Some(url),
+            status,
+            elapsed_ms: Some(sending.elapsed().as_millis()),
+            error,
+        });
+    }
+
+    tracing::info!(
+        "Replayed {sent} request(s) from {} in {}",
+        file.display(),
+        humantime::format_duration(Duration::from_millis(started.elapsed().as_millis() as u64)),
+    );
+
+    match failed {
+        0 => Ok(()),
+        failed => anyhow::bail!("{failed} recorded request(s) failed to replay"),
+    }
+}
+
+fn report(outcome: &ReplayOutcome) {
+    if let Ok(line) = serde_json::to_string(outcome) {
+        println!("{line}");
+    }
+}
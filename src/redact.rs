@@ -0,0 +1,80 @@
+// Echo Redaction
+
+// Standard Library Imports
+use std::str::FromStr;
+
+// Third Party Imports
+use serde_json::Value;
+
+/// What redacted values are replaced with
+const MASK: &str = "[REDACTED]";
+
+/// Fields holding (part of) the request verbatim, which would otherwise
+/// leak any redacted headers, so are masked whenever anything is redacted
+const VERBATIM: &[&str] = &["raw_head"];
+
+/// A dot-separated path to a value in the echo payload (e.g. `headers.authorization`),
+/// where `*` matches every key or element at that level
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct RedactPath(Vec<String>);
+
+impl FromStr for RedactPath {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let segments = value
+            .trim()
+            .split('.')
+            .map(|segment| segment.trim().to_owned())
+            .collect::<Vec<String>>();
+
+        if segments.iter().any(String::is_empty) {
+            Err(format!("{value:?}: empty path segment"))
+        } else {
+            Ok(Self(segments))
+        }
+    }
+}
+
+/// Mask the value(s) at each of the given paths, matching object keys case-insensitively
+pub(crate) fn apply(paths: &[RedactPath], echo: &mut Value) {
+    for RedactPath(segments) in paths {
+        mask(echo, segments);
+    }
+
+    if paths.is_empty() {
+        return;
+    }
+
+    for field in VERBATIM {
+        if let Some(value) = echo.get_mut(field) {
+            mask(value, &[]);
+        }
+    }
+}
+
+fn mask(value: &mut Value, segments: &[String]) {
+    let Some((segment, rest)) = segments.split_first() else {
+        if !value.is_null() {
+            *value = Value::String(MASK.to_owned());
+        }
+        return;
+    };
+
+    match value {
+        Value::Object(map) => map
+            .iter_mut()
+            .filter(|(key, _)| segment == "*" || key.eq_ignore_ascii_case(segment))
+            .for_each(|(_, value)| mask(value, rest)),
+        Value::Array(items) => match segment.parse::<usize>() {
+            Ok(index) => {
+                if let Some(item) = items.get_mut(index) {
+                    mask(item, rest);
+                }
+            }
+            Err(_) if segment == "*" => items.iter_mut().for_each(|item| mask(item, rest)),
+            Err(_) => {}
+        },
+        _ => {}
+    }
+}
@@ -0,0 +1,49 @@
+// Request ID Generation and Propagation
+
+// Third Party Imports
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+// Crate-Level Imports
+use crate::jwt::random_id;
+
+/// Header carrying each request's ID, taken from the request if the client set one
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The longest incoming request ID that's reused, rather than replaced
+const MAX_LEN: usize = 128;
+
+/// The ID of the request being handled
+#[derive(Clone, Debug)]
+pub(crate) struct RequestId(pub(crate) String);
+
+/// Give each request an ID (reusing the one it arrived with, if it's sensible),
+/// and have it sent along with the request wherever it's proxied or mirrored,
+/// echoed back in the response, and attached to every log line it causes
+#[tracing::instrument(skip_all)]
+pub(crate) async fn assign<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_LEN)
+        .map_or_else(|| random_id(12), ToOwned::to_owned);
+
+    let value = HeaderValue::try_from(id.as_str()).expect("ids are valid header values");
+
+    req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next
+        .run(req)
+        .instrument(tracing::info_span!("request", request_id = %id))
+        .await;
+
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
@@ -0,0 +1,1116 @@
+// Per-Route Behaviors
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Instant,
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Body, Bytes, Empty, StreamBody},
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex_lite::Regex;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use tokio_util::io::ReaderStream;
+
+// Crate-Level Imports
+use crate::{
+    admin::constant_time_eq,
+    chaos::{Delay, Fault},
+    client_ip::Cidr,
+    errors::Failure,
+    template::{is_template, TemplateCounters, TemplateRequest, Templates},
+    tls::TlsFiles,
+    unmatched::UnmatchedRequests,
+};
+
+/// How a matched request is answered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RouteMode {
+    /// Mirror the request back, as usual
+    #[default]
+    Mirror,
+    /// Log the request, but answer with an empty body
+    LogOnly,
+}
+
+/// Credentials a matched request must present
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) enum RouteAuth {
+    /// `Authorization: Bearer <token>`
+    Bearer(String),
+    /// `Authorization: Basic <base64(user:password)>`, given as `user:password`
+    Basic(String),
+}
+
+/// Parse credentials given as `bearer:<token>` or `basic:<user>:<password>`
+impl FromStr for RouteAuth {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            Some(("bearer", token)) if !token.is_empty() => Ok(Self::Bearer(token.to_owned())),
+            Some(("basic", pair)) if pair.contains(':') => Ok(Self::Basic(pair.to_owned())),
+            _ => Err("expected `bearer:<token>` or `basic:<user>:<password>`".to_owned()),
+        }
+    }
+}
+
+impl RouteAuth {
+    pub(crate) fn challenge(&self) -> &'static str {
+        match self {
+            Self::Bearer(_) => "Bearer",
+            Self::Basic(_) => "Basic realm=\"echo-rs\"",
+        }
+    }
+
+    pub(crate) fn permits(&self, headers: &HeaderMap) -> bool {
+        let Some(credentials) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+
+        match self {
+            Self::Bearer(token) => credentials
+                .strip_prefix("Bearer ")
+                .is_some_and(|candidate| {
+                    constant_time_eq(token.as_bytes(), candidate.trim().as_bytes())
+                }),
+            Self::Basic(pair) => credentials
+                .strip_prefix("Basic ")
+                .and_then(|candidate| STANDARD.decode(candidate.trim()).ok())
+                .is_some_and(|candidate| constant_time_eq(pair.as_bytes(), &candidate)),
+        }
+    }
+}
+
+/// One of the responses a route rule answers with in turn, in place of its own
+/// status, body, or content type (and in addition to its own headers)
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ResponseSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) status: Option<u16>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) body_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+    /// How many consecutive matching requests get this response
+    #[serde(default = "ResponseSpec::once")]
+    pub(crate) times: u64,
+}
+
+impl ResponseSpec {
+    fn once() -> u64 {
+        1
+    }
+}
+
+/// A route rule as written in the configuration file
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RouteRuleSpec {
+    /// Identifier reported for matching requests (defaults to the
+    /// path pattern, prefixed with the methods the rule applies to)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<String>,
+    /// Path pattern, where `*` matches within a single path segment and `**` across segments
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) path: String,
+    /// Regular expression the path must match, in place of a `path` pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) path_regex: Option<String>,
+    /// Methods the rule applies to (any, if none are given)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) methods: Vec<String>,
+    /// Headers the request must carry, and regular expressions their values must match
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) match_headers: BTreeMap<String, String>,
+    /// JSONPath expressions that must each select something from the (JSON) request body
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) match_body: Vec<String>,
+    /// Networks (e.g. `10.1.0.0/16`) one of which the client's address must be in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) match_clients: Vec<String>,
+    /// Status code to respond with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) status: Option<u16>,
+    /// Delay applied before the request is handled, fixed or drawn from a distribution
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) delay: Option<Delay>,
+    /// Fault injected into a fraction of the matching requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) fault: Option<Fault>,
+    /// Headers added to the response
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) headers: BTreeMap<String, String>,
+    /// Canned response body, rendered as a template, answered in place of the echo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) body: Option<String>,
+    /// File (e.g. an image or a protobuf blob) streamed from disk as the response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) body_file: Option<PathBuf>,
+    /// Content type of the canned (or file) body, sniffed (or assumed binary) if omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+    /// How the request is answered
+    #[serde(default)]
+    pub(crate) mode: RouteMode,
+    /// Credentials the request must present
+    #[serde(
+        default,
+        with = "serde_yaml::with::singleton_map",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) auth: Option<RouteAuth>,
+    /// Responses given to successive matching requests, e.g. two 503s then a 200
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) responses: Vec<ResponseSpec>,
+    /// Start over from the first of the `responses` once they've all been given,
+    /// rather than repeating the last one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) cycle: bool,
+}
+
+/// Headers as they're written, split into literal values and templates rendered per request
+fn response_headers(
+    headers: &BTreeMap<String, String>,
+) -> anyhow::Result<(HeaderMap, Vec<(HeaderName, String)>)> {
+    let (templated, literal) = headers
+        .iter()
+        .partition::<Vec<(&String, &String)>, _>(|(_, value)| is_template(value));
+
+    let literal = literal
+        .into_iter()
+        .map(|(name, value)| {
+            Ok((
+                HeaderName::try_from(name.as_str())?,
+                HeaderValue::try_from(value.as_str())?,
+            ))
+        })
+        .collect::<anyhow::Result<HeaderMap>>()?;
+
+    let templated = templated
+        .into_iter()
+        .map(|(name, value)| Ok((HeaderName::try_from(name.as_str())?, value.clone())))
+        .collect::<anyhow::Result<Vec<(HeaderName, String)>>>()?;
+
+    Ok((literal, templated))
+}
+
+/// A compiled response of a route rule's sequence
+#[derive(Clone, Debug)]
+struct SequencedResponse {
+    status: Option<StatusCode>,
+    headers: HeaderMap,
+    header_templates: Vec<(HeaderName, String)>,
+    body: Option<String>,
+    body_file: Option<PathBuf>,
+    content_type: Option<HeaderValue>,
+    times: u64,
+}
+
+impl TryFrom<ResponseSpec> for SequencedResponse {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: ResponseSpec) -> Result<Self, Self::Error> {
+        if spec.times == 0 {
+            anyhow::bail!("`times` must be at least 1");
+        }
+
+        if let Some(path) = spec.body_file.as_ref() {
+            if spec.body.is_some() {
+                anyhow::bail!("`body` and `body_file` are exclusive");
+            }
+
+            if !path.is_file() {
+                anyhow::bail!("no such file: {}", path.display());
+            }
+        }
+
+        let (headers, header_templates) = response_headers(&spec.headers)?;
+
+        Ok(Self {
+            status: spec.status.map(StatusCode::from_u16).transpose()?,
+            headers,
+            header_templates,
+            body: spec.body,
+            body_file: spec.body_file,
+            content_type: spec
+                .content_type
+                .as_deref()
+                .map(HeaderValue::try_from)
+                .transpose()?,
+            times: spec.times,
+        })
+    }
+}
+
+/// The responses a route rule gives in turn, and how many it's given so far
+#[derive(Debug)]
+struct ResponseSequence {
+    responses: Vec<SequencedResponse>,
+    cycle: bool,
+    served: AtomicU64,
+}
+
+impl ResponseSequence {
+    /// The response due to the next matching request
+    fn next(&self) -> (usize, &SequencedResponse) {
+        let total = self
+            .responses
+            .iter()
+            .map(|response| response.times)
+            .sum::<u64>();
+        let mut served = self.served.fetch_add(1, Ordering::Relaxed);
+
+        if served >= total && !self.cycle {
+            return (
+                self.responses.len() - 1,
+                &self.responses[self.responses.len() - 1],
+            );
+        }
+
+        served %= total;
+
+        self.responses
+            .iter()
+            .enumerate()
+            .find(|(_, response)| {
+                let due = served < response.times;
+                served = served.saturating_sub(response.times);
+                due
+            })
+            .expect("the responses' times add up to the total")
+    }
+
+    fn reset(&self) {
+        self.served.store(0, Ordering::Relaxed);
+    }
+}
+
+/// A compiled route rule
+#[derive(Clone, Debug)]
+pub(crate) struct RouteRule {
+    pub(crate) id: String,
+    pub(crate) pattern: String,
+    matcher: Regex,
+    /// Methods the rule applies to, or any if empty
+    methods: Vec<Method>,
+    header_matchers: Vec<(HeaderName, Regex)>,
+    body_matchers: Vec<JsonPath>,
+    /// Networks the client must be in, or any if empty
+    clients: Vec<Cidr>,
+    status: Option<StatusCode>,
+    delay: Option<Delay>,
+    fault: Option<Fault>,
+    headers: HeaderMap,
+    /// Headers whose values are rendered (as templates) per request
+    header_templates: Vec<(HeaderName, String)>,
+    body: Option<String>,
+    body_file: Option<PathBuf>,
+    content_type: Option<HeaderValue>,
+    mode: RouteMode,
+    auth: Option<RouteAuth>,
+    /// Responses given in turn, in place of the rule's own
+    sequence: Option<Arc<ResponseSequence>>,
+}
+
+impl TryFrom<RouteRuleSpec> for RouteRule {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: RouteRuleSpec) -> Result<Self, Self::Error> {
+        let (pattern, matcher) = match (spec.path.is_empty(), spec.path_regex.as_ref()) {
+            (false, None) => (spec.path.clone(), glob(&spec.path)?),
+            (true, Some(regex)) => (
+                regex.clone(),
+                Regex::new(regex).map_err(|error| anyhow::anyhow!("route {regex:?}: {error}"))?,
+            ),
+            (false, Some(_)) => {
+                anyhow::bail!(
+                    "route {:?}: `path` and `path_regex` are exclusive",
+                    spec.path
+                )
+            }
+            (true, None) => anyhow::bail!("route rules require a `path` or `path_regex`"),
+        };
+
+        let header_matchers = spec
+            .match_headers
+            .iter()
+            .map(|(name, regex)| {
+                Ok((
+                    HeaderName::try_from(name.as_str())?,
+                    Regex::new(regex).map_err(|error| anyhow::anyhow!("{regex:?}: {error}"))?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<(HeaderName, Regex)>>>()
+            .map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))?;
+
+        let body_matchers = spec
+            .match_body
+            .iter()
+            .map(|path| {
+                JsonPath::parse(path).map_err(|error| {
+                    anyhow::anyhow!("route {pattern:?}: invalid JSONPath {path:?}: {error}")
+                })
+            })
+            .collect::<anyhow::Result<Vec<JsonPath>>>()?;
+
+        let clients = spec
+            .match_clients
+            .iter()
+            .map(|cidr| cidr.parse::<Cidr>())
+            .collect::<Result<Vec<Cidr>, String>>()
+            .map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))?;
+
+        let status = spec
+            .status
+            .map(StatusCode::from_u16)
+            .transpose()
+            .map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))?;
+
+        let methods = spec
+            .methods
+            .iter()
+            .map(|method| Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()))
+            .collect::<Result<Vec<Method>, _>>()
+            .map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))?;
+
+        let (headers, header_templates) = response_headers(&spec.headers)
+            .map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))?;
+
+        let content_type = spec
+            .content_type
+            .as_deref()
+            .map(HeaderValue::try_from)
+            .transpose()
+            .map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))?;
+
+        if let Some(path) = spec.body_file.as_ref() {
+            if spec.body.is_some() {
+                anyhow::bail!("route {pattern:?}: `body` and `body_file` are exclusive");
+            }
+
+            if !path.is_file() {
+                anyhow::bail!("route {pattern:?}: no such file: {}", path.display());
+            }
+        }
+
+        let responses = spec
+            .responses
+            .into_iter()
+            .enumerate()
+            .map(|(index, response)| {
+                SequencedResponse::try_from(response).map_err(|error| {
+                    anyhow::anyhow!("route {pattern:?}: response #{}: {error}", index + 1)
+                })
+            })
+            .collect::<anyhow::Result<Vec<SequencedResponse>>>()?;
+
+        let sequence = (!responses.is_empty()).then(|| {
+            Arc::new(ResponseSequence {
+                responses,
+                cycle: spec.cycle,
+                served: AtomicU64::new(0),
+            })
+        });
+
+        Ok(Self {
+            matcher,
+            // method-scoped rules for the same path need telling apart
+            id: spec.id.unwrap_or_else(|| match methods.is_empty() {
+                true => pattern.clone(),
+                false => format!(
+                    "{} {}",
+                    methods
+                        .iter()
+                        .map(Method::as_str)
+                        .collect::<Vec<&str>>()
+                        .join(","),
+                    pattern
+                ),
+            }),
+            pattern,
+            methods,
+            header_matchers,
+            body_matchers,
+            clients,
+            status,
+            delay: spec.delay,
+            fault: spec.fault,
+            headers,
+            header_templates,
+            body: spec.body,
+            body_file: spec.body_file,
+            content_type,
+            mode: spec.mode,
+            auth: spec.auth,
+            sequence,
+        })
+    }
+}
+
+impl RouteRule {
+    pub(crate) fn matches(&self, candidate: &Candidate<'_>) -> bool {
+        (self.methods.is_empty() || self.methods.contains(candidate.method))
+            && self.matcher.is_match(candidate.path)
+            && (self.clients.is_empty()
+                || candidate
+                    .client
+                    .is_some_and(|client| self.clients.iter().any(|cidr| cidr.contains(client))))
+            && self.header_matchers.iter().all(|(name, pattern)| {
+                candidate
+                    .headers
+                    .get_all(name)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .any(|value| pattern.is_match(value))
+            })
+            && (self.body_matchers.is_empty()
+                || candidate.body.is_some_and(|body| {
+                    self.body_matchers
+                        .iter()
+                        .all(|path| !path.query(body).is_empty())
+                }))
+    }
+
+    /// Whether matching the rule requires the request body
+    fn matches_body(&self) -> bool {
+        !self.body_matchers.is_empty()
+    }
+
+    /// Every template the rule renders (for the request at hand)
+    fn templates(&self) -> impl Iterator<Item = &str> {
+        self.body.as_deref().into_iter().chain(
+            self.header_templates
+                .iter()
+                .map(|(_, value)| value.as_str()),
+        )
+    }
+
+    /// Every template the rule may ever render, across its sequence of responses
+    fn all_templates(&self) -> impl Iterator<Item = &str> {
+        let sequenced = self.sequence.iter().flat_map(|sequence| {
+            sequence.responses.iter().flat_map(|response| {
+                response.body.as_deref().into_iter().chain(
+                    response
+                        .header_templates
+                        .iter()
+                        .map(|(_, value)| value.as_str()),
+                )
+            })
+        });
+
+        self.templates().chain(sequenced)
+    }
+
+    /// The rule as it answers the request at hand, i.e. with the
+    /// next of its sequence of responses (if it has one) in place
+    fn advance(mut self) -> Self {
+        let Some(sequence) = self.sequence.clone() else {
+            return self;
+        };
+
+        let (index, response) = sequence.next();
+
+        tracing::debug!("Route {:?} answering with response #{}", self.id, index + 1);
+
+        self.status = response.status.or(self.status);
+        self.headers.extend(response.headers.clone());
+        self.header_templates
+            .extend(response.header_templates.iter().cloned());
+
+        if response.body.is_some() || response.body_file.is_some() {
+            self.body = response.body.clone();
+            self.body_file = response.body_file.clone();
+        }
+
+        self.content_type = response.content_type.clone().or(self.content_type);
+        self
+    }
+
+    /// Start the rule's sequence of responses over
+    fn reset(&self) {
+        if let Some(sequence) = self.sequence.as_ref() {
+            sequence.reset();
+        }
+    }
+}
+
+/// The parts of a request route rules are matched against
+pub(crate) struct Candidate<'a> {
+    pub(crate) method: &'a Method,
+    pub(crate) path: &'a str,
+    pub(crate) headers: &'a HeaderMap,
+    /// The client's address (as identified behind any trusted proxies)
+    pub(crate) client: Option<IpAddr>,
+    /// The request body, if it's JSON (and needed)
+    pub(crate) body: Option<&'a Value>,
+}
+
+/// Translate a path glob into an anchored regular expression
+pub(crate) fn glob(pattern: &str) -> anyhow::Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            other => regex.push_str(&regex_lite::escape(other.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    regex.push('$');
+
+    Regex::new(&regex).map_err(|error| anyhow::anyhow!("route {pattern:?}: {error}"))
+}
+
+/// A virtual host as written in the configuration file
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct VirtualHostSpec {
+    /// `Host` (or SNI name) pattern, where `*` matches any run of characters
+    pub(crate) host: String,
+    /// Name reported for requests to the host (defaults to the pattern itself)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    /// Behaviors specific to the host, consulted before the global ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) routes: Vec<RouteRuleSpec>,
+    /// Certificate presented to clients asking for the host via SNI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) tls: Option<TlsFiles>,
+}
+
+impl VirtualHostSpec {
+    /// The host pattern, compiled for matching against (lowercase) host names
+    pub(crate) fn matcher(&self) -> anyhow::Result<Regex> {
+        glob(&self.host.to_ascii_lowercase())
+    }
+}
+
+/// The identifier of the route rule a request matched, attached
+/// to both the request and its response
+#[derive(Clone, Debug)]
+pub(crate) struct MatchedRule(pub(crate) String);
+
+/// The name of the virtual host a request was addressed to
+#[derive(Clone, Debug)]
+pub(crate) struct VirtualHostName(pub(crate) String);
+
+/// A compiled virtual host
+#[derive(Clone, Debug)]
+pub(crate) struct VirtualHost {
+    name: VirtualHostName,
+    matcher: Regex,
+    rules: Vec<RouteRule>,
+}
+
+/// A compiled set of route rules, swapped out wholesale when they're reloaded
+#[derive(Debug, Default)]
+struct RuleSet {
+    /// The (global) rules as they were written, for exporting
+    specs: Vec<RouteRuleSpec>,
+    rules: Vec<RouteRule>,
+    hosts: Vec<VirtualHost>,
+}
+
+impl RuleSet {
+    /// The virtual host serving the given host name, if any
+    fn host(&self, host: Option<&str>) -> Option<&VirtualHost> {
+        let host = host?.to_ascii_lowercase();
+
+        self.hosts
+            .iter()
+            .find(|vhost| vhost.matcher.is_match(&host))
+    }
+
+    /// The first rule matching the request, preferring the virtual host's own rules
+    fn find<'a>(
+        &'a self,
+        host: Option<&'a VirtualHost>,
+        candidate: &Candidate<'_>,
+    ) -> Option<&'a RouteRule> {
+        host.into_iter()
+            .flat_map(|host| host.rules.iter())
+            .chain(self.rules.iter())
+            .find(|rule| rule.matches(candidate))
+    }
+
+    /// Whether matching any of the rules requires the request body
+    fn match_bodies(&self, host: Option<&VirtualHost>) -> bool {
+        host.into_iter()
+            .flat_map(|host| host.rules.iter())
+            .chain(self.rules.iter())
+            .any(RouteRule::matches_body)
+    }
+}
+
+/// The route rules in effect, consulted in order
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RouteRules {
+    set: Arc<RwLock<Arc<RuleSet>>>,
+    /// Whether the rules may be replaced while serving, even if there are none yet
+    reloadable: bool,
+    /// Where requests matching no rule are recorded
+    unmatched: Arc<UnmatchedRequests>,
+    /// The status requests matching no rule are rejected with, in strict mode
+    strict: Option<StatusCode>,
+    /// Renders canned response bodies
+    templates: Arc<Templates>,
+}
+
+impl RouteRules {
+    pub(crate) fn new(
+        specs: Vec<RouteRuleSpec>,
+        hosts: Vec<VirtualHostSpec>,
+    ) -> anyhow::Result<Self> {
+        let rules = Self::default();
+
+        rules.replace(specs, hosts)?;
+
+        Ok(rules)
+    }
+
+    /// Compile the given rules, then (only if they're all valid) put them in effect
+    /// in place of the current ones, returning how many there are
+    pub(crate) fn replace(
+        &self,
+        specs: Vec<RouteRuleSpec>,
+        hosts: Vec<VirtualHostSpec>,
+    ) -> anyhow::Result<usize> {
+        let templates = &self.templates;
+
+        let compile = |specs: Vec<RouteRuleSpec>| {
+            specs
+                .into_iter()
+                .map(|spec| {
+                    let rule = RouteRule::try_from(spec)?;
+
+                    for template in rule.all_templates() {
+                        templates.validate(template).map_err(|error| {
+                            anyhow::anyhow!("route {:?}: {error}", rule.pattern)
+                        })?;
+                    }
+
+                    Ok(rule)
+                })
+                .collect::<anyhow::Result<Vec<RouteRule>>>()
+        };
+
+        let hosts = hosts
+            .into_iter()
+            .map(|spec| {
+                Ok(VirtualHost {
+                    matcher: spec.matcher()?,
+                    name: VirtualHostName(spec.name.unwrap_or(spec.host)),
+                    rules: compile(spec.routes)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<VirtualHost>>>()?;
+
+        let set = RuleSet {
+            rules: compile(specs.clone())?,
+            specs,
+            hosts,
+        };
+
+        let count = set.rules.len() + set.hosts.iter().map(|host| host.rules.len()).sum::<usize>();
+
+        *self.set.write().unwrap() = Arc::new(set);
+
+        Ok(count)
+    }
+
+    /// The rules in effect right now
+    fn current(&self) -> Arc<RuleSet> {
+        self.set.read().unwrap().clone()
+    }
+
+    /// Have the echo routes consult the rules even while there are none,
+    /// so that rules loaded later take effect
+    pub(crate) fn reloadable(mut self) -> Self {
+        self.reloadable = true;
+        self
+    }
+
+    pub(crate) fn is_reloadable(&self) -> bool {
+        self.reloadable
+    }
+
+    /// Record requests matching no rule in the given log, optionally
+    /// rejecting them (with the given status) rather than echoing them
+    pub(crate) fn with_unmatched(
+        mut self,
+        unmatched: Arc<UnmatchedRequests>,
+        strict: Option<StatusCode>,
+    ) -> Self {
+        self.unmatched = unmatched;
+        self.strict = strict;
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        let set = self.current();
+        set.rules.is_empty() && set.hosts.is_empty()
+    }
+
+    /// The (global) rules as they were written
+    pub(crate) fn specs(&self) -> Vec<RouteRuleSpec> {
+        self.current().specs.clone()
+    }
+
+    /// Renders canned response bodies (and every other template)
+    pub(crate) fn templates(&self) -> Arc<Templates> {
+        self.templates.clone()
+    }
+
+    /// The counters shared by every canned response template
+    pub(crate) fn template_counters(&self) -> Arc<TemplateCounters> {
+        self.templates.counters.clone()
+    }
+
+    /// Whether requests matching no rule are rejected, rather than echoed
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict.is_some()
+    }
+
+    /// Start every rule's sequence of responses over (or, given an
+    /// identifier, only that rule's), returning whether there was any
+    pub(crate) fn reset_sequences(&self, id: Option<&str>) -> bool {
+        let set = self.current();
+
+        set.hosts
+            .iter()
+            .flat_map(|host| host.rules.iter())
+            .chain(set.rules.iter())
+            .filter(|rule| rule.sequence.is_some() && id.is_none_or(|id| rule.id == id))
+            .inspect(|rule| rule.reset())
+            .count()
+            > 0
+    }
+
+    /// The identifiers of every rule, including those of virtual hosts
+    pub(crate) fn ids(&self) -> Vec<String> {
+        let set = self.current();
+
+        set.hosts
+            .iter()
+            .flat_map(|host| host.rules.iter())
+            .chain(set.rules.iter())
+            .map(|rule| rule.id.clone())
+            .collect()
+    }
+}
+
+/// The host a request was addressed to, sans port
+fn request_host<B>(req: &Request<B>) -> Option<&str> {
+    let authority = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| req.uri().authority().map(|authority| authority.as_str()))?;
+
+    Some(match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or(bracketed),
+        None => authority.split(':').next().unwrap_or(authority),
+    })
+}
+
+/// A canned response body, labeled as JSON if it looks like it
+pub(crate) fn canned(body: String, content_type: Option<&HeaderValue>) -> Response {
+    let content_type = content_type.cloned().unwrap_or_else(|| {
+        HeaderValue::from_static(match serde_json::from_str::<serde::de::IgnoredAny>(&body) {
+            Ok(_) => "application/json",
+            Err(_) => "text/plain; charset=utf-8",
+        })
+    });
+
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// Stream a file from disk as the response body
+async fn file(path: &Path, content_type: Option<&HeaderValue>) -> Response {
+    let content_type = content_type
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_static("application/octet-stream"));
+
+    let opened = async {
+        let file = tokio::fs::File::open(path).await?;
+        let length = file.metadata().await?.len();
+
+        std::io::Result::Ok((file, length))
+    };
+
+    match opened.await {
+        Ok((file, length)) => (
+            [
+                (header::CONTENT_TYPE, content_type),
+                (header::CONTENT_LENGTH, HeaderValue::from(length)),
+            ],
+            StreamBody::new(ReaderStream::new(file)),
+        )
+            .into_response(),
+        Err(error) => {
+            tracing::warn!("Failed to read {}: {error}", path.display());
+            Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+/// Buffer the request's body, so it can be looked at before the request is passed on
+async fn buffer(req: Request<Body>) -> Result<(Request<Body>, Bytes), Response> {
+    let (parts, body) = req.into_parts();
+
+    let body = crate::body::buffer(&parts, body).await?;
+
+    Ok((Request::from_parts(parts, Body::from(body.clone())), body))
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn apply(
+    State(rules): State<RouteRules>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    // the rules are fixed for the request's lifetime, even if they're reloaded meanwhile
+    let set = rules.current();
+    let host = set.host(request_host(&req));
+
+    if let Some(host) = host {
+        req.extensions_mut().insert(host.name.clone());
+    }
+
+    // rules may match on the request body, in which case it's buffered for them
+    let mut buffered = None;
+
+    if set.match_bodies(host) {
+        match buffer(req).await {
+            Ok((request, body)) => (req, buffered) = (request, Some(body)),
+            Err(response) => return response,
+        }
+    }
+
+    let json = buffered
+        .as_ref()
+        .and_then(|body| serde_json::from_slice::<Value>(body).ok());
+
+    let candidate = Candidate {
+        method: req.method(),
+        path: req.uri().path(),
+        headers: req.headers(),
+        client: req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(client)| client.ip()),
+        body: json.as_ref(),
+    };
+
+    let Some(rule) = set.find(host, &candidate).cloned() else {
+        rules.unmatched.record(
+            req.method().as_str(),
+            req.uri().path(),
+            req.uri().query(),
+            request_host(&req),
+        );
+
+        if let Some(status) = rules.strict {
+            tracing::error!(
+                "Strict stubs: rejecting {} {} with {status}",
+                req.method(),
+                req.uri()
+            );
+
+            return (
+                status,
+                format!(
+                    "no route rule matches {} {}",
+                    req.method(),
+                    req.uri().path()
+                ),
+            )
+                .into_response();
+        }
+
+        return next.run(req).await;
+    };
+
+    let (matched, start) = (MatchedRule(rule.id.clone()), Instant::now());
+
+    metrics::increment_counter!("route_rule_matches_total", "rule" => rule.id.clone());
+
+    if let Some(auth) = rule.auth.as_ref() {
+        if !auth.permits(req.headers()) {
+            let mut response = (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, auth.challenge())],
+            )
+                .into_response();
+
+            response.extensions_mut().insert(matched);
+            observe(&rule, start, &response);
+
+            return response;
+        }
+    }
+
+    let rule = rule.advance();
+
+    if let Some(delay) = rule.delay.as_ref() {
+        tokio::time::sleep(delay.sample()).await;
+    }
+
+    if let Some(mut response) = rule.fault.as_ref().and_then(|fault| fault.inject(&rule.id)) {
+        tracing::info!(
+            "{} {} (matched route {:?}, fault injected)",
+            req.method(),
+            req.uri(),
+            rule.pattern
+        );
+
+        response.extensions_mut().insert(matched);
+        observe(&rule, start, &response);
+
+        return response;
+    }
+
+    // templates may refer to the request body, so it's buffered for them
+    let context = match (rule.templates().next(), buffered) {
+        (None, _) => None,
+        (Some(_), Some(body)) => Some(TemplateRequest::new(
+            req.method(),
+            req.uri(),
+            req.headers(),
+            &body,
+        )),
+        (Some(_), None) => match buffer(req).await {
+            Ok((request, body)) => {
+                req = request;
+                Some(TemplateRequest::new(
+                    req.method(),
+                    req.uri(),
+                    req.headers(),
+                    &body,
+                ))
+            }
+            Err(response) => return response,
+        },
+    };
+
+    let mut response = match (
+        rule.body.as_deref(),
+        rule.body_file.as_deref(),
+        context.as_ref(),
+        rule.mode,
+    ) {
+        (_, Some(path), _, _) => {
+            tracing::info!(
+                "{} {} (matched route {:?}, file response)",
+                req.method(),
+                req.uri(),
+                rule.pattern
+            );
+
+            file(path, rule.content_type.as_ref()).await
+        }
+        (Some(template), _, Some(context), _) => {
+            tracing::info!(
+                "{} {} (matched route {:?}, canned response)",
+                req.method(),
+                req.uri(),
+                rule.pattern
+            );
+
+            match rules.templates.render(template, context) {
+                Ok(body) => canned(body, rule.content_type.as_ref()),
+                Err(error) => {
+                    tracing::warn!("Failed to render route {:?}: {error}", rule.pattern);
+                    Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error).into_response()
+                }
+            }
+        }
+        (_, _, _, RouteMode::Mirror) => {
+            req.extensions_mut().insert(matched.clone());
+            next.run(req).await
+        }
+        (_, _, _, RouteMode::LogOnly) => {
+            tracing::info!(
+                "{} {} (matched route {:?}, log-only)",
+                req.method(),
+                req.uri(),
+                rule.pattern
+            );
+
+            Response::new(body::boxed(Empty::new()))
+        }
+    };
+
+    if let Some(status) = rule.status {
+        *response.status_mut() = status;
+    }
+
+    response.headers_mut().extend(rule.headers.clone());
+
+    if let Some(context) = context.as_ref() {
+        for (name, template) in &rule.header_templates {
+            let rendered = rules
+                .templates
+                .render(template, context)
+                .and_then(|value| HeaderValue::try_from(value).map_err(|error| error.to_string()));
+
+            match rendered {
+                Ok(value) => {
+                    response.headers_mut().insert(name.clone(), value);
+                }
+                Err(error) => {
+                    tracing::warn!("route {:?}: header {name}: {error}", rule.pattern);
+                }
+            }
+        }
+    }
+
+    response.extensions_mut().insert(matched);
+    observe(&rule, start, &response);
+
+    response
+}
+
+/// Record how long the rule took to answer a request it matched (its
+/// simulated delay included), so latency can be attributed to the
+/// simulated endpoint rather than just the path
+fn observe(rule: &RouteRule, start: Instant, response: &Response) {
+    metrics::histogram!(
+        "route_rule_duration_seconds",
+        start.elapsed().as_secs_f64(),
+        "rule" => rule.id.clone(),
+        "status" => response.status().as_u16().to_string()
+    );
+}
@@ -0,0 +1,858 @@
+// S3-Compatible Object Storage Emulation
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+// Third Party Imports
+use axum::{
+    body::{Body, Bytes, StreamBody},
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use quick_xml::escape::escape;
+use tokio_util::io::ReaderStream;
+
+// Crate-Level Imports
+use crate::{
+    request_id::RequestId,
+    uploads::{sha256_of, Spool},
+};
+
+/// Namespace of every S3 response document
+const S3_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+/// The most keys a listing holds, unless a lower `max-keys` is asked for
+const MAX_KEYS: usize = 1000;
+
+/// Whether the request is an S3 API call, i.e. (pre)signed as the AWS SDKs
+/// sign them, or otherwise carrying `x-amz-content-sha256`
+fn is_s3_call<B>(req: &Request<B>) -> bool {
+    let headers = req.headers();
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("AWS4-HMAC-SHA256") || value.starts_with("AWS "))
+        || headers.contains_key("x-amz-content-sha256")
+        || req
+            .uri()
+            .query()
+            .is_some_and(|query| query.contains("X-Amz-Algorithm="))
+}
+
+/// Decode the percent-encoded (e.g. path) component of a URI
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// Decode an `aws-chunked` body (as the SDKs stream uploads), dropping the
+/// chunk signatures and any trailing checksums
+fn decode_aws_chunked(body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(body.len());
+    let mut rest = body;
+
+    loop {
+        let end = rest
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or("unterminated chunk header")?;
+
+        let size = std::str::from_utf8(&rest[..end])
+            .ok()
+            .and_then(|line| line.split(';').next())
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .ok_or("invalid chunk size")?;
+
+        rest = &rest[end + 2..];
+
+        if size == 0 {
+            return Ok(decoded);
+        }
+
+        let chunk = rest.get(..size).ok_or("truncated chunk")?;
+        decoded.extend_from_slice(chunk);
+
+        rest = rest
+            .get(size..)
+            .and_then(|rest| rest.strip_prefix(b"\r\n"))
+            .ok_or("unterminated chunk")?;
+    }
+}
+
+/// An S3 error, as it's reported
+#[derive(Debug)]
+struct S3Error {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn internal(error: io::Error) -> Self {
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "InternalError",
+            error.to_string(),
+        )
+    }
+
+    fn access_denied() -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", "Access Denied")
+    }
+
+    fn no_such_bucket(bucket: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchBucket",
+            format!("The specified bucket does not exist: {bucket}"),
+        )
+    }
+
+    fn no_such_key(key: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "NoSuchKey",
+            format!("The specified key does not exist: {key}"),
+        )
+    }
+
+    fn not_implemented(operation: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "NotImplemented",
+            format!("{operation} is not emulated"),
+        )
+    }
+
+    fn respond(&self, resource: &str, request_id: &str, head: bool) -> Response {
+        let document = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><Error><Code>{}</Code><Message>{}</Message><Resource>{}</Resource><RequestId>{}</RequestId></Error>"#,
+            self.code,
+            escape(&self.message),
+            escape(resource),
+            escape(request_id),
+        );
+
+        // the error is only told by the status code in answer to HEAD
+        let body = if head { String::new() } else { document };
+
+        (
+            self.status,
+            [(header::CONTENT_TYPE, "application/xml")],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// An object, as it's listed
+#[derive(Debug)]
+struct Object {
+    key: String,
+    path: PathBuf,
+}
+
+/// Buckets (directories) and objects (files) kept in the spool directory
+#[derive(Debug)]
+pub(crate) struct S3 {
+    spool: Arc<Spool>,
+}
+
+impl S3 {
+    pub(crate) fn new(spool: Arc<Spool>) -> Self {
+        Self { spool }
+    }
+
+    /// Whether the key segment can be kept as is, i.e. it's not empty, and doesn't begin
+    /// with a `.` (which would escape the bucket, or be hidden among partial uploads)
+    fn valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.starts_with('.') && !name.contains(['\\', '\0'])
+    }
+
+    /// Whether the name follows S3's bucket naming rules (i.e. 3 to 63
+    /// lowercase letters, digits, dots, and hyphens, beginning and ending
+    /// with a letter or digit, without any adjacent dots, that isn't an IP address)
+    fn valid_bucket_name(name: &str) -> bool {
+        let alphanumeric = |char: char| char.is_ascii_lowercase() || char.is_ascii_digit();
+
+        (3..=63).contains(&name.len())
+            && name
+                .chars()
+                .all(|char| alphanumeric(char) || char == '.' || char == '-')
+            && name.starts_with(alphanumeric)
+            && name.ends_with(alphanumeric)
+            && !name.contains("..")
+            && name.parse::<std::net::Ipv4Addr>().is_err()
+    }
+
+    /// The path, so long as it (or as much of it as exists) resolves
+    /// to somewhere inside the spool directory
+    fn confine(&self, path: PathBuf) -> Result<PathBuf, S3Error> {
+        let root = self.spool.dir().canonicalize().map_err(S3Error::internal)?;
+
+        let mut existing = path.as_path();
+
+        while !existing.exists() {
+            existing = existing.parent().ok_or_else(S3Error::access_denied)?;
+        }
+
+        match existing.canonicalize() {
+            Ok(resolved) if resolved.starts_with(&root) => Ok(path),
+            _ => Err(S3Error::access_denied()),
+        }
+    }
+
+    /// The directory the named bucket is kept in
+    fn bucket(&self, bucket: &str) -> Result<PathBuf, S3Error> {
+        if Self::valid_bucket_name(bucket) {
+            self.confine(self.spool.dir().join(bucket))
+        } else {
+            Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidBucketName",
+                format!("The specified bucket is not valid: {bucket}"),
+            ))
+        }
+    }
+
+    /// The directory the named bucket is kept in, if it exists
+    async fn existing_bucket(&self, bucket: &str) -> Result<PathBuf, S3Error> {
+        let dir = self.bucket(bucket)?;
+
+        match tokio::fs::metadata(&dir).await {
+            Ok(metadata) if metadata.is_dir() => Ok(dir),
+            _ => Err(S3Error::no_such_bucket(bucket)),
+        }
+    }
+
+    /// The file the keyed object is kept in (each `/`-separated segment
+    /// of the key being a directory)
+    fn object(&self, dir: &Path, key: &str) -> Result<PathBuf, S3Error> {
+        if key.split('/').all(Self::valid_name) {
+            self.confine(
+                key.split('/')
+                    .fold(dir.to_owned(), |path, segment| path.join(segment)),
+            )
+        } else {
+            Err(S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidArgument",
+                format!("Object keys with empty segments, or ones beginning with `.`, aren't supported: {key}"),
+            ))
+        }
+    }
+
+    /// Every object in the bucket, in key order (partial uploads aside)
+    fn objects(dir: &Path) -> io::Result<Vec<Object>> {
+        let mut objects = Vec::new();
+        let mut pending = vec![(dir.to_owned(), String::new())];
+
+        while let Some((dir, prefix)) = pending.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let kind = entry.file_type()?;
+
+                if kind.is_dir() {
+                    pending.push((entry.path(), format!("{prefix}{name}/")));
+                } else if kind.is_file() && !name.starts_with('.') {
+                    objects.push(Object {
+                        key: format!("{prefix}{name}"),
+                        path: entry.path(),
+                    });
+                }
+            }
+        }
+
+        objects.sort_by(|left, right| left.key.cmp(&right.key));
+
+        Ok(objects)
+    }
+}
+
+/// The object's entity tag, derived from its content's hash
+async fn etag(path: &Path) -> io::Result<String> {
+    Ok(format!("\"{}\"", &sha256_of(path).await?[..32]))
+}
+
+fn iso8601(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+/// Answer S3 API calls from the spool directory (path-style, e.g.
+/// `PUT /<bucket>/<key>`), once they've been echoed (and so logged,
+/// captured, etc.) just as any other request is
+#[tracing::instrument(skip_all)]
+pub(crate) async fn serve(
+    State(s3): State<Arc<S3>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if !is_s3_call(&req) {
+        return next.run(req).await;
+    }
+
+    let (parts, body) = req.into_parts();
+
+    let body = match crate::body::buffer(&parts, body).await {
+        Ok(body) => body,
+        Err(rejection) => return rejection,
+    };
+
+    let (method, uri, headers) = (
+        parts.method.clone(),
+        parts.uri.clone(),
+        parts.headers.clone(),
+    );
+    let request_id = parts
+        .extensions
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.clone())
+        .unwrap_or_default();
+
+    let _ = next
+        .run(Request::from_parts(parts, Body::from(body.clone())))
+        .await;
+
+    let query: HashMap<String, String> =
+        serde_urlencoded::from_str(uri.query().unwrap_or_default()).unwrap_or_default();
+
+    let resource = uri.path().to_owned();
+    let (bucket, key) = match resource.trim_start_matches('/').split_once('/') {
+        Some((bucket, key)) => (bucket, Some(key).filter(|key| !key.is_empty())),
+        None => (resource.trim_start_matches('/'), None),
+    };
+
+    let (bucket, key) = match (percent_decode(bucket), key.map(percent_decode)) {
+        (Some(bucket), None) => (Some(bucket).filter(|bucket| !bucket.is_empty()), None),
+        (Some(bucket), Some(Some(key))) => (Some(bucket), Some(key)),
+        _ => {
+            return S3Error::new(
+                StatusCode::BAD_REQUEST,
+                "InvalidURI",
+                "Couldn't parse the URI",
+            )
+            .respond(&resource, &request_id, method == Method::HEAD)
+        }
+    };
+
+    let operation = match (&method, &bucket, &key) {
+        (&Method::GET, None, None) => "ListBuckets",
+        (&Method::PUT, Some(_), None) => "CreateBucket",
+        (&Method::HEAD, Some(_), None) => "HeadBucket",
+        (&Method::DELETE, Some(_), None) => "DeleteBucket",
+        (&Method::GET, Some(_), None) if query.contains_key("location") => "GetBucketLocation",
+        (&Method::GET, Some(_), None) => "ListObjects",
+        (&Method::PUT, Some(_), Some(_))
+            if query.contains_key("uploadId") || headers.contains_key("x-amz-copy-source") =>
+        {
+            "UploadPart/CopyObject"
+        }
+        (&Method::PUT, Some(_), Some(_)) => "PutObject",
+        (&Method::GET, Some(_), Some(_)) => "GetObject",
+        (&Method::HEAD, Some(_), Some(_)) => "HeadObject",
+        (&Method::DELETE, Some(_), Some(_)) => "DeleteObject",
+        _ => "",
+    };
+
+    metrics::increment_counter!("s3_requests_total", "operation" => operation);
+
+    let bucket = bucket.unwrap_or_default();
+    let key = key.unwrap_or_default();
+
+    let answered = match operation {
+        "ListBuckets" => list_buckets(&s3).await,
+        "CreateBucket" => create_bucket(&s3, &bucket).await,
+        "HeadBucket" => s3
+            .existing_bucket(&bucket)
+            .await
+            .map(|_| StatusCode::OK.into_response()),
+        "DeleteBucket" => delete_bucket(&s3, &bucket).await,
+        "GetBucketLocation" => s3
+            .existing_bucket(&bucket)
+            .await
+            .map(|_| xml(format!(r#"<LocationConstraint xmlns="{S3_NAMESPACE}"/>"#))),
+        "ListObjects" => list_objects(&s3, &bucket, &query).await,
+        "PutObject" => put_object(&s3, &bucket, &key, &headers, body).await,
+        "GetObject" => get_object(&s3, &bucket, &key, false).await,
+        "HeadObject" => get_object(&s3, &bucket, &key, true).await,
+        "DeleteObject" => delete_object(&s3, &bucket, &key).await,
+        "" => Err(S3Error::not_implemented(&format!("{method} {resource}"))),
+        operation => Err(S3Error::not_implemented(operation)),
+    };
+
+    let mut response = match answered {
+        Ok(response) => response,
+        Err(error) => {
+            tracing::debug!("S3 {operation} on {resource} failed: {}", error.message);
+            error.respond(&resource, &request_id, method == Method::HEAD)
+        }
+    };
+
+    if let Ok(value) = HeaderValue::try_from(request_id) {
+        response.headers_mut().insert("x-amz-request-id", value);
+    }
+
+    response
+}
+
+fn xml(document: String) -> Response {
+    (
+        [(header::CONTENT_TYPE, "application/xml")],
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?>{document}"#),
+    )
+        .into_response()
+}
+
+async fn list_buckets(s3: &S3) -> Result<Response, S3Error> {
+    let mut entries = tokio::fs::read_dir(s3.spool.dir())
+        .await
+        .map_err(S3Error::internal)?;
+    let mut buckets = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await.map_err(S3Error::internal)? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let metadata = entry.metadata().await.map_err(S3Error::internal)?;
+
+        if metadata.is_dir() && !name.starts_with('.') {
+            let created = metadata.created().or_else(|_| metadata.modified());
+            buckets.push((name, created.map(iso8601).unwrap_or_default()));
+        }
+    }
+
+    buckets.sort();
+
+    let buckets: String = buckets
+        .into_iter()
+        .map(|(name, created)| {
+            format!(
+                "<Bucket><Name>{}</Name><CreationDate>{created}</CreationDate></Bucket>",
+                escape(&name)
+            )
+        })
+        .collect();
+
+    Ok(xml(format!(
+        r#"<ListAllMyBucketsResult xmlns="{S3_NAMESPACE}"><Owner><ID>echo-rs</ID><DisplayName>echo-rs</DisplayName></Owner><Buckets>{buckets}</Buckets></ListAllMyBucketsResult>"#
+    )))
+}
+
+async fn create_bucket(s3: &S3, bucket: &str) -> Result<Response, S3Error> {
+    let dir = s3.bucket(bucket)?;
+
+    if tokio::fs::metadata(&dir).await.is_ok() {
+        return Err(S3Error::new(
+            StatusCode::CONFLICT,
+            "BucketAlreadyOwnedByYou",
+            format!("The bucket already exists: {bucket}"),
+        ));
+    }
+
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(S3Error::internal)?;
+
+    Ok(([(header::LOCATION, format!("/{bucket}"))], StatusCode::OK).into_response())
+}
+
+async fn delete_bucket(s3: &S3, bucket: &str) -> Result<Response, S3Error> {
+    let dir = s3.existing_bucket(bucket).await?;
+
+    let objects = {
+        let dir = dir.clone();
+        tokio::task::spawn_blocking(move || S3::objects(&dir))
+            .await
+            .map_err(|error| S3Error::internal(io::Error::other(error)))?
+            .map_err(S3Error::internal)?
+    };
+
+    if !objects.is_empty() {
+        return Err(S3Error::new(
+            StatusCode::CONFLICT,
+            "BucketNotEmpty",
+            format!("The bucket you tried to delete is not empty: {bucket}"),
+        ));
+    }
+
+    tokio::fs::remove_dir_all(&dir)
+        .await
+        .map_err(S3Error::internal)?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// `ListObjects` (or, given `list-type=2`, `ListObjectsV2`)
+async fn list_objects(
+    s3: &S3,
+    bucket: &str,
+    query: &HashMap<String, String>,
+) -> Result<Response, S3Error> {
+    let dir = s3.existing_bucket(bucket).await?;
+
+    let objects = tokio::task::spawn_blocking(move || S3::objects(&dir))
+        .await
+        .map_err(|error| S3Error::internal(io::Error::other(error)))?
+        .map_err(S3Error::internal)?;
+
+    let v2 = query.get("list-type").is_some_and(|kind| kind == "2");
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let delimiter = query
+        .get("delimiter")
+        .filter(|delimiter| !delimiter.is_empty());
+    let max_keys = query
+        .get("max-keys")
+        .and_then(|max| max.parse::<usize>().ok())
+        .map_or(MAX_KEYS, |max| max.min(MAX_KEYS));
+
+    // listings pick up after the last key (or common prefix) of the page before
+    let after = if v2 {
+        query
+            .get("continuation-token")
+            .or_else(|| query.get("start-after"))
+    } else {
+        query.get("marker")
+    }
+    .cloned()
+    .unwrap_or_default();
+
+    let (mut contents, mut prefixes, mut truncated, mut last) =
+        (Vec::new(), Vec::<String>::new(), false, None);
+
+    for object in objects {
+        if !object.key.starts_with(&prefix) || object.key <= after {
+            continue;
+        }
+
+        let common = delimiter.and_then(|delimiter| {
+            object.key[prefix.len()..]
+                .find(delimiter.as_str())
+                .map(|index| object.key[..prefix.len() + index + delimiter.len()].to_owned())
+        });
+
+        if let Some(common) = common.as_ref() {
+            if prefixes.last() == Some(common) || *common <= after {
+                continue;
+            }
+        }
+
+        if contents.len() + prefixes.len() >= max_keys {
+            truncated = true;
+            break;
+        }
+
+        match common {
+            Some(common) => {
+                last = Some(common.clone());
+                prefixes.push(common);
+            }
+            None => {
+                last = Some(object.key.clone());
+                contents.push(object);
+            }
+        }
+    }
+
+    let mut entries = String::new();
+
+    for object in contents.iter() {
+        let metadata = tokio::fs::metadata(&object.path)
+            .await
+            .map_err(S3Error::internal)?;
+
+        entries.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            escape(&object.key),
+            metadata.modified().map(iso8601).unwrap_or_default(),
+            escape(&etag(&object.path).await.map_err(S3Error::internal)?),
+            metadata.len(),
+        ));
+    }
+
+    for common in prefixes.iter() {
+        entries.push_str(&format!(
+            "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+            escape(common)
+        ));
+    }
+
+    let mut listing = format!(
+        "<Name>{}</Name><Prefix>{}</Prefix><MaxKeys>{max_keys}</MaxKeys><IsTruncated>{truncated}</IsTruncated>",
+        escape(bucket),
+        escape(&prefix),
+    );
+
+    if let Some(delimiter) = delimiter {
+        listing.push_str(&format!("<Delimiter>{}</Delimiter>", escape(delimiter)));
+    }
+
+    let next = last.filter(|_| truncated).unwrap_or_default();
+
+    if v2 {
+        listing.push_str(&format!(
+            "<KeyCount>{}</KeyCount>",
+            contents.len() + prefixes.len()
+        ));
+
+        if let Some(token) = query.get("continuation-token") {
+            listing.push_str(&format!(
+                "<ContinuationToken>{}</ContinuationToken>",
+                escape(token)
+            ));
+        }
+
+        if truncated {
+            listing.push_str(&format!(
+                "<NextContinuationToken>{}</NextContinuationToken>",
+                escape(&next)
+            ));
+        }
+    } else {
+        listing.push_str(&format!("<Marker>{}</Marker>", escape(&after)));
+
+        if truncated {
+            listing.push_str(&format!("<NextMarker>{}</NextMarker>", escape(&next)));
+        }
+    }
+
+    Ok(xml(format!(
+        r#"<ListBucketResult xmlns="{S3_NAMESPACE}">{listing}{entries}</ListBucketResult>"#
+    )))
+}
+
+async fn put_object(
+    s3: &S3,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response, S3Error> {
+    let dir = s3.existing_bucket(bucket).await?;
+    let path = s3.object(&dir, key)?;
+
+    let chunked = headers
+        .get("x-amz-content-sha256")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("STREAMING-"))
+        || headers
+            .get(header::CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("aws-chunked"));
+
+    let body = if chunked {
+        Bytes::from(
+            decode_aws_chunked(&body)
+                .map_err(|error| S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", error))?,
+        )
+    } else {
+        body
+    };
+
+    let stored = async {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let partial = s3.spool.partial(&dir, "object");
+
+        if let Err(error) = tokio::fs::write(&partial, &body).await {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(error);
+        }
+
+        tokio::fs::rename(&partial, &path).await
+    };
+
+    stored.await.map_err(S3Error::internal)?;
+
+    let etag = etag(&path).await.map_err(S3Error::internal)?;
+
+    Ok(([(header::ETAG, etag)], StatusCode::OK).into_response())
+}
+
+/// `GetObject` (or, without its body, `HeadObject`)
+async fn get_object(s3: &S3, bucket: &str, key: &str, head: bool) -> Result<Response, S3Error> {
+    let dir = s3.existing_bucket(bucket).await?;
+    let path = s3.object(&dir, key)?;
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Err(S3Error::no_such_key(key))
+        }
+        Err(error) => return Err(S3Error::internal(error)),
+    };
+
+    let metadata = file.metadata().await.map_err(S3Error::internal)?;
+
+    if !metadata.is_file() {
+        return Err(S3Error::no_such_key(key));
+    }
+
+    let mut headers = vec![
+        (
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        ),
+        (header::CONTENT_LENGTH, HeaderValue::from(metadata.len())),
+        (
+            header::ETAG,
+            HeaderValue::try_from(etag(&path).await.map_err(S3Error::internal)?)
+                .map_err(|error| S3Error::internal(io::Error::other(error)))?,
+        ),
+    ];
+
+    if let Ok(Ok(modified)) = metadata
+        .modified()
+        .map(|modified| HeaderValue::try_from(httpdate::fmt_http_date(modified)))
+    {
+        headers.push((header::LAST_MODIFIED, modified));
+    }
+
+    let mut response = if head {
+        StatusCode::OK.into_response()
+    } else {
+        StreamBody::new(ReaderStream::new(file)).into_response()
+    };
+
+    response.headers_mut().extend(headers);
+
+    Ok(response)
+}
+
+async fn delete_object(s3: &S3, bucket: &str, key: &str) -> Result<Response, S3Error> {
+    let dir = s3.existing_bucket(bucket).await?;
+    let path = s3.object(&dir, key)?;
+
+    // deleting what isn't there succeeds all the same
+    match tokio::fs::remove_file(&path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(S3Error::internal(error)),
+        _ => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An S3 emulation spooling to a fresh directory of its own
+    fn s3(name: &str) -> (S3, PathBuf) {
+        let base = std::env::temp_dir().join(format!("echo-rs-s3-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let spool = Spool::new(base.join("spool")).unwrap();
+
+        (S3::new(Arc::new(spool)), base)
+    }
+
+    #[test]
+    fn bucket_names() {
+        let valid = ["abc", "my-bucket", "my.bucket.1", &"a".repeat(63)];
+        let invalid = [
+            "ab",
+            &"a".repeat(64),
+            "My-Bucket",
+            "-bucket",
+            "bucket.",
+            "my..bucket",
+            "192.168.5.4",
+            "bucket_name",
+            "..",
+        ];
+
+        for name in valid {
+            assert!(S3::valid_bucket_name(name), "{name:?} should be valid");
+        }
+
+        for name in invalid {
+            assert!(!S3::valid_bucket_name(name), "{name:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%20b%2Fc").as_deref(), Some("a b/c"));
+        assert_eq!(percent_decode("%2e%2e").as_deref(), Some(".."));
+        assert_eq!(percent_decode("%2"), None);
+        assert_eq!(percent_decode("%zz"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn aws_chunked_bodies() {
+        let body = b"5;chunk-signature=abc\r\nhello\r\n6;chunk-signature=def\r\n world\r\n\
+            0;chunk-signature=ghi\r\nx-amz-checksum-crc32:AAAAAA==\r\n\
+            x-amz-trailer-signature:jkl\r\n\r\n";
+
+        assert_eq!(decode_aws_chunked(body).unwrap(), b"hello world");
+        assert!(decode_aws_chunked(b"5\r\nhel").is_err());
+        assert!(decode_aws_chunked(b"5\r\nhelloX").is_err());
+        assert!(decode_aws_chunked(b"zz\r\n").is_err());
+        assert!(decode_aws_chunked(b"5").is_err());
+    }
+
+    #[test]
+    fn keys_stay_in_their_bucket() {
+        let (s3, base) = s3("keys");
+        let dir = s3.bucket("bucket").unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert!(s3.object(&dir, "a/b.txt").is_ok());
+
+        let decoded = percent_decode("%2e%2e/%2e%2e/escaped").unwrap();
+
+        for key in ["..", "../escaped", &decoded, "a//b", ".hidden", "a/.part"] {
+            assert!(s3.object(&dir, key).is_err(), "{key:?} should be refused");
+        }
+
+        assert!(s3
+            .confine(dir.join("..").join("..").join("escaped"))
+            .is_err());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_stay_in_the_spool() {
+        let (s3, base) = s3("symlinks");
+        let dir = s3.bucket("bucket").unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        std::os::unix::fs::symlink(base.join("outside"), dir.join("link")).unwrap();
+
+        assert!(s3.object(&dir, "link/secret").is_err());
+        assert!(s3.object(&dir, "link").is_err());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+}
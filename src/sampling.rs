@@ -0,0 +1,87 @@
+// Exemplar Sampling
+
+// Standard Library Imports
+use std::{str::FromStr, time::Duration};
+
+// Third Party Imports
+use axum::http::StatusCode;
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// A single condition under which a request is sampled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SampleWhen {
+    /// Every request
+    All,
+    /// Requests answered with a 5xx status
+    Errors,
+    /// Requests answered with a 4xx or 5xx status
+    Failures,
+    /// Requests taking longer than the threshold to handle
+    SlowerThan(Duration),
+    /// A random fraction of requests
+    Ratio(f64),
+}
+
+impl FromStr for SampleWhen {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+
+        if let Some(threshold) = value.strip_prefix("slow>") {
+            return humantime::parse_duration(threshold.trim())
+                .map(Self::SlowerThan)
+                .map_err(|error| format!("{threshold:?}: {error}"));
+        }
+
+        if let Some(ratio) = value.strip_prefix("ratio=") {
+            return match ratio.trim().parse::<f64>() {
+                Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(Self::Ratio(ratio)),
+                _ => Err(format!("{ratio:?}: expected a ratio between 0 and 1")),
+            };
+        }
+
+        match value {
+            "all" => Ok(Self::All),
+            "errors" => Ok(Self::Errors),
+            "failures" => Ok(Self::Failures),
+            other => Err(format!(
+                "unknown sampling condition {other:?} (expected `all`, `errors`, `failures`, `slow><duration>`, or `ratio=<0..1>`)"
+            )),
+        }
+    }
+}
+
+/// Decides which requests get exemplars recorded for them
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Sampler {
+    conditions: Vec<SampleWhen>,
+}
+
+impl Sampler {
+    pub(crate) fn new(conditions: Vec<SampleWhen>) -> Self {
+        Self { conditions }
+    }
+
+    /// Whether a request answered with the given status after the given latency is sampled
+    pub(crate) fn samples(&self, status: StatusCode, latency: Duration) -> bool {
+        self.conditions.iter().any(|condition| match condition {
+            SampleWhen::All => true,
+            SampleWhen::Errors => status.is_server_error(),
+            SampleWhen::Failures => status.is_client_error() || status.is_server_error(),
+            SampleWhen::SlowerThan(threshold) => latency > *threshold,
+            SampleWhen::Ratio(ratio) => coin_flip() < *ratio,
+        })
+    }
+}
+
+/// A uniformly-distributed value in `[0, 1)`
+pub(crate) fn coin_flip() -> f64 {
+    let mut bytes = [0u8; 8];
+
+    if SystemRandom::new().fill(&mut bytes).is_err() {
+        return 1.0;
+    }
+
+    (u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,78 @@
+// Scenario Resets
+
+// Standard Library Imports
+use std::sync::Arc;
+
+// Third Party Imports
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+    fail_window::FailWindow,
+    routes::RouteRules,
+    sequence::Sequencer,
+    template::TemplateCounters,
+};
+
+/// Every piece of state that persists between requests, and so between test cases
+#[derive(Debug)]
+pub(crate) struct Scenarios {
+    pub(crate) sequencer: Arc<Sequencer>,
+    pub(crate) counters: Arc<TemplateCounters>,
+    pub(crate) fail_window: Option<Arc<FailWindow>>,
+    pub(crate) routes: RouteRules,
+}
+
+#[tracing::instrument]
+pub(crate) fn router(scenarios: Arc<Scenarios>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_scenarios/reset", routing::post(reset))
+        .route("/_scenarios/:name/reset", routing::post(reset_one))
+        .with_state(scenarios);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+/// Restore sequence numbers, template counters, route rules' sequences
+/// of responses, and the fail window to their initial state
+#[tracing::instrument(skip_all)]
+async fn reset(State(scenarios): State<Arc<Scenarios>>) -> StatusCode {
+    scenarios.sequencer.reset();
+    scenarios.counters.reset();
+    scenarios.routes.reset_sequences(None);
+
+    if let Some(window) = scenarios.fail_window.as_ref() {
+        window.reset();
+    }
+
+    tracing::info!("All scenarios reset");
+
+    StatusCode::NO_CONTENT
+}
+
+/// Restart the named template counter, and the sequence of responses of the route rule with that id
+#[tracing::instrument(skip_all)]
+async fn reset_one(State(scenarios): State<Arc<Scenarios>>, Path(name): Path<String>) -> Response {
+    let counter = scenarios.counters.reset_one(&name);
+    let sequence = scenarios.routes.reset_sequences(Some(&name));
+
+    if counter || sequence {
+        tracing::info!("Scenario {name:?} reset");
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        Failure::new(StatusCode::NOT_FOUND, format!("no such scenario: {name:?}")).into_response()
+    }
+}
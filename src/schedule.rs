@@ -0,0 +1,141 @@
+// Time-Based Schedules
+
+// Standard Library Imports
+use std::{
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Parse a period, allowing the count to be omitted for single units (e.g. `s`, `day`)
+pub(crate) fn parse_period(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+
+    let period = if value.starts_with(|char: char| char.is_ascii_digit()) {
+        humantime::parse_duration(value)
+    } else {
+        humantime::parse_duration(&format!("1{value}"))
+    }
+    .map_err(|error| format!("{value:?}: {error}"))?;
+
+    if period.is_zero() {
+        Err(format!("{value:?}: period must be non-zero"))
+    } else {
+        Ok(period)
+    }
+}
+
+/// A request budget replenished at the start of every period, e.g. `100/1m`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Quota {
+    pub(crate) limit: u64,
+    pub(crate) period: Duration,
+}
+
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (limit, period) = value
+            .split_once('/')
+            .ok_or_else(|| format!("expected `<count>/<period>`, got {value:?}"))?;
+
+        Ok(Self {
+            limit: limit
+                .trim()
+                .parse()
+                .map_err(|error| format!("{limit:?}: {error}"))?,
+            period: parse_period(period)?,
+        })
+    }
+}
+
+/// Alternating "up" and "down" phases, e.g. `30s/10s` for
+/// thirty seconds up followed by ten seconds down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Cycle {
+    pub(crate) up: Duration,
+    pub(crate) down: Duration,
+}
+
+impl FromStr for Cycle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (up, down) = value
+            .split_once('/')
+            .ok_or_else(|| format!("expected `<up>/<down>`, got {value:?}"))?;
+
+        Ok(Self {
+            up: parse_period(up)?,
+            down: parse_period(down)?,
+        })
+    }
+}
+
+impl Cycle {
+    /// The time left in the "down" phase `elapsed` into the cycle, if it is down
+    pub(crate) fn down_remaining(&self, elapsed: Duration) -> Option<Duration> {
+        let period = (self.up + self.down).as_nanos();
+        let offset = Duration::from_nanos((elapsed.as_nanos() % period) as u64);
+
+        offset
+            .checked_sub(self.up)
+            .map(|into_down| self.down - into_down)
+    }
+}
+
+/// Fixed-window counter enforcing a [`Quota`], with windows aligned to its creation
+#[derive(Debug)]
+pub(crate) struct QuotaWindow {
+    quota: Quota,
+    started: Instant,
+    state: Mutex<(u128, u64)>,
+}
+
+impl QuotaWindow {
+    pub(crate) fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            started: Instant::now(),
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Count a request against the quota, returning the remaining budget
+    /// on success or the time left until the budget is replenished if exhausted
+    pub(crate) fn acquire(&self) -> Result<u64, Duration> {
+        let elapsed = self.started.elapsed();
+        let window = elapsed.as_nanos() / self.quota.period.as_nanos();
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.0 != window {
+            *state = (window, 0);
+        }
+
+        if state.1 >= self.quota.limit {
+            return Err(self.reset_in(elapsed));
+        }
+
+        state.1 += 1;
+
+        Ok(self.quota.limit - state.1)
+    }
+
+    /// The quota being enforced
+    pub(crate) fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// The time left until the budget is next replenished
+    pub(crate) fn until_reset(&self) -> Duration {
+        self.reset_in(self.started.elapsed())
+    }
+
+    fn reset_in(&self, elapsed: Duration) -> Duration {
+        let period = self.quota.period.as_nanos();
+
+        Duration::from_nanos((period - elapsed.as_nanos() % period) as u64)
+    }
+}
@@ -0,0 +1,139 @@
+// Echo Payload Schema Versions
+
+// Standard Library Imports
+use std::collections::HashMap;
+
+// Third Party Imports
+use axum::http::{header, HeaderMap, HeaderValue};
+
+/// Layout of the echo payload, reported in it as `schema_version`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EchoSchema {
+    /// Each header as a single string (repeated headers keep their first value)
+    #[default]
+    V1,
+    /// Each header and query parameter as a list of every value it was sent with
+    V2,
+}
+
+/// The request's headers, in the shape the echo schema calls for
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(untagged)]
+pub(crate) enum EchoHeaders {
+    Single(HashMap<String, String>),
+    Multi(HashMap<String, Vec<String>>),
+}
+
+/// The request's query parameters, in the shape the echo schema calls for
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(untagged)]
+pub(crate) enum EchoParams {
+    Single(HashMap<String, String>),
+    Multi(HashMap<String, Vec<String>>),
+}
+
+/// The request's cookies, in the shape the echo schema calls for
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(untagged)]
+pub(crate) enum EchoCookies {
+    Single(HashMap<String, String>),
+    Multi(HashMap<String, Vec<String>>),
+}
+
+impl EchoCookies {
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Self::Single(cookies) => cookies.is_empty(),
+            Self::Multi(cookies) => cookies.is_empty(),
+        }
+    }
+}
+
+impl EchoSchema {
+    pub(crate) fn headers(self, headers: &HeaderMap) -> EchoHeaders {
+        let value = |value: &HeaderValue| value.to_str().unwrap_or("<non-ascii string>").to_owned();
+
+        match self {
+            Self::V1 => EchoHeaders::Single(
+                headers
+                    .keys()
+                    .filter_map(|name| Some((name.as_str().to_owned(), value(headers.get(name)?))))
+                    .collect(),
+            ),
+            Self::V2 => EchoHeaders::Multi(
+                headers
+                    .keys()
+                    .map(|name| {
+                        (
+                            name.as_str().to_owned(),
+                            headers.get_all(name).iter().map(value).collect(),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Every `name=value` pair of the request's `Cookie` header(s), where
+    /// repeated cookies keep their first value under `v1` (which, as clients
+    /// send the most specific first, is the one a server would act on)
+    pub(crate) fn cookies(self, headers: &HeaderMap) -> EchoCookies {
+        let pairs = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                let value = value.trim();
+
+                Some((
+                    name.trim().to_owned(),
+                    value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .unwrap_or(value)
+                        .to_owned(),
+                ))
+            })
+            .filter(|(name, _)| !name.is_empty());
+
+        match self {
+            Self::V1 => {
+                let mut cookies = HashMap::new();
+
+                for (name, value) in pairs {
+                    cookies.entry(name).or_insert(value);
+                }
+
+                EchoCookies::Single(cookies)
+            }
+            Self::V2 => {
+                let mut cookies = HashMap::<String, Vec<String>>::new();
+
+                for (name, value) in pairs {
+                    cookies.entry(name).or_default().push(value);
+                }
+
+                EchoCookies::Multi(cookies)
+            }
+        }
+    }
+
+    /// Repeated parameters keep their last value under `v1`
+    pub(crate) fn params(self, pairs: &[(String, String)]) -> EchoParams {
+        match self {
+            Self::V1 => EchoParams::Single(pairs.iter().cloned().collect()),
+            Self::V2 => {
+                let mut params = HashMap::<String, Vec<String>>::new();
+
+                for (name, value) in pairs {
+                    params.entry(name.clone()).or_default().push(value.clone());
+                }
+
+                EchoParams::Multi(params)
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Synthetic Self-Traffic
+
+// Standard Library Imports
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Third Party Imports
+use tokio::time::{Instant, MissedTickBehavior};
+
+// Crate-Level Imports
+use crate::{clock, schedule::Quota};
+
+/// Header marking a synthetic request with its sequence number
+pub(crate) const SELF_TRAFFIC_HEADER: &str = "x-echo-self-traffic";
+
+/// Path synthetic requests are made to, when the server makes them to itself
+pub(crate) const SELF_TRAFFIC_PATH: &str = "/heartbeat";
+
+/// How long a synthetic request may take before it's counted as an error
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Synthetic requests generated at a steady rate, as a heartbeat
+#[derive(Clone, Debug)]
+pub(crate) struct SelfTraffic {
+    /// How many requests to make, per period
+    pub(crate) rate: Quota,
+    /// URL each request is made to
+    pub(crate) url: String,
+    /// Whether to skip verifying the target's certificate
+    /// (as when the target is the server itself)
+    pub(crate) insecure: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Heartbeat {
+    sequence: u64,
+    /// When the request was sent, as an RFC 3339 timestamp
+    sent_at: String,
+}
+
+/// Make synthetic requests at the configured rate, forever
+#[tracing::instrument(skip_all)]
+pub(crate) async fn generate(traffic: SelfTraffic) {
+    let client = match reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .danger_accept_invalid_certs(traffic.insecure)
+        .timeout(TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(error) => {
+            tracing::error!("Unable to generate self-traffic: {error}");
+            return;
+        }
+    };
+
+    let spacing = (traffic.rate.period / u32::try_from(traffic.rate.limit).unwrap_or(u32::MAX))
+        .max(Duration::from_millis(1));
+
+    // the first request waits a beat, so the server's listening by the time it's sent
+    let mut interval = tokio::time::interval_at(Instant::now() + spacing, spacing);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    tracing::info!(
+        "Generating self-traffic to {} every {}",
+        traffic.url,
+        humantime::format_duration(spacing)
+    );
+
+    for sequence in 1u64.. {
+        interval.tick().await;
+
+        let heartbeat = Heartbeat {
+            sequence,
+            sent_at: humantime::format_rfc3339_millis(clock::now()).to_string(),
+        };
+
+        let started = std::time::Instant::now();
+
+        let outcome = client
+            .post(&traffic.url)
+            .header(SELF_TRAFFIC_HEADER, sequence)
+            .json(&heartbeat)
+            .send()
+            .await;
+
+        let (status, succeeded) = match outcome {
+            // read in full, so the recorded time covers the whole exchange
+            Ok(response) => {
+                let status = response.status();
+
+                match response.bytes().await {
+                    Ok(_) => (status.as_str().to_owned(), !status.is_server_error()),
+                    Err(error) => {
+                        tracing::warn!("Self-traffic request #{sequence} failed: {error}");
+                        ("error".to_owned(), false)
+                    }
+                }
+            }
+            Err(error) => {
+                tracing::warn!("Self-traffic request #{sequence} failed: {error}");
+                ("error".to_owned(), false)
+            }
+        };
+
+        metrics::increment_counter!("self_traffic_requests_total", "status" => status);
+        metrics::histogram!(
+            "self_traffic_duration_seconds",
+            started.elapsed().as_secs_f64()
+        );
+
+        if succeeded {
+            // by the system's clock, rather than any frozen one, so staleness can be alerted on
+            metrics::gauge!(
+                "self_traffic_last_success_timestamp_seconds",
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64()
+            );
+        }
+    }
+}
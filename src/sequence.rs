@@ -0,0 +1,112 @@
+// Monotonic Request Sequencing
+
+// Standard Library Imports
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+/// How many distinct paths are sequenced separately at once
+const MAX_PATHS: usize = 1_000;
+
+/// How many distinct client addresses are sequenced separately at once
+const MAX_CLIENTS: usize = 10_000;
+
+/// A request's position in each of the sequences it belongs to (all 1-based)
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+pub(crate) struct Sequence {
+    /// Position among every request the server has received
+    pub(crate) global: u64,
+    /// Position among requests for the same path
+    pub(crate) path: u64,
+    /// Position among requests from the same client address
+    pub(crate) client: u64,
+}
+
+/// A per-key sequence's latest number, and the global number it was claimed alongside
+type Counters<K> = Mutex<HashMap<K, (u64, u64)>>;
+
+/// Hands out monotonically increasing sequence numbers
+///
+/// Only so many paths and clients are tracked at once: once either limit is reached, the
+/// least recently seen key is forgotten to make room, and its sequence starts again at 1
+/// should it be seen again.
+#[derive(Debug, Default)]
+pub(crate) struct Sequencer {
+    global: AtomicU64,
+    paths: Counters<String>,
+    clients: Counters<IpAddr>,
+}
+
+impl Sequencer {
+    /// Claim the next sequence numbers for a request
+    pub(crate) fn next(&self, path: &str, client: IpAddr) -> Sequence {
+        let global = self.global.fetch_add(1, Ordering::Relaxed) + 1;
+
+        Sequence {
+            global,
+            path: increment(&self.paths, path, global, MAX_PATHS),
+            client: increment(&self.clients, &client, global, MAX_CLIENTS),
+        }
+    }
+    /// Restart every sequence from the beginning
+    pub(crate) fn reset(&self) {
+        self.global.store(0, Ordering::Relaxed);
+        self.paths.lock().unwrap().clear();
+        self.clients.lock().unwrap().clear();
+    }
+}
+
+fn increment<K, Q>(counters: &Counters<K>, key: &Q, global: u64, max: usize) -> u64
+where
+    K: Clone + Eq + Hash + Borrow<Q>,
+    Q: Eq + Hash + ToOwned<Owned = K> + ?Sized,
+{
+    let mut counters = counters.lock().unwrap();
+
+    if let Some((count, seen)) = counters.get_mut(key) {
+        *count += 1;
+        *seen = global;
+        return *count;
+    }
+
+    if counters.len() >= max {
+        let stalest = counters
+            .iter()
+            .min_by_key(|(_, (_, seen))| *seen)
+            .map(|(key, _)| key.clone());
+
+        if let Some(stalest) = stalest {
+            counters.remove(stalest.borrow());
+        }
+    }
+
+    counters.insert(key.to_owned(), (1, global));
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_stalest_key_once_full() {
+        let counters = Counters::<String>::default();
+
+        assert_eq!(increment(&counters, "a", 1, 2), 1);
+        assert_eq!(increment(&counters, "b", 2, 2), 1);
+        assert_eq!(increment(&counters, "a", 3, 2), 2);
+
+        // "b" is the stalest, so makes room for "c" and restarts once seen again
+        assert_eq!(increment(&counters, "c", 4, 2), 1);
+        assert_eq!(increment(&counters, "a", 5, 2), 3);
+        assert_eq!(increment(&counters, "b", 6, 2), 1);
+        assert_eq!(counters.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,286 @@
+// Response Shaping
+
+// Standard Library Imports
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, BoxBody, Bytes, HttpBody},
+    extract::State,
+    http::{HeaderMap, HeaderName, HeaderValue, Request, Version},
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Sleep;
+
+/// Request header overriding the delay before the response head is sent
+pub(crate) const TTFB_DELAY_HEADER: &str = "x-echo-ttfb-delay";
+
+/// Request header overriding the delay between the response head and its body
+pub(crate) const BODY_DELAY_HEADER: &str = "x-echo-body-delay";
+
+/// Request header adding trailers (as `name=value` pairs) to the response (over HTTP/2)
+pub(crate) const TRAILER_HEADER: &str = "x-echo-trailer";
+
+/// Request header overriding the rate (in bytes/sec) the response body is sent at
+pub(crate) const THROTTLE_HEADER: &str = "x-echo-throttle";
+
+/// How often a rate-limited response body is trickled out
+const THROTTLE_TICK: Duration = Duration::from_millis(100);
+
+/// Server-wide response shaping defaults
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Shaping {
+    /// Delay before the response head (status + headers) is sent
+    pub(crate) ttfb_delay: Option<Duration>,
+    /// Delay between sending the response head and the response body
+    pub(crate) body_delay: Option<Duration>,
+    /// Trailers sent after the response body
+    pub(crate) trailers: HeaderMap,
+    /// Rate (in bytes/sec) the response body is trickled out at
+    pub(crate) bytes_per_second: Option<usize>,
+}
+
+impl Shaping {
+    /// Apply any per-request overrides supplied via request headers
+    fn with_overrides(&self, headers: &HeaderMap) -> Self {
+        let mut trailers = self.trailers.clone();
+
+        trailers.extend(
+            headers
+                .get_all(TRAILER_HEADER)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(|pair| {
+                    parse_header_pair(pair)
+                        .map_err(|error| tracing::warn!("Ignoring invalid trailer: {error}"))
+                        .ok()
+                }),
+        );
+
+        Self {
+            ttfb_delay: header_duration(headers, TTFB_DELAY_HEADER).or(self.ttfb_delay),
+            body_delay: header_duration(headers, BODY_DELAY_HEADER).or(self.body_delay),
+            trailers,
+            bytes_per_second: headers
+                .get(THROTTLE_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| {
+                    parse_rate(value)
+                        .map_err(|error| {
+                            tracing::warn!("Ignoring invalid `{THROTTLE_HEADER}` value: {error}")
+                        })
+                        .ok()
+                })
+                .or(self.bytes_per_second),
+        }
+    }
+}
+
+/// Parse a `name=value` pair into a header name and value
+pub(crate) fn parse_header_pair(value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=value`, got {value:?}"))?;
+
+    Ok((
+        HeaderName::try_from(name.trim()).map_err(|error| format!("{name:?}: {error}"))?,
+        HeaderValue::try_from(value.trim()).map_err(|error| format!("{value:?}: {error}"))?,
+    ))
+}
+
+/// Parse a (non-zero) transfer rate in bytes/sec, e.g. `512`, `10k`, or `1MiB/s`
+pub(crate) fn parse_rate(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+
+    match crate::body::parse_size(value.strip_suffix("/s").unwrap_or(value))? {
+        0 => Err(format!("{value:?}: rate must be non-zero")),
+        rate => Ok(rate),
+    }
+}
+
+/// Parse a human-friendly duration (e.g. `250ms`, `1s`) from the named request header
+pub(crate) fn header_duration(headers: &HeaderMap, name: &str) -> Option<Duration> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+
+    humantime::parse_duration(value)
+        .map_err(|error| tracing::warn!("Ignoring invalid `{name}` value {value:?}: {error}"))
+        .ok()
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn shape_response<B>(
+    State(defaults): State<Shaping>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let mut shaping = defaults.with_overrides(req.headers());
+
+    // hyper only sends trailers over HTTP/2 (and later), so there's
+    // no point announcing (or holding the body open for) any otherwise
+    if req.version() < Version::HTTP_2 && !shaping.trailers.is_empty() {
+        tracing::debug!(
+            "Dropping {} trailer(s), as trailers are only sent over HTTP/2",
+            shaping.trailers.len()
+        );
+        shaping.trailers.clear();
+    }
+
+    let response = next.run(req).await;
+
+    if let Some(delay) = shaping.ttfb_delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    if shaping.body_delay.is_none()
+        && shaping.trailers.is_empty()
+        && shaping.bytes_per_second.is_none()
+    {
+        return response;
+    }
+
+    response.map(|inner| {
+        body::boxed(ShapedBody {
+            inner,
+            delay: shaping
+                .body_delay
+                .map(|delay| Box::pin(tokio::time::sleep(delay))),
+            trailers: Some(shaping.trailers).filter(|trailers| !trailers.is_empty()),
+            throttle: shaping.bytes_per_second.map(Throttle::new),
+            pending: Bytes::new(),
+            pause: None,
+        })
+    })
+}
+
+/// How a rate-limited response body is split up and paced
+#[derive(Clone, Copy, Debug)]
+struct Throttle {
+    chunk: usize,
+    interval: Duration,
+}
+
+impl Throttle {
+    fn new(bytes_per_second: usize) -> Self {
+        let chunk = (bytes_per_second as f64 * THROTTLE_TICK.as_secs_f64()).max(1.0) as usize;
+
+        Self {
+            chunk,
+            interval: Duration::from_secs_f64(chunk as f64 / bytes_per_second as f64),
+        }
+    }
+}
+
+/// Response body wrapper holding back the wrapped body's data until its
+/// delay elapses, trickling it out at the configured rate (if any), and
+/// appending any configured trailers once it's done
+pub(crate) struct ShapedBody {
+    inner: BoxBody,
+    delay: Option<Pin<Box<Sleep>>>,
+    trailers: Option<HeaderMap>,
+    throttle: Option<Throttle>,
+    /// What's yet to be sent of the wrapped body's latest chunk
+    pending: Bytes,
+    /// The pause before the next throttled chunk may be sent
+    pause: Option<Pin<Box<Sleep>>>,
+}
+
+impl std::fmt::Debug for ShapedBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShapedBody")
+            .field("delayed", &self.delay.is_some())
+            .field("trailers", &self.trailers)
+            .field("throttle", &self.throttle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HttpBody for ShapedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if let Some(delay) = self.delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
+        let Some(throttle) = self.throttle else {
+            return Pin::new(&mut self.inner).poll_data(cx);
+        };
+
+        if let Some(pause) = self.pause.as_mut() {
+            if pause.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.pause = None;
+        }
+
+        if self.pending.is_empty() {
+            match Pin::new(&mut self.inner).poll_data(cx) {
+                Poll::Ready(Some(Ok(data))) => self.pending = data,
+                polled => return polled,
+            }
+        }
+
+        let len = throttle.chunk.min(self.pending.len());
+        let chunk = self.pending.split_to(len);
+
+        self.pause = Some(Box::pin(tokio::time::sleep(throttle.interval)));
+
+        Poll::Ready(Some(Ok(chunk)))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let inner = match Pin::new(&mut self.inner).poll_trailers(cx) {
+            Poll::Ready(Ok(inner)) => inner,
+            polled => return polled,
+        };
+
+        Poll::Ready(Ok(match (inner, self.trailers.take()) {
+            (Some(mut inner), Some(trailers)) => {
+                inner.extend(trailers);
+                Some(inner)
+            }
+            (inner, trailers) => inner.or(trailers),
+        }))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.delay.is_none()
+            && self.trailers.is_none()
+            && self.pending.is_empty()
+            && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        let hint = self.inner.size_hint();
+        let pending = self.pending.len() as u64;
+
+        match hint.exact() {
+            Some(exact) => http_body::SizeHint::with_exact(exact + pending),
+            None => {
+                let mut total = http_body::SizeHint::new();
+                if let Some(upper) = hint.upper() {
+                    total.set_upper(upper + pending);
+                }
+                total.set_lower(hint.lower() + pending);
+                total
+            }
+        }
+    }
+}
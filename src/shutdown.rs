@@ -0,0 +1,194 @@
+// Graceful Shutdown
+
+// Standard Library Imports
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Bytes, HttpBody},
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use axum_server::Handle;
+use tokio::signal;
+
+// Crate-Level Imports
+use crate::admin::{self, AdminToken};
+
+/// Coordinates draining every server in the process
+#[derive(Clone, Debug)]
+pub(crate) struct Shutdown {
+    handle: Handle,
+    drain: Duration,
+    /// How long to keep accepting connections (while reporting not-ready)
+    /// before draining starts, so load balancers can stop routing here first
+    delay: Duration,
+    draining: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub(crate) fn new(drain: Duration) -> Self {
+        Self {
+            handle: Handle::new(),
+            drain,
+            delay: Duration::ZERO,
+            draining: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Whether the server has been asked to stop
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// The handle every server should be bound with
+    pub(crate) fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// Report not-ready for the shutdown delay, then stop accepting new
+    /// connections and give in-flight requests up to the drain period to complete
+    pub(crate) fn drain(&self) {
+        if self.draining.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        metrics::gauge!("shutdown_draining", 1.0);
+
+        if self.delay.is_zero() {
+            return self.close();
+        }
+
+        tracing::info!(
+            "Shutting down, reporting not-ready for {} before draining connections",
+            humantime::format_duration(self.delay)
+        );
+
+        let shutdown = self.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(shutdown.delay).await;
+            shutdown.close();
+        });
+    }
+
+    fn close(&self) {
+        tracing::info!(
+            "Draining connections (for up to {}) before exiting",
+            humantime::format_duration(self.drain)
+        );
+
+        self.handle.graceful_shutdown(Some(self.drain));
+    }
+
+    /// Drain once the process is asked to stop (via `SIGINT` or `SIGTERM`)
+    pub(crate) async fn on_signal(self) {
+        #[cfg(unix)]
+        let terminate = async {
+            match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    terminate.recv().await;
+                }
+                Err(error) => {
+                    tracing::warn!("Unable to listen for SIGTERM: {error}");
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = signal::ctrl_c() => {},
+            _ = terminate => {},
+        }
+
+        self.drain();
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(shutdown: Shutdown, admin_token: Option<AdminToken>) -> Router {
+    match admin_token {
+        None => Router::new(),
+        Some(token) => Router::new()
+            .route("/_quitquitquit", routing::post(quit))
+            .with_state(shutdown)
+            .route_layer(middleware::from_fn_with_state(token, admin::require_token)),
+    }
+}
+
+/// Envoy-style "please go away" endpoint
+#[tracing::instrument(skip_all)]
+async fn quit(State(shutdown): State<Shutdown>) -> Response {
+    // draining only starts once the acknowledgement has been written,
+    // otherwise the caller's own connection gets torn down underneath it
+    (
+        StatusCode::ACCEPTED,
+        body::boxed(DrainAfter {
+            message: Some(Bytes::from_static(b"draining\n")),
+            shutdown: Some(shutdown),
+        }),
+    )
+        .into_response()
+}
+
+/// Response body that starts draining the server once it has been fully sent
+#[derive(Debug)]
+struct DrainAfter {
+    message: Option<Bytes>,
+    shutdown: Option<Shutdown>,
+}
+
+impl HttpBody for DrainAfter {
+    type Data = Bytes;
+    type Error = std::convert::Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if let Some(message) = self.message.take() {
+            return Poll::Ready(Some(Ok(message)));
+        }
+
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.drain();
+        }
+
+        Poll::Ready(None)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+impl Drop for DrainAfter {
+    fn drop(&mut self) {
+        // the caller hung up before the acknowledgement was sent, drain anyway
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.drain();
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// SOAP / WSDL Stubs
+
+// Standard Library Imports
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+// Third Party Imports
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use quick_xml::{escape::escape, events::Event};
+use serde_json::{json, Value};
+
+// Crate-Level Imports
+use crate::{
+    body::BodyParser,
+    errors::Failure,
+    parsers::Xml,
+    template::{is_template, TemplateRequest, Templates},
+};
+
+/// Envelope namespace of SOAP 1.1
+const SOAP_11: &str = "http://schemas.xmlsoap.org/soap/envelope/";
+
+/// Envelope namespace of SOAP 1.2
+const SOAP_12: &str = "http://www.w3.org/2003/05/soap-envelope";
+
+/// Parse a SOAP action's stubbed response, as a `name=path` pair
+pub(crate) fn parse_action(value: &str) -> Result<(String, PathBuf), String> {
+    let (name, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=path`, got {value:?}"))?;
+
+    Ok((name.trim().to_owned(), PathBuf::from(path.trim())))
+}
+
+/// The SOAP version a request was made with, and its reply is given in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Version {
+    Soap11,
+    Soap12,
+}
+
+impl Version {
+    fn of(namespace: &str) -> Option<Self> {
+        match namespace {
+            SOAP_11 => Some(Self::Soap11),
+            SOAP_12 => Some(Self::Soap12),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Soap11 => "1.1",
+            Self::Soap12 => "1.2",
+        }
+    }
+
+    fn namespace(self) -> &'static str {
+        match self {
+            Self::Soap11 => SOAP_11,
+            Self::Soap12 => SOAP_12,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Soap11 => "text/xml; charset=utf-8",
+            Self::Soap12 => "application/soap+xml; charset=utf-8",
+        }
+    }
+
+    /// Wrap the given body content in an envelope
+    fn envelope(self, body: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><soap:Envelope xmlns:soap="{}"><soap:Body>{body}</soap:Body></soap:Envelope>"#,
+            self.namespace(),
+        )
+    }
+
+    /// A fault envelope, blaming either the sender (client) or the receiver (server)
+    fn fault(self, sender: bool, reason: &str) -> Response {
+        let reason = escape(reason);
+
+        let (status, fault) = match (self, sender) {
+            (Self::Soap11, sender) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    "<soap:Fault><faultcode>soap:{}</faultcode><faultstring>{reason}</faultstring></soap:Fault>",
+                    if sender { "Client" } else { "Server" },
+                ),
+            ),
+            (Self::Soap12, true) => (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    r#"<soap:Fault><soap:Code><soap:Value>soap:Sender</soap:Value></soap:Code><soap:Reason><soap:Text xml:lang="en">{reason}</soap:Text></soap:Reason></soap:Fault>"#
+                ),
+            ),
+            (Self::Soap12, false) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!(
+                    r#"<soap:Fault><soap:Code><soap:Value>soap:Receiver</soap:Value></soap:Code><soap:Reason><soap:Text xml:lang="en">{reason}</soap:Text></soap:Reason></soap:Fault>"#
+                ),
+            ),
+        };
+
+        (
+            status,
+            [(header::CONTENT_TYPE, self.content_type())],
+            self.envelope(&fault),
+        )
+            .into_response()
+    }
+}
+
+/// What a SOAP envelope says about the call it makes
+#[derive(Debug)]
+struct Envelope {
+    version: Option<Version>,
+    /// The local name of the first element in the `Body`
+    operation: Option<String>,
+    /// The first element in the `Body`, as (echo-style) JSON
+    params: Value,
+}
+
+impl Envelope {
+    fn parse(document: &[u8]) -> Result<Self, String> {
+        let mut reader = quick_xml::Reader::from_reader(document);
+        let mut buf = Vec::new();
+
+        let mut depth = 0usize;
+        let mut rooted = false;
+        let mut in_body = false;
+        let mut version = None;
+        let mut operation = None;
+        let mut span: (usize, Option<usize>) = (0, None);
+
+        loop {
+            let position = reader.buffer_position();
+
+            let (start, empty) = match reader
+                .read_event_into(&mut buf)
+                .map_err(|error| format!("at byte {}: {error}", reader.buffer_position()))?
+            {
+                Event::Start(start) => (start.into_owned(), false),
+                Event::Empty(start) => (start.into_owned(), true),
+                Event::End(_) => {
+                    if depth == 3 && in_body && span.1.is_none() {
+                        span.1 = Some(reader.buffer_position());
+                    }
+
+                    if depth == 2 {
+                        in_body = false;
+                    }
+
+                    depth = depth.saturating_sub(1);
+                    buf.clear();
+                    continue;
+                }
+                Event::Eof => break,
+                _ => {
+                    buf.clear();
+                    continue;
+                }
+            };
+
+            let local = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+
+            match depth + 1 {
+                1 => {
+                    if local != "Envelope" {
+                        return Err(format!("expected an `Envelope`, got `{local}`"));
+                    }
+
+                    rooted = true;
+
+                    let prefix = start
+                        .name()
+                        .prefix()
+                        .map(|prefix| String::from_utf8_lossy(prefix.as_ref()).into_owned());
+                    let declaration = match prefix {
+                        Some(prefix) => format!("xmlns:{prefix}"),
+                        None => "xmlns".to_owned(),
+                    };
+
+                    version = start
+                        .attributes()
+                        .flatten()
+                        .find(|attribute| attribute.key.as_ref() == declaration.as_bytes())
+                        .and_then(|attribute| {
+                            Version::of(&String::from_utf8_lossy(&attribute.value))
+                        });
+                }
+                2 => in_body = local == "Body" && !empty,
+                3 if in_body && operation.is_none() => {
+                    operation = Some(local);
+                    span = (position, empty.then(|| reader.buffer_position()));
+                }
+                _ => {}
+            }
+
+            if !empty {
+                depth += 1;
+            }
+
+            buf.clear();
+        }
+
+        if !rooted {
+            return Err("expected an `Envelope`".into());
+        }
+
+        let params = match (operation.as_ref(), span) {
+            (Some(_), (start, Some(end))) => Xml
+                .parse(&document[start..end])?
+                .as_object()
+                .and_then(|element| element.values().next().cloned())
+                .unwrap_or(Value::Null),
+            _ => Value::Null,
+        };
+
+        Ok(Self {
+            version,
+            operation,
+            params,
+        })
+    }
+}
+
+/// The SOAP action a request names, via the SOAP 1.1 `SOAPAction` header or
+/// the SOAP 1.2 `action` media type parameter
+fn requested_action(headers: &HeaderMap) -> Option<String> {
+    let action = headers
+        .get("soapaction")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .or_else(|| {
+            headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())?
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("action"))
+                .map(|(_, value)| value.to_owned())
+        })?;
+
+    let action = action.trim().trim_matches('"');
+
+    (!action.is_empty()).then(|| action.to_owned())
+}
+
+/// The stubbed WSDL and SOAP actions
+#[derive(Debug)]
+pub(crate) struct SoapStubs {
+    wsdl: Option<String>,
+    /// Envelope templates, keyed by action
+    actions: HashMap<String, String>,
+    templates: Arc<Templates>,
+}
+
+impl SoapStubs {
+    /// Load the WSDL and action templates from the given files
+    pub(crate) fn load(
+        wsdl: Option<&PathBuf>,
+        actions: &[(String, PathBuf)],
+        templates: Arc<Templates>,
+    ) -> anyhow::Result<Self> {
+        let read = |path: &PathBuf| {
+            let source = std::fs::read_to_string(path)
+                .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+            templates
+                .validate(&source)
+                .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+            anyhow::Ok(source)
+        };
+
+        Ok(Self {
+            wsdl: wsdl.map(read).transpose()?,
+            actions: actions
+                .iter()
+                .map(|(name, path)| Ok((name.clone(), read(path)?)))
+                .collect::<anyhow::Result<_>>()?,
+            templates,
+        })
+    }
+
+    /// The names of the stubbed actions
+    pub(crate) fn actions(&self) -> Vec<String> {
+        let mut actions: Vec<_> = self.actions.keys().cloned().collect();
+        actions.sort();
+        actions
+    }
+
+    pub(crate) fn has_wsdl(&self) -> bool {
+        self.wsdl.is_some()
+    }
+
+    /// The stubbed action, and its template, answering a call. The action is
+    /// looked up by the action URI named, then its last segment, then the
+    /// operation (the first element in the `Body`).
+    fn find(&self, action: Option<&str>, operation: Option<&str>) -> Option<(&str, &str)> {
+        let tail = action.map(|action| action.rsplit(['/', '#', ':']).next().unwrap_or(action));
+
+        [action, tail, operation]
+            .into_iter()
+            .flatten()
+            .find_map(|name| self.actions.get_key_value(name))
+            .map(|(name, template)| (name.as_str(), template.as_str()))
+    }
+}
+
+#[tracing::instrument(skip(stubs))]
+pub(crate) fn router(path: &str, stubs: Arc<SoapStubs>) -> Router {
+    Router::new()
+        .route(path, routing::get(wsdl).post(call))
+        .with_state(stubs)
+}
+
+/// Serve the WSDL, in answer to `GET <path>?wsdl`
+#[tracing::instrument(skip_all)]
+async fn wsdl(
+    State(stubs): State<Arc<SoapStubs>>,
+    Query(query): Query<HashMap<String, String>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    if !query.keys().any(|key| key.eq_ignore_ascii_case("wsdl")) {
+        return Failure::new(StatusCode::NOT_FOUND, "expected `?wsdl`").into_response();
+    }
+
+    let Some(source) = stubs.wsdl.as_deref() else {
+        return Failure::new(StatusCode::NOT_FOUND, "no WSDL is configured").into_response();
+    };
+
+    let document = if !is_template(source) {
+        source.to_owned()
+    } else {
+        let request = TemplateRequest::new(&method, &uri, &headers, &[]);
+
+        match stubs.templates.render(source, &request) {
+            Ok(document) => document,
+            Err(error) => {
+                return Failure::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("WSDL template failed to render: {error}"),
+                )
+                .into_response()
+            }
+        }
+    };
+
+    (
+        [(header::CONTENT_TYPE, "text/xml; charset=utf-8")],
+        document,
+    )
+        .into_response()
+}
+
+/// Answer a SOAP call with its action's rendered envelope, or a fault
+#[tracing::instrument(skip_all)]
+async fn call(
+    State(stubs): State<Arc<SoapStubs>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let action = requested_action(&headers);
+
+    // absent an envelope to go by, reply in kind with the media type
+    let fallback = match headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(media_type) if media_type.starts_with("application/soap+xml") => Version::Soap12,
+        _ => Version::Soap11,
+    };
+
+    let envelope = match Envelope::parse(&body) {
+        Ok(envelope) => envelope,
+        Err(error) => {
+            metrics::increment_counter!("soap_requests_total", "action" => "", "result" => "fault");
+            return fallback.fault(true, &format!("malformed envelope: {error}"));
+        }
+    };
+
+    let version = envelope.version.unwrap_or(fallback);
+
+    let Some((name, source)) = stubs.find(action.as_deref(), envelope.operation.as_deref()) else {
+        metrics::increment_counter!("soap_requests_total", "action" => "", "result" => "fault");
+        return version.fault(
+            true,
+            &format!(
+                "no stub for action {:?} (operation {:?})",
+                action.unwrap_or_default(),
+                envelope.operation.unwrap_or_default(),
+            ),
+        );
+    };
+
+    let request = TemplateRequest::new(&method, &uri, &headers, &body);
+    let soap = json!({
+        "action": action,
+        "operation": envelope.operation,
+        "version": version.name(),
+        "params": envelope.params,
+    });
+
+    let rendered = match stubs.templates.render_soap(source, &request, &soap) {
+        Ok(rendered) => rendered,
+        Err(error) => {
+            metrics::increment_counter!("soap_requests_total", "action" => name.to_owned(), "result" => "fault");
+            return version.fault(false, &format!("template failed to render: {error}"));
+        }
+    };
+
+    // templates may give just the `Body`'s content, or a whole envelope
+    let (document, reply) = match Envelope::parse(rendered.as_bytes()) {
+        Ok(reply) => (rendered, reply),
+        Err(_) => {
+            let document = version.envelope(&rendered);
+            let reply = Envelope::parse(document.as_bytes()).ok();
+            (
+                document,
+                reply.unwrap_or(Envelope {
+                    version: None,
+                    operation: None,
+                    params: Value::Null,
+                }),
+            )
+        }
+    };
+
+    let (status, result) = match reply.operation.as_deref() {
+        Some("Fault") => (StatusCode::INTERNAL_SERVER_ERROR, "fault"),
+        _ => (StatusCode::OK, "ok"),
+    };
+
+    metrics::increment_counter!("soap_requests_total", "action" => name.to_owned(), "result" => result);
+
+    (
+        status,
+        [(
+            header::CONTENT_TYPE,
+            reply.version.unwrap_or(version).content_type(),
+        )],
+        document,
+    )
+        .into_response()
+}
@@ -0,0 +1,265 @@
+// Stub Import & Export
+
+// Standard Library Imports
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+// Third Party Imports
+use axum::{extract::State, middleware, routing, Json, Router};
+use tokio::time::MissedTickBehavior;
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    clock,
+    config::{self, Config},
+    routes::{RouteRuleSpec, RouteRules, VirtualHostSpec},
+};
+
+/// The contents of a stub file: a single route rule, or a list of them
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum StubFile {
+    Many(Vec<RouteRuleSpec>),
+    One(Box<RouteRuleSpec>),
+}
+
+/// Read every `.yaml`, `.yml`, and `.json` stub file in the
+/// given directory, in file name order
+pub(crate) fn load_dir(dir: &Path) -> anyhow::Result<Vec<RouteRuleSpec>> {
+    let mut paths = std::fs::read_dir(dir)
+        .map_err(|error| anyhow::anyhow!("{}: {error}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    paths.retain(|path| {
+        path.is_file()
+            && path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| matches!(extension, "yaml" | "yml" | "json"))
+    });
+    paths.sort();
+
+    let mut stubs = Vec::new();
+
+    for path in paths {
+        let contents = std::fs::read_to_string(&path)
+            .and_then(|contents| config::interpolate(&contents))
+            .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?;
+
+        stubs.extend(parse(&path, &contents, dir)?);
+    }
+
+    tracing::info!("Loaded {} stub(s) from {}", stubs.len(), dir.display());
+
+    Ok(stubs)
+}
+
+/// Parse a stub file's contents, taking the body files its
+/// rules refer to as relative to the given directory
+pub(crate) fn parse(path: &Path, contents: &str, dir: &Path) -> anyhow::Result<Vec<RouteRuleSpec>> {
+    let loaded = match serde_yaml::from_str::<StubFile>(contents)
+        .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))?
+    {
+        StubFile::Many(many) => many,
+        StubFile::One(one) => vec![*one],
+    };
+
+    // body files are relative to the stubs directory, so it can be moved around wholesale
+    Ok(loaded
+        .into_iter()
+        .map(|mut stub| {
+            stub.body_file = stub.body_file.map(|file| dir.join(file));
+
+            for response in &mut stub.responses {
+                response.body_file = response.body_file.take().map(|file| dir.join(file));
+            }
+
+            stub
+        })
+        .collect())
+}
+
+/// How the last attempts to reload the route rules went
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub(crate) struct ReloadStatus {
+    /// The files (and directory) the rules are loaded from
+    sources: Vec<PathBuf>,
+    /// How many rules are in effect
+    rules: usize,
+    /// When the rules in effect were loaded
+    loaded_at: Option<String>,
+    reloads: u64,
+    failures: u64,
+    /// Why the last reload failed, if it did (the rules in effect being kept)
+    error: Option<String>,
+    failed_at: Option<String>,
+}
+
+/// Reloads the route rules of the configuration file and stubs directory
+/// whenever either changes, keeping the current ones if the new ones don't parse
+#[derive(Debug)]
+pub(crate) struct Reloader {
+    config: Option<PathBuf>,
+    dir: Option<PathBuf>,
+    routes: RouteRules,
+    status: Arc<RwLock<ReloadStatus>>,
+}
+
+impl Reloader {
+    pub(crate) fn new(config: Option<PathBuf>, dir: Option<PathBuf>, routes: RouteRules) -> Self {
+        let status = ReloadStatus {
+            sources: config.iter().chain(dir.iter()).cloned().collect(),
+            rules: routes.ids().len(),
+            loaded_at: Some(timestamp()),
+            ..ReloadStatus::default()
+        };
+
+        Self {
+            config,
+            dir,
+            routes,
+            status: Arc::new(RwLock::new(status)),
+        }
+    }
+
+    pub(crate) fn status(&self) -> Arc<RwLock<ReloadStatus>> {
+        self.status.clone()
+    }
+
+    /// The modification time of every file the rules are loaded from
+    fn modified(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let modified = |path: PathBuf| {
+            let time = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .ok();
+            (path, time)
+        };
+
+        let mut files = self
+            .dir
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .chain(self.config.iter().cloned())
+            .map(modified)
+            .collect::<Vec<_>>();
+
+        files.sort();
+        files
+    }
+
+    /// Read every rule afresh, from the configuration file then the stubs directory
+    fn load(&self) -> anyhow::Result<(Vec<RouteRuleSpec>, Vec<VirtualHostSpec>)> {
+        let config = match self.config.as_deref() {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        let stubs = match self.dir.as_deref() {
+            Some(dir) => load_dir(dir)?,
+            None => Vec::new(),
+        };
+
+        Ok((
+            config.routes.into_iter().chain(stubs).collect(),
+            config.hosts,
+        ))
+    }
+
+    /// Check for changes every `interval`, reloading when there are any, forever
+    pub(crate) async fn watch(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        let mut modified = self.modified();
+
+        loop {
+            ticker.tick().await;
+
+            let current = self.modified();
+
+            if current == modified {
+                continue;
+            }
+
+            modified = current;
+
+            let reloaded = self
+                .load()
+                .and_then(|(specs, hosts)| self.routes.replace(specs, hosts));
+
+            let mut status = self.status.write().unwrap();
+
+            match reloaded {
+                Ok(count) => {
+                    tracing::info!("Reloaded {count} route rule(s)");
+                    metrics::increment_counter!("stub_reloads_total", "result" => "success");
+
+                    status.rules = count;
+                    status.loaded_at = Some(timestamp());
+                    status.reloads += 1;
+                    status.error = None;
+                    status.failed_at = None;
+                }
+                Err(error) => {
+                    tracing::error!("Keeping current route rules, reload failed: {error}");
+                    metrics::increment_counter!("stub_reloads_total", "result" => "failure");
+
+                    status.failures += 1;
+                    status.error = Some(format!("{error:#}"));
+                    status.failed_at = Some(timestamp());
+                }
+            }
+        }
+    }
+}
+
+fn timestamp() -> String {
+    humantime::format_rfc3339_millis(clock::now()).to_string()
+}
+
+/// Export the registered (global) route rules as a JSON bundle, suitable
+/// for saving to a file in a `--stubs-dir`, and report on their reloading
+#[tracing::instrument(skip(reload_status))]
+pub(crate) fn router(
+    routes: RouteRules,
+    reload_status: Option<Arc<RwLock<ReloadStatus>>>,
+    admin_token: Option<AdminToken>,
+) -> Router {
+    let mut router = Router::new()
+        .route("/_stubs", routing::get(export))
+        .with_state(routes);
+
+    if let Some(status) = reload_status {
+        router = router.merge(
+            Router::new()
+                .route("/_stubs/status", routing::get(status_of))
+                .with_state(status),
+        );
+    }
+
+    // rules may carry credentials, so they're kept behind the admin token (if any)
+    match admin_token {
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+        None => router,
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn export(State(routes): State<RouteRules>) -> Json<Vec<RouteRuleSpec>> {
+    Json(routes.specs())
+}
+
+#[tracing::instrument(skip_all)]
+async fn status_of(State(status): State<Arc<RwLock<ReloadStatus>>>) -> Json<ReloadStatus> {
+    Json(status.read().unwrap().clone())
+}
@@ -0,0 +1,81 @@
+// Live Request Tail
+
+// Standard Library Imports
+use std::{convert::Infallible, sync::Arc};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    middleware,
+    response::sse::{Event, KeepAlive, Sse},
+    routing, Router,
+};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+
+// Crate-Level Imports
+use crate::admin::{self, AdminToken};
+
+/// How many echoes a slow subscriber can fall behind by before it starts missing them
+const CAPACITY: usize = 256;
+
+/// Fans each echoed request out to everyone tailing them
+#[derive(Debug)]
+pub(crate) struct RequestTail {
+    sender: broadcast::Sender<Arc<Value>>,
+}
+
+impl Default for RequestTail {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl RequestTail {
+    pub(crate) fn publish(&self, echo: &Value) {
+        // only bother cloning the echo if someone's actually watching
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(Arc::new(echo.clone()));
+        }
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(tail: Arc<RequestTail>, capture_token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_echo/tail", routing::get(stream))
+        .with_state(tail);
+
+    match capture_token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+/// Stream every echoed request, as it's echoed, as server-sent events
+#[tracing::instrument(skip_all)]
+async fn stream(
+    State(tail): State<Arc<RequestTail>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let echoes = BroadcastStream::new(tail.sender.subscribe()).map(|echo| {
+        Ok(match echo {
+            Ok(echo) => Event::default()
+                .event("echo")
+                .json_data(echo.as_ref())
+                .unwrap_or_else(|error| Event::default().event("error").data(error.to_string())),
+            Err(BroadcastStreamRecvError::Lagged(missed)) => {
+                Event::default().event("lagged").data(missed.to_string())
+            }
+        })
+    });
+
+    Sse::new(echoes).keep_alive(KeepAlive::default())
+}
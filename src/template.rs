@@ -0,0 +1,244 @@
+// Response Templates
+
+// Standard Library Imports
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+// Third Party Imports
+use axum::http::{HeaderMap, Method, Uri};
+use minijinja::{Environment, Error, ErrorKind};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::Value;
+
+// Crate-Level Imports
+use crate::{clock, sampling::coin_flip};
+
+/// How many instructions a single rendering (or evaluation) may execute
+const FUEL: u64 = 1_000_000;
+
+/// The longest a request's own template (or assertion) may take to render
+const OFFLOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Named, sequential counters shared by every template
+#[derive(Debug, Default)]
+pub(crate) struct TemplateCounters {
+    counters: Mutex<HashMap<String, u64>>,
+}
+
+impl TemplateCounters {
+    /// The next value of the named counter, starting from 1
+    fn next(&self, name: &str) -> u64 {
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters.entry(name.to_owned()).or_default();
+
+        *counter += 1;
+        *counter
+    }
+
+    /// Restart every counter from 1
+    pub(crate) fn reset(&self) {
+        self.counters.lock().unwrap().clear();
+    }
+
+    /// Restart the named counter from 1, returning whether it had been used
+    pub(crate) fn reset_one(&self, name: &str) -> bool {
+        self.counters.lock().unwrap().remove(name).is_some()
+    }
+}
+
+/// The request a template is rendered in response to
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct TemplateRequest {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+    /// The body, parsed as JSON if it is any, as text otherwise
+    body: Value,
+}
+
+impl TemplateRequest {
+    pub(crate) fn new(method: &Method, uri: &Uri, headers: &HeaderMap, body: &[u8]) -> Self {
+        Self {
+            method: method.to_string(),
+            path: uri.path().to_owned(),
+            query: serde_urlencoded::from_str(uri.query().unwrap_or_default()).unwrap_or_default(),
+            headers: headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                })
+                .collect(),
+            body: match serde_json::from_slice::<Value>(body) {
+                Ok(json) => json,
+                Err(_) if body.is_empty() => Value::Null,
+                Err(_) => Value::String(String::from_utf8_lossy(body).into_owned()),
+            },
+        }
+    }
+}
+
+/// Whether the given text is a template, rather than a literal
+pub(crate) fn is_template(source: &str) -> bool {
+    source.contains("{{") || source.contains("{%")
+}
+
+/// Renders canned response bodies, with helpers for dynamic values:
+///
+/// - `uuid()`: a random (v4) UUID
+/// - `now(format="rfc3339")`: the current time, as `rfc3339`, `http`, `unix`, or `unix_ms`
+/// - `random(min, max)`: a random integer in the inclusive range
+/// - `counter(name="default")`: the next value of a named counter, starting from 1
+///
+/// The request is available to templates as `request` (`method`, `path`, `query`, `headers`, `body`),
+/// or, for templates the echo is rendered with, as `echo` (the echo payload, field for field).
+#[derive(Debug)]
+pub(crate) struct Templates {
+    env: Environment<'static>,
+    pub(crate) counters: Arc<TemplateCounters>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        let counters = Arc::new(TemplateCounters::default());
+        let mut env = Environment::new();
+
+        env.set_fuel(Some(FUEL));
+        env.add_function("uuid", uuid);
+        env.add_function("now", now);
+        env.add_function("random", random);
+        env.add_function("counter", {
+            let counters = counters.clone();
+            move |name: Option<String>| counters.next(name.as_deref().unwrap_or("default"))
+        });
+
+        Self { env, counters }
+    }
+}
+
+impl Templates {
+    /// Render (or evaluate) something a request supplied itself on the blocking
+    /// pool, as it may be arbitrarily expensive, giving up on it after a while
+    pub(crate) async fn offload<T: Send + 'static>(
+        self: &Arc<Self>,
+        render: impl FnOnce(&Self) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        let templates = self.clone();
+        let rendering = tokio::task::spawn_blocking(move || render(&templates));
+
+        match tokio::time::timeout(OFFLOAD_TIMEOUT, rendering).await {
+            Ok(Ok(rendered)) => rendered,
+            Ok(Err(error)) => Err(error.to_string()),
+            Err(_) => Err(format!(
+                "gave up after {}",
+                humantime::format_duration(OFFLOAD_TIMEOUT)
+            )),
+        }
+    }
+
+    /// Check that the given template compiles
+    pub(crate) fn validate(&self, source: &str) -> anyhow::Result<()> {
+        self.env
+            .template_from_str(source)
+            .map(|_| ())
+            .map_err(|error| anyhow::anyhow!("invalid template: {error}"))
+    }
+
+    /// Render the given template in response to a request
+    pub(crate) fn render(&self, source: &str, request: &TemplateRequest) -> Result<String, String> {
+        self.env
+            .render_str(source, minijinja::context! { request })
+            .map_err(|error| error.to_string())
+    }
+
+    /// Render the given template in response to a SOAP call, described as `soap`
+    pub(crate) fn render_soap(
+        &self,
+        source: &str,
+        request: &TemplateRequest,
+        soap: &Value,
+    ) -> Result<String, String> {
+        self.env
+            .render_str(source, minijinja::context! { request, soap })
+            .map_err(|error| error.to_string())
+    }
+
+    /// Render the given template with an echo (in place of the echo itself)
+    pub(crate) fn render_echo(&self, source: &str, echo: &Value) -> Result<String, String> {
+        self.env
+            .render_str(source, minijinja::context! { echo })
+            .map_err(|error| error.to_string())
+    }
+
+    /// Evaluate the given expression (e.g. `body.id == 42`) against an echo,
+    /// whose fields are available as variables
+    pub(crate) fn evaluate(&self, expression: &str, echo: &Value) -> Result<bool, String> {
+        self.env
+            .compile_expression(expression)
+            .and_then(|compiled| compiled.eval(echo))
+            .map(|value| value.is_true())
+            .map_err(|error| error.to_string())
+    }
+}
+
+fn uuid() -> Result<String, Error> {
+    let mut bytes = [0u8; 16];
+
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::new(ErrorKind::InvalidOperation, "randomness is unavailable"))?;
+
+    // version 4, variant 1
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+fn now(format: Option<String>) -> Result<String, Error> {
+    let now = clock::now();
+    let since_epoch = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+
+    match format.as_deref().unwrap_or("rfc3339") {
+        "rfc3339" => Ok(humantime::format_rfc3339_millis(now).to_string()),
+        "http" => Ok(httpdate::fmt_http_date(now)),
+        "unix" => Ok(since_epoch.as_secs().to_string()),
+        "unix_ms" => Ok(since_epoch.as_millis().to_string()),
+        other => Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!(
+                "unknown time format {other:?} (expected `rfc3339`, `http`, `unix`, or `unix_ms`)"
+            ),
+        )),
+    }
+}
+
+fn random(min: i64, max: i64) -> Result<i64, Error> {
+    if min > max {
+        return Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("empty range: {min} > {max}"),
+        ));
+    }
+
+    let span = (max - min) as f64 + 1.0;
+
+    Ok(min + ((coin_flip() * span) as i64).min(max - min))
+}
@@ -0,0 +1,238 @@
+// 429 / Retry-After Simulation
+
+// Standard Library Imports
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::{header, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::{
+    clock,
+    errors::Failure,
+    schedule::{Cycle, Quota, QuotaWindow},
+};
+
+/// How the `Retry-After` header on throttled responses is expressed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum RetryAfterFormat {
+    /// A (whole) number of seconds to wait
+    #[default]
+    Seconds,
+    /// The HTTP-date after which to retry
+    HttpDate,
+}
+
+impl RetryAfterFormat {
+    pub(crate) fn header_value(self, wait: Duration) -> HeaderValue {
+        // never advertise a zero-second wait, as some clients spin on it
+        let wait = Duration::from_secs(wait.as_secs_f64().ceil().max(1.0) as u64);
+
+        match self {
+            Self::Seconds => HeaderValue::from(wait.as_secs()),
+            Self::HttpDate => HeaderValue::try_from(httpdate::fmt_http_date(clock::now() + wait))
+                .expect("HTTP-dates are valid header values"),
+        }
+    }
+}
+
+/// Server-wide 429 simulation, driven by a request quota and/or an up/down schedule
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    started: Instant,
+    quota: Option<QuotaWindow>,
+    schedule: Option<Cycle>,
+    format: RetryAfterFormat,
+}
+
+impl Throttle {
+    /// Create a throttle if either a quota or a schedule was actually configured
+    pub(crate) fn new(
+        quota: Option<Quota>,
+        schedule: Option<Cycle>,
+        format: RetryAfterFormat,
+    ) -> Option<Self> {
+        (quota.is_some() || schedule.is_some()).then(|| Self {
+            started: Instant::now(),
+            quota: quota.map(QuotaWindow::new),
+            schedule,
+            format,
+        })
+    }
+
+    /// Determine how long the current request's client should
+    /// wait before retrying, if it should be throttled at all
+    fn check(&self) -> Option<Duration> {
+        if let Some(wait) = self
+            .schedule
+            .and_then(|schedule| schedule.down_remaining(self.started.elapsed()))
+        {
+            return Some(wait);
+        }
+
+        self.quota.as_ref().and_then(|quota| quota.acquire().err())
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce<B>(
+    State(throttle): State<Arc<Throttle>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match throttle.check() {
+        None => next.run(req).await,
+        Some(wait) => {
+            tracing::debug!(
+                "Throttling {} {} for {}",
+                req.method(),
+                req.uri().path(),
+                humantime::format_duration(wait)
+            );
+
+            (
+                [(header::RETRY_AFTER, throttle.format.header_value(wait))],
+                Failure::new(StatusCode::TOO_MANY_REQUESTS, "too many requests"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The request budget of a quota'd path
+static RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+/// What's left of a quota'd path's request budget
+static RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+/// Seconds until a quota'd path's request budget is replenished
+static RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// A request quota shared by a path and everything beneath it, e.g. `/expensive=100/day`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PathQuota {
+    path: String,
+    quota: Quota,
+}
+
+impl PathQuota {
+    fn covers(&self, path: &str) -> bool {
+        self.path == "/"
+            || path
+                .strip_prefix(&self.path)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+impl FromStr for PathQuota {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (path, quota) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected `<path>=<count>/<period>`, got {value:?}"))?;
+
+        let path = path.trim();
+
+        if !path.starts_with('/') {
+            return Err(format!("{path:?}: paths must start with '/'"));
+        }
+
+        Ok(Self {
+            path: match path.trim_end_matches('/') {
+                "" => "/",
+                trimmed => trimmed,
+            }
+            .to_owned(),
+            quota: quota.parse()?,
+        })
+    }
+}
+
+/// Per-path request quotas, emulating a quota'd third-party API
+#[derive(Debug)]
+pub(crate) struct PathQuotas {
+    quotas: Vec<(PathQuota, QuotaWindow)>,
+    format: RetryAfterFormat,
+}
+
+impl PathQuotas {
+    /// Create the quotas if any were actually configured
+    pub(crate) fn new(mut quotas: Vec<PathQuota>, format: RetryAfterFormat) -> Option<Self> {
+        // the most specific path's quota is the one that applies
+        quotas.sort_by_key(|quota| std::cmp::Reverse(quota.path.len()));
+
+        (!quotas.is_empty()).then(|| Self {
+            quotas: quotas
+                .into_iter()
+                .map(|quota| {
+                    let window = QuotaWindow::new(quota.quota);
+                    (quota, window)
+                })
+                .collect(),
+            format,
+        })
+    }
+
+    fn window(&self, path: &str) -> Option<&QuotaWindow> {
+        self.quotas
+            .iter()
+            .find(|(quota, _)| quota.covers(path))
+            .map(|(_, window)| window)
+    }
+}
+
+/// Attach the quota's `X-RateLimit-*` headers to the response
+fn with_quota_headers(mut response: Response, window: &QuotaWindow, remaining: u64) -> Response {
+    let reset = window.until_reset().as_secs_f64().ceil() as u64;
+    let headers = response.headers_mut();
+
+    headers.insert(&RATE_LIMIT_LIMIT, HeaderValue::from(window.quota().limit));
+    headers.insert(&RATE_LIMIT_REMAINING, HeaderValue::from(remaining));
+    headers.insert(&RATE_LIMIT_RESET, HeaderValue::from(reset));
+
+    response
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce_quotas<B>(
+    State(quotas): State<Arc<PathQuotas>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(window) = quotas.window(req.uri().path()) else {
+        return next.run(req).await;
+    };
+
+    match window.acquire() {
+        Ok(remaining) => with_quota_headers(next.run(req).await, window, remaining),
+        Err(wait) => {
+            tracing::debug!(
+                "Quota exhausted for {} {}, replenished in {}",
+                req.method(),
+                req.uri().path(),
+                humantime::format_duration(wait)
+            );
+
+            with_quota_headers(
+                (
+                    [(header::RETRY_AFTER, quotas.format.header_value(wait))],
+                    Failure::new(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        format!("request quota for {} exhausted", req.uri().path()),
+                    ),
+                )
+                    .into_response(),
+                window,
+                0,
+            )
+        }
+    }
+}
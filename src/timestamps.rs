@@ -0,0 +1,231 @@
+// Signed Timestamp Skew Reporting
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Third Party Imports
+use axum::http::HeaderMap;
+
+// Crate-Level Imports
+use crate::clock;
+
+/// How a signed timestamp is written
+#[derive(Clone, Copy, Debug)]
+enum Format {
+    /// Seconds since the unix epoch
+    Unix,
+    /// The `t=` element of a `Stripe-Signature` header
+    Stripe,
+    /// AWS SigV4's basic ISO 8601 form, e.g. `20240101T000000Z`
+    Amz,
+}
+
+/// Headers carrying timestamps covered by a request's signature, and how they're written
+const SIGNED_HEADERS: &[(&str, Format)] = &[
+    ("stripe-signature", Format::Stripe),
+    ("x-amz-date", Format::Amz),
+    ("x-slack-request-timestamp", Format::Unix),
+    ("svix-timestamp", Format::Unix),
+    ("webhook-timestamp", Format::Unix),
+];
+
+/// How far signed timestamps may be from the server's clock, unless configured
+/// otherwise (i.e. the tolerance Stripe's and Slack's own libraries check with)
+pub(crate) const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// The latest timestamp that can be reported, i.e. `9999-12-31T23:59:59Z`
+const LATEST: Duration = Duration::from_secs(253_402_300_799);
+
+/// Query parameter carrying a presigned AWS URL's timestamp
+const AMZ_DATE_PARAM: &str = "X-Amz-Date";
+
+/// A timestamp a request was signed with, compared against the server's clock
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct SignedTimestamp {
+    /// The header (or query parameter) the timestamp was found in
+    source: String,
+    /// The timestamp as it was received
+    value: String,
+    /// The timestamp as RFC 3339, if it could be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    /// How far the server's clock is ahead of the timestamp (negative if it's behind)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skew_seconds: Option<f64>,
+    /// Whether the skew is within the tolerance signatures are typically checked with
+    within_tolerance: bool,
+}
+
+impl SignedTimestamp {
+    fn check(source: &str, value: &str, format: Format, tolerance: Duration) -> Self {
+        let timestamp = parse(value, format);
+        let now = clock::now();
+
+        let skew_seconds = timestamp.map(|timestamp| match now.duration_since(timestamp) {
+            Ok(behind) => behind.as_secs_f64(),
+            Err(ahead) => -ahead.duration().as_secs_f64(),
+        });
+
+        let within_tolerance =
+            skew_seconds.is_some_and(|skew| skew.abs() <= tolerance.as_secs_f64());
+
+        if !within_tolerance {
+            tracing::warn!(
+                "Signed timestamp in `{source}` ({value:?}) is {}",
+                match skew_seconds {
+                    Some(skew) => format!(
+                        "{skew:+.3}s off the server's clock (tolerance {})",
+                        humantime::format_duration(tolerance)
+                    ),
+                    None => "unreadable".to_owned(),
+                }
+            );
+            metrics::increment_counter!(
+                "signed_timestamps_out_of_tolerance_total",
+                "source" => source.to_owned()
+            );
+        }
+
+        Self {
+            source: source.to_owned(),
+            value: value.to_owned(),
+            timestamp: timestamp
+                .map(|timestamp| humantime::format_rfc3339_seconds(timestamp).to_string()),
+            skew_seconds,
+            within_tolerance,
+        }
+    }
+}
+
+/// Check every signed timestamp the request carries against the server's clock
+pub(crate) fn check(
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+    tolerance: Duration,
+) -> Vec<SignedTimestamp> {
+    let mut checked = SIGNED_HEADERS
+        .iter()
+        .filter_map(|(name, format)| {
+            let value = headers.get(*name)?.to_str().ok()?;
+            Some(SignedTimestamp::check(
+                name,
+                value.trim(),
+                *format,
+                tolerance,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(value) = params.get(AMZ_DATE_PARAM) {
+        checked.push(SignedTimestamp::check(
+            AMZ_DATE_PARAM,
+            value.trim(),
+            Format::Amz,
+            tolerance,
+        ));
+    }
+
+    checked
+}
+
+/// The timestamp, if it's readable and no later than RFC 3339 can express
+fn parse(value: &str, format: Format) -> Option<SystemTime> {
+    let unix = |seconds: &str| {
+        seconds
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .map(Duration::from_secs)
+            .filter(|since| *since <= LATEST)
+            .and_then(|since| UNIX_EPOCH.checked_add(since))
+    };
+
+    match format {
+        Format::Unix => unix(value),
+        Format::Stripe => value
+            .split(',')
+            .find_map(|element| element.trim().strip_prefix("t="))
+            .and_then(unix),
+        Format::Amz => {
+            // i.e. `YYYYMMDDTHHMMSSZ`, re-punctuated as RFC 3339
+            let (date, time) = value.strip_suffix('Z')?.split_once('T')?;
+
+            if date.len() != 8 || time.len() != 6 || !date.is_ascii() || !time.is_ascii() {
+                return None;
+            }
+
+            humantime::parse_rfc3339(&format!(
+                "{}-{}-{}T{}:{}:{}Z",
+                &date[..4],
+                &date[4..6],
+                &date[6..],
+                &time[..2],
+                &time[2..4],
+                &time[4..]
+            ))
+            .ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(value: &str, format: Format) -> Option<u64> {
+        parse(value, format).map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    #[test]
+    fn unix_timestamps() {
+        assert_eq!(secs("1700000000", Format::Unix), Some(1_700_000_000));
+        assert_eq!(secs(" 0 ", Format::Unix), Some(0));
+        assert_eq!(secs("-1", Format::Unix), None);
+        assert_eq!(secs("soon", Format::Unix), None);
+    }
+
+    #[test]
+    fn stripe_signatures() {
+        let header =
+            "t=1700000000,v1=5257a869e7ecebeda32affa62cdca3fa51cad7e77a0e56ff536d0ce8e108d8bd";
+
+        assert_eq!(secs(header, Format::Stripe), Some(1_700_000_000));
+        assert_eq!(secs("v1=abc, t=42", Format::Stripe), Some(42));
+        assert_eq!(secs("v1=abc", Format::Stripe), None);
+        assert_eq!(secs("t=,v1=abc", Format::Stripe), None);
+    }
+
+    #[test]
+    fn amz_dates() {
+        assert_eq!(secs("20231114T221320Z", Format::Amz), Some(1_700_000_000));
+
+        for value in [
+            "2023-11-14T22:13:20Z",
+            "20231114T221320",
+            "20231114T2213Z",
+            "2023111T221320Z",
+            "20231314T221320Z",
+            "202311éT221320Z",
+            "20231114T2213éZ",
+        ] {
+            assert_eq!(
+                secs(value, Format::Amz),
+                None,
+                "{value:?} should be unreadable"
+            );
+        }
+    }
+
+    #[test]
+    fn latest_timestamp() {
+        let latest = LATEST.as_secs();
+
+        assert_eq!(secs(&latest.to_string(), Format::Unix), Some(latest));
+        assert_eq!(secs(&(latest + 1).to_string(), Format::Unix), None);
+        assert_eq!(secs(&u64::MAX.to_string(), Format::Stripe), None);
+        assert_eq!(secs("99991231T235959Z", Format::Amz), Some(latest));
+    }
+}
@@ -0,0 +1,366 @@
+// TLS Configuration
+
+// Standard Library Imports
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+// Third Party Imports
+use axum_server::tls_rustls::RustlsConfig;
+use regex_lite::Regex;
+use ring::digest;
+use rustls::{
+    server::{
+        AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientCertVerifier,
+        ClientHello, NoClientAuth, ResolvesServerCert,
+    },
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, RootCertStore, ServerConfig,
+};
+use rustls_pemfile::Item;
+use tokio::{signal, time::MissedTickBehavior};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+/// Read every item out of a PEM file
+fn read_pem(path: &Path) -> anyhow::Result<Vec<Item>> {
+    std::fs::read(path)
+        .map_err(|error| anyhow::anyhow!("{}: {error}", path.display()))
+        .and_then(|pem| Ok(rustls_pemfile::read_all(&mut pem.as_slice())?))
+}
+
+/// A certificate chain and private key, both PEM-encoded
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsFiles {
+    pub(crate) cert: PathBuf,
+    pub(crate) key: PathBuf,
+}
+
+impl TlsFiles {
+    /// Load the certificate chain and key into a form rustls can serve
+    fn load(&self) -> anyhow::Result<CertifiedKey> {
+        let chain = read_pem(&self.cert)?
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::X509Certificate(der) => Some(Certificate(der)),
+                _ => None,
+            })
+            .collect::<Vec<Certificate>>();
+
+        if chain.is_empty() {
+            anyhow::bail!("{}: no certificates found", self.cert.display());
+        }
+
+        let key = read_pem(&self.key)?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("{}: no private key found", self.key.display()))?;
+
+        let key = sign::any_supported_type(&key)
+            .map_err(|error| anyhow::anyhow!("{}: {error}", self.key.display()))?;
+
+        Ok(CertifiedKey::new(chain, key))
+    }
+}
+
+/// Client certificate (i.e. mutual TLS) requirements
+#[derive(Clone, Debug)]
+pub(crate) struct ClientAuth {
+    /// PEM bundle of the CA certificate(s) client certificates must chain to
+    pub(crate) ca: PathBuf,
+    /// Whether clients may connect without presenting a certificate at all
+    pub(crate) optional: bool,
+}
+
+/// Build the verifier applied to client certificates, if they're asked for at all
+fn client_verifier(auth: Option<&ClientAuth>) -> anyhow::Result<Arc<dyn ClientCertVerifier>> {
+    let Some(auth) = auth else {
+        return Ok(NoClientAuth::boxed());
+    };
+
+    let mut roots = RootCertStore::empty();
+
+    for item in read_pem(&auth.ca)? {
+        if let Item::X509Certificate(der) = item {
+            roots
+                .add(&Certificate(der))
+                .map_err(|error| anyhow::anyhow!("{}: {error}", auth.ca.display()))?;
+        }
+    }
+
+    if roots.is_empty() {
+        anyhow::bail!("{}: no certificates found", auth.ca.display());
+    }
+
+    Ok(match auth.optional {
+        true => AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed(),
+        false => AllowAnyAuthenticatedClient::new(roots).boxed(),
+    })
+}
+
+/// The (verified) certificate a client presented during the TLS handshake
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct ClientCertificate {
+    subject: String,
+    issuer: String,
+    sans: Vec<String>,
+    serial: String,
+    not_before: String,
+    not_after: String,
+    /// Hex-encoded SHA-256 digest of the DER-encoded certificate
+    fingerprint: String,
+}
+
+impl ClientCertificate {
+    /// Describe the leaf of the supplied chain, if there is one
+    pub(crate) fn from_chain(chain: Option<&[Certificate]>) -> Option<Self> {
+        let der = chain?.first()?.0.as_slice();
+
+        let (_, cert) = X509Certificate::from_der(der)
+            .map_err(|error| tracing::warn!("Unparsable client certificate: {error}"))
+            .ok()?;
+
+        let sans = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|sans| {
+                sans.value
+                    .general_names
+                    .iter()
+                    .map(|name| match name {
+                        GeneralName::DNSName(name) => format!("DNS:{name}"),
+                        GeneralName::RFC822Name(email) => format!("email:{email}"),
+                        GeneralName::URI(uri) => format!("URI:{uri}"),
+                        GeneralName::IPAddress(ip) => <[u8; 4]>::try_from(*ip)
+                            .map(IpAddr::from)
+                            .or_else(|_| <[u8; 16]>::try_from(*ip).map(IpAddr::from))
+                            .map_or_else(|_| format!("IP:{ip:02x?}"), |ip| format!("IP:{ip}")),
+                        other => other.to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sans,
+            serial: cert.raw_serial_as_string(),
+            not_before: cert.validity().not_before.to_string(),
+            not_after: cert.validity().not_after.to_string(),
+            fingerprint: digest::digest(&digest::SHA256, der)
+                .as_ref()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        })
+    }
+}
+
+/// Picks the certificate to present based on the SNI name the client asked for
+struct SniResolver {
+    default: Arc<CertifiedKey>,
+    hosts: Vec<(Regex, Arc<CertifiedKey>)>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = hello.server_name().map(str::to_ascii_lowercase);
+
+        Some(
+            name.and_then(|name| {
+                self.hosts
+                    .iter()
+                    .find(|(pattern, _)| pattern.is_match(&name))
+                    .map(|(_, key)| key.clone())
+            })
+            .unwrap_or_else(|| self.default.clone()),
+        )
+    }
+}
+
+/// Resolve certificates by SNI, falling back to the default certificate
+fn resolver(default: &TlsFiles, hosts: Vec<(Regex, TlsFiles)>) -> anyhow::Result<Arc<SniResolver>> {
+    Ok(Arc::new(SniResolver {
+        default: Arc::new(default.load()?),
+        hosts: hosts
+            .into_iter()
+            .map(|(pattern, files)| Ok((pattern, Arc::new(files.load()?))))
+            .collect::<anyhow::Result<Vec<(Regex, Arc<CertifiedKey>)>>>()?,
+    }))
+}
+
+/// Build the server's TLS configuration, presenting per-host
+/// certificates (by SNI name) where any are configured
+pub(crate) fn server_config(
+    default: &TlsFiles,
+    hosts: Vec<(Regex, TlsFiles)>,
+    client_auth: Option<&ClientAuth>,
+) -> anyhow::Result<ServerConfig> {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(client_verifier(client_auth)?)
+        .with_cert_resolver(resolver(default, hosts)?);
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// Build the HTTP/3 (QUIC) server's TLS configuration, which requires TLS 1.3
+pub(crate) fn quic_config(
+    default: &TlsFiles,
+    hosts: Vec<(Regex, TlsFiles)>,
+    client_auth: Option<&ClientAuth>,
+) -> anyhow::Result<ServerConfig> {
+    let mut config = ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_client_cert_verifier(client_verifier(client_auth)?)
+        .with_cert_resolver(resolver(default, hosts)?);
+
+    config.alpn_protocols = vec![b"h3".to_vec()];
+
+    Ok(config)
+}
+
+/// Swaps freshly loaded certificates into the running listeners whenever
+/// the files they're loaded from change (or the process gets `SIGHUP`)
+pub(crate) struct Reloader {
+    default: TlsFiles,
+    hosts: Vec<(Regex, TlsFiles)>,
+    client_auth: Option<ClientAuth>,
+    app: RustlsConfig,
+    quic: Option<quinn::Endpoint>,
+    metrics: Option<RustlsConfig>,
+}
+
+impl Reloader {
+    pub(crate) fn new(
+        default: TlsFiles,
+        hosts: Vec<(Regex, TlsFiles)>,
+        client_auth: Option<ClientAuth>,
+        app: RustlsConfig,
+    ) -> Self {
+        Self {
+            default,
+            hosts,
+            client_auth,
+            app,
+            quic: None,
+            metrics: None,
+        }
+    }
+
+    pub(crate) fn with_quic(mut self, endpoint: quinn::Endpoint) -> Self {
+        self.quic = Some(endpoint);
+        self
+    }
+
+    pub(crate) fn with_metrics(mut self, config: RustlsConfig) -> Self {
+        self.metrics = Some(config);
+        self
+    }
+
+    /// Modification times of every file the configuration is loaded from
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        std::iter::once(&self.default)
+            .chain(self.hosts.iter().map(|(_, files)| files))
+            .flat_map(|files| [&files.cert, &files.key])
+            .chain(self.client_auth.iter().map(|auth| &auth.ca))
+            .map(|path| {
+                std::fs::metadata(path)
+                    .and_then(|meta| meta.modified())
+                    .ok()
+            })
+            .collect()
+    }
+
+    async fn reload(&self) -> anyhow::Result<()> {
+        let config = server_config(&self.default, self.hosts.clone(), self.client_auth.as_ref())?;
+
+        let quic = self
+            .quic
+            .as_ref()
+            .map(|endpoint| {
+                quic_config(&self.default, self.hosts.clone(), self.client_auth.as_ref())
+                    .map(|quic| (endpoint, quic))
+            })
+            .transpose()?;
+
+        // everything that can fail has to, before any listener is changed
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics
+                .reload_from_pem_file(&self.default.cert, &self.default.key)
+                .await?;
+        }
+
+        if let Some((endpoint, quic)) = quic {
+            endpoint.set_server_config(Some(quinn::ServerConfig::with_crypto(Arc::new(quic))));
+        }
+
+        self.app.reload_from_config(Arc::new(config));
+
+        Ok(())
+    }
+
+    /// Check for changed files every `interval`, reloading when there are any, forever
+    pub(crate) async fn watch(self, interval: Duration) {
+        #[cfg(unix)]
+        let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+            .map_err(|error| tracing::warn!("Unable to listen for SIGHUP: {error}"))
+            .ok();
+
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.tick().await;
+
+        let mut modified = self.modified();
+
+        loop {
+            #[cfg(unix)]
+            let hungup = async {
+                match hangup.as_mut() {
+                    Some(hangup) => hangup.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            #[cfg(not(unix))]
+            let hungup = std::future::pending::<Option<()>>();
+
+            let reason = tokio::select! {
+                _ = ticker.tick() => {
+                    let current = self.modified();
+
+                    if current == modified {
+                        continue;
+                    }
+
+                    modified = current;
+                    "certificate files changed"
+                }
+                _ = hungup => "SIGHUP",
+            };
+
+            match self.reload().await {
+                Ok(()) => {
+                    tracing::info!("Reloaded TLS certificates ({reason})");
+                    metrics::increment_counter!("tls_reloads_total", "result" => "success");
+                }
+                Err(error) => {
+                    tracing::warn!("Keeping current TLS certificates, reload failed: {error}");
+                    metrics::increment_counter!("tls_reloads_total", "result" => "failure");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// Per-Client Byte Accounting
+
+// Standard Library Imports
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+// Third Party Imports
+use axum::{
+    body::{self, Body, BoxBody, Bytes, HttpBody},
+    extract::{ConnectInfo, Json, Path, State},
+    http::{HeaderMap, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+use tokio_stream::StreamExt;
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    errors::Failure,
+};
+
+/// What clients and paths beyond those accounted for separately are accounted for as
+const OVERFLOW: &str = "other";
+
+/// How many distinct clients are accounted for separately, before
+/// any others are accounted for together (as `other`)
+const MAX_CLIENTS: usize = 10_000;
+
+/// How many distinct paths each client's traffic is broken down by, before
+/// any others are accounted for together (as `other`)
+const MAX_PATHS: usize = 100;
+
+/// Requests, and the body bytes received with and sent in answer to them
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+struct Tally {
+    requests: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+}
+
+impl Tally {
+    fn add(&mut self, bytes_in: u64, bytes_out: u64) {
+        self.requests += 1;
+        self.bytes_in += bytes_in;
+        self.bytes_out += bytes_out;
+    }
+}
+
+/// A client's traffic, in total and by path
+#[derive(Clone, Debug, Default, serde::Serialize)]
+struct ClientTally {
+    #[serde(flatten)]
+    total: Tally,
+    paths: BTreeMap<String, Tally>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct TrafficReport {
+    #[serde(flatten)]
+    total: Tally,
+    clients: BTreeMap<String, ClientTally>,
+}
+
+/// Per-client (and per-path) counts of the body bytes received and sent
+#[derive(Debug, Default)]
+pub(crate) struct TrafficAccounts {
+    clients: Mutex<BTreeMap<String, ClientTally>>,
+}
+
+impl TrafficAccounts {
+    fn add(&self, client: &str, path: &str, bytes_in: u64, bytes_out: u64) {
+        let mut clients = self.clients.lock().unwrap();
+
+        let client = bounded(&clients, client, MAX_CLIENTS);
+        let tally = clients.entry(client).or_default();
+
+        let path = bounded(&tally.paths, path, MAX_PATHS);
+
+        tally.total.add(bytes_in, bytes_out);
+        tally
+            .paths
+            .entry(path)
+            .or_default()
+            .add(bytes_in, bytes_out);
+    }
+}
+
+/// The key to account under, i.e. the given one, unless it's new and
+/// there are already as many as are allowed (keeping memory use in bounds)
+fn bounded<V>(accounts: &BTreeMap<String, V>, key: &str, max: usize) -> String {
+    if accounts.contains_key(key) || accounts.len() < max {
+        key.to_owned()
+    } else {
+        OVERFLOW.to_owned()
+    }
+}
+
+/// A request's traffic, added to its client's account once both its
+/// body and its response's body are done with
+#[derive(Debug)]
+struct Exchange {
+    accounts: Arc<TrafficAccounts>,
+    client: String,
+    path: String,
+    received: AtomicU64,
+    sent: AtomicU64,
+}
+
+impl Drop for Exchange {
+    fn drop(&mut self) {
+        let (received, sent) = (
+            self.received.load(Ordering::Relaxed),
+            self.sent.load(Ordering::Relaxed),
+        );
+
+        self.accounts.add(&self.client, &self.path, received, sent);
+
+        // labelled by client, the series would be unbounded, so clients are told apart in the report only
+        metrics::counter!("client_received_bytes_total", received);
+        metrics::counter!("client_sent_bytes_total", sent);
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(accounts: Arc<TrafficAccounts>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_traffic", routing::get(report).delete(reset))
+        .route("/_traffic/:client", routing::get(report_client))
+        .with_state(accounts);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+/// Every client's traffic (since the last reset)
+#[tracing::instrument(skip_all)]
+async fn report(State(accounts): State<Arc<TrafficAccounts>>) -> Json<TrafficReport> {
+    let clients = accounts.clients.lock().unwrap().clone();
+
+    let total = clients
+        .values()
+        .fold(Tally::default(), |total, client| Tally {
+            requests: total.requests + client.total.requests,
+            bytes_in: total.bytes_in + client.total.bytes_in,
+            bytes_out: total.bytes_out + client.total.bytes_out,
+        });
+
+    Json(TrafficReport { total, clients })
+}
+
+/// The given client's traffic (since the last reset)
+#[tracing::instrument(skip(accounts))]
+async fn report_client(
+    State(accounts): State<Arc<TrafficAccounts>>,
+    Path(client): Path<String>,
+) -> Response {
+    let tally = accounts.clients.lock().unwrap().get(&client).cloned();
+
+    match tally {
+        Some(tally) => Json(tally).into_response(),
+        None => Failure::new(
+            StatusCode::NOT_FOUND,
+            format!("no traffic from client {client:?}"),
+        )
+        .into_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn reset(State(accounts): State<Arc<TrafficAccounts>>) -> StatusCode {
+    accounts.clients.lock().unwrap().clear();
+
+    StatusCode::NO_CONTENT
+}
+
+/// Count the body bytes each request brings in and its response sends out,
+/// against the client it came from and the path it was made to
+#[tracing::instrument(skip_all)]
+pub(crate) async fn account(
+    State(accounts): State<Arc<TrafficAccounts>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let exchange = Arc::new(Exchange {
+        accounts,
+        // i.e. the client as identified via the PROXY protocol or trusted
+        // forwarding headers (if configured), whose layer wraps this one
+        client: req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or_else(
+                || "unknown".to_owned(),
+                |ConnectInfo(client)| client.ip().to_canonical().to_string(),
+            ),
+        path: req.uri().path().to_owned(),
+        received: AtomicU64::new(0),
+        sent: AtomicU64::new(0),
+    });
+
+    let receiving = exchange.clone();
+    let req = req.map(|body| {
+        Body::wrap_stream(body.map(move |chunk| {
+            if let Ok(chunk) = &chunk {
+                receiving
+                    .received
+                    .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            }
+
+            chunk
+        }))
+    });
+
+    next.run(req)
+        .await
+        .map(|inner| body::boxed(CountedBody { inner, exchange }))
+}
+
+/// Response body wrapper counting the bytes sent
+#[derive(Debug)]
+struct CountedBody {
+    inner: BoxBody,
+    exchange: Arc<Exchange>,
+}
+
+impl HttpBody for CountedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_data(cx);
+
+        if let Poll::Ready(Some(Ok(data))) = &polled {
+            self.exchange
+                .sent
+                .fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+
+        polled
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Pin::new(&mut self.inner).poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,159 @@
+// Proxy Request Transformations
+
+// Standard Library Imports
+use std::collections::BTreeMap;
+
+// Third Party Imports
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use regex_lite::Regex;
+
+// Crate-Level Imports
+use crate::routes::glob;
+
+/// A regular-expression substitution, where `to` may refer to
+/// capture groups in `from` as `$1`, `$name`, etc.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RewriteSpec {
+    pub(crate) from: String,
+    pub(crate) to: String,
+}
+
+/// A transformation of proxied requests as written in the configuration file
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct TransformSpec {
+    /// Path pattern the transformation applies to (defaults to every path)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) path: Option<String>,
+    /// Substitution applied to the request path (sans query)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) rewrite_path: Option<RewriteSpec>,
+    /// Headers removed from the request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) remove_headers: Vec<String>,
+    /// Substitutions applied to the values of the named request headers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) rewrite_headers: BTreeMap<String, RewriteSpec>,
+    /// Headers added to the request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) add_headers: BTreeMap<String, String>,
+}
+
+/// A compiled regular-expression substitution
+#[derive(Clone, Debug)]
+struct Rewrite {
+    from: Regex,
+    to: String,
+}
+
+impl TryFrom<RewriteSpec> for Rewrite {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: RewriteSpec) -> Result<Self, Self::Error> {
+        Ok(Self {
+            from: Regex::new(&spec.from)
+                .map_err(|error| anyhow::anyhow!("rewrite {:?}: {error}", spec.from))?,
+            to: spec.to,
+        })
+    }
+}
+
+/// A compiled transformation of proxied requests
+#[derive(Clone, Debug)]
+pub(crate) struct Transform {
+    matcher: Option<Regex>,
+    rewrite_path: Option<Rewrite>,
+    remove_headers: Vec<HeaderName>,
+    rewrite_headers: Vec<(HeaderName, Rewrite)>,
+    add_headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl TryFrom<TransformSpec> for Transform {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: TransformSpec) -> Result<Self, Self::Error> {
+        let header = |name: &str| {
+            HeaderName::try_from(name).map_err(|error| anyhow::anyhow!("header {name:?}: {error}"))
+        };
+
+        Ok(Self {
+            matcher: spec.path.as_deref().map(glob).transpose()?,
+            rewrite_path: spec.rewrite_path.map(Rewrite::try_from).transpose()?,
+            remove_headers: spec
+                .remove_headers
+                .iter()
+                .map(|name| header(name))
+                .collect::<anyhow::Result<Vec<HeaderName>>>()?,
+            rewrite_headers: spec
+                .rewrite_headers
+                .into_iter()
+                .map(|(name, rewrite)| Ok((header(&name)?, Rewrite::try_from(rewrite)?)))
+                .collect::<anyhow::Result<Vec<(HeaderName, Rewrite)>>>()?,
+            add_headers: spec
+                .add_headers
+                .iter()
+                .map(|(name, value)| {
+                    Ok((
+                        header(name)?,
+                        HeaderValue::try_from(value.as_str())
+                            .map_err(|error| anyhow::anyhow!("header {name:?}: {error}"))?,
+                    ))
+                })
+                .collect::<anyhow::Result<Vec<(HeaderName, HeaderValue)>>>()?,
+        })
+    }
+}
+
+impl Transform {
+    fn matches(&self, path: &str) -> bool {
+        self.matcher
+            .as_ref()
+            .is_none_or(|matcher| matcher.is_match(path))
+    }
+
+    /// Transform the given path and headers in place
+    fn apply(&self, path: &mut String, headers: &mut HeaderMap) {
+        if let Some(rewrite) = self.rewrite_path.as_ref() {
+            *path = rewrite.from.replace(path, rewrite.to.as_str()).into_owned();
+        }
+
+        for name in &self.remove_headers {
+            headers.remove(name);
+        }
+
+        for (name, rewrite) in &self.rewrite_headers {
+            let rewritten = headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .map(|value| rewrite.from.replace(value, rewrite.to.as_str()))
+                .filter_map(|value| HeaderValue::try_from(value.as_ref()).ok())
+                .collect::<Vec<HeaderValue>>();
+
+            headers.remove(name);
+
+            for value in rewritten {
+                headers.append(name.clone(), value);
+            }
+        }
+
+        for (name, value) in &self.add_headers {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+/// Apply every transformation matching the (progressively transformed)
+/// path, in order, returning the transformed path
+pub(crate) fn apply(transforms: &[Transform], path: &str, headers: &mut HeaderMap) -> String {
+    let mut path = path.to_owned();
+
+    for transform in transforms {
+        if transform.matches(&path) {
+            transform.apply(&mut path, headers);
+        }
+    }
+
+    path
+}
@@ -0,0 +1,106 @@
+// Unmatched-Request Tracking
+
+// Standard Library Imports
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+    middleware, routing, Router,
+};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    jwt::unix_now,
+};
+
+/// How many of the most recent unmatched requests are kept
+const CAPACITY: usize = 1_000;
+
+/// A request that matched none of the configured route rules
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct UnmatchedRequest {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) host: Option<String>,
+    /// Seconds since the unix epoch
+    pub(crate) received_at: u64,
+}
+
+/// The most recent requests to have matched no route rule
+#[derive(Debug, Default)]
+pub(crate) struct UnmatchedRequests {
+    total: AtomicU64,
+    requests: Mutex<VecDeque<UnmatchedRequest>>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+struct UnmatchedReport {
+    total: u64,
+    requests: Vec<UnmatchedRequest>,
+}
+
+impl UnmatchedRequests {
+    pub(crate) fn record(&self, method: &str, path: &str, query: Option<&str>, host: Option<&str>) {
+        tracing::warn!("{method} {path} matched no route rule");
+
+        metrics::increment_counter!("route_unmatched_requests_total", "method" => method.to_owned());
+
+        self.total.fetch_add(1, Ordering::Relaxed);
+
+        let mut requests = self.requests.lock().unwrap();
+
+        if requests.len() >= CAPACITY {
+            requests.pop_front();
+        }
+
+        requests.push_back(UnmatchedRequest {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query: query.map(str::to_owned),
+            host: host.map(str::to_owned),
+            received_at: unix_now(),
+        });
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(unmatched: Arc<UnmatchedRequests>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_requests/unmatched", routing::get(report).delete(reset))
+        .with_state(unmatched);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn report(State(unmatched): State<Arc<UnmatchedRequests>>) -> Json<UnmatchedReport> {
+    Json(UnmatchedReport {
+        total: unmatched.total.load(Ordering::Relaxed),
+        requests: unmatched.requests.lock().unwrap().iter().cloned().collect(),
+    })
+}
+
+#[tracing::instrument(skip_all)]
+async fn reset(State(unmatched): State<Arc<UnmatchedRequests>>) -> StatusCode {
+    unmatched.requests.lock().unwrap().clear();
+    unmatched.total.store(0, Ordering::Relaxed);
+
+    StatusCode::NO_CONTENT
+}
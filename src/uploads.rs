@@ -0,0 +1,344 @@
+// Upload Sink
+
+// Standard Library Imports
+use std::{
+    io,
+    path::{Path as FilePath, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    body::{Body, StreamBody},
+    extract::{Path, State},
+    http::{header, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use ring::digest::{Context, SHA256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+
+// Crate-Level Imports
+use crate::{body::BodyLimit, errors::Failure};
+
+/// A file in the spool directory
+#[derive(Clone, Debug, serde::Serialize)]
+struct Upload {
+    name: String,
+    size: u64,
+    sha256: String,
+    /// When it was last written, as an RFC 3339 timestamp
+    modified: Option<String>,
+}
+
+/// The spool directory uploaded files are written to
+#[derive(Debug)]
+pub(crate) struct Spool {
+    dir: PathBuf,
+    /// Tells apart the partial files of concurrent uploads
+    sequence: AtomicU64,
+}
+
+impl Spool {
+    /// Use (creating it, if need be) the given directory as the spool
+    pub(crate) fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .map_err(|error| anyhow::anyhow!("{}: {error}", dir.display()))?;
+
+        Ok(Self {
+            dir,
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn dir(&self) -> &FilePath {
+        &self.dir
+    }
+
+    /// The path the named upload is kept at, if the name is a plain file name
+    fn path(&self, name: &str) -> Result<PathBuf, Failure> {
+        let valid = !name.is_empty()
+            && name.len() <= 255
+            && !name.starts_with('.')
+            && !name.contains(['/', '\\', '\0']);
+
+        if valid {
+            Ok(self.dir.join(name))
+        } else {
+            Err(Failure::new(
+                StatusCode::BAD_REQUEST,
+                format!("invalid upload name: {name:?}"),
+            ))
+        }
+    }
+
+    /// Where to write an upload to the given directory as it arrives, so
+    /// it only replaces what was there once all of it has
+    pub(crate) fn partial(&self, dir: &FilePath, name: &str) -> PathBuf {
+        dir.join(format!(
+            ".{name}.{}.{}.part",
+            std::process::id(),
+            self.sequence.fetch_add(1, Ordering::Relaxed),
+        ))
+    }
+
+    /// Describe the named upload, hashing its content
+    async fn describe(&self, name: &str) -> io::Result<Upload> {
+        let path = self.dir.join(name);
+        let metadata = tokio::fs::metadata(&path).await?;
+
+        Ok(Upload {
+            name: name.to_owned(),
+            size: metadata.len(),
+            sha256: sha256_of(&path).await?,
+            modified: metadata
+                .modified()
+                .ok()
+                .map(|modified| humantime::format_rfc3339_millis(modified).to_string()),
+        })
+    }
+
+    /// The names of every upload in the spool, in order (partial uploads aside)
+    async fn names(&self) -> io::Result<Vec<String>> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        let mut names = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if !name.starts_with('.') && entry.file_type().await?.is_file() {
+                names.push(name);
+            }
+        }
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Every upload in the spool, by name
+    async fn list(&self) -> io::Result<Vec<Upload>> {
+        let names = self.names().await?;
+        let mut uploads = Vec::with_capacity(names.len());
+
+        for name in names {
+            match self.describe(&name).await {
+                Ok(upload) => uploads.push(upload),
+                // deleted since it was listed
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(uploads)
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The (hex-encoded) SHA-256 hash of the given file's content
+pub(crate) async fn sha256_of(path: &FilePath) -> io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut digest = Context::new(&SHA256);
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        match file.read(&mut buf).await? {
+            0 => break,
+            read => digest.update(&buf[..read]),
+        }
+    }
+
+    Ok(hex(digest.finish().as_ref()))
+}
+
+#[tracing::instrument]
+pub(crate) fn router(spool: Arc<Spool>) -> Router {
+    Router::new()
+        .route("/upload", routing::get(list).delete(clear))
+        .route(
+            "/upload/:name",
+            routing::put(upload).get(download).delete(remove),
+        )
+        .with_state(spool)
+}
+
+/// Stream the request's body into the spool, under the given name, replacing
+/// whatever was there only once all of it has arrived
+#[tracing::instrument(skip(spool, req))]
+async fn upload(
+    State(spool): State<Arc<Spool>>,
+    Path(name): Path<String>,
+    req: Request<Body>,
+) -> Response {
+    let path = match spool.path(&name) {
+        Ok(path) => path,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let limit = req.extensions().get::<BodyLimit>().copied();
+    let partial = spool.partial(&spool.dir, &name);
+
+    let written = async {
+        let mut file = tokio::fs::File::create(&partial).await?;
+        let mut body = req.into_body();
+        let mut digest = Context::new(&SHA256);
+        let mut size = 0u64;
+
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(io::Error::other)?;
+            size += chunk.len() as u64;
+
+            if let Some(limit) = limit.filter(|limit| size > limit.0 as u64) {
+                return Ok(Err(limit.reject()));
+            }
+
+            digest.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+
+        file.sync_all().await?;
+
+        io::Result::Ok(Ok((size, digest.finish())))
+    };
+
+    let (size, digest) = match written.await {
+        Ok(Ok(written)) => written,
+        Ok(Err(rejection)) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return rejection;
+        }
+        Err(error) => {
+            let _ = tokio::fs::remove_file(&partial).await;
+            tracing::warn!("Failed to receive upload {name:?}: {error}");
+            return Failure::new(StatusCode::BAD_REQUEST, error.to_string()).into_response();
+        }
+    };
+
+    let replaced = tokio::fs::try_exists(&path).await.unwrap_or(false);
+
+    if let Err(error) = tokio::fs::rename(&partial, &path).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        tracing::warn!("Failed to store upload {name:?}: {error}");
+        return Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response();
+    }
+
+    metrics::increment_counter!("uploads_total");
+    metrics::counter!("upload_bytes_total", size);
+
+    let modified = tokio::fs::metadata(&path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|modified| humantime::format_rfc3339_millis(modified).to_string());
+
+    (
+        if replaced {
+            StatusCode::OK
+        } else {
+            StatusCode::CREATED
+        },
+        Json(Upload {
+            name,
+            size,
+            sha256: hex(digest.as_ref()),
+            modified,
+        }),
+    )
+        .into_response()
+}
+
+/// Every upload in the spool
+#[tracing::instrument(skip_all)]
+async fn list(State(spool): State<Arc<Spool>>) -> Response {
+    match spool.list().await {
+        Ok(uploads) => Json(uploads).into_response(),
+        Err(error) => {
+            Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+/// Stream the named upload back, as it was received
+#[tracing::instrument(skip(spool))]
+async fn download(State(spool): State<Arc<Spool>>, Path(name): Path<String>) -> Response {
+    let path = match spool.path(&name) {
+        Ok(path) => path,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let opened = async {
+        let file = tokio::fs::File::open(&path).await?;
+        let length = file.metadata().await?.len();
+
+        io::Result::Ok((file, length))
+    };
+
+    match opened.await {
+        Ok((file, length)) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/octet-stream"),
+                ),
+                (header::CONTENT_LENGTH, HeaderValue::from(length)),
+            ],
+            StreamBody::new(ReaderStream::new(file)),
+        )
+            .into_response(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Failure::new(StatusCode::NOT_FOUND, format!("no upload named {name:?}")).into_response()
+        }
+        Err(error) => {
+            Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+/// Delete the named upload
+#[tracing::instrument(skip(spool))]
+async fn remove(State(spool): State<Arc<Spool>>, Path(name): Path<String>) -> Response {
+    let path = match spool.path(&name) {
+        Ok(path) => path,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    match tokio::fs::remove_file(&path).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Failure::new(StatusCode::NOT_FOUND, format!("no upload named {name:?}")).into_response()
+        }
+        Err(error) => {
+            Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
+
+/// Delete every upload in the spool
+#[tracing::instrument(skip_all)]
+async fn clear(State(spool): State<Arc<Spool>>) -> Response {
+    let cleared = async {
+        for name in spool.names().await? {
+            match tokio::fs::remove_file(spool.dir.join(name)).await {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => {}
+            }
+        }
+
+        io::Result::Ok(())
+    };
+
+    match cleared.await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) => {
+            Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// httpbin-Style Utility Endpoints
+
+// Standard Library Imports
+use std::time::Duration;
+
+// Third Party Imports
+use axum::{
+    body::{self, Bytes, Empty, Full},
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ring::rand::{SecureRandom, SystemRandom};
+
+// Crate-Level Imports
+use crate::errors::Failure;
+
+/// The longest `/delay/{seconds}` (or a delay hint) will wait
+pub(crate) const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The most `/bytes/{n}` will send
+const MAX_BYTES: usize = 10 * 1024 * 1024;
+
+/// The most lines `/stream/{n}` will send
+const MAX_STREAM_LINES: usize = 1000;
+
+/// The most hops `/redirect/{n}` will send a client through
+const MAX_REDIRECTS: usize = 100;
+
+/// A utility endpoint, and its argument
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Endpoint {
+    /// Respond with the given status code (and no body)
+    Status(StatusCode),
+    /// Echo the request after the given delay
+    Delay(Duration),
+    /// Respond with the given number of random bytes
+    Bytes(usize),
+    /// Respond with the given number of copies of the echo, one per line
+    Stream(usize),
+    /// Redirect the given number of times before echoing the request
+    Redirect(usize),
+}
+
+impl Endpoint {
+    /// The endpoint the path addresses, if any
+    fn of(path: &str) -> Option<Result<Self, String>> {
+        let (name, argument) = path.strip_prefix('/')?.split_once('/')?;
+
+        fn bounded(argument: &str, max: usize) -> Result<usize, String> {
+            match argument.parse::<usize>() {
+                Ok(value) if value <= max => Ok(value),
+                Ok(_) => Err(format!("{argument} exceeds the limit of {max}")),
+                Err(error) => Err(format!("invalid count {argument:?}: {error}")),
+            }
+        }
+
+        Some(match name {
+            "status" => argument
+                .parse::<u16>()
+                .ok()
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .map(Self::Status)
+                .ok_or_else(|| format!("invalid status code {argument:?}")),
+            "delay" => argument
+                .parse::<f64>()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .map(|delay| Self::Delay(delay.min(MAX_DELAY)))
+                .ok_or_else(|| format!("invalid delay {argument:?}")),
+            "bytes" => bounded(argument, MAX_BYTES).map(Self::Bytes),
+            "stream" => bounded(argument, MAX_STREAM_LINES).map(Self::Stream),
+            "redirect" => bounded(argument, MAX_REDIRECTS).map(Self::Redirect),
+            _ => return None,
+        })
+    }
+}
+
+/// Answer `/status/{code}`, `/delay/{seconds}`, `/bytes/{n}`, `/stream/{n}`,
+/// and `/redirect/{n}` as httpbin would, once they've been handled (and so
+/// logged) as usual
+#[tracing::instrument(skip_all)]
+pub(crate) async fn serve<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    let endpoint = match Endpoint::of(req.uri().path()) {
+        None => return next.run(req).await,
+        Some(Ok(endpoint)) => endpoint,
+        Some(Err(error)) => return Failure::new(StatusCode::BAD_REQUEST, error).into_response(),
+    };
+
+    match endpoint {
+        Endpoint::Delay(delay) => {
+            tokio::time::sleep(delay).await;
+            next.run(req).await
+        }
+        Endpoint::Stream(lines) => {
+            // the echo is repeated verbatim, so it mustn't be compressed
+            req.headers_mut().remove(header::ACCEPT_ENCODING);
+
+            let response = next.run(req).await;
+
+            if !response.status().is_success() {
+                return response;
+            }
+
+            let (mut parts, echo) = response.into_parts();
+
+            let echo = match hyper::body::to_bytes(echo).await {
+                Ok(echo) => echo,
+                Err(error) => {
+                    return Failure::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+                        .into_response()
+                }
+            };
+
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/x-ndjson"),
+            );
+
+            Response::from_parts(parts, body::boxed(Full::new(stream(&echo, lines))))
+        }
+        Endpoint::Status(status) => {
+            let _ = next.run(req).await;
+            let mut response = Response::new(body::boxed(Empty::new()));
+            *response.status_mut() = status;
+            response
+        }
+        Endpoint::Bytes(len) => {
+            let _ = next.run(req).await;
+            let mut bytes = vec![0u8; len];
+
+            SystemRandom::new()
+                .fill(&mut bytes)
+                .expect("system randomness is available");
+
+            (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/octet-stream"),
+                )],
+                bytes,
+            )
+                .into_response()
+        }
+        Endpoint::Redirect(hops) => {
+            let _ = next.run(req).await;
+            let location = match hops {
+                0 | 1 => "/".to_owned(),
+                hops => format!("/redirect/{}", hops - 1),
+            };
+
+            (
+                StatusCode::FOUND,
+                [(header::LOCATION, location)],
+                body::boxed(Empty::new()),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// The echo, repeated once per line (numbered by an `id` field, if it's a JSON object)
+fn stream(echo: &[u8], lines: usize) -> Bytes {
+    let object = serde_json::from_slice::<serde_json::Value>(echo)
+        .ok()
+        .filter(serde_json::Value::is_object);
+
+    let mut stream = Vec::with_capacity((echo.len() + 16) * lines);
+
+    for id in 0..lines {
+        match object.clone() {
+            Some(mut object) => {
+                object["id"] = id.into();
+                serde_json::to_writer(&mut stream, &object).expect("echoes are serializable");
+            }
+            None => stream.extend(echo.iter().filter(|byte| **byte != b'\n')),
+        }
+
+        stream.push(b'\n');
+    }
+
+    stream.into()
+}
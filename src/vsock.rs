@@ -0,0 +1,40 @@
+// AF_VSOCK Listener
+
+// Standard Library Imports
+use std::net::{Ipv4Addr, SocketAddr};
+
+// Third Party Imports
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper::server::conn::Http;
+use tokio_vsock::{VsockAddr, VsockListener, VMADDR_CID_ANY};
+
+/// Serve the app on the given vsock port (of any CID), for as long as the process runs
+#[tracing::instrument(skip(app))]
+pub(crate) async fn serve(port: u32, app: Router) -> anyhow::Result<()> {
+    // vsock peers have a CID rather than an IP address, but the echo handler wants one all the same
+    let app = app.layer(Extension(ConnectInfo(SocketAddr::from((
+        Ipv4Addr::UNSPECIFIED,
+        0,
+    )))));
+
+    let listener = VsockListener::bind(VsockAddr::new(VMADDR_CID_ANY, port))?;
+
+    tracing::info!("`echo-rs` server listening at: vsock://*:{port}");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let app = app.clone();
+
+        tracing::debug!("Accepted vsock connection from {peer}");
+
+        tokio::spawn(async move {
+            if let Err(error) = Http::new()
+                .serve_connection(stream, app)
+                .with_upgrades()
+                .await
+            {
+                tracing::debug!("Vsock connection from {peer} failed: {error}");
+            }
+        });
+    }
+}
@@ -0,0 +1,122 @@
+// Cold-Start Simulation
+
+// Standard Library Imports
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+// Third Party Imports
+use axum::{
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Crate-Level Imports
+use crate::{errors::Failure, throttle::RetryAfterFormat};
+
+/// How requests are treated while the server warms up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum WarmupMode {
+    /// Serve requests slowly, less so as the warm-up goes on
+    #[default]
+    Slow,
+    /// Respond with 503 until the warm-up is over
+    Unavailable,
+}
+
+/// A period after startup during which the server performs poorly,
+/// as a freshly-started JVM (or similar) would
+#[derive(Debug)]
+pub(crate) struct Warmup {
+    started: Instant,
+    period: Duration,
+    mode: WarmupMode,
+    latency: Duration,
+    format: RetryAfterFormat,
+    warm: AtomicBool,
+}
+
+impl Warmup {
+    /// Create a warm-up if a (non-zero) period was actually configured
+    pub(crate) fn new(
+        period: Option<Duration>,
+        mode: WarmupMode,
+        latency: Duration,
+        format: RetryAfterFormat,
+    ) -> Option<Self> {
+        period
+            .filter(|period| !period.is_zero())
+            .map(|period| Self {
+                started: Instant::now(),
+                period,
+                mode,
+                latency,
+                format,
+                warm: AtomicBool::new(false),
+            })
+    }
+
+    /// What's left of the warm-up, if it isn't over
+    fn remaining(&self) -> Option<Duration> {
+        if self.warm.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let remaining = self.period.checked_sub(self.started.elapsed())?;
+
+        Some(remaining).filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Note (once) that the warm-up is over
+    fn finish(&self) {
+        if !self.warm.swap(true, Ordering::Relaxed) {
+            tracing::info!(
+                "Warm-up complete after {}",
+                humantime::format_duration(self.period)
+            );
+        }
+    }
+}
+
+#[tracing::instrument(skip_all)]
+pub(crate) async fn enforce<B>(
+    State(warmup): State<Arc<Warmup>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(remaining) = warmup.remaining() else {
+        warmup.finish();
+        return next.run(req).await;
+    };
+
+    match warmup.mode {
+        WarmupMode::Slow => {
+            // tapering off linearly, from the full latency at startup to none once warm
+            let delay = warmup
+                .latency
+                .mul_f64(remaining.as_secs_f64() / warmup.period.as_secs_f64());
+
+            tracing::debug!(
+                "Warming up, delaying {} {} by {}",
+                req.method(),
+                req.uri().path(),
+                humantime::format_duration(delay)
+            );
+
+            tokio::time::sleep(delay).await;
+
+            next.run(req).await
+        }
+        WarmupMode::Unavailable => (
+            [(header::RETRY_AFTER, warmup.format.header_value(remaining))],
+            Failure::new(StatusCode::SERVICE_UNAVAILABLE, "warming up"),
+        )
+            .into_response(),
+    }
+}
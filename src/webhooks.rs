@@ -0,0 +1,299 @@
+// Webhook Delivery Retry Tracking
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+// Third Party Imports
+use axum::{
+    body::Body,
+    extract::{Json, Path, State},
+    http::{HeaderName, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing, Router,
+};
+
+// Crate-Level Imports
+use crate::{
+    admin::{self, AdminToken},
+    chaos::{Injected, Reset},
+    clock,
+    errors::Failure,
+};
+
+/// How many of the most recently first-seen deliveries are tracked
+const CAPACITY: usize = 1_000;
+
+/// Headers well-known webhook producers identify each delivery by (which
+/// retries repeat), along with the name of the producer they belong to
+const DELIVERY_HEADERS: &[(&str, &str)] = &[
+    ("x-github-delivery", "github"),
+    ("x-gitlab-event-uuid", "gitlab"),
+    ("x-shopify-webhook-id", "shopify"),
+    ("svix-id", "svix"),
+    ("webhook-id", "standard-webhooks"),
+    ("i-twilio-idempotency-token", "twilio"),
+];
+
+/// How many of a delivery's most recent attempts are kept
+const MAX_ATTEMPTS: usize = 100;
+
+/// A single attempt at a delivery
+#[derive(Clone, Debug, serde::Serialize)]
+struct Attempt {
+    /// 1-based
+    attempt: usize,
+    /// When the attempt was received, as an RFC 3339 timestamp
+    received_at: String,
+    /// The status the attempt was answered with (absent if its connection was reset instead)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    /// Whether the answer was an injected fault
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    injected: bool,
+    /// Seconds since the previous attempt (i.e. the producer's backoff)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backoff_seconds: Option<f64>,
+    #[serde(skip)]
+    received: Instant,
+}
+
+/// Every attempt at delivering a webhook, identified by its delivery ID
+#[derive(Clone, Debug, serde::Serialize)]
+struct Delivery {
+    id: String,
+    provider: String,
+    method: String,
+    path: String,
+    attempts: Vec<Attempt>,
+    /// Whether any attempt was answered with a success (`2xx`) status
+    delivered: bool,
+}
+
+/// A delivery, sans its attempts
+#[derive(Clone, Debug, serde::Serialize)]
+struct DeliverySummary {
+    id: String,
+    provider: String,
+    path: String,
+    attempts: usize,
+    delivered: bool,
+    first_attempt_at: String,
+    last_attempt_at: String,
+}
+
+impl From<&Delivery> for DeliverySummary {
+    fn from(delivery: &Delivery) -> Self {
+        let at = |attempt: Option<&Attempt>| {
+            attempt.map_or_else(String::new, |attempt| attempt.received_at.clone())
+        };
+
+        Self {
+            id: delivery.id.clone(),
+            provider: delivery.provider.clone(),
+            path: delivery.path.clone(),
+            attempts: delivery
+                .attempts
+                .last()
+                .map_or(0, |attempt| attempt.attempt),
+            delivered: delivery.delivered,
+            first_attempt_at: at(delivery.attempts.first()),
+            last_attempt_at: at(delivery.attempts.last()),
+        }
+    }
+}
+
+/// Webhook deliveries (and their retries), by delivery ID
+#[derive(Debug)]
+pub(crate) struct WebhookDeliveries {
+    /// Headers identifying deliveries, and the producer each belongs to
+    headers: Vec<(HeaderName, String)>,
+    deliveries: Mutex<HashMap<String, Delivery>>,
+}
+
+impl WebhookDeliveries {
+    /// Track deliveries identified by the well-known producers' headers, plus the given ones
+    pub(crate) fn new(extra: &[String]) -> anyhow::Result<Self> {
+        let mut headers = DELIVERY_HEADERS
+            .iter()
+            .map(|(name, provider)| (HeaderName::from_static(name), (*provider).to_owned()))
+            .collect::<Vec<_>>();
+
+        for name in extra {
+            let header = HeaderName::try_from(name.trim())
+                .map_err(|error| anyhow::anyhow!("webhook delivery header {name:?}: {error}"))?;
+
+            headers.push((header.clone(), header.as_str().to_owned()));
+        }
+
+        Ok(Self {
+            headers,
+            deliveries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The delivery ID the request carries, and the producer it belongs to
+    fn identify<B>(&self, req: &Request<B>) -> Option<(String, String)> {
+        self.headers.iter().find_map(|(header, provider)| {
+            let id = req.headers().get(header)?.to_str().ok()?.trim();
+            (!id.is_empty()).then(|| (id.to_owned(), provider.clone()))
+        })
+    }
+
+    fn record(&self, delivery: Delivery, status: Option<u16>, injected: bool) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+
+        if !deliveries.contains_key(&delivery.id) && deliveries.len() >= CAPACITY {
+            let oldest = deliveries
+                .values()
+                .min_by_key(|delivery| delivery.attempts.first().map(|attempt| attempt.received))
+                .map(|delivery| delivery.id.clone());
+
+            if let Some(oldest) = oldest {
+                deliveries.remove(&oldest);
+            }
+        }
+
+        let tracked = deliveries.entry(delivery.id.clone()).or_insert(delivery);
+
+        let received = Instant::now();
+        let backoff = tracked
+            .attempts
+            .last()
+            .map(|previous| received.duration_since(previous.received));
+
+        if let Some(backoff) = backoff {
+            metrics::histogram!(
+                "webhook_retry_backoff_seconds",
+                backoff.as_secs_f64(),
+                "provider" => tracked.provider.clone()
+            );
+        }
+
+        metrics::increment_counter!(
+            "webhook_delivery_attempts_total",
+            "provider" => tracked.provider.clone(),
+            "status" => status.map_or_else(|| "reset".to_owned(), |status| status.to_string())
+        );
+
+        let attempt = tracked
+            .attempts
+            .last()
+            .map_or(1, |previous| previous.attempt + 1);
+
+        if tracked.attempts.len() >= MAX_ATTEMPTS {
+            tracked.attempts.remove(0);
+        }
+
+        tracked.delivered |= status.is_some_and(|status| (200..300).contains(&status));
+        tracked.attempts.push(Attempt {
+            attempt,
+            received_at: humantime::format_rfc3339_millis(clock::now()).to_string(),
+            status,
+            injected,
+            backoff_seconds: backoff.map(|backoff| backoff.as_secs_f64()),
+            received,
+        });
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router(deliveries: Arc<WebhookDeliveries>, token: Option<AdminToken>) -> Router {
+    let router = Router::new()
+        .route("/_webhooks", routing::get(report).delete(reset))
+        .route("/_webhooks/:id", routing::get(timeline))
+        .with_state(deliveries);
+
+    match token {
+        None => router,
+        Some(token) => {
+            router.route_layer(middleware::from_fn_with_state(token, admin::require_token))
+        }
+    }
+}
+
+/// Every tracked delivery, most recently attempted first
+#[tracing::instrument(skip_all)]
+async fn report(State(deliveries): State<Arc<WebhookDeliveries>>) -> Json<Vec<DeliverySummary>> {
+    let deliveries = deliveries.deliveries.lock().unwrap();
+
+    let mut summaries = deliveries
+        .values()
+        .map(|delivery| {
+            (
+                delivery.attempts.last().map(|attempt| attempt.received),
+                DeliverySummary::from(delivery),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    summaries.sort_by(|(left, _), (right, _)| right.cmp(left));
+
+    Json(summaries.into_iter().map(|(_, summary)| summary).collect())
+}
+
+/// Every attempt at the given delivery
+#[tracing::instrument(skip(deliveries))]
+async fn timeline(
+    State(deliveries): State<Arc<WebhookDeliveries>>,
+    Path(id): Path<String>,
+) -> Response {
+    let delivery = deliveries.deliveries.lock().unwrap().get(&id).cloned();
+
+    match delivery {
+        Some(delivery) => Json(delivery).into_response(),
+        None => Failure::new(
+            StatusCode::NOT_FOUND,
+            format!("no webhook delivery with id {id:?}"),
+        )
+        .into_response(),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn reset(State(deliveries): State<Arc<WebhookDeliveries>>) -> StatusCode {
+    deliveries.deliveries.lock().unwrap().clear();
+
+    StatusCode::NO_CONTENT
+}
+
+/// Record each attempt at a webhook delivery, along with what it was answered with
+#[tracing::instrument(skip_all)]
+pub(crate) async fn track(
+    State(deliveries): State<Arc<WebhookDeliveries>>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let Some((id, provider)) = deliveries.identify(&req) else {
+        return next.run(req).await;
+    };
+
+    let delivery = Delivery {
+        id,
+        provider,
+        method: req.method().to_string(),
+        path: req.uri().path().to_owned(),
+        attempts: Vec::new(),
+        delivered: false,
+    };
+
+    let response = next.run(req).await;
+
+    let status = response
+        .extensions()
+        .get::<Reset>()
+        .is_none()
+        .then(|| response.status().as_u16());
+
+    deliveries.record(
+        delivery,
+        status,
+        response.extensions().get::<Injected>().is_some(),
+    );
+
+    response
+}
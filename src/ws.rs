@@ -0,0 +1,280 @@
+// WebSocket Echo and Broadcast Rooms
+
+// Standard Library Imports
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// Third Party Imports
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::{header, HeaderMap, HeaderName},
+    response::Response,
+    routing, Router,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Normal closure, per RFC 6455
+const NORMAL_CLOSURE: u16 = 1000;
+
+/// How many messages a slow member can fall behind by before it starts missing them
+const ROOM_CAPACITY: usize = 256;
+
+/// How a session should be ended by the server, if at all
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+struct SessionParams {
+    /// Close the session once this many messages have been echoed
+    close_after: Option<u64>,
+    /// Code to close the session with (1000, by default)
+    close_code: Option<u16>,
+}
+
+/// What was negotiated for (and what became of) a session
+#[derive(Clone, Debug, Default, serde::Serialize)]
+struct Summary {
+    /// The subprotocol the session speaks, i.e. the client's first choice
+    protocol: Option<String>,
+    offered_protocols: Vec<String>,
+    /// Extensions (e.g. `permessage-deflate`) offered by the client,
+    /// none of which are accepted
+    offered_extensions: Vec<String>,
+    extensions: Vec<String>,
+    messages: u64,
+    bytes: u64,
+    closed_by: Option<&'static str>,
+    close_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    close_reason: Option<String>,
+}
+
+impl Summary {
+    fn close(&mut self, by: &'static str, frame: Option<&CloseFrame<'_>>) {
+        self.closed_by = Some(by);
+        self.close_code = frame.map(|frame| frame.code);
+        self.close_reason = frame
+            .map(|frame| frame.reason.to_string())
+            .filter(|reason| !reason.is_empty());
+    }
+}
+
+/// The comma-separated values of every instance of the given header
+fn offered(headers: &HeaderMap, name: &HeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// A message sent to a room, and who sent it
+#[derive(Clone, Debug)]
+struct Broadcast {
+    from: u64,
+    message: Message,
+}
+
+/// Named rooms, each fanning its members' messages out to every other member
+#[derive(Debug, Default)]
+pub(crate) struct Rooms {
+    rooms: Mutex<HashMap<String, broadcast::Sender<Broadcast>>>,
+    next_member: AtomicU64,
+}
+
+impl Rooms {
+    /// Join the named room (creating it if need be), as a newly-numbered member
+    fn join(
+        &self,
+        room: &str,
+    ) -> (
+        u64,
+        broadcast::Sender<Broadcast>,
+        broadcast::Receiver<Broadcast>,
+    ) {
+        let member = self.next_member.fetch_add(1, Ordering::Relaxed);
+        let mut rooms = self.rooms.lock().unwrap();
+        let sender = rooms
+            .entry(room.to_owned())
+            .or_insert_with(|| broadcast::channel(ROOM_CAPACITY).0);
+
+        // subscribed while the lock is held, so the room can't be forgotten in between
+        (member, sender.clone(), sender.subscribe())
+    }
+
+    /// Forget the named room once its last member has left
+    fn leave(&self, room: &str) {
+        let mut rooms = self.rooms.lock().unwrap();
+
+        if rooms
+            .get(room)
+            .is_some_and(|sender| sender.receiver_count() == 0)
+        {
+            rooms.remove(room);
+        }
+    }
+}
+
+#[tracing::instrument]
+pub(crate) fn router() -> Router {
+    Router::new()
+        .route("/_ws", routing::get(upgrade))
+        .route("/ws/rooms/:room", routing::get(join))
+        .with_state(Arc::new(Rooms::default()))
+}
+
+/// Echo every message sent over a WebSocket back to its sender, speaking
+/// whichever subprotocol the client prefers, and optionally closing the
+/// session (after a final summary frame) once `?close_after=` messages
+/// have been echoed
+#[tracing::instrument(skip_all)]
+async fn upgrade(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    Query(params): Query<SessionParams>,
+) -> Response {
+    let summary = Summary {
+        offered_protocols: offered(&headers, &header::SEC_WEBSOCKET_PROTOCOL),
+        offered_extensions: offered(&headers, &header::SEC_WEBSOCKET_EXTENSIONS),
+        ..Summary::default()
+    };
+
+    ws.protocols(summary.offered_protocols.first().cloned())
+        .on_upgrade(move |socket| session(socket, summary, params))
+}
+
+async fn session(mut socket: WebSocket, mut summary: Summary, params: SessionParams) {
+    summary.protocol = socket
+        .protocol()
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned);
+
+    tracing::info!(
+        "WebSocket session opened (protocol: {:?}, offered extensions: {:?})",
+        summary.protocol,
+        summary.offered_extensions
+    );
+
+    while let Some(message) = socket.recv().await {
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                tracing::debug!("WebSocket session failed: {error}");
+                summary.close("connection", None);
+                break;
+            }
+        };
+
+        match message {
+            Message::Text(_) | Message::Binary(_) => {
+                summary.messages += 1;
+                summary.bytes += message.clone().into_data().len() as u64;
+
+                if socket.send(message).await.is_err() {
+                    summary.close("connection", None);
+                    break;
+                }
+
+                if params
+                    .close_after
+                    .is_some_and(|limit| summary.messages >= limit)
+                {
+                    let frame = CloseFrame {
+                        code: params.close_code.unwrap_or(NORMAL_CLOSURE),
+                        reason: "".into(),
+                    };
+
+                    summary.close("server", Some(&frame));
+
+                    let summary = serde_json::to_string(&summary).unwrap_or_default();
+                    let _ = socket.send(Message::Text(summary)).await;
+                    let _ = socket.send(Message::Close(Some(frame))).await;
+                    break;
+                }
+            }
+            Message::Close(frame) => {
+                summary.close("client", frame.as_ref());
+                break;
+            }
+            // pings are answered by the socket itself
+            Message::Ping(_) | Message::Pong(_) => {}
+        }
+    }
+
+    tracing::info!(
+        "WebSocket session closed: {}",
+        serde_json::to_string(&summary).unwrap_or_default()
+    );
+}
+
+/// Relay every message sent to a room by one of its members to all of the others
+#[tracing::instrument(skip_all)]
+async fn join(
+    ws: WebSocketUpgrade,
+    State(rooms): State<Arc<Rooms>>,
+    Path(room): Path<String>,
+) -> Response {
+    ws.on_upgrade(move |socket| membership(socket, rooms, room))
+}
+
+async fn membership(mut socket: WebSocket, rooms: Arc<Rooms>, room: String) {
+    let (member, sender, mut receiver) = rooms.join(&room);
+
+    tracing::info!(
+        "Member {member} joined WebSocket room {room:?} ({} present)",
+        sender.receiver_count()
+    );
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(message @ (Message::Text(_) | Message::Binary(_)))) => {
+                    let size = message.clone().into_data().len();
+
+                    tracing::info!("Member {member} sent {size} bytes to WebSocket room {room:?}");
+                    metrics::increment_counter!(
+                        "websocket_room_messages_total",
+                        "room" => room.clone()
+                    );
+                    metrics::counter!(
+                        "websocket_room_bytes_total",
+                        size as u64,
+                        "room" => room.clone()
+                    );
+
+                    let _ = sender.send(Broadcast { from: member, message });
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // pings are answered by the socket itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+            },
+            broadcast = receiver.recv() => match broadcast {
+                Ok(Broadcast { from, message }) if from != member => {
+                    if socket.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::debug!(
+                        "Member {member} of WebSocket room {room:?} missed {missed} messages"
+                    );
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+
+    drop(receiver);
+    rooms.leave(&room);
+
+    tracing::info!("Member {member} left WebSocket room {room:?}");
+}